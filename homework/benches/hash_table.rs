@@ -0,0 +1,196 @@
+//! Throughput of the hash tables under a mix of reads and writes, per number of threads.
+//!
+//! ```sh
+//! cargo bench --bench hash_table -- [--threads 1,2,4,8] [--reads 90] [--keys 65536] [--ops 1000000]
+//! ```
+//!
+//! - `--threads`: numbers of threads to run with, comma-separated.
+//! - `--reads`: percentage of lookups. The other operations are inserts and deletes, half each, so
+//!   that the number of items stays around half of the keys.
+//! - `--keys`: number of keys, which are drawn uniformly.
+//! - `--ops`: number of operations per thread.
+
+use std::collections::HashMap;
+use std::env;
+use std::hint::black_box;
+use std::sync::{Barrier, Mutex};
+use std::thread::scope;
+use std::time::{Duration, Instant};
+
+use crossbeam_epoch::pin;
+use cs431_homework::{ConcurrentMap, SplitOrderedList, StripedHashMap};
+
+/// Map under benchmark. The results only tell whether the operation succeeded, as the baseline
+/// with a single lock cannot return references to the values.
+trait Map: Default + Sync {
+    const NAME: &'static str;
+
+    fn lookup(&self, key: usize) -> bool;
+    fn insert(&self, key: usize) -> bool;
+    fn delete(&self, key: usize) -> bool;
+}
+
+impl Map for SplitOrderedList<usize> {
+    const NAME: &'static str = "SplitOrderedList";
+
+    fn lookup(&self, key: usize) -> bool {
+        ConcurrentMap::lookup(self, &key, &pin()).is_some()
+    }
+
+    fn insert(&self, key: usize) -> bool {
+        ConcurrentMap::insert(self, key, key, &pin()).is_ok()
+    }
+
+    fn delete(&self, key: usize) -> bool {
+        ConcurrentMap::delete(self, &key, &pin()).is_ok()
+    }
+}
+
+impl Map for StripedHashMap<usize, usize> {
+    const NAME: &'static str = "StripedHashMap";
+
+    fn lookup(&self, key: usize) -> bool {
+        ConcurrentMap::lookup(self, &key, &pin()).is_some()
+    }
+
+    fn insert(&self, key: usize) -> bool {
+        ConcurrentMap::insert(self, key, key, &pin()).is_ok()
+    }
+
+    fn delete(&self, key: usize) -> bool {
+        ConcurrentMap::delete(self, &key, &pin()).is_ok()
+    }
+}
+
+impl Map for Mutex<HashMap<usize, usize>> {
+    const NAME: &'static str = "Mutex<HashMap>";
+
+    fn lookup(&self, key: usize) -> bool {
+        self.lock().unwrap().contains_key(&key)
+    }
+
+    fn insert(&self, key: usize) -> bool {
+        let mut map = self.lock().unwrap();
+        if map.contains_key(&key) {
+            return false;
+        }
+        let _ = map.insert(key, key);
+        true
+    }
+
+    fn delete(&self, key: usize) -> bool {
+        self.lock().unwrap().remove(&key).is_some()
+    }
+}
+
+#[derive(Debug)]
+struct Config {
+    threads: Vec<usize>,
+    reads: u64,
+    keys: usize,
+    ops: usize,
+}
+
+impl Config {
+    fn from_args() -> Self {
+        let mut config = Self {
+            threads: vec![1, 2, 4, 8],
+            reads: 90,
+            keys: 1 << 16,
+            ops: 1_000_000,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().unwrap_or_else(|| panic!("no value for {arg}"));
+            match arg.as_str() {
+                "--threads" => {
+                    config.threads = value()
+                        .split(',')
+                        .map(|t| t.parse().expect("invalid number of threads"))
+                        .collect()
+                }
+                "--reads" => config.reads = value().parse().expect("invalid percentage"),
+                "--keys" => config.keys = value().parse().expect("invalid number of keys"),
+                "--ops" => config.ops = value().parse().expect("invalid number of operations"),
+                // Passed by `cargo bench`.
+                "--bench" => {}
+                _ => panic!("unknown argument {arg}"),
+            }
+        }
+        assert!(
+            config.reads <= 100,
+            "the percentage of reads should be at most 100"
+        );
+        assert!(config.keys > 0, "there should be at least one key");
+        config
+    }
+}
+
+/// Xorshift generator, so that generating the operations costs little next to running them.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Runs `config.ops` operations on each of `threads` threads, and returns the elapsed time.
+fn run<M: Map>(config: &Config, threads: usize) -> Duration {
+    let map = M::default();
+    for key in (0..config.keys).step_by(2) {
+        let _ = map.insert(key);
+    }
+
+    let barrier = Barrier::new(threads + 1);
+    scope(|s| {
+        for t in 0..threads {
+            let (map, barrier) = (&map, &barrier);
+            let _ = s.spawn(move || {
+                let mut rng = Rng(0x9E37_79B9_7F4A_7C15 ^ (t as u64 + 1));
+                let _ = barrier.wait();
+                for _ in 0..config.ops {
+                    let r = rng.next();
+                    let key = (r >> 8) as usize % config.keys;
+                    let _ = black_box(match r % 100 {
+                        p if p < config.reads => map.lookup(key),
+                        p if p % 2 == 0 => map.insert(key),
+                        _ => map.delete(key),
+                    });
+                }
+                let _ = barrier.wait();
+            });
+        }
+        let _ = barrier.wait();
+        let start = Instant::now();
+        let _ = barrier.wait();
+        start.elapsed()
+    })
+}
+
+fn bench<M: Map>(config: &Config) {
+    for &threads in &config.threads {
+        let elapsed = run::<M>(config, threads);
+        let mops = (config.ops * threads) as f64 / elapsed.as_secs_f64() / 1e6;
+        println!(
+            "{:<20} {threads:>7} {:>6}% {mops:>10.2} {:>14.2}",
+            M::NAME,
+            config.reads,
+            mops / threads as f64
+        );
+    }
+}
+
+fn main() {
+    let config = Config::from_args();
+    println!(
+        "{:<20} {:>7} {:>7} {:>10} {:>14}",
+        "map", "threads", "reads", "Mops/s", "Mops/s/thread"
+    );
+    bench::<SplitOrderedList<usize>>(&config);
+    bench::<StripedHashMap<usize, usize>>(&config);
+    bench::<Mutex<HashMap<usize, usize>>>(&config);
+}
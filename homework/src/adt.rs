@@ -13,6 +13,19 @@ pub trait ConcurrentMap<K: ?Sized, V> {
     /// Unlike stack or queue's pop that can return `Option<V>`, since a `delete`d
     /// value may also be `lookup`ed, we can only return a reference, not full ownership.
     fn delete<'a>(&'a self, key: &K, guard: &'a Guard) -> Result<&'a V, ()>;
+
+    /// Number of key-value pairs. It may be off while pairs are inserted or deleted concurrently.
+    fn len(&self) -> usize;
+
+    /// Returns `true` iff there are no key-value pairs. See `len`.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` iff the map contains the given key.
+    fn contains_key(&self, key: &K, guard: &Guard) -> bool {
+        self.lookup(key, guard).is_some()
+    }
 }
 
 /// Trait for a concurrent set.
@@ -25,4 +38,12 @@ pub trait ConcurrentSet<T> {
 
     /// Removes the value from the set. Returns whether the value was present in the set.
     fn remove(&self, value: &T) -> bool;
+
+    /// Removes the smallest value from the set, and returns it if the set was not empty.
+    ///
+    /// The sets whose readers may still access a removed value, e.g. with optimistic locking,
+    /// return a clone of it, and drop the value once the readers are done.
+    fn pop_min(&self) -> Option<T>
+    where
+        T: Clone;
 }
@@ -2,9 +2,7 @@ use std::io;
 use std::sync::Arc;
 use std::sync::mpsc::{channel, sync_channel};
 
-use cs431_homework::hello_server::{
-    CancellableTcpListener, Handler, Statistics, THREADPOOL, ThreadPool,
-};
+use cs431_homework::hello_server::{CancellableTcpListener, Handler, Statistics, ThreadPool};
 
 const ADDR: &str = "localhost:7878";
 
@@ -25,7 +23,7 @@ fn main() -> io::Result<()> {
     //
     // - A reporter: it aggregates the reports from the workers and processes the statistics. When
     //   it ends, it sends the statistics to the main thread.
-    let pool = &THREADPOOL;
+    let pool = Arc::new(ThreadPool::new(7));
 
     // The (MPSC) channel of reports between workers and the reporter.
     let (report_sender, report_receiver) = channel();
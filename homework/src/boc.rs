@@ -5,7 +5,7 @@ use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 use core::{fmt, hint, ptr};
 use std::sync::Arc;
 
-use crate::hello_server::ThreadPool;
+use crate::pool::ThreadPool;
 
 /// A trait representing a `Cown`.
 ///
@@ -250,7 +250,7 @@ impl Behavior {
 
         let self_owned: Box<Self> = unsafe { Box::from_raw(this as *mut Self) };
 
-        ThreadPool::new(0).execute(move || {
+        ThreadPool::global().execute(move || {
             (self_owned.thunk)();
             for req in &self_owned.requests {
                 unsafe { req.release() };
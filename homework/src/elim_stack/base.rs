@@ -1,21 +1,31 @@
+use core::hint;
 use core::marker::PhantomData;
 use core::mem::ManuallyDrop;
 use core::ops::Deref;
-use std::time;
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicU64, AtomicUsize};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crossbeam_epoch::{Atomic, Guard, Owned, pin};
 use rand::{Rng, thread_rng};
 
+/// Default number of elimination slots.
 pub(crate) const ELIM_SIZE: usize = 16;
-pub(crate) const ELIM_DELAY: time::Duration = time::Duration::from_millis(10);
+/// Default upper bound of the wait for a partner in an elimination slot.
+pub(crate) const ELIM_MAX_WAIT: Duration = Duration::from_millis(10);
+/// Lower bound of the wait for a partner, unless the upper bound is even lower.
+pub(crate) const ELIM_MIN_WAIT: Duration = Duration::from_micros(1);
 pub(crate) const IDLE: usize = 0;
 pub(crate) const PUSH_PENDING: usize = 1;
 pub(crate) const POP_PENDING: usize = 2;
+pub(crate) const ACK: usize = 3;
 
-#[inline]
-pub(crate) fn get_random_elim_index() -> usize {
-    thread_rng().r#gen::<usize>() % ELIM_SIZE
-}
+/// Number of rounds of spinning, with `2^round` spins each, while waiting for a partner.
+const SPIN_LIMIT: u32 = 6;
+/// Number of rounds, including those of spinning, after which the waiter parks instead of
+/// yielding.
+const YIELD_LIMIT: u32 = 10;
 
 /// Concurrent stack types.
 pub trait Stack<T>: Default {
@@ -63,22 +73,115 @@ pub trait Stack<T>: Default {
 }
 
 /// Elimination backoff stack
+///
+/// The wait for a partner in a slot and the number of slots in use adapt to the recent
+/// outcomes of elimination. A successful elimination doubles the wait, up to the maximum. A wait
+/// that times out halves both the wait and the slots in use, so that a stack with few concurrent
+/// pushes and pops quickly stops waiting. A slot found busy doubles the slots in use.
 #[derive(Debug)]
 pub struct ElimStack<T, S: Stack<T>> {
     pub(crate) inner: S,
-    // slot tags:
+    // slot tags, in the two low bits of the pointer to the push request:
     // - 0: no request
     // - 1: push request
     // - 2: pop request
     // - 3: request acknowledged
-    pub(crate) slots: [Atomic<S::PushReq>; ELIM_SIZE],
+    pub(crate) slots: Box<[Atomic<S::PushReq>]>,
+    max_wait: Duration,
+    /// Current wait for a partner in nanoseconds.
+    wait: AtomicU64,
+    /// Number of slots in use, from the first one.
+    range: AtomicUsize,
 }
 
-impl<T, S: Stack<T>> Default for ElimStack<T, S> {
-    fn default() -> Self {
+impl<T, S: Stack<T>> ElimStack<T, S> {
+    /// Creates a new stack with `slots` elimination slots, where a push or pop waits for a
+    /// partner for at most `max_wait`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slots` is zero.
+    pub fn with_config(slots: usize, max_wait: Duration) -> Self {
+        assert!(slots > 0, "there should be at least one slot");
+        const { assert!(align_of::<S::PushReq>() > ACK, "no room for the slot tags") };
         Self {
             inner: Default::default(),
-            slots: Default::default(),
+            slots: (0..slots).map(|_| Atomic::null()).collect(),
+            max_wait,
+            wait: AtomicU64::new(Self::nanos(ELIM_MIN_WAIT.min(max_wait))),
+            range: AtomicUsize::new(slots),
+        }
+    }
+
+    fn nanos(duration: Duration) -> u64 {
+        duration.as_nanos().try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Returns a random slot among those in use.
+    pub(crate) fn random_slot(&self) -> &Atomic<S::PushReq> {
+        let range = self.range.load(Relaxed);
+        &self.slots[thread_rng().gen_range(0..range)]
+    }
+
+    /// Waits until `done` returns `true`, for at most the current wait. Returns whether `done`
+    /// returned `true`.
+    ///
+    /// The waiter spins first, then yields, and then parks for at most the time it has waited so
+    /// far, as no partner unparks it.
+    pub(crate) fn wait_for(&self, mut done: impl FnMut() -> bool) -> bool {
+        let wait = Duration::from_nanos(self.wait.load(Relaxed));
+        let start = Instant::now();
+        for round in 0.. {
+            if done() {
+                return true;
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= wait {
+                break;
+            }
+            if round <= SPIN_LIMIT {
+                for _ in 0..1 << round {
+                    hint::spin_loop();
+                }
+            } else if round <= YIELD_LIMIT {
+                thread::yield_now();
+            } else {
+                thread::park_timeout((wait - elapsed).min(elapsed));
+            }
         }
+        false
+    }
+
+    // The tuning below races with that of the other threads, and may lose some of their updates,
+    // which is fine for a heuristic.
+
+    /// Records that a push was eliminated with a pop.
+    pub(crate) fn on_eliminated(&self) {
+        let wait = self.wait.load(Relaxed).saturating_mul(2);
+        self.wait
+            .store(wait.min(Self::nanos(self.max_wait)), Relaxed);
+    }
+
+    /// Records that no partner came within the wait.
+    pub(crate) fn on_timeout(&self) {
+        let wait = self.wait.load(Relaxed) / 2;
+        self.wait.store(
+            wait.max(Self::nanos(ELIM_MIN_WAIT.min(self.max_wait))),
+            Relaxed,
+        );
+        let range = self.range.load(Relaxed) / 2;
+        self.range.store(range.max(1), Relaxed);
+    }
+
+    /// Records that a slot was taken by another thread of the same kind.
+    pub(crate) fn on_busy(&self) {
+        let range = self.range.load(Relaxed).saturating_mul(2);
+        self.range.store(range.min(self.slots.len()), Relaxed);
+    }
+}
+
+impl<T, S: Stack<T>> Default for ElimStack<T, S> {
+    fn default() -> Self {
+        Self::with_config(ELIM_SIZE, ELIM_MAX_WAIT)
     }
 }
@@ -2,12 +2,147 @@ use core::ops::Deref;
 use core::sync::atomic::Ordering;
 use core::{mem, ptr};
 use std::mem::ManuallyDrop;
-use std::thread;
 
 use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
 
 use super::base::*;
 
+// Protocol of a slot, as (request, tag):
+//
+// - A pusher offers its request on an idle slot with (null, IDLE) -> (req, PUSH_PENDING). A popper
+//   takes the offer with (req, PUSH_PENDING) -> (null, IDLE), or the pusher withdraws it with the
+//   same CAS if no popper comes.
+// - A popper waits on an idle slot with (null, IDLE) -> (null, POP_PENDING). A pusher hands its
+//   request over with (null, POP_PENDING) -> (req, ACK). Then the popper takes it, and stores
+//   (null, IDLE). The popper withdraws with the reverse of its first CAS if no pusher comes.
+//
+// Hence a push is only matched with a pop. A slot with a pending request of the same kind, or
+// with an acknowledged request, is busy.
+
+impl<T, S: Stack<T>> ElimStack<T, S> {
+    /// Tries to eliminate the push of `req` with a pop. Returns whether it succeeded. Otherwise,
+    /// `req` is owned by the caller again.
+    fn eliminate_push(&self, req: Shared<'_, S::PushReq>, guard: &Guard) -> bool {
+        let slot_ref = self.random_slot();
+        let slot = slot_ref.load(Ordering::Acquire, guard);
+
+        match slot.tag() {
+            IDLE => {
+                let offer = req.with_tag(PUSH_PENDING);
+                if slot_ref
+                    .compare_exchange(slot, offer, Ordering::Release, Ordering::Relaxed, guard)
+                    .is_err()
+                {
+                    self.on_busy();
+                    return false;
+                }
+
+                let _ = self.wait_for(|| slot_ref.load(Ordering::Relaxed, guard) != offer);
+
+                // Check Collision. `req` is not freed while we are pinned, so the slot cannot
+                // hold `offer` again after a popper has taken it.
+                if slot_ref
+                    .compare_exchange(offer, slot, Ordering::Relaxed, Ordering::Relaxed, guard)
+                    .is_ok()
+                {
+                    self.on_timeout();
+                    return false;
+                }
+                self.on_eliminated();
+                true
+            }
+            POP_PENDING => {
+                if slot_ref
+                    .compare_exchange(
+                        slot,
+                        req.with_tag(ACK),
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                        guard,
+                    )
+                    .is_err()
+                {
+                    self.on_busy();
+                    return false;
+                }
+                self.on_eliminated();
+                true
+            }
+            _ => {
+                self.on_busy();
+                false
+            }
+        }
+    }
+
+    /// Tries to eliminate a pop with a push. Returns the value of the push if it succeeded.
+    fn eliminate_pop(&self, guard: &Guard) -> Option<T> {
+        let slot_ref = self.random_slot();
+        let slot = slot_ref.load(Ordering::Acquire, guard);
+
+        let req = match slot.tag() {
+            PUSH_PENDING => {
+                if slot_ref
+                    .compare_exchange(
+                        slot,
+                        Shared::null(),
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                        guard,
+                    )
+                    .is_err()
+                {
+                    self.on_busy();
+                    return None;
+                }
+                slot
+            }
+            IDLE => {
+                let request = slot.with_tag(POP_PENDING);
+                if slot_ref
+                    .compare_exchange(slot, request, Ordering::Relaxed, Ordering::Relaxed, guard)
+                    .is_err()
+                {
+                    self.on_busy();
+                    return None;
+                }
+
+                let _ = self.wait_for(|| slot_ref.load(Ordering::Relaxed, guard).tag() == ACK);
+
+                // Only a pusher can change our request, to acknowledge it.
+                let acked = match slot_ref.compare_exchange(
+                    request,
+                    slot,
+                    Ordering::Relaxed,
+                    Ordering::Acquire,
+                    guard,
+                ) {
+                    Ok(_) => {
+                        self.on_timeout();
+                        return None;
+                    }
+                    Err(e) => e.current,
+                };
+                debug_assert_eq!(acked.tag(), ACK);
+                slot_ref.store(Shared::null(), Ordering::Relaxed);
+                acked
+            }
+            _ => {
+                self.on_busy();
+                return None;
+            }
+        };
+
+        // Exchanged. The pusher gave up the request with the exchange, so we are the unique owner
+        // of the request node.
+        let req = req.with_tag(0);
+        let data: T = unsafe { ManuallyDrop::into_inner(ptr::read(req.deref().deref())) };
+        unsafe { guard.defer_destroy(req) };
+        self.on_eliminated();
+        Some(data)
+    }
+}
+
 impl<T, S: Stack<T>> Stack<T> for ElimStack<T, S> {
     type PushReq = S::PushReq;
 
@@ -20,53 +155,13 @@ impl<T, S: Stack<T>> Stack<T> for ElimStack<T, S> {
             return Ok(());
         };
 
-        let index = get_random_elim_index();
-        let slot_ref = unsafe { self.slots.get_unchecked(index) };
         let req = req.into_shared(guard);
-
-        let Ok(req) = slot_ref.compare_exchange(
-            Shared::null(),
-            req,
-            Ordering::Relaxed,
-            Ordering::Relaxed,
-            guard,
-        ) else {
-            // Current slot occupied, Retry and return.
-            let Err(req) = self
-                .inner
-                .try_push(unsafe { req.try_into_owned().unwrap() }, guard)
-            else {
-                return Ok(());
-            };
-            return Err(req);
-        };
-
-        thread::sleep(ELIM_DELAY);
-
-        // Check Collision
-        if slot_ref
-            .compare_exchange(
-                req,
-                Shared::null(),
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-                guard,
-            )
-            .is_err()
-        {
-            // Collision
+        if self.eliminate_push(req, guard) {
             return Ok(());
-        };
+        }
 
         // Retry
-        let Err(req) = self
-            .inner
-            .try_push(unsafe { req.try_into_owned().unwrap() }, guard)
-        else {
-            return Ok(());
-        };
-
-        Err(req)
+        self.inner.try_push(unsafe { req.into_owned() }, guard)
     }
 
     fn try_pop(&self, guard: &Guard) -> Result<Option<T>, ()> {
@@ -74,45 +169,12 @@ impl<T, S: Stack<T>> Stack<T> for ElimStack<T, S> {
             return Ok(result);
         }
 
-        let index = get_random_elim_index();
-        let slot_ref = unsafe { self.slots.get_unchecked(index) };
-        let mut slot = slot_ref.load(Ordering::Relaxed, guard);
-
-        if slot.is_null() {
-            thread::sleep(ELIM_DELAY);
-
-            // Try again
-            slot = slot_ref.load(Ordering::Relaxed, guard);
-
-            if slot.is_null() {
-                // Still idle.
-                if let Ok(result) = self.inner.try_pop(guard) {
-                    return Ok(result);
-                }
-                return Err(());
-            }
-        }
-
-        if slot_ref
-            .compare_exchange(
-                slot,
-                Shared::null(),
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-                guard,
-            )
-            .is_ok()
-        {
-            // Exchanged.
-            let data: T = unsafe { ManuallyDrop::into_inner(ptr::read(slot.deref().deref())) };
+        if let Some(data) = self.eliminate_pop(guard) {
             return Ok(Some(data));
         }
 
         // Retry
-        if let Ok(result) = self.inner.try_pop(guard) {
-            return Ok(result);
-        }
-        Err(())
+        self.inner.try_pop(guard)
     }
 
     fn is_empty(&self, guard: &Guard) -> bool {
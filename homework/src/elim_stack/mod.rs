@@ -12,19 +12,84 @@ pub use base::Stack;
 
 #[cfg(test)]
 mod test {
+    use core::marker::PhantomData;
     use std::thread::scope;
+    use std::time::Duration;
 
-    use super::{ElimStack, Stack};
+    use crossbeam_epoch::{Guard, Owned};
+
+    use super::treiber_stack::Node;
+    use super::{ElimStack, Stack, base};
+    use crate::test::scale;
+
+    /// Stack whose CASes always fail, so that every push and pop on top of it is eliminated.
+    #[derive(Debug)]
+    struct Contended<T>(PhantomData<T>);
+
+    impl<T> Default for Contended<T> {
+        fn default() -> Self {
+            Self(PhantomData)
+        }
+    }
+
+    impl<T> Stack<T> for Contended<T> {
+        type PushReq = Node<T>;
+
+        fn try_push(&self, req: Owned<Node<T>>, _: &Guard) -> Result<(), Owned<Node<T>>> {
+            Err(req)
+        }
+
+        fn try_pop(&self, _: &Guard) -> Result<Option<T>, ()> {
+            Err(())
+        }
+
+        fn is_empty(&self, _: &Guard) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn eliminate() {
+        const THREADS: usize = 2;
+        const ITER: usize = scale(1_000, 16);
+
+        let stack = base::ElimStack::<_, Contended<_>>::with_config(2, Duration::from_millis(1));
+        let mut popped = scope(|scope| {
+            for t in 0..THREADS {
+                let stack = &stack;
+                let _ = scope.spawn(move || {
+                    for i in 0..ITER {
+                        stack.push(t * ITER + i);
+                    }
+                });
+            }
+            let handles = (0..THREADS)
+                .map(|_| {
+                    scope.spawn(|| (0..ITER).map(|_| stack.pop().unwrap()).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        popped.sort_unstable();
+        assert!(popped.into_iter().eq(0..THREADS * ITER));
+    }
 
     #[test]
     fn push() {
+        const THREADS: usize = scale(10, 2);
+        const ITER: usize = scale(10_000, 64);
+
         let stack = ElimStack::default();
 
         scope(|scope| {
             let mut handles = Vec::new();
-            for _ in 0..10 {
+            for _ in 0..THREADS {
                 let handle = scope.spawn(|| {
-                    for i in 0..10_000 {
+                    for i in 0..ITER {
                         stack.push(i);
                         assert!(stack.pop().is_some());
                     }
@@ -108,16 +108,20 @@ mod test {
     use std::thread::scope;
 
     use super::*;
+    use crate::test::scale;
 
     #[test]
     fn push() {
+        const THREADS: usize = scale(10, 2);
+        const ITER: usize = scale(10_000, 64);
+
         let stack = TreiberStack::default();
 
         scope(|scope| {
             let mut handles = Vec::new();
-            for _ in 0..10 {
+            for _ in 0..THREADS {
                 let handle = scope.spawn(|| {
-                    for i in 0..10_000 {
+                    for i in 0..ITER {
                         stack.push(i);
                         assert!(stack.pop().is_some());
                     }
@@ -0,0 +1,118 @@
+//! Striped counter for the number of items in `SplitOrderedList`.
+
+use core::num::NonZero;
+use core::sync::atomic::Ordering::*;
+use core::sync::atomic::{AtomicIsize, AtomicUsize};
+
+/// Counter that does not make a single cache line a hotspot when many threads update it.
+///
+/// Each thread adds to one of the `stripes`, and moves the stripe's value to `total` only once it
+/// reaches `BATCH` in absolute value, so `total` is updated once per `BATCH` updates. The exact
+/// value is folded from all the stripes on read.
+#[derive(Debug)]
+pub(super) struct StripedCounter {
+    total: Padded,
+    stripes: Box<[Padded]>,
+}
+
+/// Cell on its own cache line.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct Padded(AtomicIsize);
+
+/// Source of the stripe indices of the threads.
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Stripe index of the current thread, modulo the number of stripes.
+    static STRIPE: usize = NEXT_STRIPE.fetch_add(1, Relaxed);
+}
+
+impl StripedCounter {
+    /// Number of updates to a stripe that are batched into one update to `total`.
+    const BATCH: usize = 32;
+
+    /// Maximum number of stripes.
+    const MAX_STRIPES: usize = 64;
+
+    /// Creates a counter of zero, with a stripe per CPU.
+    pub(super) fn new() -> Self {
+        let stripes = std::thread::available_parallelism()
+            .map_or(1, NonZero::get)
+            .next_power_of_two()
+            .min(Self::MAX_STRIPES);
+        Self {
+            total: Padded::default(),
+            stripes: (0..stripes).map(|_| Padded::default()).collect(),
+        }
+    }
+
+    /// Adds `delta`, and returns an estimate of the new value.
+    ///
+    /// The estimate misses the updates not yet moved from the other stripes, so it is off by less
+    /// than `BATCH` per stripe. It is exact if only the current thread updates the counter.
+    pub(super) fn add(&self, delta: isize) -> usize {
+        let index = STRIPE.with(|stripe| stripe & (self.stripes.len() - 1));
+        let stripe = &self.stripes[index].0;
+        let local = stripe.fetch_add(delta, Relaxed) + delta;
+        let estimate = if local.unsigned_abs() >= Self::BATCH {
+            // Other threads on the same stripe may have updated it since, so take what is there.
+            let local = stripe.swap(0, Relaxed);
+            self.total.0.fetch_add(local, Relaxed) + local
+        } else {
+            self.total.0.load(Relaxed) + local
+        };
+        estimate.max(0) as usize
+    }
+
+    /// Folds the stripes into the current value. It may be off while the counter is updated
+    /// concurrently.
+    pub(super) fn sum(&self) -> usize {
+        let sum = self
+            .stripes
+            .iter()
+            .fold(self.total.0.load(Relaxed), |sum, stripe| {
+                sum + stripe.0.load(Relaxed)
+            });
+        sum.max(0) as usize
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod tests {
+    use std::thread::scope;
+
+    use super::StripedCounter;
+    use crate::test::scale;
+
+    #[test]
+    fn single_thread_exact() {
+        let counter = StripedCounter::new();
+        for i in 1..=1000 {
+            assert_eq!(counter.add(1), i);
+        }
+        for i in (0..1000).rev() {
+            assert_eq!(counter.add(-1), i);
+        }
+        assert_eq!(counter.sum(), 0);
+    }
+
+    #[test]
+    fn concurrent_sum() {
+        const THREADS: usize = 16;
+        const STEPS: usize = scale(10_000, 64);
+
+        let counter = StripedCounter::new();
+        scope(|s| {
+            for t in 0..THREADS {
+                let counter = &counter;
+                let _ = s.spawn(move || {
+                    for _ in 0..STEPS {
+                        let _ = counter.add(if t % 4 == 0 { -1 } else { 2 });
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.sum(), THREADS / 4 * STEPS * 5);
+    }
+}
@@ -3,11 +3,11 @@
 use core::fmt::Debug;
 use core::mem::{self, ManuallyDrop};
 use core::sync::atomic::Ordering::*;
-use core::sync::atomic::fence;
+use core::sync::atomic::{AtomicUsize, fence};
 
-use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
+use crossbeam_epoch::{Atomic, Guard, Owned, Shared, unprotected};
 
-/// Growable array of `Atomic<T>`.
+/// Growable array of `Atomic<T>`, made of segments of `2^LOGSIZE` pointers.
 ///
 /// This is more complete version of the dynamic sized array from the paper. In the paper, the
 /// segment table is an array of arrays (segments) of pointers to the elements. In this
@@ -16,7 +16,7 @@ use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
 ///
 /// # Example run
 ///
-/// Suppose `SEGMENT_LOGSIZE = 3` (segment size 8).
+/// Suppose `LOGSIZE = 3` (segment size 8).
 ///
 /// When a new `GrowableArray` is created, `root` is initialized with `Atomic::null()`.
 ///
@@ -127,61 +127,104 @@ use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
 ///
 /// Instead, it should be handled by the container that the elements actually belong to. For
 /// example, in `SplitOrderedList` the destruction of elements are handled by the inner `List`.
+///
+/// # Segment size
+///
+/// A larger `LOGSIZE` makes the tree shallower, but each segment takes `2^LOGSIZE` pointers even
+/// if only one of them is used, e.g. 8 KiB for the default `LOGSIZE = 10`. Small arrays may prefer
+/// a smaller `LOGSIZE`, e.g. `GrowableArray<T, 4>`.
 #[derive(Debug)]
-pub struct GrowableArray<T> {
+pub struct GrowableArray<T, const LOGSIZE: usize = 10> {
     root: Atomic<Segment<T>>,
+    /// Number of segments in the tree.
+    segments: AtomicUsize,
     // test_arr: Vec<Atomic<T>>,
 }
 
-const SEGMENT_LOGSIZE: usize = 10;
-
-/// A fixed size array of atomic pointers to other `Segment<T>` or `T`.
+/// An array of `2^LOGSIZE` atomic pointers to other `Segment<T>` or `T`.
 ///
 /// Each segment is either a child segment with pointers to `Segment<T>` or an element segment with
 /// pointers to `T`. This is determined by the height of this segment in the main array, which one
 /// needs to track separately. For example, use the main array root's tag.
 ///
-/// Since destructing segments requires its height information, it is not recommended to
-/// implement [`Drop`]. Rather, implement and use the custom [`Segment::deallocate`] method that
-/// accounts for the height of the segment.
+/// Since destructing the child segments requires the height information, dropping a segment only
+/// frees its own pointers. Use [`Segment::deallocate`] to free a segment with its descendants.
+///
+/// The pointers are boxed, as the size of an array cannot depend on `LOGSIZE` yet. The segment is
+/// aligned to 64 bytes, so that the tag of a pointer to the root segment has room for its height
+/// even for `LOGSIZE = 1`.
+#[repr(align(64))]
 union Segment<T> {
-    children: ManuallyDrop<[Atomic<Segment<T>>; 1 << SEGMENT_LOGSIZE]>,
-    elements: ManuallyDrop<[Atomic<T>; 1 << SEGMENT_LOGSIZE]>,
+    children: ManuallyDrop<Box<[Atomic<Segment<T>>]>>,
+    elements: ManuallyDrop<Box<[Atomic<T>]>>,
+}
+
+/// Returns a slice of `len` null `Atomic` pointers.
+///
+/// This is used instead of `mem::zeroed()`. A zeroed `Atomic` happens to be a null pointer, but
+/// relying on that depends on the internal representation of `crossbeam_epoch::Atomic`.
+fn null_slice<P>(len: usize) -> Box<[Atomic<P>]> {
+    (0..len).map(|_| Atomic::null()).collect()
 }
 
 impl<T> Segment<T> {
-    /// Create a new segment filled with null pointers. It is up to the callee to whether to use
-    /// this as a children or an element segment.
-    fn new() -> Owned<Self> {
-        Owned::new(
-            // SAFETY: An array of null pointers can be interperted as either an element segment or
-            // a children segment.
-            unsafe { mem::zeroed() },
-        )
+    /// Create a new segment of `len` null pointers. It is up to the callee to whether to use this
+    /// as a children or an element segment.
+    fn new(len: usize) -> Owned<Self> {
+        // NOTE: Both variants are boxed slices of null `Atomic`s, which have the same layout
+        // regardless of the pointee type. Hence the segment can be read as either variant.
+        Owned::new(Self {
+            children: ManuallyDrop::new(null_slice(len)),
+        })
     }
 
-    /// Deallocates a segment of `height`.
+    /// Deallocates a segment of `height` together with its descendant segments.
     ///
     /// # Safety
     ///
-    /// `self` must actually have height `height`.
-    unsafe fn deallocate(self, height: usize) {
-        unsafe {
-            let guard = &crossbeam_epoch::pin();
-            if height > 0 {
-                for i in 0..self.children.len() {
-                    if self.children[i].load(Relaxed, guard).is_null() {
-                        continue;
-                    }
-                    self.children[i]
-                        .clone()
-                        .into_owned()
-                        .into_box()
-                        .deallocate(height - 1);
+    /// `self` must actually have height `height`, and no other thread may access it or its
+    /// descendants.
+    unsafe fn deallocate(self, height: usize, guard: &Guard) {
+        if height > 0 {
+            for child in unsafe { self.children.iter() } {
+                let child = child.load(Relaxed, guard);
+                if !child.is_null() {
+                    unsafe { child.into_owned().into_box().deallocate(height - 1, guard) };
                 }
             }
         }
     }
+
+    /// Number of segments in the subtree of `self`, which has height `height`.
+    fn count(&self, height: usize, guard: &Guard) -> usize {
+        if height == 0 {
+            return 1;
+        }
+        1 + unsafe { self.children.iter() }
+            .filter_map(|child| unsafe { child.load(Acquire, guard).as_ref() })
+            .map(|child| child.count(height - 1, guard))
+            .sum::<usize>()
+    }
+}
+
+impl<T> Drop for Segment<T> {
+    /// Frees the pointers, but not the segments or the elements they point to.
+    fn drop(&mut self) {
+        // Frees the pointers of either variant, as they have the same layout.
+        unsafe { ManuallyDrop::drop(&mut self.children) };
+    }
+}
+
+/// Hints the CPU to fetch the pointers of `segment` into the cache.
+#[inline]
+fn prefetch<T>(segment: &Segment<T>) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use core::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+        _mm_prefetch::<_MM_HINT_T0>(segment.children.as_ptr().cast());
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = segment;
 }
 
 impl<T> Debug for Segment<T> {
@@ -190,30 +233,72 @@ impl<T> Debug for Segment<T> {
     }
 }
 
-impl<T> Drop for GrowableArray<T> {
+/// Iterator over the occupied slots of a `GrowableArray`, in the order of their indices. See
+/// `GrowableArray::iter`.
+#[derive(Debug)]
+pub struct Iter<'g, T, const LOGSIZE: usize> {
+    guard: &'g Guard,
+    /// The segments being walked, with their heights, the indices of their first slots, and the
+    /// offsets of their next slots to visit.
+    stack: Vec<(&'g Segment<T>, usize, usize, usize)>,
+}
+
+impl<'g, T, const LOGSIZE: usize> Iterator for Iter<'g, T, LOGSIZE> {
+    type Item = (usize, Shared<'g, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((segment, height, base, offset)) = self.stack.last_mut() {
+            if *offset == 1 << LOGSIZE {
+                let _ = self.stack.pop();
+                continue;
+            }
+            let (segment, height) = (*segment, *height);
+            // Wraps only for the slots of the root beyond `usize::MAX`, which are never occupied.
+            let index = base.wrapping_add(offset.wrapping_shl((LOGSIZE * height) as u32));
+            let slot = *offset;
+            *offset += 1;
+
+            if height == 0 {
+                let ptr = unsafe { &segment.elements[slot] }.load(Acquire, self.guard);
+                if !ptr.is_null() {
+                    return Some((index, ptr));
+                }
+            } else {
+                let child = unsafe { &segment.children[slot] }.load(Acquire, self.guard);
+                if let Some(child) = unsafe { child.as_ref() } {
+                    self.stack.push((child, height - 1, index, 0));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<T, const LOGSIZE: usize> Drop for GrowableArray<T, LOGSIZE> {
     /// Deallocate segments, but not the individual elements.
     fn drop(&mut self) {
-        unsafe {
-            self.root
-                .clone()
-                .into_owned()
-                .into_box()
-                .deallocate(self.root.load(Relaxed, &crossbeam_epoch::pin()).tag());
+        // SAFETY: no other thread can access the segments of `self`.
+        let guard = unsafe { unprotected() };
+        let root = self.root.load(Relaxed, guard);
+        if !root.is_null() {
+            unsafe { root.into_owned().into_box().deallocate(root.tag(), guard) };
         }
     }
 }
 
-impl<T> Default for GrowableArray<T> {
+impl<T, const LOGSIZE: usize> Default for GrowableArray<T, LOGSIZE> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<T> GrowableArray<T> {
-    /// Create a new growable array.
+impl<T, const LOGSIZE: usize> GrowableArray<T, LOGSIZE> {
+    /// Create a new growable array. The first segment is allocated by the first `get`.
     pub fn new() -> Self {
+        const { assert!(LOGSIZE > 0 && LOGSIZE < usize::BITS as usize) };
         Self {
-            root: Atomic::from(Segment::<T>::new().with_tag(0)),
+            root: Atomic::null(),
+            segments: AtomicUsize::new(0),
             // test_arr: vec![Atomic::null(); 100000],
         }
     }
@@ -221,72 +306,361 @@ impl<T> GrowableArray<T> {
     /// Returns the reference to the `Atomic` pointer at `index`. Allocates new segments if
     /// necessary.
     pub fn get<'g>(&self, mut index: usize, guard: &'g Guard) -> &'g Atomic<T> {
-        let mut mask = (1 << SEGMENT_LOGSIZE) - 1;
+        let mut mask = (1 << LOGSIZE) - 1;
         let mut height = 0;
         // println!("Mask init: 0x{mask:2x}");
         while index & mask != index {
             height += 1;
-            mask = mask << SEGMENT_LOGSIZE | mask;
+            mask = mask << LOGSIZE | mask;
             // println!("Mask padded: 0x{mask:2x}");
         }
 
         // println!("Getting: 0x{index:2x}, height: {height}");
 
-        if height > (std::mem::size_of::<usize>() << 3) / SEGMENT_LOGSIZE {
+        if height > (std::mem::size_of::<usize>() << 3) / LOGSIZE {
             panic!(
                 "growable_array::get : Index overflow. {height} > {}. Idx: 0x{:02x}",
-                (std::mem::size_of::<usize>() << 3) / SEGMENT_LOGSIZE,
+                (std::mem::size_of::<usize>() << 3) / LOGSIZE,
                 index
             );
         }
 
-        // Create segments bottom-up
-        let mask =
-            ((1usize << SEGMENT_LOGSIZE) - 1).wrapping_shl((SEGMENT_LOGSIZE * height) as u32);
         fence(Acquire);
-        for layer in (0..height).rev() {
-            // println!("Layer: {layer}");
-            let ptr = self.root.load(Relaxed, guard);
-            if ptr.tag() >= height {
-                break;
-            }
-            let mut new_segment = Segment::<T>::new().with_tag(ptr.tag() + 1);
-            unsafe { new_segment.children[0] = Atomic::from(ptr) };
-            let _ = self
-                .root
-                .compare_exchange(ptr, new_segment, AcqRel, Acquire, guard);
-        }
+        let mut ptr = self.grow(height, guard);
 
         // Locate element top-down
         let mut atm_ptr = &self.root;
-        let mask = (1 << SEGMENT_LOGSIZE) - 1;
-        let mut ptr = atm_ptr.load(Acquire, guard);
+        let mask = (1 << LOGSIZE) - 1;
         unsafe {
             for layer in (0..=ptr.tag()).rev() {
-                let offset = (index >> (SEGMENT_LOGSIZE * layer)) & mask;
+                let offset = (index >> (LOGSIZE * layer)) & mask;
                 // println!("Index: 0x{:2X}, Offset: 0x{:2X}, Mask: 0x{:2X}", index, offset, mask);
-                if !ptr.is_null() {
-                    if layer == 0 {
-                        return &ptr.as_ref().unwrap().elements[offset];
+                // `truncate` may detach the segment again before it is reloaded, so the segment is
+                // taken from the result of the CAS, and installed again if it is gone.
+                let segment = loop {
+                    if let Some(segment) = ptr.as_ref() {
+                        break segment;
                     }
                     // println!("Layer: {layer}, Prev Ptr: {atm_ptr:?}");
-                    atm_ptr = &ptr.as_ref().unwrap().children[offset];
-                    // println!("Layer: {layer}, Goto Ptr: {atm_ptr:?}");
-                } else {
-                    // println!("Layer: {layer}, Prev Ptr: {atm_ptr:?}");
-                    let new_segment = Segment::<T>::new().with_tag(layer);
-                    let res = atm_ptr.compare_exchange(ptr, new_segment, AcqRel, Acquire, guard);
-                    // println!("Layer: {layer}, Allocated Ptr: {atm_ptr:?}");
-                    if layer == 0 {
-                        return &atm_ptr.load(Relaxed, guard).as_ref().unwrap().elements[offset];
+                    let new_segment = Segment::<T>::new(1 << LOGSIZE).with_tag(layer);
+                    // If another thread installs a segment first, the new one is dropped.
+                    match atm_ptr.compare_exchange(ptr, new_segment, AcqRel, Acquire, guard) {
+                        Ok(new) => {
+                            let _ = self.segments.fetch_add(1, Relaxed);
+                            ptr = new;
+                        }
+                        Err(e) => ptr = e.current,
                     }
-                    atm_ptr = &atm_ptr.load(Relaxed, guard).as_ref().unwrap().children[offset];
-                    // println!("Layer: {layer}, Goto Ptr: {atm_ptr:?}");
+                    // println!("Layer: {layer}, Allocated Ptr: {atm_ptr:?}");
+                };
+                if layer == 0 {
+                    return &segment.elements[offset];
                 }
-                ptr = atm_ptr.load(Relaxed, guard);
+                atm_ptr = &segment.children[offset];
+                // println!("Layer: {layer}, Goto Ptr: {atm_ptr:?}");
+                ptr = atm_ptr.load(Acquire, guard);
             }
         }
 
         panic!("growablearray_get: possible overflow of layers.");
     }
+
+    /// Grows the root to at least `height`, and returns it.
+    ///
+    /// `truncate` may lower the root between the growth and the load of the root, so it is grown
+    /// again until the loaded root covers `height`. Otherwise, a walk from a lower root would drop
+    /// the upper bits of the index.
+    fn grow<'g>(&self, height: usize, guard: &'g Guard) -> Shared<'g, Segment<T>> {
+        loop {
+            // Create segments bottom-up
+            // The first root is allocated with `height`, as it has no child yet. The root is never
+            // null below, as it is only replaced by another root.
+            if self.root.load(Relaxed, guard).is_null()
+                && self
+                    .root
+                    .compare_exchange(
+                        Shared::null(),
+                        Segment::<T>::new(1 << LOGSIZE).with_tag(height),
+                        AcqRel,
+                        Acquire,
+                        guard,
+                    )
+                    .is_ok()
+            {
+                let _ = self.segments.fetch_add(1, Relaxed);
+            }
+            for _ in 0..height {
+                let ptr = self.root.load(Relaxed, guard);
+                if ptr.tag() >= height {
+                    break;
+                }
+                let mut new_segment = Segment::<T>::new(1 << LOGSIZE).with_tag(ptr.tag() + 1);
+                unsafe { new_segment.children[0] = Atomic::from(ptr) };
+                // If another thread grows the root first, the new segment is dropped without its
+                // child.
+                if self
+                    .root
+                    .compare_exchange(ptr, new_segment, AcqRel, Acquire, guard)
+                    .is_ok()
+                {
+                    let _ = self.segments.fetch_add(1, Relaxed);
+                }
+            }
+
+            let root = self.root.load(Acquire, guard);
+            if root.tag() >= height {
+                return root;
+            }
+        }
+    }
+
+    /// Height of the tree, i.e. the number of child segment levels above the element segments.
+    pub fn height(&self, guard: &Guard) -> usize {
+        self.root.load(Acquire, guard).tag()
+    }
+
+    /// Number of bytes taken by the segments, excluding the elements.
+    ///
+    /// Segments installed concurrently with `truncate` above its bound may not be accounted.
+    pub fn allocated_bytes(&self) -> usize {
+        self.segments.load(Relaxed)
+            * (mem::size_of::<Segment<T>>() + (mem::size_of::<Atomic<T>>() << LOGSIZE))
+    }
+
+    /// Stores `ptr` at `index`, allocating new segments if necessary.
+    ///
+    /// The store is `Release`, so a thread that loads `ptr` with `Acquire` from the `Atomic` of
+    /// `get` sees the initialization of the pointee, e.g. when publishing a bucket pointer.
+    pub fn set<'g>(&self, index: usize, ptr: Shared<'g, T>, guard: &'g Guard) {
+        self.get(index, guard).store(ptr, Release);
+    }
+
+    /// Stores `new` at `index` if the current pointer is `current`, allocating new segments if
+    /// necessary. As with `Atomic::compare_exchange`, returns `new` on success, and the current
+    /// pointer on failure.
+    ///
+    /// Uses `AcqRel` on success and `Acquire` on failure, so that `new` is published as with `set`
+    /// and the returned pointer can be dereferenced in either case.
+    pub fn compare_exchange_at<'g>(
+        &self,
+        index: usize,
+        current: Shared<'g, T>,
+        new: Shared<'g, T>,
+        guard: &'g Guard,
+    ) -> Result<Shared<'g, T>, Shared<'g, T>> {
+        self.get(index, guard)
+            .compare_exchange(current, new, AcqRel, Acquire, guard)
+            .map_err(|e| e.current)
+    }
+
+    /// Returns the references to the `Atomic` pointers at `indices`, in the same order. Allocates
+    /// new segments if necessary, as `get`.
+    ///
+    /// The indices are visited in sorted order, so that the segments shared by consecutive indices
+    /// are walked only once, and the first segment of the next index that is not shared is
+    /// prefetched.
+    pub fn get_many<'g>(&self, indices: &[usize], guard: &'g Guard) -> Vec<&'g Atomic<T>> {
+        let Some(&max_index) = indices.iter().max() else {
+            return Vec::new();
+        };
+        // Grow the root to cover all the indices.
+        let mut height = 0;
+        while max_index
+            .checked_shr((LOGSIZE * (height + 1)) as u32)
+            .is_some_and(|upper| upper != 0)
+        {
+            height += 1;
+        }
+        let root = self.grow(height, guard);
+        let height = root.tag();
+        let mask = (1 << LOGSIZE) - 1;
+
+        let mut order = (0..indices.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&i| indices[i]);
+
+        // The lowest layer whose segment is on the paths of both indices.
+        let shared_layer = |prev: usize, index: usize| {
+            let mut layer = height;
+            while layer > 0
+                && (prev ^ index)
+                    .checked_shr((LOGSIZE * layer) as u32)
+                    .is_none_or(|diff| diff == 0)
+            {
+                layer -= 1;
+            }
+            layer
+        };
+
+        let mut result = vec![None; indices.len()];
+        // `path[layer]` is the segment of `layer` on the path to the previous index.
+        let mut path = vec![unsafe { root.deref() }; height + 1];
+        let mut prev = None;
+        for (n, &i) in order.iter().enumerate() {
+            let index = indices[i];
+            let layer = prev.map_or(height, |prev| shared_layer(prev, index));
+            for layer in (1..=layer).rev() {
+                let offset = (index >> (LOGSIZE * layer)) & mask;
+                path[layer - 1] = self.child(path[layer], offset, layer - 1, guard);
+            }
+            result[i] = Some(unsafe { &path[0].elements[index & mask] });
+            prev = Some(index);
+
+            if let Some(&next) = order.get(n + 1) {
+                let next = indices[next];
+                let layer = shared_layer(index, next);
+                if layer > 0 {
+                    let offset = (next >> (LOGSIZE * layer)) & mask;
+                    let child = unsafe { &path[layer].children[offset] }.load(Acquire, guard);
+                    if let Some(child) = unsafe { child.as_ref() } {
+                        prefetch(child);
+                    }
+                }
+            }
+        }
+        result.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// Returns the child of `segment` at `offset`, allocating it with `height` if it is null.
+    fn child<'g>(
+        &self,
+        segment: &'g Segment<T>,
+        offset: usize,
+        height: usize,
+        guard: &'g Guard,
+    ) -> &'g Segment<T> {
+        let child = unsafe { &segment.children[offset] };
+        if let Some(child) = unsafe { child.load(Acquire, guard).as_ref() } {
+            return child;
+        }
+        let new_segment = Segment::<T>::new(1 << LOGSIZE).with_tag(height);
+        // If another thread installs a segment first, the new one is dropped.
+        match child.compare_exchange(Shared::null(), new_segment, AcqRel, Acquire, guard) {
+            Ok(new) => {
+                let _ = self.segments.fetch_add(1, Relaxed);
+                unsafe { new.deref() }
+            }
+            Err(e) => unsafe { e.current.deref() },
+        }
+    }
+
+    /// Returns an iterator over the indices and the non-null pointers of the occupied slots, in the
+    /// order of the indices. Walks the allocated segments depth-first, and does not allocate any.
+    ///
+    /// The slots stored concurrently may or may not be visited.
+    pub fn iter<'g>(&'g self, guard: &'g Guard) -> Iter<'g, T, LOGSIZE> {
+        let root = self.root.load(Acquire, guard);
+        Iter {
+            guard,
+            stack: unsafe { root.as_ref() }
+                .map(|segment| (segment, root.tag(), 0, 0))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Detaches the segments whose slots are all above `max_index`, and lowers the height of the
+    /// tree while the root segment covers more than `max_index` needs. The detached segments are
+    /// deallocated once no thread may hold a reference into them, but their elements are not, as
+    /// in `drop`.
+    ///
+    /// The slots above `max_index` in the segment that contains `max_index` are kept. Stores to the
+    /// indices above `max_index` that race with `truncate` may be lost.
+    pub fn truncate(&self, max_index: usize, guard: &Guard) {
+        let mask = (1 << LOGSIZE) - 1;
+        let root = self.root.load(Acquire, guard);
+        let height = root.tag();
+        if max_index
+            .checked_shr((LOGSIZE * (height + 1)) as u32)
+            .is_some_and(|upper| upper != 0)
+        {
+            return;
+        }
+
+        // Detach the children right of the path to `max_index`.
+        let mut ptr = root;
+        for layer in (1..=height).rev() {
+            let Some(segment) = (unsafe { ptr.as_ref() }) else {
+                break;
+            };
+            let offset = (max_index >> (LOGSIZE * layer)) & mask;
+            for child in unsafe { &segment.children[offset + 1..] } {
+                let child = child.swap(Shared::null(), AcqRel, guard);
+                if let Some(segment) = unsafe { child.as_ref() } {
+                    let _ = self
+                        .segments
+                        .fetch_sub(segment.count(layer - 1, guard), Relaxed);
+                    unsafe {
+                        guard.defer_unchecked(move || {
+                            child
+                                .into_owned()
+                                .into_box()
+                                .deallocate(layer - 1, unprotected())
+                        })
+                    };
+                }
+            }
+            ptr = unsafe { &segment.children[offset] }.load(Acquire, guard);
+        }
+
+        // Replace the root with its first child while the others are not needed.
+        let mut root = root;
+        while root.tag() > 0 && max_index >> (LOGSIZE * root.tag()) == 0 {
+            let height = root.tag();
+            let segment = unsafe { root.deref() };
+            if unsafe { &segment.children[1..] }
+                .iter()
+                .any(|child| !child.load(Acquire, guard).is_null())
+            {
+                break;
+            }
+            let child = unsafe { &segment.children[0] }.load(Acquire, guard);
+            if child.is_null() {
+                break;
+            }
+            let child = child.with_tag(height - 1);
+            if self
+                .root
+                .compare_exchange(root, child, AcqRel, Acquire, guard)
+                .is_err()
+            {
+                break;
+            }
+            let _ = self.segments.fetch_sub(1, Relaxed);
+            unsafe {
+                guard.defer_unchecked(move || {
+                    let segment = root.into_owned().into_box();
+                    // The first child is the new root. The other children are the ones that
+                    // racing `get`s installed in the old root, which are deallocated with it.
+                    segment.children[0].store(Shared::null(), Relaxed);
+                    segment.deallocate(height, unprotected());
+                })
+            };
+            root = child;
+        }
+    }
+
+    /// Returns the reference to the `Atomic` pointer at `index` if its segments are allocated, or
+    /// `None` otherwise. Unlike `get`, never allocates segments, so it suits pure lookups.
+    pub fn get_if_exists<'g>(&self, index: usize, guard: &'g Guard) -> Option<&'g Atomic<T>> {
+        let mask = (1 << LOGSIZE) - 1;
+        let mut ptr = self.root.load(Acquire, guard);
+        let height = ptr.tag();
+        // The root segment of `height` covers the lowest `LOGSIZE * (height + 1)` bits.
+        if index
+            .checked_shr((LOGSIZE * (height + 1)) as u32)
+            .is_some_and(|upper| upper != 0)
+        {
+            return None;
+        }
+
+        for layer in (0..=height).rev() {
+            let offset = (index >> (LOGSIZE * layer)) & mask;
+            let segment = unsafe { ptr.as_ref() }?;
+            if layer == 0 {
+                return Some(unsafe { &segment.elements[offset] });
+            }
+            ptr = unsafe { &segment.children[offset] }.load(Acquire, guard);
+        }
+        unreachable!()
+    }
 }
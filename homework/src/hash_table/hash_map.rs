@@ -0,0 +1,240 @@
+//! Hash map of arbitrary keys on top of `SplitOrderedList`.
+
+use core::hash::{BuildHasher, Hash};
+use core::sync::atomic::Ordering::*;
+use std::hash::RandomState;
+
+use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
+
+use super::SplitOrderedList;
+use super::counter::StripedCounter;
+use crate::ConcurrentMap;
+
+/// Lock-free hash map from `K` to `V`.
+///
+/// A key is hashed into the key of the inner `SplitOrderedList`. The entries whose keys have the
+/// same hash are kept in a `Chain`, which is the value for the hash in the inner list, and are
+/// told apart by comparing the keys.
+#[derive(Debug)]
+pub struct SplitOrderedHashMap<K, V, S = RandomState> {
+    list: SplitOrderedList<Chain<K, V>>,
+    hasher: S,
+    /// Number of entries, as the inner list counts the chains.
+    count: StripedCounter,
+}
+
+/// Entry of a `Chain`. The tag of `next` is 1 iff the entry is deleted.
+#[derive(Debug)]
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    next: Atomic<Entry<K, V>>,
+}
+
+/// Lock-free unordered list of the entries with the same hash. New entries are pushed at the head.
+///
+/// The tag of `head` is 1 iff the chain is sealed, i.e. it is empty and being removed from the
+/// map, so no more entries can be pushed.
+#[derive(Debug)]
+struct Chain<K, V> {
+    head: Atomic<Entry<K, V>>,
+}
+
+/// Result of `Chain::insert`.
+enum Insert<K, V> {
+    Inserted,
+    Exists(Owned<Entry<K, V>>),
+    Sealed(Owned<Entry<K, V>>),
+}
+
+impl<K: Eq, V> Chain<K, V> {
+    /// Finds the entry of `key`, unlinking the deleted entries on the way. Returns `Err(())` if the
+    /// chain is sealed.
+    fn find<'g>(&'g self, key: &K, guard: &'g Guard) -> Result<Option<&'g Entry<K, V>>, ()> {
+        'retry: loop {
+            let mut prev = &self.head;
+            let mut curr = prev.load(Acquire, guard);
+            if curr.tag() == 1 {
+                return Err(());
+            }
+
+            while let Some(entry) = unsafe { curr.as_ref() } {
+                let next = entry.next.load(Acquire, guard);
+                if next.tag() == 1 {
+                    if prev
+                        .compare_exchange(curr, next.with_tag(0), AcqRel, Acquire, guard)
+                        .is_err()
+                    {
+                        continue 'retry;
+                    }
+                    // SAFETY: the entry is unlinked by us.
+                    unsafe { guard.defer_destroy(curr) };
+                    curr = next.with_tag(0);
+                    continue;
+                }
+                if entry.key == *key {
+                    return Ok(Some(entry));
+                }
+                prev = &entry.next;
+                curr = next;
+            }
+            return Ok(None);
+        }
+    }
+
+    /// Pushes `entry` if there is no entry with the same key.
+    fn insert(&self, mut entry: Owned<Entry<K, V>>, guard: &Guard) -> Insert<K, V> {
+        loop {
+            // If the head does not change until the push, no entry with the key is pushed after
+            // `find`.
+            let head = self.head.load(Acquire, guard);
+            match self.find(&entry.key, guard) {
+                Err(()) => return Insert::Sealed(entry),
+                Ok(Some(_)) => return Insert::Exists(entry),
+                Ok(None) => {}
+            }
+            entry.next.store(head, Relaxed);
+            match self
+                .head
+                .compare_exchange(head, entry, AcqRel, Acquire, guard)
+            {
+                Ok(_) => return Insert::Inserted,
+                Err(e) => entry = e.new,
+            }
+        }
+    }
+
+    /// Deletes the entry of `key`, and returns its value. The `bool` is `true` iff the chain became
+    /// empty and is sealed by us, so we should remove it from the map.
+    fn delete<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<(&'g V, bool)> {
+        loop {
+            let entry = self.find(key, guard).ok()??;
+            if entry.next.fetch_or(1, AcqRel, guard).tag() == 1 {
+                // Deleted by another thread. It may have been replaced by a new entry.
+                continue;
+            }
+            // Unlink the entry.
+            let _ = self.find(key, guard);
+            let sealed = self
+                .head
+                .compare_exchange(
+                    Shared::null(),
+                    Shared::null().with_tag(1),
+                    AcqRel,
+                    Acquire,
+                    guard,
+                )
+                .is_ok();
+            return Some((&entry.value, sealed));
+        }
+    }
+}
+
+impl<K, V> Drop for Chain<K, V> {
+    fn drop(&mut self) {
+        let guard = unsafe { crossbeam_epoch::unprotected() };
+        let mut curr = self.head.load(Relaxed, guard).with_tag(0);
+        while !curr.is_null() {
+            let entry = unsafe { curr.into_owned() };
+            curr = entry.next.load(Relaxed, guard).with_tag(0);
+        }
+    }
+}
+
+impl<K, V> SplitOrderedHashMap<K, V> {
+    /// Creates a new hash map with the default `RandomState` hasher.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S> SplitOrderedHashMap<K, V, S> {
+    /// Creates a new hash map that hashes the keys with `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            list: SplitOrderedList::new(),
+            hasher,
+            count: StripedCounter::new(),
+        }
+    }
+}
+
+impl<K: Hash, V, S: BuildHasher> SplitOrderedHashMap<K, V, S> {
+    /// The key of `key` in the inner list.
+    fn hash(&self, key: &K) -> usize {
+        self.hasher.hash_one(key) as usize
+    }
+}
+
+impl<K, V, S: Default> Default for SplitOrderedHashMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> ConcurrentMap<K, V> for SplitOrderedHashMap<K, V, S> {
+    fn lookup<'a>(&'a self, key: &K, guard: &'a Guard) -> Option<&'a V> {
+        let chain = self.list.lookup(&self.hash(key), guard)?;
+        let entry = chain.find(key, guard).ok()??;
+        Some(&entry.value)
+    }
+
+    fn insert(&self, key: K, value: V, guard: &Guard) -> Result<(), V> {
+        let hash = self.hash(&key);
+        let mut entry = Owned::new(Entry {
+            key,
+            value,
+            next: Atomic::null(),
+        });
+
+        loop {
+            if let Some(chain) = self.list.lookup(&hash, guard) {
+                match chain.insert(entry, guard) {
+                    Insert::Inserted => {
+                        let _ = self.count.add(1);
+                        return Ok(());
+                    }
+                    Insert::Exists(entry) => return Err(entry.into_box().value),
+                    // The chain is about to be removed. Help remove it instead of waiting for the
+                    // thread that sealed it, and then insert a new one.
+                    Insert::Sealed(e) => {
+                        let _ = self.list.delete_if_same(hash, chain, guard);
+                        entry = e;
+                        continue;
+                    }
+                }
+            }
+
+            // A failed push to a sealed chain may have left `next` pointing into that chain.
+            entry.next.store(Shared::null(), Relaxed);
+            let chain = Chain {
+                head: Atomic::from(entry),
+            };
+            match self.list.insert(hash, chain, guard) {
+                Ok(()) => {
+                    let _ = self.count.add(1);
+                    return Ok(());
+                }
+                Err(chain) => {
+                    entry = unsafe { chain.head.swap(Shared::null(), Relaxed, guard).into_owned() }
+                }
+            }
+        }
+    }
+
+    fn delete<'a>(&'a self, key: &K, guard: &'a Guard) -> Result<&'a V, ()> {
+        let hash = self.hash(key);
+        let chain = self.list.lookup(&hash, guard).ok_or(())?;
+        let (value, sealed) = chain.delete(key, guard).ok_or(())?;
+        let _ = self.count.add(-1);
+        // Another thread may have removed the chain and inserted a new one already.
+        if sealed {
+            let _ = self.list.delete_if_same(hash, chain, guard);
+        }
+        Ok(value)
+    }
+
+    fn len(&self) -> usize {
+        self.count.sum()
+    }
+}
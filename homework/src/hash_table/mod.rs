@@ -1,7 +1,12 @@
 //! Lock-free hash table based on <https://dl.acm.org/doi/abs/10.1145/1147954.1147958>
 
+mod counter;
 mod growable_array;
+mod hash_map;
 mod split_ordered_list;
+mod striped;
 
-pub use growable_array::GrowableArray;
-pub use split_ordered_list::SplitOrderedList;
+pub use growable_array::{GrowableArray, Iter as GrowableArrayIter};
+pub use hash_map::SplitOrderedHashMap;
+pub use split_ordered_list::{Iter as SplitOrderedListIter, SplitOrderedList};
+pub use striped::StripedHashMap;
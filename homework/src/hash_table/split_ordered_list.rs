@@ -1,31 +1,162 @@
 //! Split-ordered linked list.
 
-use core::mem::{self, MaybeUninit};
+use core::ops::{Bound, RangeBounds};
 use core::ptr;
+#[cfg(not(feature = "check-loom"))]
+use core::sync::atomic::AtomicPtr;
 use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering::*;
 
-use crossbeam_epoch::{Atomic, Guard, Owned};
-use cs431::lockfree::list::{Cursor, List, Node};
+use crossbeam_epoch::{Guard, Shared};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::AtomicPtr;
 
+use super::counter::StripedCounter;
 use super::growable_array::GrowableArray;
 use crate::ConcurrentMap;
+use crate::reclaim::list::{self, Cursor, List, Node};
+use crate::reclaim::{Epoch, Reclaim};
 
-/// Lock-free map from `usize` in range \[0, 2^63-1\] to `V`.
+/// Lock-free map from `usize` to `V`, whose deleted nodes and values are reclaimed with `R`.
+///
+/// The operations take the guard of `R`, with which they retire the deleted nodes and values. The
+/// references they return are protected by the guard, which they take mutably to hand the
+/// protection over. `iter`, `range`, and `ConcurrentMap` are only for `Epoch`, whose guard
+/// protects any number of references, as they return many references with one guard. The guard
+/// of `HazardPointers` protects one.
+///
+/// NOTE: The segments of `buckets` are always reclaimed with crossbeam-epoch, pinned only while an
+/// operation looks up a bucket. They hold pointers to the sentinel nodes, which are never deleted.
 ///
 /// NOTE: We don't care about hashing in this homework for simplicity.
+///
+/// NOTE: The value of a deleted key cannot be moved out, e.g. by a `remove` returning `V`, as
+/// concurrent lookups may still hold references to it until their guards are dropped.
 #[derive(Debug)]
-pub struct SplitOrderedList<V> {
+pub struct SplitOrderedList<V, R: Reclaim = Epoch> {
     /// Lock-free list sorted by recursive-split order.
     ///
-    /// Use `MaybeUninit::uninit()` when creating sentinel nodes.
-    list: List<usize, MaybeUninit<V>>,
+    /// Use `Item::sentinel` when creating sentinel nodes.
+    list: List<SplitKey, Item<V>, R>,
     /// Array of pointers to the buckets.
-    buckets: GrowableArray<Node<usize, MaybeUninit<V>>>,
+    buckets: GrowableArray<Node<SplitKey, Item<V>>>,
     /// Number of buckets.
     size: AtomicUsize,
-    /// Number of items.
-    count: AtomicUsize,
+    /// Number of items. It is striped, as every insert and delete updates it.
+    count: StripedCounter,
+    /// Next bucket to be initialized eagerly by `insert`.
+    next_init: AtomicUsize,
+    /// `size` is doubled when `count > size * load_factor`.
+    load_factor: usize,
+    /// Initial and minimum number of buckets.
+    min_size: usize,
+}
+
+/// Key of a node in `SplitOrderedList::list`, ordered by the reversed bits of the key and then
+/// by whether the node is a regular one.
+///
+/// The sentinel node of a bucket comes right before the regular node of the same key, if any, and
+/// hence before all the other regular nodes of the bucket. Unlike marking the regular nodes with
+/// the LSB of the reversed key, which would take the MSB of the key, this covers every `usize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct SplitKey {
+    reversed: usize,
+    regular: bool,
+}
+
+impl SplitKey {
+    /// Key of the regular node of `key`.
+    fn regular(key: usize) -> Self {
+        Self {
+            reversed: key.reverse_bits(),
+            regular: true,
+        }
+    }
+
+    /// Key of the sentinel node of `bucket`.
+    fn sentinel(bucket: usize) -> Self {
+        Self {
+            reversed: bucket.reverse_bits(),
+            regular: false,
+        }
+    }
+
+    /// The key before reversing the bits.
+    fn key(self) -> usize {
+        self.reversed.reverse_bits()
+    }
+}
+
+/// Value of a node in `SplitOrderedList::list`.
+///
+/// The value is behind an `AtomicPtr`, so that `upsert` replaces it in place. `delete` takes the
+/// value out before it marks the node, so an item of a regular node without a value is deleted,
+/// and is treated as absent even before its node is marked.
+#[derive(Debug)]
+struct Item<V> {
+    /// Null for sentinel nodes and deleted items.
+    value: AtomicPtr<V>,
+}
+
+impl<V> Item<V> {
+    fn sentinel() -> Self {
+        Self {
+            value: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Creates an item of a regular node, which owns `value` from then on.
+    fn new(value: Box<V>) -> Self {
+        Self {
+            value: AtomicPtr::new(Box::into_raw(value)),
+        }
+    }
+
+    /// Takes the value out of an item that has never been shared.
+    fn into_value(self) -> Box<V> {
+        unsafe { Box::from_raw(self.value.swap(ptr::null_mut(), Relaxed)) }
+    }
+
+    /// Returns the value protected by `guard`, or `None` if the node is a sentinel node or the
+    /// item is deleted.
+    fn protect<R: Reclaim>(&self, guard: &mut R::Guard) -> Option<*mut V> {
+        let value = R::protect(guard, &self.value);
+        (!value.is_null()).then_some(value)
+    }
+}
+
+impl<V> Drop for Item<V> {
+    fn drop(&mut self) {
+        // The node of the item is unreachable, and so is its value. The values replaced by `upsert`
+        // or taken by `delete` are retired separately.
+        #[cfg(not(feature = "check-loom"))]
+        let value = *self.value.get_mut();
+        #[cfg(feature = "check-loom")]
+        let value = self.value.load(Relaxed);
+
+        if !value.is_null() {
+            drop(unsafe { Box::from_raw(value) });
+        }
+    }
+}
+
+/// Iterator over the items of a `SplitOrderedList` in split order. See `SplitOrderedList::iter`.
+#[derive(Debug)]
+pub struct Iter<'g, V> {
+    nodes: list::Iter<'g, SplitKey, Item<V>>,
+}
+
+impl<'g, V> Iterator for Iter<'g, V> {
+    type Item = (usize, &'g V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.find_map(|node| {
+            // SAFETY: the guard of `nodes` is pinned, so even if the value is replaced or deleted
+            // since, it is destroyed only after the guard is dropped.
+            let value = unsafe { node.value().value.load(Acquire).as_ref() }?;
+            Some((node.key().key(), value))
+        })
+    }
 }
 
 impl<V> Default for SplitOrderedList<V> {
@@ -34,191 +165,670 @@ impl<V> Default for SplitOrderedList<V> {
     }
 }
 
-impl<V> SplitOrderedList<V> {
-    /// `size` is doubled when `count > size * LOAD_FACTOR`.
-    const LOAD_FACTOR: usize = 2;
+/// Later values replace earlier values of the same key.
+impl<V> FromIterator<(usize, V)> for SplitOrderedList<V> {
+    fn from_iter<I: IntoIterator<Item = (usize, V)>>(iter: I) -> Self {
+        let list = Self::new();
+        {
+            let mut guard = crossbeam_epoch::pin();
+            for (key, value) in iter {
+                let _ = list.upsert(key, value, &mut guard);
+            }
+        }
+        list
+    }
+}
 
+impl<V> SplitOrderedList<V> {
     /// Creates a new split ordered list.
     pub fn new() -> Self {
-        let buckets: GrowableArray<Node<usize, MaybeUninit<V>>> = GrowableArray::new();
-        let node = Node::new(0usize, MaybeUninit::<V>::uninit());
-        let guard = crossbeam_epoch::pin();
-        let list = List::new();
-        let mut cursor = list.head(&guard);
-        assert!(cursor.insert(Owned::new(node), &guard).is_ok());
-        buckets.get(0, &guard).store(cursor.curr(), Relaxed);
-        // println!("Inserted {:?}", buckets.get(0, &guard));
-        // println!("List {:?}", list);
+        Self::new_in(Epoch)
+    }
+
+    /// Creates a new split ordered list with `initial_buckets` buckets, which is also the minimum
+    /// when shrinking. The number of buckets is doubled when there are more than `load_factor`
+    /// items per bucket on average.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `initial_buckets` is not a power of two, or `load_factor` is zero.
+    pub fn with_config(initial_buckets: usize, load_factor: usize) -> Self {
+        Self::with_config_in(initial_buckets, load_factor, Epoch)
+    }
+
+    /// Returns an iterator over the keys and the values, in split order, i.e. the order of the
+    /// reversed bits of the keys.
+    ///
+    /// The items inserted or deleted concurrently may or may not be visited.
+    pub fn iter<'g>(&'g self, guard: &'g Guard) -> Iter<'g, V> {
+        Iter {
+            nodes: self.list.iter(guard),
+        }
+    }
+
+    /// Returns the items whose keys are in `range`, sorted by the keys.
+    ///
+    /// A range of keys is not contiguous in split order, as reversing the bits scatters it over
+    /// the whole list. Only the keys of a bucket, which share their lowest bits, are: they lie
+    /// between the sentinel node of the bucket and the first key whose reversed bits are above
+    /// those of the bucket. Hence a range narrower than the number of buckets is collected from
+    /// the buckets of its keys only, and a wider one from a walk of the whole list. In either
+    /// case, each item is linearized separately.
+    pub fn range<'g, B: RangeBounds<usize>>(
+        &'g self,
+        range: B,
+        guard: &'g Guard,
+    ) -> Vec<(usize, &'g V)> {
+        // The bounds are made inclusive, as an exclusive end cannot cover `usize::MAX`.
+        let start = match range.start_bound() {
+            Bound::Included(&start) => Some(start),
+            Bound::Excluded(&start) => start.checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => Some(end),
+            Bound::Excluded(&end) => end.checked_sub(1),
+            Bound::Unbounded => Some(usize::MAX),
+        };
+        let (Some(start), Some(end)) = (start, end) else {
+            return Vec::new();
+        };
+        if start > end {
+            return Vec::new();
+        }
+
+        let in_range = |node: &&'g Node<SplitKey, Item<V>>| {
+            let key = node.key();
+            key.regular && (start..=end).contains(&key.key())
+        };
+        let value = |node: &'g Node<SplitKey, Item<V>>| {
+            // SAFETY: `guard` is pinned, as in `Iter`.
+            let value = unsafe { node.value().value.load(Acquire).as_ref() }?;
+            Some((node.key().key(), value))
+        };
+        let size = self.size.load(Relaxed);
+        let mut items = if end - start < size - 1 {
+            // The keys of the bucket `b` of `size` buckets are the ones whose reversed bits start
+            // with those of `b`.
+            let shift = size.trailing_zeros();
+            (start..=end)
+                .flat_map(|key| {
+                    let bucket = key & (size - 1);
+                    let last = bucket.reverse_bits() | (usize::MAX >> shift);
+                    let sentinel = self.lookup_bucket(bucket, guard);
+                    self.list
+                        .iter_after(sentinel, guard)
+                        .take_while(move |node| node.key().reversed <= last)
+                        .filter(in_range)
+                        .filter_map(value)
+                })
+                .collect::<Vec<_>>()
+        } else {
+            self.list
+                .iter(guard)
+                .filter(in_range)
+                .filter_map(value)
+                .collect()
+        };
+        items.sort_unstable_by_key(|(key, _)| *key);
+        items
+    }
+}
+
+impl<V: Clone, R: Reclaim> SplitOrderedList<V, R> {
+    /// Returns a copy of the keys and the values, in split order as `iter`.
+    ///
+    /// Each item is linearized separately, so the items inserted or deleted concurrently may or
+    /// may not be copied. The list can be rebuilt from the copy with `FromIterator`.
+    ///
+    /// Unlike `iter`, this is for any `R`, as each value is cloned while a cursor protects its
+    /// node. The cursor is recreated from the bucket of the next key, as it cannot go on once the
+    /// node before it is deleted.
+    pub fn snapshot(&self, guard: &R::Guard) -> Vec<(usize, V)> {
+        let mut items = Vec::new();
+        let mut next = Some(SplitKey::sentinel(0));
+        while let Some(key) = next {
+            let (_, sentinel) = self.bucket(key.key(), guard);
+            let (_, cursor) = Self::find(sentinel, &key, guard);
+            let Some(node) = cursor.curr() else {
+                break;
+            };
+            let key = *node.key();
+            let mut value_guard = R::guard();
+            if let Some(value) = node.value().protect::<R>(&mut value_guard) {
+                items.push((key.key(), unsafe { &*value }.clone()));
+            }
+            // The key right after `key`.
+            next = if key.regular {
+                key.reversed.checked_add(1).map(|reversed| SplitKey {
+                    reversed,
+                    regular: false,
+                })
+            } else {
+                Some(SplitKey::regular(key.key()))
+            };
+        }
+        items
+    }
+}
+
+impl<V, R: Reclaim> SplitOrderedList<V, R> {
+    /// Default `load_factor`.
+    const LOAD_FACTOR: usize = 2;
+
+    /// `size` is halved when `count < size / SHRINK_FACTOR`, but not below `min_size`.
+    const SHRINK_FACTOR: usize = 4;
+
+    /// Default `min_size`.
+    const MIN_SIZE: usize = 2;
+
+    /// Creates a new split ordered list with the reclamation scheme `R`.
+    pub fn new_in(reclaim: R) -> Self {
+        Self::with_config_in(Self::MIN_SIZE, Self::LOAD_FACTOR, reclaim)
+    }
+
+    /// Like `with_config`, but with the reclamation scheme `R`.
+    pub fn with_config_in(initial_buckets: usize, load_factor: usize, reclaim: R) -> Self {
+        assert!(
+            initial_buckets.is_power_of_two(),
+            "the number of buckets should be a power of two"
+        );
+        assert!(load_factor > 0, "the load factor should be positive");
+
+        let list = List::new_in(reclaim);
+        let node = Node::new(SplitKey::sentinel(0), Item::sentinel());
+        let Ok(sentinel) = list.head(&R::guard()).insert(Box::new(node)) else {
+            unreachable!("the list is empty");
+        };
+        let buckets = GrowableArray::new();
+        buckets.set(
+            0,
+            Shared::from(sentinel.cast_const()),
+            &crossbeam_epoch::pin(),
+        );
 
         Self {
             list,
             buckets,
-            size: AtomicUsize::new(2),
-            count: AtomicUsize::new(0),
+            size: AtomicUsize::new(initial_buckets),
+            count: StripedCounter::new(),
+            next_init: AtomicUsize::new(1),
+            load_factor,
+            min_size: initial_buckets,
         }
     }
 
-    /// Creates a cursor and moves it to the bucket for the given index.  If the bucket doesn't
-    /// exist, recursively initializes the buckets.
-    fn lookup_bucket<'s>(
-        &'s self,
-        key: usize,
-        guard: &'s Guard,
-    ) -> Cursor<'s, usize, MaybeUninit<V>> {
-        let index = key & (usize::MAX >> 1);
-        let bucket_ptr_ref = self.buckets.get(index, guard);
-        let bucket_ptr = bucket_ptr_ref.load(Acquire, guard);
-        // println!("Trying index: {}", index);
-        if !bucket_ptr.is_null() {
-            // println!("Found Index: {}", index);
-            return Cursor::new(bucket_ptr_ref, bucket_ptr);
+    /// Halves `size` if it is still `size`, and releases the segments of `buckets` above the new
+    /// size.
+    ///
+    /// The sentinel nodes of the buckets above the new size stay in the list, as the keys in them
+    /// still belong to the parent buckets. If `size` grows again, `init_bucket` finds and reuses
+    /// them.
+    ///
+    /// Threads that loaded the old size may still look up the buckets above the new size. Their
+    /// slots are either in the detached segments, which are freed only after they unpin, or in new
+    /// segments allocated by `buckets`, in which `init_bucket` republishes the same sentinel nodes.
+    fn shrink(&self, size: usize) {
+        if self
+            .size
+            .compare_exchange(size, size >> 1, Relaxed, Relaxed)
+            .is_err()
+        {
+            return;
         }
+        let _ = self.next_init.fetch_min(size >> 1, Relaxed);
+        self.buckets
+            .truncate((size >> 1) - 1, &crossbeam_epoch::pin());
+    }
 
-        // Eliminate left-most 1
-        let mut parent_mask = key;
-        let mut size_usize = std::mem::size_of::<usize>();
-        for sz in (0..size_usize).rev() {
-            // println!("Squashing {} = {}", sz, parent_mask);
-            parent_mask |= parent_mask >> sz;
+    /// Initializes the next bucket below `size` that is not yet initialized eagerly, if any.
+    ///
+    /// Each `insert` calls this once, so that the lookups after a doubling do not pay for the
+    /// initialization of the new buckets and their parents. As `size` is doubled after `size *
+    /// load_factor` more inserts, the new buckets are all initialized before the next doubling.
+    fn init_next_bucket(&self, guard: &R::Guard) {
+        let size = self.size.load(Relaxed);
+        let mut next = self.next_init.load(Relaxed);
+        while next < size {
+            match self
+                .next_init
+                .compare_exchange(next, next + 1, Relaxed, Relaxed)
+            {
+                Ok(_) => {
+                    let _ = self.lookup_bucket(next, guard);
+                    return;
+                }
+                Err(current) => next = current,
+            }
         }
-        // println!("Squashed = {}", parent_mask);
-        parent_mask ^= parent_mask >> 1;
-        // println!("Left most 1 = {}", parent_mask);
-        parent_mask -= 1;
-        // println!("Parent_mask = {}", parent_mask);
-
-        let mut prev_bkt = self.lookup_bucket(key & parent_mask, guard);
+    }
 
-        let index = index.reverse_bits();
-        let mut node = Owned::from(Node::new(index, MaybeUninit::uninit()));
-        loop {
-            let mut bkt = prev_bkt.clone();
-            if let Ok(r) = bkt.find_harris_michael(&index, guard) {
-                if r {
-                    bucket_ptr_ref.store(bkt.curr(), Release);
-                    return bkt;
-                }
+    /// Returns the sentinel node of the bucket for the given index. If the bucket doesn't exist,
+    /// recursively initializes the buckets.
+    ///
+    /// The sentinel nodes are never deleted, so they live as long as the list.
+    fn lookup_bucket(&self, key: usize, guard: &R::Guard) -> &Node<SplitKey, Item<V>> {
+        let pin = crossbeam_epoch::pin();
+        let bucket = key & (usize::MAX >> 1);
+        let bucket_ptr = self.buckets.get(bucket, &pin).load(Acquire, &pin);
+        if !bucket_ptr.is_null() {
+            return unsafe { &*bucket_ptr.as_raw() };
+        }
 
-                if let Err(e) = bkt.insert(node, guard) {
-                    node = e;
-                } else {
-                    // Safety: If insert successful, next bkt finding should always be successful
-                    node = unsafe { Owned::from_raw(ptr::null_mut()) };
-                }
+        // The ancestors of the bucket, each of which is the previous one without its left-most 1,
+        // down to the bucket 0 that is always initialized. They share most of their segments, so
+        // their slots are fetched at once.
+        let mut ancestors = vec![bucket];
+        while let Some(&ancestor) = ancestors.last()
+            && ancestor != 0
+        {
+            ancestors.push(ancestor & !(1 << ancestor.ilog2()));
+        }
+        let slots = self.buckets.get_many(&ancestors, &pin);
 
-                // println!("Bucket: Invalid cursor. Retry.")
-            }
+        // Initialize the buckets down from the closest initialized ancestor.
+        let (depth, mut sentinel) = slots
+            .iter()
+            .enumerate()
+            .find_map(|(depth, &slot)| {
+                let ptr = slot.load(Acquire, &pin);
+                (!ptr.is_null()).then(|| (depth, unsafe { &*ptr.as_raw() }))
+            })
+            .expect("bucket 0 is initialized in `new`");
+        for &bucket in ancestors[..depth].iter().rev() {
+            sentinel = self.init_bucket(bucket, sentinel, guard, &pin);
         }
+        sentinel
     }
 
-    /// Moves the bucket cursor returned from `lookup_bucket` to the position of the given key.
-    fn find<'s>(
+    /// Inserts the sentinel node of `bucket` after the sentinel node of its parent, and publishes
+    /// it in `buckets`.
+    ///
+    /// The same node is retried until it is inserted or the sentinel node inserted by another
+    /// thread is found, in which case it is freed. The sentinel node is published only if the slot
+    /// is still null, since a failure means another thread has published the same node.
+    fn init_bucket<'s>(
         &'s self,
-        key: &usize,
-        guard: &'s Guard,
-    ) -> (bool, Cursor<'s, usize, MaybeUninit<V>>) {
+        bucket: usize,
+        parent: &'s Node<SplitKey, Item<V>>,
+        guard: &R::Guard,
+        pin: &Guard,
+    ) -> &'s Node<SplitKey, Item<V>> {
+        let index = SplitKey::sentinel(bucket);
+        let mut node = Box::new(Node::new(index, Item::sentinel()));
+        let sentinel = loop {
+            let mut cursor = unsafe { Cursor::<_, _, R>::after(parent, guard) };
+            let Ok(found) = cursor.find(&index) else {
+                continue;
+            };
+            if found {
+                // `node` has never been shared, and is dropped.
+                break ptr::from_ref(cursor.curr().unwrap()).cast_mut();
+            }
+            match cursor.insert(node) {
+                Ok(sentinel) => break sentinel,
+                Err(n) => node = n,
+            }
+        };
+        let _ = self.buckets.compare_exchange_at(
+            bucket,
+            Shared::null(),
+            Shared::from(sentinel.cast_const()),
+            pin,
+        );
+        unsafe { &*sentinel }
+    }
+
+    /// Returns the size and the sentinel node of the bucket of `key`.
+    fn bucket(&self, key: usize, guard: &R::Guard) -> (usize, &Node<SplitKey, Item<V>>) {
         let size = self.size.load(Relaxed);
+        (size, self.lookup_bucket(key & (size - 1), guard))
+    }
 
-        let bkt_cursor = self.lookup_bucket(key & (size - 1), guard);
-        // println!("Found bkt: {} {bkt_cursor:?}", key & (size-1));
-        let key = key.reverse_bits() | 1;
+    /// Creates a cursor in the bucket of `sentinel` at the node of `key` or where it would be
+    /// inserted, and returns whether it is found.
+    fn find<'s>(
+        sentinel: &'s Node<SplitKey, Item<V>>,
+        key: &SplitKey,
+        guard: &'s R::Guard,
+    ) -> (bool, Cursor<'s, SplitKey, Item<V>, R>) {
         loop {
-            let mut cur = bkt_cursor.clone();
-            if let Ok(result) = cur.find_harris_michael(&key, guard) {
-                return (result, cur);
+            let mut cursor = unsafe { Cursor::after(sentinel, guard) };
+            if let Ok(found) = cursor.find(key) {
+                return (found, cursor);
             }
-            // println!("Find: Invalid cursor. Retry.")
         }
     }
 
-    fn assert_valid_key(key: usize) {
-        assert!(key.leading_zeros() != 0);
+    /// Counts an inserted item, and grows `size` if it is still `size` and the list is too full.
+    ///
+    /// The estimate of `count.add` is off by up to `BATCH` per stripe, which is larger than the
+    /// thresholds of a small list. Hence the thresholds are checked again with `count.sum()`, so
+    /// that the list does not grow and shrink back and forth with the estimate.
+    fn inserted(&self, size: usize, guard: &R::Guard) {
+        let threshold = size * self.load_factor;
+        if self.count.add(1) > threshold && self.count.sum() > threshold {
+            let _ = self
+                .size
+                .compare_exchange(size, size << 1, Relaxed, Relaxed);
+        }
+        self.init_next_bucket(guard);
     }
-}
 
-impl<V> ConcurrentMap<usize, V> for SplitOrderedList<V> {
-    fn lookup<'a>(&'a self, key: &usize, guard: &'a Guard) -> Option<&'a V> {
-        Self::assert_valid_key(*key);
-        // println!("Lookup {}",key);
+    /// Marks the node of `cursor` whose item is deleted, and unlinks it if possible, in case the
+    /// thread that deleted the item has not done so yet.
+    fn help_delete(cursor: &Cursor<'_, SplitKey, Item<V>, R>) {
+        let _ = cursor.delete();
+    }
 
-        let (r, c) = self.find(key, guard);
-        unsafe {
-            if r {
-                Some(c.lookup().assume_init_ref())
-            } else {
-                None
+    /// Returns the value of `key` and the guard protecting it.
+    fn get_protected(&self, key: usize, guard: &R::Guard) -> Option<(*mut V, R::Guard)> {
+        let (_, sentinel) = self.bucket(key, guard);
+        let (found, cursor) = Self::find(sentinel, &SplitKey::regular(key), guard);
+        let node = cursor.curr().filter(|_| found)?;
+        let mut value_guard = R::guard();
+        let value = node.value().protect::<R>(&mut value_guard)?;
+        Some((value, value_guard))
+    }
+
+    /// Returns the value of `key`, which `guard` protects from then on.
+    pub fn get<'g>(&'g self, key: &usize, guard: &'g mut R::Guard) -> Option<&'g V> {
+        let (value, value_guard) = self.get_protected(*key, guard)?;
+        R::hand_over(value_guard, guard);
+        Some(unsafe { &*value })
+    }
+
+    /// Inserts `value` for `key`, or gives it back if `key` is already present.
+    pub fn insert(&self, key: usize, value: V, guard: &R::Guard) -> Result<(), V> {
+        let (size, sentinel) = self.bucket(key, guard);
+        let key = SplitKey::regular(key);
+        let mut node = Box::new(Node::new(key, Item::new(Box::new(value))));
+
+        loop {
+            let (found, cursor) = Self::find(sentinel, &key, guard);
+            if let Some(curr) = cursor.curr()
+                && found
+            {
+                if curr.value().value.load(Acquire).is_null() {
+                    Self::help_delete(&cursor);
+                    continue;
+                }
+                return Err(*node.into_value().into_value());
+            }
+            match cursor.insert(node) {
+                Ok(_) => {
+                    self.inserted(size, guard);
+                    return Ok(());
+                }
+                Err(n) => node = n,
             }
         }
-        // let key = key.reverse_bits() | 1;
-        // if let Some(v) = self.list.harris_michael_lookup(&key, guard) {
-        //     unsafe { Some(v.assume_init_ref()) }
-        // }
-        // else {
-        //     None
-        // }
     }
 
-    fn insert(&self, key: usize, value: V, guard: &Guard) -> Result<(), V> {
-        Self::assert_valid_key(key);
-
-        // println!("Insert {}", key);
-        let size = self.size.load(Relaxed);
-        let bkt_cursor = self.lookup_bucket(key & (size - 1), guard);
-        let key = key.reverse_bits() | 1;
-        let mut n = Owned::new(Node::new(key, MaybeUninit::new(value)));
+    /// Deletes `key` if its value is `expected` (any value if `None`), and returns its value and
+    /// the guard protecting it.
+    fn delete_protected(
+        &self,
+        key: usize,
+        expected: Option<*const V>,
+        guard: &R::Guard,
+    ) -> Option<(*mut V, R::Guard)> {
+        let (size, sentinel) = self.bucket(key, guard);
+        let key = SplitKey::regular(key);
 
         loop {
-            // println!("Trying to insert: 0x{key:2X} Find: Result: {r} Cursor: {cur:?}");
-            let mut cur = bkt_cursor.clone();
-            let Ok(mut r) = cur.find_harris_michael(&key, guard) else {
-                // println!("Insert: Invalid cursor. Retry.");
+            let (found, cursor) = Self::find(sentinel, &key, guard);
+            let item = cursor.curr().filter(|_| found)?.value();
+            let mut value_guard = R::guard();
+            let Some(value) = item.protect::<R>(&mut value_guard) else {
+                // Deleted by another thread.
+                Self::help_delete(&cursor);
                 continue;
             };
-            if r {
-                // println!("Insertion Failed. Key exist.");
-                return unsafe { Err(n.into_box().into_value().assume_init()) };
+            if expected.is_some_and(|expected| !ptr::eq(value, expected)) {
+                return None;
             }
-            if let Err(ret) = cur.insert(n, guard) {
-                // println!("Insertion Failed. ret: {ret:?}, Retrying");
-                n = ret;
+            // Taking the value deletes the item. It fails if the value is replaced or taken.
+            if item
+                .value
+                .compare_exchange(value, ptr::null_mut(), AcqRel, Relaxed)
+                .is_err()
+            {
                 continue;
             }
-            // println!("Inserted into {cur:?}");
-
-            let count = self.count.fetch_add(1, Relaxed);
-            if count + 1 > size * Self::LOAD_FACTOR {
-                let _ = self
-                    .size
-                    .compare_exchange(size, size << 1, Relaxed, Relaxed);
-                // println!("SIZE GROW: {}", size << 1);
-            }
+            // Fails only if another thread has helped.
+            let _ = cursor.delete();
+            // SAFETY: the value is unreachable from the item now.
+            unsafe { R::retire(guard, value) };
 
-            return Ok(());
+            // See `inserted` for checking the threshold again.
+            let threshold = size / Self::SHRINK_FACTOR;
+            if self.count.add(-1) < threshold
+                && size > self.min_size
+                && self.count.sum() < threshold
+            {
+                self.shrink(size);
+            }
+            return Some((value, value_guard));
         }
     }
 
-    fn delete<'a>(&'a self, key: &usize, guard: &'a Guard) -> Result<&'a V, ()> {
-        Self::assert_valid_key(*key);
+    /// Deletes `key`, and returns its value, which `guard` protects from then on. The value is
+    /// retired with `guard`.
+    ///
+    /// This is `ConcurrentMap::delete` for any `R`.
+    pub fn unlink<'g>(&'g self, key: &usize, guard: &'g mut R::Guard) -> Option<&'g V> {
+        let (value, value_guard) = self.delete_protected(*key, None, guard)?;
+        R::hand_over(value_guard, guard);
+        Some(unsafe { &*value })
+    }
 
-        // println!("Delete {}", key);
-        let size = self.size.load(Relaxed);
-        let bkt_cursor = self.lookup_bucket(key & (size - 1), guard);
-        let key = key.reverse_bits() | 1;
+    /// Deletes `key` only if its value is `value`, compared by address, e.g. to help the thread
+    /// that is about to delete the value. Returns whether it deleted `key`.
+    pub(crate) fn delete_if_same(&self, key: usize, value: &V, guard: &R::Guard) -> bool {
+        self.delete_protected(key, Some(value), guard).is_some()
+    }
 
-        loop {
-            let mut cur = bkt_cursor.clone();
-            let Ok(mut r) = cur.find_harris_michael(&key, guard) else {
-                // println!("Delete: Invalid cursor. Retry.");
-                continue;
-            };
-            if !r {
-                return Err(());
+    /// Returns the value of `key`, inserting the value made by `f` if there is none. `guard`
+    /// protects the value from then on.
+    ///
+    /// `f` is called at most once, and only if `key` is not found. If another thread inserts `key`
+    /// first, the value made by `f` is dropped and the value of the other thread is returned.
+    pub fn get_or_insert_with<'g, F: FnOnce() -> V>(
+        &'g self,
+        key: usize,
+        f: F,
+        guard: &'g mut R::Guard,
+    ) -> &'g V {
+        let (size, sentinel) = self.bucket(key, guard);
+        let key = SplitKey::regular(key);
+        let mut f = Some(f);
+        let mut value: Option<Box<V>> = None;
+
+        let (value, value_guard) = loop {
+            let (found, cursor) = Self::find(sentinel, &key, guard);
+            let mut value_guard = R::guard();
+            if let Some(curr) = cursor.curr()
+                && found
+            {
+                match curr.value().protect::<R>(&mut value_guard) {
+                    Some(value) => break (value, value_guard),
+                    None => {
+                        Self::help_delete(&cursor);
+                        continue;
+                    }
+                }
             }
-            if let Ok(v) = cur.delete(guard) {
-                self.count.fetch_sub(1, Relaxed);
-                return unsafe { Ok(v.assume_init_ref()) };
+
+            let new = value
+                .take()
+                .unwrap_or_else(|| Box::new((f.take().unwrap())()));
+            let node = Box::new(Node::new(key, Item::new(new)));
+            // Protect the value before it is published, as other threads may replace or delete it
+            // right after.
+            let new = node
+                .value()
+                .protect::<R>(&mut value_guard)
+                .expect("the value is not shared yet");
+            match cursor.insert(node) {
+                Ok(_) => {
+                    self.inserted(size, guard);
+                    break (new, value_guard);
+                }
+                Err(n) => value = Some(n.into_value().into_value()),
             }
-            // println!("Delete failed {}", key);
+        };
+        R::hand_over(value_guard, guard);
+        unsafe { &*value }
+    }
+
+    /// Inserts `value` for `key`, replacing the current value if any. Returns the replaced value,
+    /// which `guard` protects from then on.
+    ///
+    /// The value of an existing item is replaced in place, so `key` is present throughout. The
+    /// replaced value is retired with `guard`.
+    pub fn upsert<'g>(&'g self, key: usize, value: V, guard: &'g mut R::Guard) -> Option<&'g V> {
+        let (size, sentinel) = self.bucket(key, guard);
+        let key = SplitKey::regular(key);
+        let mut node = Box::new(Node::new(key, Item::new(Box::new(value))));
+
+        let (old, old_guard) = loop {
+            let (found, cursor) = Self::find(sentinel, &key, guard);
+            if let Some(curr) = cursor.curr()
+                && found
+            {
+                let item = curr.value();
+                let mut old_guard = R::guard();
+                let Some(old) = item.protect::<R>(&mut old_guard) else {
+                    Self::help_delete(&cursor);
+                    continue;
+                };
+                let new = node.value().value.load(Relaxed);
+                if item
+                    .value
+                    .compare_exchange(old, new, AcqRel, Relaxed)
+                    .is_err()
+                {
+                    continue;
+                }
+                // `new` is owned by the item now.
+                node.value().value.store(ptr::null_mut(), Relaxed);
+                // SAFETY: the old value is unreachable from the item now.
+                unsafe { R::retire(guard, old) };
+                break (old, old_guard);
+            }
+            match cursor.insert(node) {
+                Ok(_) => {
+                    self.inserted(size, guard);
+                    return None;
+                }
+                Err(n) => node = n,
+            }
+        };
+        R::hand_over(old_guard, guard);
+        Some(unsafe { &*old })
+    }
+
+    /// Returns the number of items.
+    pub fn len(&self) -> usize {
+        self.count.sum()
+    }
+
+    /// Returns whether there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<V> ConcurrentMap<usize, V> for SplitOrderedList<V> {
+    fn lookup<'a>(&'a self, key: &usize, guard: &'a Guard) -> Option<&'a V> {
+        // `guard` is pinned, and hence protects the value as the dropped guard does.
+        let (value, _) = self.get_protected(*key, guard)?;
+        Some(unsafe { &*value })
+    }
+
+    fn insert(&self, key: usize, value: V, guard: &Guard) -> Result<(), V> {
+        SplitOrderedList::insert(self, key, value, guard)
+    }
+
+    fn delete<'a>(&'a self, key: &usize, guard: &'a Guard) -> Result<&'a V, ()> {
+        // As in `lookup`.
+        let (value, _) = self.delete_protected(*key, None, guard).ok_or(())?;
+        Ok(unsafe { &*value })
+    }
+
+    fn len(&self) -> usize {
+        SplitOrderedList::len(self)
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod tests {
+    use super::SplitOrderedList;
+    use crate::ConcurrentMap;
+
+    // The buckets are initialized by inserts, before any lookup needs them.
+    #[test]
+    fn eager_bucket_init() {
+        let list = SplitOrderedList::new();
+        let guard = crossbeam_epoch::pin();
+        for i in 0..1024 {
+            assert_eq!(list.insert(i, i, &guard), Ok(()));
+        }
+        // `size` is 512 after 1024 inserts, each of which initialized a bucket below the `size`
+        // then.
+        assert_eq!(list.size.load(core::sync::atomic::Ordering::Relaxed), 512);
+        assert_eq!(list.buckets.iter(&guard).count(), 512);
+    }
+
+    #[test]
+    fn with_config() {
+        let list = SplitOrderedList::with_config(16, 1);
+        let guard = crossbeam_epoch::pin();
+        let size = || list.size.load(core::sync::atomic::Ordering::Relaxed);
+        for i in 0..16 {
+            assert_eq!(list.insert(i, i, &guard), Ok(()));
+        }
+        assert_eq!(size(), 16);
+        assert_eq!(list.insert(16, 16, &guard), Ok(()));
+        assert_eq!(size(), 32);
+
+        // `size` does not shrink below the initial one.
+        for i in 0..17 {
+            assert_eq!(list.delete(&i, &guard), Ok(&i));
+        }
+        assert_eq!(size(), 16);
+    }
+
+    #[test]
+    #[should_panic]
+    fn with_config_not_power_of_two() {
+        let _ = SplitOrderedList::<usize>::with_config(3, 2);
+    }
+
+    // `size` is halved as the items are deleted, and the items are still found after that.
+    #[test]
+    fn shrink() {
+        let list = SplitOrderedList::new();
+        let guard = crossbeam_epoch::pin();
+        for i in 0..1024 {
+            assert_eq!(list.insert(i, i, &guard), Ok(()));
+        }
+        for i in 0..1000 {
+            assert_eq!(list.delete(&i, &guard), Ok(&i));
+        }
+        // 24 items are left, so `size` is halved until `24 >= size / 4`.
+        assert_eq!(list.size.load(core::sync::atomic::Ordering::Relaxed), 64);
+        for i in 0..1024 {
+            assert_eq!(list.lookup(&i, &guard), (i >= 1000).then_some(&i));
+        }
+
+        // the sentinel nodes left by shrinking are reused when growing again.
+        for i in 0..1000 {
+            assert_eq!(list.insert(i, i, &guard), Ok(()));
+        }
+        for i in 0..1024 {
+            assert_eq!(list.lookup(&i, &guard), Some(&i));
         }
     }
 }
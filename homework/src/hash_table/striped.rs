@@ -0,0 +1,99 @@
+//! Lock-striped hash map, the baseline for the lock-free hash tables.
+
+use core::hash::{BuildHasher, Hash};
+use std::collections::HashMap;
+use std::hash::RandomState;
+use std::sync::Mutex;
+
+use crossbeam_epoch::{Guard, Owned};
+
+use crate::ConcurrentMap;
+
+/// Hash map split into shards of `std::collections::HashMap`, each guarded by a `Mutex`. The shard
+/// of a key is chosen by its hash.
+///
+/// The values are boxed, and the deleted ones are dropped with the guard of `delete`, so that the
+/// reference returned by `lookup` outlives the lock of the shard as `ConcurrentMap` requires.
+#[derive(Debug)]
+pub struct StripedHashMap<K, V> {
+    shards: Box<[Shard<K, V>]>,
+    hasher: RandomState,
+}
+
+type Shard<K, V> = Mutex<HashMap<K, Owned<V>>>;
+
+impl<K, V> StripedHashMap<K, V> {
+    /// Default number of shards.
+    const SHARDS: usize = 16;
+
+    /// Creates a new hash map with the default number of shards.
+    pub fn new() -> Self {
+        Self::with_shards(Self::SHARDS)
+    }
+
+    /// Creates a new hash map with `shards` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "there should be at least one shard");
+        Self {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: Hash, V> StripedHashMap<K, V> {
+    fn shard(&self, key: &K) -> &Shard<K, V> {
+        let hash = self.hasher.hash_one(key) as usize;
+        &self.shards[hash % self.shards.len()]
+    }
+}
+
+impl<K, V> Default for StripedHashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> ConcurrentMap<K, V> for StripedHashMap<K, V> {
+    fn lookup<'a>(&'a self, key: &K, guard: &'a Guard) -> Option<&'a V> {
+        let shard = self.shard(key).lock().unwrap();
+        let value: *const V = &**shard.get(key)?;
+        // SAFETY: the box is dropped only after `guard` is unpinned, or with `&mut self`.
+        Some(unsafe { &*value })
+    }
+
+    fn insert(&self, key: K, value: V, guard: &Guard) -> Result<(), V> {
+        let mut shard = self.shard(&key).lock().unwrap();
+        if shard.contains_key(&key) {
+            return Err(value);
+        }
+        let _ = shard.insert(key, Owned::new(value));
+        Ok(())
+    }
+
+    fn delete<'a>(&'a self, key: &K, guard: &'a Guard) -> Result<&'a V, ()> {
+        let value = self
+            .shard(key)
+            .lock()
+            .unwrap()
+            .remove(key)
+            .ok_or(())?
+            .into_shared(guard);
+        // SAFETY: the value is removed from the map by us, so no later `lookup` finds it.
+        unsafe {
+            guard.defer_destroy(value);
+            Ok(value.deref())
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+}
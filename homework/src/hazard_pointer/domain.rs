@@ -0,0 +1,152 @@
+use core::mem;
+#[cfg(not(feature = "check-loom"))]
+use std::sync::Mutex;
+
+#[cfg(feature = "check-loom")]
+use loom::sync::Mutex;
+
+use super::retire::{self, Retired, free};
+use super::{HazardBag, HazardPointer, RetiredSet, Shield};
+
+/// Isolated hazard pointer domain: a `HazardBag` together with the pointers retired in it.
+///
+/// A data structure that owns its domain only scans its own shields when it reclaims memory,
+/// instead of the shields of every structure that uses the global `HAZARDS`. The shields and the
+/// hazard pointers of a domain borrow it, so they cannot outlive it.
+///
+/// Unlike the thread-local `retire`, the retired pointers are shared by the threads, so any of them
+/// may free a pointer that another one retired.
+#[derive(Debug)]
+pub struct HazardDomain {
+    hazards: HazardBag,
+    retired: Mutex<Vec<Retired>>,
+}
+
+// SAFETY: the retired pointers are `Send`, as required by `retire`.
+unsafe impl Send for HazardDomain {}
+unsafe impl Sync for HazardDomain {}
+
+impl HazardDomain {
+    /// Creates a new hazard domain.
+    pub fn new() -> Self {
+        Self {
+            hazards: HazardBag::new(),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The hazard bag of this domain.
+    pub fn hazards(&self) -> &HazardBag {
+        &self.hazards
+    }
+
+    /// Creates a new shield in this domain.
+    ///
+    /// ```compile_fail
+    /// use cs431_homework::hazard_pointer::HazardDomain;
+    ///
+    /// // the shield cannot outlive the domain.
+    /// let shield = HazardDomain::new().shield();
+    /// shield.clear();
+    /// ```
+    pub fn shield(&self) -> Shield<'_> {
+        Shield::new(&self.hazards)
+    }
+
+    /// Creates a new typed hazard pointer in this domain.
+    pub fn hazard_pointer<T>(&self) -> HazardPointer<'_, T> {
+        HazardPointer::new(&self.hazards)
+    }
+
+    /// Creates a new thread-local retired pointer list for this domain. It can be used instead of
+    /// `retire` to avoid the lock, as long as it is collected before the domain is dropped.
+    pub fn retired_set(&self) -> RetiredSet<'_> {
+        RetiredSet::new(&self.hazards)
+    }
+
+    /// Retires a pointer. Triggers `collect` when enough pointers are retired.
+    ///
+    /// # Safety
+    ///
+    /// * `pointer` must be removed from shared memory before calling this function, and must be
+    ///   valid.
+    /// * The same `pointer` should only be retired once.
+    /// * `pointer` must only be protected by the shields of this domain.
+    pub unsafe fn retire<T: Send>(&self, pointer: *mut T) {
+        let mut retired = self.retired.lock().unwrap();
+        retired.push((pointer as *mut (), free::<T>));
+        self.hazards.record_retire();
+        if retired.len() >= RetiredSet::THRESHOLD {
+            drop(retired);
+            self.collect();
+        }
+    }
+
+    /// Frees the pointers that are retired in this domain and not protected by its shields.
+    pub fn collect(&self) {
+        // Do not hold the lock while freeing, as the destructors may retire other pointers.
+        let mut retired = mem::take(&mut *self.retired.lock().unwrap());
+        retire::collect(&self.hazards, &mut retired, &mut Vec::new());
+        self.retired.lock().unwrap().append(&mut retired);
+    }
+}
+
+impl Default for HazardDomain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardDomain {
+    /// Frees all the retired pointers, as no shield of this domain is left.
+    fn drop(&mut self) {
+        let retired = mem::take(&mut *self.retired.lock().unwrap());
+        for (ptr, free) in retired {
+            unsafe { free(ptr) };
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+    use super::HazardDomain;
+
+    // The pointers protected in the domain are kept until they are unprotected, and the remaining
+    // ones are freed when the domain is dropped.
+    #[test]
+    fn domain_retire_collect() {
+        struct Tester(Arc<AtomicUsize>);
+        impl Drop for Tester {
+            fn drop(&mut self) {
+                let _ = self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let freed = Arc::new(AtomicUsize::new(0));
+        let domain = HazardDomain::new();
+        let first = Box::into_raw(Box::new(Tester(freed.clone())));
+        let shield = domain.shield();
+        let _ = shield.protect(&AtomicPtr::new(first));
+        // a shield of another domain does not protect the pointer.
+        let other_domain = HazardDomain::new();
+        let other = other_domain.shield();
+        let second = Box::into_raw(Box::new(Tester(freed.clone())));
+        let _ = other.protect(&AtomicPtr::new(second));
+
+        unsafe {
+            domain.retire(first);
+            domain.retire(second);
+        }
+        domain.collect();
+        assert_eq!(freed.load(Ordering::Relaxed), 1);
+
+        let third = Box::into_raw(Box::new(Tester(freed.clone())));
+        let _ = shield.protect(&AtomicPtr::new(third));
+        unsafe { domain.retire(third) };
+        drop(shield);
+        drop(domain);
+        assert_eq!(freed.load(Ordering::Relaxed), 3);
+    }
+}
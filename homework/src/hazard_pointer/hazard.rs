@@ -1,3 +1,6 @@
+#[cfg(not(feature = "check-loom"))]
+use core::cell::RefCell;
+use core::marker::PhantomData;
 use core::ptr::{self, NonNull};
 #[cfg(not(feature = "check-loom"))]
 use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering, fence};
@@ -9,23 +12,55 @@ use loom::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering, fence};
 
 use super::HAZARDS;
 
+/// Number of steps of `backoff` that spin. The later steps yield the thread.
+const SPIN_LIMIT: u32 = 6;
+
+/// Backs off for the `step`-th time and advances `step`: spins `2^step` times, or yields the thread
+/// once `step` exceeds `SPIN_LIMIT`.
+fn backoff(step: &mut u32) {
+    if *step <= SPIN_LIMIT {
+        for _ in 0..1 << *step {
+            core::hint::spin_loop();
+        }
+        *step += 1;
+        return;
+    }
+    #[cfg(not(feature = "check-loom"))]
+    std::thread::yield_now();
+    #[cfg(feature = "check-loom")]
+    loom::thread::yield_now();
+}
+
 /// Represents the ownership of a hazard pointer slot.
-pub struct Shield {
+///
+/// `Shield::default` takes the slot from a thread-local cache of slots of `HAZARDS`, and gives it
+/// back to the cache when dropped, so that the common case does not touch the global bag.
+///
+/// A shield borrows the `HazardBag` of its slot, so that it cannot outlive the bag, e.g. of a
+/// `HazardDomain`.
+pub struct Shield<'s> {
     slot: NonNull<HazardSlot>,
+    // Whether the slot is returned to the thread-local cache when dropped.
+    cached: bool,
+    _marker: PhantomData<&'s HazardBag>,
 }
 
-impl Shield {
+impl<'s> Shield<'s> {
     /// Creates a new shield for hazard pointer.
-    pub fn new(hazards: &HazardBag) -> Self {
+    pub fn new(hazards: &'s HazardBag) -> Self {
         let slot = hazards.acquire_slot().into();
-        Self { slot }
+        Self {
+            slot,
+            cached: false,
+            _marker: PhantomData,
+        }
     }
+}
 
+impl Shield<'_> {
     /// Store `pointer` to the hazard slot.
     pub fn set<T>(&self, pointer: *mut T) {
-        let r = unsafe { self.slot.as_ref() };
-        r.hazard.store(pointer as *mut (), Ordering::Release);
-        // r.active.store(true, Ordering::Release);
+        unsafe { self.slot.as_ref() }.set(pointer);
     }
 
     /// Clear the hazard slot.
@@ -47,41 +82,63 @@ impl Shield {
     /// If "`src` still pointing to `pointer`" implies that `pointer` is not retired, then `Ok(())`
     /// means that this shield is validated.
     pub fn try_protect<T>(&self, pointer: *mut T, src: &AtomicPtr<T>) -> Result<(), *mut T> {
-        self.set(pointer);
-        // println!("{self:?} {pointer:?}, {src:?}");
-        Self::validate(pointer, src).inspect_err(|_| self.clear())
+        unsafe { self.slot.as_ref() }.try_protect(pointer, src)
     }
 
     /// Get a protected pointer from `src`.
     ///
     /// See `try_protect()`.
     pub fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
-        let mut pointer = src.load(Ordering::Relaxed);
-        while let Err(new) = self.try_protect(pointer, src) {
-            pointer = new;
-            #[cfg(feature = "check-loom")]
-            loom::sync::atomic::spin_loop_hint();
-        }
-        pointer
+        unsafe { self.slot.as_ref() }.protect(src)
+    }
+
+    /// Like `protect`, but backs off exponentially after each failed validation, so that threads
+    /// protecting a pointer that changes constantly do not keep invalidating each other.
+    pub fn protect_with_backoff<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        unsafe { self.slot.as_ref() }.protect_with_backoff(src)
+    }
+
+    /// Like `protect`, but gives up after `attempts` failed validations. Returns the last value of
+    /// `src` then, which is not protected.
+    pub fn try_protect_n<T>(&self, src: &AtomicPtr<T>, attempts: usize) -> Result<*mut T, *mut T> {
+        unsafe { self.slot.as_ref() }.try_protect_n(src, attempts)
     }
 }
 
-impl Default for Shield {
+impl Default for Shield<'static> {
     fn default() -> Self {
+        // The cache is unavailable while the thread-locals are destroyed.
+        #[cfg(not(feature = "check-loom"))]
+        if let Ok(slot) = SLOT_CACHE.try_with(|cache| cache.borrow_mut().pop()) {
+            return Self {
+                slot,
+                cached: true,
+                _marker: PhantomData,
+            };
+        }
         Self::new(&HAZARDS)
     }
 }
 
-impl Drop for Shield {
+impl Drop for Shield<'_> {
     /// Clear and release the ownership of the hazard slot.
     fn drop(&mut self) {
-        let r = unsafe { self.slot.as_ref() };
-        r.hazard.store(ptr::null_mut(), Ordering::Relaxed);
-        r.active.store(false, Ordering::Release);
+        #[cfg(not(feature = "check-loom"))]
+        if self.cached {
+            self.clear();
+            let slot = self.slot;
+            if SLOT_CACHE
+                .try_with(|cache| cache.borrow_mut().push(slot))
+                .unwrap_or(false)
+            {
+                return;
+            }
+        }
+        unsafe { self.slot.as_ref() }.release();
     }
 }
 
-impl fmt::Debug for Shield {
+impl fmt::Debug for Shield<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Shield")
             .field("slot address", &self.slot)
@@ -90,12 +147,214 @@ impl fmt::Debug for Shield {
     }
 }
 
+/// Number of slots of `HAZARDS` that a thread acquires at once when its cache is empty.
+#[cfg(not(feature = "check-loom"))]
+const SLOT_BLOCK: usize = 8;
+
+/// Maximum number of slots in a thread's cache. The slots of the `Shield`s dropped beyond that are
+/// released to `HAZARDS`.
+#[cfg(not(feature = "check-loom"))]
+const SLOT_CACHE_CAPACITY: usize = 4 * SLOT_BLOCK;
+
+/// Thread-local cache of active and cleared slots of `HAZARDS`. The slots are released to
+/// `HAZARDS` when the thread exits.
+///
+/// NOTE: The cache is disabled for loom, so that the models exercise the acquisition of slots.
+#[cfg(not(feature = "check-loom"))]
+#[derive(Debug, Default)]
+struct SlotCache {
+    slots: Vec<NonNull<HazardSlot>>,
+}
+
+#[cfg(not(feature = "check-loom"))]
+impl SlotCache {
+    /// Takes a slot, acquiring a block of slots from `HAZARDS` if the cache is empty.
+    fn pop(&mut self) -> NonNull<HazardSlot> {
+        if self.slots.is_empty() {
+            self.slots.extend(HAZARDS.acquire_slots::<SLOT_BLOCK>());
+        }
+        self.slots.pop().unwrap()
+    }
+
+    /// Gives back a cleared slot. Returns `false` if the cache is full.
+    fn push(&mut self, slot: NonNull<HazardSlot>) -> bool {
+        if self.slots.len() >= SLOT_CACHE_CAPACITY {
+            return false;
+        }
+        self.slots.push(slot);
+        true
+    }
+}
+
+#[cfg(not(feature = "check-loom"))]
+impl Drop for SlotCache {
+    /// Releases the slots, and removes the ones that stay unused from `HAZARDS`, so that a burst
+    /// of threads does not inflate the bag for good.
+    fn drop(&mut self) {
+        for slot in self.slots.drain(..) {
+            unsafe { slot.as_ref() }.release();
+        }
+        let _ = HAZARDS.compact();
+    }
+}
+
+#[cfg(not(feature = "check-loom"))]
+std::thread_local! {
+    static SLOT_CACHE: RefCell<SlotCache> = RefCell::new(SlotCache::default());
+}
+
+/// Represents the ownership of `N` hazard pointer slots, for protecting several pointers at once,
+/// e.g. the previous and the current nodes of a traversal.
+///
+/// The slots are acquired in one pass over the `HazardBag`, and the slots that are missing are
+/// allocated together and pushed with a single CAS. This is cheaper than creating `N` `Shield`s,
+/// each of which walks the bag on its own. As `Shield`, it borrows the bag.
+pub struct ShieldSet<'s, const N: usize> {
+    slots: [NonNull<HazardSlot>; N],
+    _marker: PhantomData<&'s HazardBag>,
+}
+
+impl<'s, const N: usize> ShieldSet<'s, N> {
+    /// Creates a new set of `N` shields for hazard pointers.
+    pub fn new(hazards: &'s HazardBag) -> Self {
+        Self {
+            slots: hazards.acquire_slots::<N>(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<const N: usize> ShieldSet<'_, N> {
+    fn slot(&self, i: usize) -> &HazardSlot {
+        unsafe { self.slots[i].as_ref() }
+    }
+
+    /// Store `pointer` to the `i`-th hazard slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i >= N`, as do the other methods given an index.
+    pub fn set<T>(&self, i: usize, pointer: *mut T) {
+        self.slot(i).set(pointer);
+    }
+
+    /// Clear the `i`-th hazard slot.
+    pub fn clear(&self, i: usize) {
+        self.set(i, ptr::null_mut::<()>())
+    }
+
+    /// Clear all the hazard slots.
+    pub fn clear_all(&self) {
+        for i in 0..N {
+            self.clear(i);
+        }
+    }
+
+    /// Swap the `i`-th and the `j`-th hazard slots, with the pointers they protect. E.g. after
+    /// moving on to the next node of a traversal, swapping the slots of the previous and the
+    /// current nodes keeps the current node protected as the previous one, without protecting it
+    /// again.
+    pub fn swap(&mut self, i: usize, j: usize) {
+        self.slots.swap(i, j);
+    }
+
+    /// Try protecting `pointer` obtained from `src` with the `i`-th slot. See
+    /// `Shield::try_protect`.
+    pub fn try_protect<T>(
+        &self,
+        i: usize,
+        pointer: *mut T,
+        src: &AtomicPtr<T>,
+    ) -> Result<(), *mut T> {
+        self.slot(i).try_protect(pointer, src)
+    }
+
+    /// Get a protected pointer from `src` with the `i`-th slot. See `Shield::protect`.
+    pub fn protect<T>(&self, i: usize, src: &AtomicPtr<T>) -> *mut T {
+        self.slot(i).protect(src)
+    }
+}
+
+impl<const N: usize> Default for ShieldSet<'static, N> {
+    fn default() -> Self {
+        Self::new(&HAZARDS)
+    }
+}
+
+impl<const N: usize> Drop for ShieldSet<'_, N> {
+    /// Clear and release the ownership of the hazard slots.
+    fn drop(&mut self) {
+        for i in 0..N {
+            self.slot(i).release();
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for ShieldSet<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..N).map(|i| self.slot(i)))
+            .finish()
+    }
+}
+
 /// Global bag (multiset) of hazards pointers.
-/// `HazardBag.head` and `HazardSlot.next` form a grow-only list of all hazard slots. Slots are
-/// never removed from this list. Instead, it gets deactivated and recycled for other `Shield`s.
+/// `HazardBag.head` and `HazardSlot.next` form a list of all hazard slots. A slot is deactivated
+/// and recycled for other `Shield`s when its shield is dropped, and is removed from the list by
+/// `compact` once it stays inactive for a while.
 #[derive(Debug)]
 pub struct HazardBag {
     head: AtomicPtr<HazardSlot>,
+    /// Number of `compact` passes so far, plus 1. Stamps the inactive slots.
+    epoch: AtomicUsize,
+    /// Number of ongoing traversals of the list, by the parity of the epoch at which they started.
+    traversals: [AtomicUsize; 2],
+    /// Whether a `compact` pass is ongoing.
+    compacting: AtomicBool,
+    /// Counters for `stats`.
+    allocated: AtomicUsize,
+    recycled: AtomicUsize,
+    retired: AtomicUsize,
+    collects: AtomicUsize,
+    freed: AtomicUsize,
+}
+
+/// Statistics of a `HazardBag`. See `HazardBag::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HazardStats {
+    /// The number of slots in the bag.
+    pub slots: usize,
+    /// The number of slots occupied by a shield, or cached by a thread for its shields.
+    pub active_slots: usize,
+    /// The number of slots allocated, including the ones freed by `compact` since.
+    pub allocated: usize,
+    /// The number of acquisitions of a slot that recycled an inactive one.
+    pub recycled: usize,
+    /// The number of pointers retired and not freed yet, by all the threads.
+    pub retired: usize,
+    /// The number of scans by `collect`.
+    pub collects: usize,
+    /// The number of pointers freed by `collect`.
+    pub freed: usize,
+}
+
+impl HazardStats {
+    /// The ratio of the acquisitions that recycled an inactive slot, or 0 if there is none.
+    pub fn recycle_rate(&self) -> f64 {
+        let acquisitions = self.allocated + self.recycled;
+        if acquisitions == 0 {
+            return 0.0;
+        }
+        self.recycled as f64 / acquisitions as f64
+    }
+
+    /// The average number of pointers freed by a `collect`, or 0 if there is none.
+    pub fn frees_per_collect(&self) -> f64 {
+        if self.collects == 0 {
+            return 0.0;
+        }
+        self.freed as f64 / self.collects as f64
+    }
 }
 
 /// See `HazardBag`
@@ -105,8 +364,23 @@ struct HazardSlot {
     active: AtomicBool,
     // Machine representation of the hazard pointer.
     hazard: AtomicPtr<()>,
-    // Immutable pointer to the next slot in the bag.
-    next: *const HazardSlot,
+    // Epoch of the `compact` pass that first found this slot inactive, or 0 if the slot has been
+    // acquired since.
+    stamp: AtomicUsize,
+    // Pointer to the next slot in the bag. Only changed by `compact`, once the slot is published.
+    next: AtomicPtr<HazardSlot>,
+}
+
+/// Registration of a traversal of the slots, which keeps the slots unlinked by `compact` from
+/// being freed until it is dropped.
+struct Traversal<'s> {
+    count: &'s AtomicUsize,
+}
+
+impl Drop for Traversal<'_> {
+    fn drop(&mut self) {
+        let _ = self.count.fetch_sub(1, Ordering::Release);
+    }
 }
 
 impl HazardSlot {
@@ -114,8 +388,92 @@ impl HazardSlot {
         Self {
             active: AtomicBool::new(false),
             hazard: AtomicPtr::new(ptr::null_mut()),
-            next: ptr::null(),
+            stamp: AtomicUsize::new(0),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Activate the slot if it is inactive.
+    fn try_activate(&self) -> bool {
+        let activated = self
+            .active
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok();
+        if activated {
+            self.stamp.store(0, Ordering::Relaxed);
+        }
+        activated
+    }
+
+    /// Claim the slot for `compact` if it has been inactive since a pass before `epoch`. Otherwise,
+    /// stamp it with `epoch` if it is inactive.
+    fn try_claim_stale(&self, epoch: usize) -> bool {
+        if self
+            .active
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        let stamp = self.stamp.load(Ordering::Relaxed);
+        if stamp != 0 && stamp < epoch {
+            return true;
+        }
+        if stamp == 0 {
+            self.stamp.store(epoch, Ordering::Relaxed);
+        }
+        self.active.store(false, Ordering::Release);
+        false
+    }
+
+    fn set<T>(&self, pointer: *mut T) {
+        self.hazard.store(pointer as *mut (), Ordering::Release);
+    }
+
+    fn try_protect<T>(&self, pointer: *mut T, src: &AtomicPtr<T>) -> Result<(), *mut T> {
+        self.set(pointer);
+        // Pairs with the fence in `RetiredSet::collect`.
+        fence(Ordering::SeqCst);
+        Shield::validate(pointer, src).inspect_err(|_| self.set(ptr::null_mut::<()>()))
+    }
+
+    fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut pointer = src.load(Ordering::Relaxed);
+        while let Err(new) = self.try_protect(pointer, src) {
+            pointer = new;
+            #[cfg(feature = "check-loom")]
+            loom::sync::atomic::spin_loop_hint();
+        }
+        pointer
+    }
+
+    fn protect_with_backoff<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut pointer = src.load(Ordering::Relaxed);
+        let mut step = 0;
+        while let Err(new) = self.try_protect(pointer, src) {
+            pointer = new;
+            backoff(&mut step);
+        }
+        pointer
+    }
+
+    fn try_protect_n<T>(&self, src: &AtomicPtr<T>, attempts: usize) -> Result<*mut T, *mut T> {
+        let mut pointer = src.load(Ordering::Relaxed);
+        for _ in 0..attempts {
+            match self.try_protect(pointer, src) {
+                Ok(()) => return Ok(pointer),
+                Err(new) => pointer = new,
+            }
         }
+        Err(pointer)
+    }
+
+    /// Clear the slot and deactivate it, so that it is recycled.
+    fn release(&self) {
+        // Release, as a scan that sees the cleared hazard before the deactivation may free the
+        // pointer that the shield protected.
+        self.hazard.store(ptr::null_mut(), Ordering::Release);
+        self.active.store(false, Ordering::Release);
     }
 }
 
@@ -125,6 +483,14 @@ impl HazardBag {
     pub const fn new() -> Self {
         Self {
             head: AtomicPtr::new(ptr::null_mut()),
+            epoch: AtomicUsize::new(1),
+            traversals: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            compacting: AtomicBool::new(false),
+            allocated: AtomicUsize::new(0),
+            recycled: AtomicUsize::new(0),
+            retired: AtomicUsize::new(0),
+            collects: AtomicUsize::new(0),
+            freed: AtomicUsize::new(0),
         }
     }
 
@@ -133,6 +499,29 @@ impl HazardBag {
     pub fn new() -> Self {
         Self {
             head: AtomicPtr::new(ptr::null_mut()),
+            epoch: AtomicUsize::new(1),
+            traversals: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            compacting: AtomicBool::new(false),
+            allocated: AtomicUsize::new(0),
+            recycled: AtomicUsize::new(0),
+            retired: AtomicUsize::new(0),
+            collects: AtomicUsize::new(0),
+            freed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts a traversal of the slots. The slots reached from `head` during the traversal are not
+    /// freed until it is dropped.
+    fn traverse(&self) -> Traversal<'_> {
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let count = &self.traversals[epoch % 2];
+            let _ = count.fetch_add(1, Ordering::SeqCst);
+            // If `compact` flipped the epoch meanwhile, it may not wait for `count`.
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                return Traversal { count };
+            }
+            let _ = count.fetch_sub(1, Ordering::Release);
         }
     }
 
@@ -140,72 +529,248 @@ impl HazardBag {
     /// slot.
     fn acquire_slot(&self) -> &HazardSlot {
         if let Some(slot) = self.try_acquire_inactive() {
+            let _ = self.recycled.fetch_add(1, Ordering::Relaxed);
             return slot;
         }
+        let _ = self.allocated.fetch_add(1, Ordering::Relaxed);
 
-        unsafe {
-            let new_slot = Box::into_raw(Box::new(HazardSlot::new()));
-            let new_ref = new_slot.as_mut().unwrap();
-            new_ref.next = self.head.load(Ordering::Acquire);
-            new_ref.active.store(true, Ordering::Release);
-
-            loop {
-                let result = self.head.compare_exchange(
-                    new_ref.next as *mut _,
-                    new_ref,
-                    Ordering::AcqRel,
-                    Ordering::Relaxed,
-                );
-                if let Err(e) = result {
-                    new_ref.next = e;
-                    fence(Ordering::Release);
-                    continue;
+        let new_slot = Box::new(HazardSlot::new());
+        new_slot.active.store(true, Ordering::Relaxed);
+        // Only use the raw pointer from now on, so that no unique reference to the slot coexists
+        // with the shared references handed out to other threads.
+        let new_slot = Box::into_raw(new_slot);
+
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            // SAFETY: `new_slot` is not published yet, so we have exclusive access to it.
+            unsafe { (*new_slot).next.store(head, Ordering::Relaxed) };
+            match self
+                .head
+                .compare_exchange(head, new_slot, Ordering::AcqRel, Ordering::Acquire)
+            {
+                // SAFETY: active slots are never freed while the bag is alive.
+                Ok(_) => return unsafe { &*new_slot },
+                Err(e) => head = e,
+            }
+        }
+    }
+
+    /// Acquires `N` slots in the hazard set, recycling the inactive slots found in one pass and
+    /// allocating the rest. The new slots are linked together and pushed with a single CAS.
+    fn acquire_slots<const N: usize>(&self) -> [NonNull<HazardSlot>; N] {
+        let mut slots = [NonNull::dangling(); N];
+        let mut acquired = 0;
+        {
+            let _traversal = self.traverse();
+            let mut slot: *const HazardSlot = self.head.load(Ordering::Acquire);
+            while acquired < N && !slot.is_null() {
+                // SAFETY: slots are not freed during the traversal.
+                let r = unsafe { &*slot };
+                if r.try_activate() {
+                    slots[acquired] = r.into();
+                    acquired += 1;
                 }
-                return new_ref;
+                slot = r.next.load(Ordering::Acquire);
+            }
+        }
+        let _ = self.recycled.fetch_add(acquired, Ordering::Relaxed);
+        if acquired == N {
+            return slots;
+        }
+        let _ = self.allocated.fetch_add(N - acquired, Ordering::Relaxed);
+
+        // Link the new slots as `first -> ... -> last -> head`.
+        let mut first: *mut HazardSlot = ptr::null_mut();
+        let mut last: *mut HazardSlot = ptr::null_mut();
+        for new_slot in &mut slots[acquired..] {
+            let r = Box::new(HazardSlot::new());
+            r.active.store(true, Ordering::Relaxed);
+            r.next.store(first, Ordering::Relaxed);
+            first = Box::into_raw(r);
+            if last.is_null() {
+                last = first;
+            }
+            // SAFETY: `first` is valid, as it is just allocated.
+            *new_slot = unsafe { NonNull::new_unchecked(first) };
+        }
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            // SAFETY: the new slots are not published yet, so we have exclusive access to them.
+            unsafe { (*last).next.store(head, Ordering::Relaxed) };
+            match self
+                .head
+                .compare_exchange(head, first, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return slots,
+                Err(e) => head = e,
             }
         }
     }
 
     /// Find an inactive slot and activate it.
     fn try_acquire_inactive(&self) -> Option<&HazardSlot> {
+        let _traversal = self.traverse();
         let mut slot: *const HazardSlot = self.head.load(Ordering::Acquire);
         unsafe {
             while !slot.is_null() {
                 let r = slot.as_ref().unwrap();
 
-                if r.active
-                    .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
-                    .is_ok()
-                {
+                // Active slots are never freed, so `r` outlives the traversal.
+                if r.try_activate() {
                     return Some(r);
                 }
 
-                slot = r.next;
+                slot = r.next.load(Ordering::Acquire);
             }
 
             None
         }
     }
 
-    /// Returns all the hazards in the set.
-    pub fn all_hazards(&self) -> HashSet<*mut ()> {
-        let mut set = HashSet::<*mut ()>::new();
-
-        let mut slot: *const _ = self.head.load(Ordering::Acquire);
+    /// Calls `f` on each hazard in the set. Null is left out, as it is never retired.
+    ///
+    /// `f` must not call `compact`, which would wait for this traversal.
+    pub fn for_each_hazard<F: FnMut(*mut ())>(&self, mut f: F) {
+        let _traversal = self.traverse();
+        let mut slot: *const HazardSlot = self.head.load(Ordering::Acquire);
         unsafe {
             while !slot.is_null() {
                 let r = slot.as_ref().unwrap();
 
                 if r.active.load(Ordering::Acquire) {
-                    set.insert(r.hazard.load(Ordering::Relaxed));
+                    let hazard = r.hazard.load(Ordering::Acquire);
+                    if !hazard.is_null() {
+                        f(hazard);
+                    }
                 }
 
-                slot = r.next;
+                slot = r.next.load(Ordering::Acquire);
             }
         }
+    }
+
+    /// Returns whether `pointer` is protected by a shield of the set.
+    pub fn protects<T>(&self, pointer: *mut T) -> bool {
+        let mut protected = false;
+        self.for_each_hazard(|hazard| protected |= hazard == pointer as *mut ());
+        protected
+    }
 
+    /// Returns all the hazards in the set. See `for_each_hazard`.
+    pub fn all_hazards(&self) -> HashSet<*mut ()> {
+        let mut set = HashSet::<*mut ()>::new();
+        self.for_each_hazard(|hazard| {
+            let _ = set.insert(hazard);
+        });
         set
     }
+
+    /// Returns the statistics of the bag. The numbers of slots are counted by a traversal, so they
+    /// may be off under concurrent acquisitions.
+    pub fn stats(&self) -> HazardStats {
+        let mut stats = HazardStats {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            recycled: self.recycled.load(Ordering::Relaxed),
+            retired: self.retired.load(Ordering::Relaxed),
+            collects: self.collects.load(Ordering::Relaxed),
+            freed: self.freed.load(Ordering::Relaxed),
+            ..HazardStats::default()
+        };
+        let _traversal = self.traverse();
+        let mut slot: *const HazardSlot = self.head.load(Ordering::Acquire);
+        while !slot.is_null() {
+            // SAFETY: slots are not freed during the traversal.
+            let r = unsafe { &*slot };
+            stats.slots += 1;
+            if r.active.load(Ordering::Relaxed) {
+                stats.active_slots += 1;
+            }
+            slot = r.next.load(Ordering::Acquire);
+        }
+        stats
+    }
+
+    /// Counts a pointer retired by a thread, for `stats`.
+    pub(super) fn record_retire(&self) {
+        let _ = self.retired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a `collect` that freed `freed` pointers, for `stats`.
+    pub(super) fn record_collect(&self, freed: usize) {
+        let _ = self.collects.fetch_add(1, Ordering::Relaxed);
+        let _ = self.freed.fetch_add(freed, Ordering::Relaxed);
+        let _ = self.retired.fetch_sub(freed, Ordering::Relaxed);
+    }
+
+    /// Removes the slots that have been inactive since the previous pass from the list, and frees
+    /// them. Returns the number of freed slots, or 0 if another pass is ongoing.
+    ///
+    /// A removed slot may still be reached by the traversals that started before, so it is only
+    /// freed once they finish. The traversals that start after the epoch is bumped are counted
+    /// separately, so they do not keep this pass waiting.
+    pub fn compact(&self) -> usize {
+        if self
+            .compacting
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return 0;
+        }
+        let epoch = self.epoch.load(Ordering::Relaxed);
+
+        // Only this pass changes the `next` of published slots, so the list is not changed under
+        // us except for the slots pushed at `head`.
+        let mut unlinked = Vec::new();
+        let mut prev: Option<&HazardSlot> = None;
+        let mut slot = self.head.load(Ordering::Acquire);
+        while !slot.is_null() {
+            // SAFETY: only this pass frees slots.
+            let r = unsafe { &*slot };
+            let next = r.next.load(Ordering::Acquire);
+            // Claim the slot so that it is not acquired while it is checked. Keep the claim if it
+            // is unlinked, so that the traversals skip it.
+            if !r.try_claim_stale(epoch) {
+                prev = Some(r);
+                slot = next;
+                continue;
+            }
+            match prev {
+                Some(p) => p.next.store(next, Ordering::Release),
+                None => {
+                    if let Err(head) =
+                        self.head
+                            .compare_exchange(slot, next, Ordering::AcqRel, Ordering::Acquire)
+                    {
+                        // New slots are pushed in front of `slot`. Find its predecessor.
+                        let mut p = unsafe { &*head };
+                        while p.next.load(Ordering::Acquire) != slot {
+                            p = unsafe { &*p.next.load(Ordering::Acquire) };
+                        }
+                        p.next.store(next, Ordering::Release);
+                        prev = Some(p);
+                    }
+                }
+            }
+            unlinked.push(slot);
+            slot = next;
+        }
+
+        // Wait for the traversals that started before the slots are unlinked.
+        let _ = self.epoch.fetch_add(1, Ordering::SeqCst);
+        while self.traversals[epoch % 2].load(Ordering::Acquire) != 0 {
+            #[cfg(not(feature = "check-loom"))]
+            std::thread::yield_now();
+            #[cfg(feature = "check-loom")]
+            loom::thread::yield_now();
+        }
+        for &slot in &unlinked {
+            // SAFETY: `slot` is unreachable, and no traversal reaches it anymore.
+            drop(unsafe { Box::from_raw(slot) });
+        }
+
+        self.compacting.store(false, Ordering::Release);
+        unlinked.len()
+    }
 }
 
 impl Default for HazardBag {
@@ -221,7 +786,7 @@ impl Drop for HazardBag {
         unsafe {
             while !slot.is_null() {
                 let r = Box::from_raw(slot as *mut HazardSlot);
-                slot = r.next;
+                slot = r.next.load(Ordering::Relaxed);
             }
         }
     }
@@ -235,13 +800,15 @@ mod tests {
     use std::collections::HashSet;
     use std::ops::Range;
     use std::sync::Arc;
-    use std::sync::atomic::AtomicPtr;
-    use std::{mem, thread};
+    use std::sync::atomic::{AtomicBool, AtomicPtr};
+    use std::{mem, ptr, thread};
 
-    use super::{HazardBag, Shield};
+    use super::{HazardBag, HazardStats, Ordering, Shield, ShieldSet};
+    use crate::hazard_pointer::RetiredSet;
+    use crate::test::scale;
 
-    const THREADS: usize = 8;
-    const VALUES: Range<usize> = 1..1024;
+    const THREADS: usize = scale(8, 2);
+    const VALUES: Range<usize> = 1..scale(1024, 64);
 
     // `all_hazards` should return hazards protected by shield(s).
     #[test]
@@ -292,12 +859,31 @@ mod tests {
         assert!(intersection.is_empty())
     }
 
+    // `protects` and `for_each_hazard` should only see the hazards of the active shields.
+    #[test]
+    fn protects_for_each_hazard() {
+        let hazard_bag = HazardBag::new();
+        let (a, b) = (2 as *mut (), 3 as *mut ());
+        let shield_a = Shield::new(&hazard_bag);
+        let shield_b = Shield::new(&hazard_bag);
+        let _ = Shield::new(&hazard_bag);
+        shield_a.set(a);
+        shield_b.set(b);
+        drop(shield_b);
+
+        assert!(hazard_bag.protects(a));
+        assert!(!hazard_bag.protects(b));
+        let mut hazards = Vec::new();
+        hazard_bag.for_each_hazard(|hazard| hazards.push(hazard));
+        assert_eq!(hazards, [a]);
+    }
+
     // `acquire_slot` should recycle existing slots.
     #[test]
     fn recycle_slots() {
         let hazard_bag = HazardBag::new();
         // allocate slots
-        let shields = (0..1024)
+        let shields = (0..scale(1024, 64))
             .map(|_| Shield::new(&hazard_bag))
             .collect::<Vec<_>>();
         // slot addresses
@@ -308,7 +894,7 @@ mod tests {
         // release the slots
         drop(shields);
 
-        let shields = (0..128)
+        let shields = (0..scale(128, 8))
             .map(|_| Shield::new(&hazard_bag))
             .collect::<Vec<_>>();
         let new_slots = shields
@@ -319,4 +905,169 @@ mod tests {
         // no new slots should've been created
         assert!(new_slots.is_subset(&old_slots));
     }
+
+    // `Shield::default` should reuse the slots cached by the thread.
+    #[test]
+    fn slot_cache_reuse() {
+        thread::spawn(|| {
+            let slot = Shield::default().slot;
+            let shields = (0..4).map(|_| Shield::default()).collect::<Vec<_>>();
+            assert!(shields.iter().all(|s| s.cached));
+            assert_eq!(shields[0].slot, slot);
+            // the cached slots are cleared.
+            assert!(shields.iter().all(|s| {
+                unsafe { s.slot.as_ref() }
+                    .hazard
+                    .load(Ordering::Relaxed)
+                    .is_null()
+            }));
+        })
+        .join()
+        .unwrap();
+    }
+
+    // `try_protect_n` should give up on a source that keeps changing.
+    #[test]
+    fn protect_bounded_attempts() {
+        let hazard_bag = HazardBag::new();
+        let shield = Shield::new(&hazard_bag);
+        let src = AtomicPtr::new(2 as *mut ());
+        assert_eq!(shield.try_protect_n(&src, 0), Err(2 as *mut ()));
+        assert!(!hazard_bag.protects(2 as *mut ()));
+        assert_eq!(shield.try_protect_n(&src, 1), Ok(2 as *mut ()));
+        assert!(hazard_bag.protects(2 as *mut ()));
+
+        // `src` changes constantly while another thread protects it with backoff.
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            let _ = s.spawn(|| {
+                let mut data = 3;
+                while !done.load(Ordering::Relaxed) {
+                    src.store(data as *mut (), Ordering::Relaxed);
+                    data += 1;
+                }
+            });
+            let shield = Shield::new(&hazard_bag);
+            let pointer = shield.protect_with_backoff(&src);
+            assert!(hazard_bag.protects(pointer));
+            done.store(true, Ordering::Relaxed);
+        });
+    }
+
+    // `compact` should free the slots that stay inactive for two passes, and keep the others.
+    #[test]
+    fn compact_inactive_slots() {
+        let hazard_bag = HazardBag::new();
+        let kept = Shield::new(&hazard_bag);
+        kept.set(2 as *mut ());
+        let shields = (0..8).map(|_| Shield::new(&hazard_bag)).collect::<Vec<_>>();
+        drop(shields);
+
+        // The first pass only stamps the inactive slots.
+        assert_eq!(hazard_bag.compact(), 0);
+        // A slot acquired and released in between is stamped again.
+        drop(Shield::new(&hazard_bag));
+        assert_eq!(hazard_bag.compact(), 7);
+        assert_eq!(hazard_bag.compact(), 1);
+        assert_eq!(hazard_bag.compact(), 0);
+        assert_eq!(hazard_bag.all_hazards(), [2 as *mut ()].into());
+    }
+
+    // `compact` should not free the slots under concurrent acquisitions and scans.
+    #[test]
+    fn compact_concurrent() {
+        let hazard_bag = HazardBag::new();
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for data in VALUES {
+                        let shield = Shield::new(&hazard_bag);
+                        let _ = shield.protect(&AtomicPtr::new(data as *mut ()));
+                        assert!(hazard_bag.protects(data as *mut ()));
+                    }
+                });
+            }
+            let _ = s.spawn(|| {
+                for _ in VALUES {
+                    let _ = hazard_bag.compact();
+                }
+            });
+        });
+        let _ = hazard_bag.compact();
+        let _ = hazard_bag.compact();
+        assert!(hazard_bag.all_hazards().is_empty());
+    }
+
+    // `stats` should count the slots, their recycling, and the retired pointers.
+    #[test]
+    fn stats_slots_retired() {
+        let hazard_bag = HazardBag::new();
+        let shield = Shield::new(&hazard_bag);
+        drop(Shield::new(&hazard_bag));
+        let _recycled = Shield::new(&hazard_bag);
+
+        let mut retired = RetiredSet::new(&hazard_bag);
+        let protected = Box::into_raw(Box::new(0));
+        shield.set(protected);
+        unsafe {
+            retired.retire(protected);
+            retired.retire(Box::into_raw(Box::new(1)));
+        }
+        retired.collect();
+        assert_eq!(retired.len(), 1);
+
+        let stats = hazard_bag.stats();
+        assert_eq!(
+            stats,
+            HazardStats {
+                slots: 2,
+                active_slots: 2,
+                allocated: 2,
+                recycled: 1,
+                retired: 1,
+                collects: 1,
+                freed: 1,
+            }
+        );
+        assert_eq!(stats.frees_per_collect(), 1.0);
+        drop(shield);
+    }
+
+    // `ShieldSet` should protect a pointer with each of its slots, and follow the swaps.
+    #[test]
+    fn shield_set_protect_swap() {
+        let hazard_bag = HazardBag::new();
+        let (a, b) = (2 as *mut (), 3 as *mut ());
+        let mut shields = ShieldSet::<3>::new(&hazard_bag);
+        assert_eq!(shields.protect(0, &AtomicPtr::new(a)), a);
+        assert_eq!(shields.protect(1, &AtomicPtr::new(b)), b);
+        assert_eq!(hazard_bag.all_hazards(), [a, b].into());
+
+        shields.swap(0, 1);
+        shields.clear(1);
+        assert_eq!(hazard_bag.all_hazards(), [b].into());
+        shields.clear_all();
+        assert!(hazard_bag.all_hazards().is_empty());
+    }
+
+    // `ShieldSet` should recycle the slots of the dropped shields and allocate the rest.
+    #[test]
+    fn shield_set_recycle_slots() {
+        let hazard_bag = HazardBag::new();
+        let shields = (0..4).map(|_| Shield::new(&hazard_bag)).collect::<Vec<_>>();
+        let old_slots = shields
+            .iter()
+            .map(|s| s.slot.as_ptr() as usize)
+            .collect::<HashSet<_>>();
+        drop(shields);
+
+        let shields = ShieldSet::<8>::new(&hazard_bag);
+        let new_slots = shields
+            .slots
+            .iter()
+            .map(|s| s.as_ptr() as usize)
+            .collect::<HashSet<_>>();
+        assert_eq!(new_slots.len(), 8);
+        assert!(new_slots.is_superset(&old_slots));
+    }
 }
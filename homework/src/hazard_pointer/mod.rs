@@ -27,10 +27,16 @@ use std::thread_local;
 #[cfg(feature = "check-loom")]
 use loom::thread_local;
 
+mod domain;
 mod hazard;
+mod pointer;
+pub mod queue;
 mod retire;
+pub mod stack;
 
-pub use hazard::{HazardBag, Shield};
+pub use domain::HazardDomain;
+pub use hazard::{HazardBag, HazardStats, Shield, ShieldSet};
+pub use pointer::{HazardPointer, Protected};
 pub use retire::RetiredSet;
 
 #[cfg(not(feature = "check-loom"))]
@@ -65,3 +71,8 @@ pub unsafe fn retire<T>(pointer: *mut T) {
 pub fn collect() {
     RETIRED.with(|r| r.borrow_mut().collect());
 }
+
+/// Returns the number of pointers that are `retire`d by the current thread and not freed yet.
+pub fn retired_len() -> usize {
+    RETIRED.with(|r| r.borrow().len())
+}
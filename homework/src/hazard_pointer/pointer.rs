@@ -0,0 +1,137 @@
+use core::marker::PhantomData;
+use core::ops::Deref;
+#[cfg(not(feature = "check-loom"))]
+use core::sync::atomic::AtomicPtr;
+use std::fmt;
+
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::AtomicPtr;
+
+use super::{HazardBag, Shield};
+
+/// Typed hazard pointer. Unlike `Shield`, which hands out raw pointers, it hands out `Protected`
+/// guards that dereference to `&T`.
+///
+/// `protect` takes `&mut self`, so the borrow checker makes sure that a guard is dropped before the
+/// slot is reused for another pointer. As `Shield`, it borrows the `HazardBag` of its slot.
+pub struct HazardPointer<'s, T> {
+    shield: Shield<'s>,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<'s, T> HazardPointer<'s, T> {
+    /// Creates a new hazard pointer.
+    pub fn new(hazards: &'s HazardBag) -> Self {
+        Self {
+            shield: Shield::new(hazards),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> HazardPointer<'_, T> {
+    /// Get a protected pointer from `src`. The pointer is protected until the guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// * Non-null pointers stored in `src` must be valid.
+    /// * A pointer must be removed from `src` before it is retired, and must not be freed otherwise
+    ///   (see `retire`).
+    pub unsafe fn protect(&mut self, src: &AtomicPtr<T>) -> Protected<'_, T> {
+        let pointer = self.shield.protect(src);
+        Protected {
+            pointer,
+            shield: &self.shield,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Try protecting `pointer` obtained from `src`. If `src` no longer points to `pointer`,
+    /// returns the current value.
+    ///
+    /// # Safety
+    ///
+    /// Same as `protect`.
+    pub unsafe fn try_protect(
+        &mut self,
+        pointer: *mut T,
+        src: &AtomicPtr<T>,
+    ) -> Result<Protected<'_, T>, *mut T> {
+        self.shield.try_protect(pointer, src)?;
+        Ok(Protected {
+            pointer,
+            shield: &self.shield,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T> Default for HazardPointer<'static, T> {
+    fn default() -> Self {
+        Self {
+            shield: Shield::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for HazardPointer<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HazardPointer")
+            .field("shield", &self.shield)
+            .finish()
+    }
+}
+
+/// Pointer protected by a `HazardPointer`. Clears the hazard slot when dropped.
+///
+/// The pointer may be null, as it is whatever `src` pointed to. Use `as_ref` to check for null;
+/// dereferencing a null `Protected` panics.
+pub struct Protected<'a, T> {
+    pointer: *mut T,
+    shield: &'a Shield<'a>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Protected<'a, T> {
+    /// The protected pointer.
+    pub fn as_ptr(&self) -> *mut T {
+        self.pointer
+    }
+
+    /// Whether the protected pointer is null.
+    pub fn is_null(&self) -> bool {
+        self.pointer.is_null()
+    }
+
+    /// A reference to the protected value, or `None` if the pointer is null.
+    pub fn as_ref(&self) -> Option<&T> {
+        // SAFETY: the pointer is valid and protected until `self` is dropped, as required by
+        // `HazardPointer::protect`.
+        unsafe { self.pointer.as_ref() }
+    }
+}
+
+impl<T> Deref for Protected<'_, T> {
+    type Target = T;
+
+    /// # Panics
+    ///
+    /// Panics if the protected pointer is null.
+    fn deref(&self) -> &T {
+        self.as_ref()
+            .expect("dereferencing a null protected pointer")
+    }
+}
+
+impl<T> Drop for Protected<'_, T> {
+    fn drop(&mut self) {
+        self.shield.clear();
+    }
+}
+
+impl<T> fmt::Debug for Protected<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Protected").field(&self.pointer).finish()
+    }
+}
@@ -0,0 +1,242 @@
+//! Michael-Scott queue with hazard pointers.
+
+use core::mem::MaybeUninit;
+use core::ptr;
+#[cfg(not(feature = "check-loom"))]
+use core::sync::atomic::{AtomicPtr, Ordering, fence};
+
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering, fence};
+
+use super::{Shield, retire};
+
+/// Michael-Scott lock-free queue, whose popped nodes are reclaimed with hazard pointers.
+///
+/// Usable with any number of producers and consumers.
+#[derive(Debug)]
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    /// Uninitialized for the sentinel, i.e. the node that `head` points to.
+    data: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+unsafe impl<T: Send> Sync for Queue<T> {}
+unsafe impl<T: Send> Send for Queue<T> {}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        let sentinel = Box::into_raw(Box::new(Node {
+            data: MaybeUninit::uninit(),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+
+        Self {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    /// Creates a new empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes a value to the back of the queue.
+    pub fn push(&self, t: T) {
+        let new = Box::into_raw(Box::new(Node {
+            data: MaybeUninit::new(t),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+        let shield = Shield::default();
+
+        loop {
+            let tail = shield.protect(&self.tail);
+            // SAFETY: `tail` is always a valid node, and it is protected.
+            let tail_ref = unsafe { &*tail };
+
+            let next = tail_ref.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                // Help the pusher that linked `next` to swing `tail`.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            if tail_ref
+                .next
+                .compare_exchange(ptr::null_mut(), new, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, new, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    /// Pops a value from the front of the queue.
+    ///
+    /// Returns `None` if the queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        let head_shield = Shield::default();
+        let next_shield = Shield::default();
+
+        loop {
+            let head = head_shield.protect(&self.head);
+            // SAFETY: `head` is always a valid node, and it is protected.
+            let head_ref = unsafe { &*head };
+
+            let next = head_ref.next.load(Ordering::Acquire);
+            if next.is_null() {
+                return None;
+            }
+            // `next` is only retired after `head` is unlinked, so validating `head` again also
+            // validates `next`.
+            next_shield.set(next);
+            fence(Ordering::SeqCst);
+            if Shield::validate(head, &self.head).is_err() {
+                continue;
+            }
+            // SAFETY: `next` is a valid node pushed by another thread, and it is protected.
+            let next_ref = unsafe { &*next };
+
+            // Do not let `tail` fall behind `head`, which would then be retired.
+            let tail = self.tail.load(Ordering::Relaxed);
+            if tail == head {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+            }
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // `next` is the new sentinel, so its data is moved out only by us.
+                let result = unsafe { next_ref.data.assume_init_read() };
+                head_shield.clear();
+                unsafe { retire(head) };
+                return Some(result);
+            }
+        }
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        let shield = Shield::default();
+        let head = shield.protect(&self.head);
+        // SAFETY: `head` is always a valid node, and it is protected.
+        unsafe { &*head }.next.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "check-loom"))]
+        let sentinel = unsafe { Box::from_raw(*self.head.get_mut()) };
+        #[cfg(feature = "check-loom")]
+        let sentinel = unsafe { Box::from_raw(self.head.load(Ordering::Relaxed)) };
+
+        let mut o_curr = sentinel.next.into_inner();
+        while !o_curr.is_null() {
+            let curr = unsafe { Box::from_raw(o_curr) };
+            drop(unsafe { curr.data.assume_init() });
+            o_curr = curr.next.into_inner();
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod test {
+    use std::sync::Arc;
+    use std::thread::{scope, yield_now};
+
+    use super::Queue;
+    use crate::hazard_pointer::collect;
+    use crate::test::scale;
+
+    #[test]
+    fn push_pop() {
+        const THREADS: usize = scale(10, 2);
+        const ITER: usize = scale(10_000, 64);
+
+        let queue = Queue::default();
+
+        scope(|scope| {
+            for _ in 0..THREADS {
+                let _ = scope.spawn(|| {
+                    for i in 0..ITER {
+                        queue.push(i);
+                        assert!(queue.try_pop().is_some());
+                    }
+                    collect();
+                });
+            }
+        });
+
+        assert!(queue.try_pop().is_none());
+        assert!(queue.is_empty());
+    }
+
+    // Each consumer should see the values of each producer in the order they are pushed.
+    #[test]
+    fn fifo_per_producer() {
+        const THREADS: usize = scale(4, 2);
+        const ITER: usize = scale(10_000, 64);
+
+        let queue = Queue::default();
+
+        scope(|scope| {
+            for t in 0..THREADS {
+                let queue = &queue;
+                let _ = scope.spawn(move || {
+                    for i in 0..ITER {
+                        queue.push((t, i));
+                    }
+                });
+            }
+            for _ in 0..THREADS {
+                let _ = scope.spawn(|| {
+                    let mut last = [None; THREADS];
+                    for _ in 0..ITER {
+                        let (t, i) = loop {
+                            if let Some(v) = queue.try_pop() {
+                                break v;
+                            }
+                            yield_now();
+                        };
+                        assert!(last[t] < Some(i));
+                        last[t] = Some(i);
+                    }
+                    collect();
+                });
+            }
+        });
+
+        assert!(queue.is_empty());
+    }
+
+    // The values left in the queue should be dropped with it.
+    #[test]
+    fn drop_remaining() {
+        let queue = Queue::new();
+        let value = Arc::new(());
+        for _ in 0..10 {
+            queue.push(value.clone());
+        }
+        drop(queue.try_pop());
+        drop(queue);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
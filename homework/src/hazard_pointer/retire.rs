@@ -7,28 +7,71 @@ use loom::sync::atomic::{Ordering, fence};
 
 use super::{HAZARDS, HazardBag};
 
-type Retired = (*mut (), unsafe fn(*mut ()));
+/// The first element of the pair is the machine representation of the pointer and the second is
+/// the function pointer to `free::<T>` where `T` is the type of the object.
+pub(super) type Retired = (*mut (), unsafe fn(*mut ()));
+
+/// Frees a pointer. This function is defined here instead of `collect()` as we know about the type
+/// of `pointer` only at the time of retiring it.
+///
+/// # Safety
+///
+/// * Subsumes the safety requirements of [`Box::from_raw`]. In particular, one must have unique
+///   ownership to `data`.
+///
+/// [`Box::from_raw`]: https://doc.rust-lang.org/std/boxed/struct.Box.html#method.from_raw
+pub(super) unsafe fn free<T>(data: *mut ()) {
+    drop(unsafe { Box::from_raw(data.cast::<T>()) })
+}
+
+/// Frees the pointers in `retired` that are not protected by `hazards`, and keeps the others.
+///
+/// The hazards are gathered into `protected`, which is sorted for binary search. It is only a
+/// buffer, reused across the calls so that a scan does not allocate.
+pub(super) fn collect(
+    hazards: &HazardBag,
+    retired: &mut Vec<Retired>,
+    protected: &mut Vec<*mut ()>,
+) {
+    // Pairs with the fence in `Shield::try_protect`: either the protecting thread sees that the
+    // pointer is unlinked and fails to validate, or this thread sees its hazard.
+    fence(Ordering::SeqCst);
+    protected.clear();
+    hazards.for_each_hazard(|hazard| protected.push(hazard));
+    protected.sort_unstable();
+
+    let len = retired.len();
+    retired.retain(|(ptr, free)| {
+        if protected.binary_search(ptr).is_ok() {
+            return true;
+        }
+        unsafe { free(*ptr) };
+        false
+    });
+    hazards.record_collect(len - retired.len());
+}
 
 /// Thread-local list of retired pointers.
 #[derive(Debug)]
 pub struct RetiredSet<'s> {
     hazards: &'s HazardBag,
-    /// The first element of the pair is the machine representation of the pointer and the second
-    /// is the function pointer to `free::<T>` where `T` is the type of the object.
     inner: Vec<Retired>,
+    /// Buffer for the hazards scanned by `collect`.
+    protected: Vec<*mut ()>,
     _marker: PhantomData<*const ()>, // !Send + !Sync
 }
 
 impl<'s> RetiredSet<'s> {
     /// The max length of retired pointer list. `collect` is triggered when `THRESHOLD` pointers
     /// are retired.
-    const THRESHOLD: usize = 64;
+    pub(super) const THRESHOLD: usize = 64;
 
     /// Create a new retired pointer list protected by the given `HazardBag`.
     pub fn new(hazards: &'s HazardBag) -> Self {
         Self {
             hazards,
             inner: Vec::with_capacity(Self::THRESHOLD),
+            protected: Vec::new(),
             _marker: PhantomData,
         }
     }
@@ -45,43 +88,27 @@ impl<'s> RetiredSet<'s> {
     ///
     /// `T: Send` is not required because the retired pointers are not sent to other threads.
     pub unsafe fn retire<T>(&mut self, pointer: *mut T) {
-        /// Frees a pointer. This function is defined here instead of `collect()` as we know about
-        /// the type of `pointer` only at the time of retiring it.
-        ///
-        /// # Safety
-        ///
-        /// * Subsumes the safety requirements of [`Box::from_raw`]. In particular, one must have
-        ///   unique ownership to `data`.
-        ///
-        /// [`Box::from_raw`]: https://doc.rust-lang.org/std/boxed/struct.Box.html#method.from_raw
-        unsafe fn free<T>(data: *mut ()) {
-            drop(unsafe { Box::from_raw(data.cast::<T>()) })
-        }
-
         self.inner.push((pointer as *mut (), free::<T>));
+        self.hazards.record_retire();
         if self.inner.len() >= Self::THRESHOLD {
-            // Free
-            println!("Retiring: {}", self.inner.len());
             self.collect();
         }
     }
 
+    /// Returns the number of retired pointers that are not freed yet.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true` if all the retired pointers are freed.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
     /// Free the pointers that are `retire`d by the current thread and not `protect`ed by any other
     /// threads.
     pub fn collect(&mut self) {
-        let mut hazards = Vec::<(*mut (), unsafe fn(*mut ()))>::with_capacity(Self::THRESHOLD);
-        let protected = self.hazards.all_hazards();
-
-        for (ptr, free) in &self.inner {
-            if protected.contains(ptr) {
-                hazards.push((*ptr, *free));
-                continue;
-            }
-            // println!("Free?");
-            unsafe { free(*ptr) };
-        }
-
-        self.inner = hazards;
+        collect(self.hazards, &mut self.inner, &mut self.protected);
     }
 }
 
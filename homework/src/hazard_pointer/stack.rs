@@ -0,0 +1,156 @@
+//! Treiber's stack with hazard pointers.
+
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::ptr;
+#[cfg(not(feature = "check-loom"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crossbeam_epoch::{Guard, Owned};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use super::{Shield, retire};
+use crate::elim_stack::Stack as StackTrait;
+
+/// Node of `Stack`, which is also its push request.
+#[derive(Debug)]
+pub struct Node<T> {
+    data: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+// Any particular `T` should never be accessed concurrently, so no need for `Sync`.
+unsafe impl<T: Send> Send for Node<T> {}
+unsafe impl<T: Send> Sync for Node<T> {}
+
+impl<T> From<T> for Node<T> {
+    fn from(t: T) -> Self {
+        Self {
+            data: ManuallyDrop::new(t),
+            next: ptr::null_mut(),
+        }
+    }
+}
+
+impl<T> Deref for Node<T> {
+    type Target = ManuallyDrop<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+/// Treiber's lock-free stack, whose popped nodes are reclaimed with hazard pointers instead of
+/// crossbeam-epoch.
+///
+/// It implements the `Stack` trait of `elim_stack`, so that it can be compared with
+/// `TreiberStack`. The `Guard`s taken by the trait are not used for reclamation.
+#[derive(Debug)]
+pub struct Stack<T> {
+    head: AtomicPtr<Node<T>>,
+    _marker: PhantomData<Node<T>>,
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> StackTrait<T> for Stack<T> {
+    type PushReq = Node<T>;
+
+    fn try_push(
+        &self,
+        req: Owned<Self::PushReq>,
+        _guard: &Guard,
+    ) -> Result<(), Owned<Self::PushReq>> {
+        let req = Box::into_raw(req.into_box());
+        let head = self.head.load(Ordering::Relaxed);
+        // SAFETY: `req` is not published yet, so we have exclusive access to it.
+        unsafe { (*req).next = head };
+
+        match self
+            .head
+            .compare_exchange(head, req, Ordering::Release, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(unsafe { Owned::from_raw(req) }),
+        }
+    }
+
+    fn try_pop(&self, _guard: &Guard) -> Result<Option<T>, ()> {
+        let shield = Shield::default();
+        let head = shield.protect(&self.head);
+        // SAFETY: `head` is protected, and nodes are only retired after they are unlinked.
+        let Some(head_ref) = (unsafe { head.as_ref() }) else {
+            return Ok(None);
+        };
+
+        let _ = self
+            .head
+            .compare_exchange(head, head_ref.next, Ordering::Relaxed, Ordering::Relaxed)
+            .map_err(|_| ())?;
+
+        let data = ManuallyDrop::into_inner(unsafe { ptr::read(&head_ref.data) });
+        drop(shield);
+        unsafe { retire(head) };
+        Ok(Some(data))
+    }
+
+    fn is_empty(&self, _guard: &Guard) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "check-loom"))]
+        let mut o_curr = *self.head.get_mut();
+        #[cfg(feature = "check-loom")]
+        let mut o_curr = self.head.load(Ordering::Relaxed);
+
+        while !o_curr.is_null() {
+            let curr = unsafe { Box::from_raw(o_curr) };
+            drop(ManuallyDrop::into_inner(curr.data));
+            o_curr = curr.next;
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod test {
+    use std::thread::scope;
+
+    use super::Stack;
+    use crate::elim_stack::Stack as _;
+    use crate::hazard_pointer::collect;
+    use crate::test::scale;
+
+    #[test]
+    fn push_pop() {
+        const THREADS: usize = scale(10, 2);
+        const ITER: usize = scale(10_000, 64);
+
+        let stack = Stack::default();
+
+        scope(|scope| {
+            for _ in 0..THREADS {
+                let _ = scope.spawn(|| {
+                    for i in 0..ITER {
+                        stack.push(i);
+                        assert!(stack.pop().is_some());
+                    }
+                    collect();
+                });
+            }
+        });
+
+        assert!(stack.pop().is_none());
+    }
+}
@@ -1,26 +1,589 @@
 //! Thread-safe key/value cache.
 
-use std::collections::hash_map::{Entry, HashMap};
-use std::hash::Hash;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, RwLock};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hash, RandomState};
+#[cfg(feature = "serde")]
+use std::io::{self, BufReader, BufWriter, Read, Write};
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, RwLock, Weak};
+#[cfg(feature = "serde")]
+use std::time::SystemTime;
+use std::time::{Duration, Instant};
+use std::{fmt, ptr};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+use crate::pool::ThreadPool;
+
+/// The number of computed values between the sweeps of the expired entries of a shard.
+const SWEEP_INTERVAL: usize = 64;
+
+/// The first bytes of a snapshot written by `Cache::save`.
+#[cfg(feature = "serde")]
+const SNAPSHOT_MAGIC: &[u8; 8] = b"CS431CCH";
+
+/// The version of the snapshot format, written after `SNAPSHOT_MAGIC`. Bump it when the format
+/// changes, so that the old snapshots are rejected rather than misread.
+#[cfg(feature = "serde")]
+const SNAPSHOT_VERSION: u32 = 2;
+
+/// The number of shards of a cache, unless set by `CacheBuilder::shards`.
+const DEFAULT_SHARDS: usize = 16;
+
+/// State of a cache entry.
+#[derive(Debug)]
+enum State<V> {
+    /// A thread is computing the value. The other threads wait on `Slot::ready`.
+    Computing,
+    /// The value, with the instant it expires at, if any. The value is fresh until `refresh_at`,
+    /// and stale after it. See `CacheBuilder::refresh_after`.
+    Ready {
+        val: Arc<V>,
+        expiry: Option<Instant>,
+        refresh_at: Option<Instant>,
+    },
+    /// The stale value, being recomputed by a job in the background. The accesses return the stale
+    /// value in the meantime.
+    Refreshing {
+        val: Arc<V>,
+        expiry: Option<Instant>,
+    },
+    /// The error of the computation, cached until `until`. See `CacheBuilder::negative_ttl`.
+    Failed {
+        error: Arc<dyn Any + Send + Sync>,
+        until: Instant,
+    },
+    /// The computation failed or panicked, and the entry is removed from the map. The waiters
+    /// retry.
+    Abandoned,
+}
+
+/// Entry of a key. Shared by the map and the threads accessing the key, so that a thread waiting
+/// for the value of one key doesn't hold the lock of its shard.
+#[derive(Debug)]
+struct Slot<V> {
+    state: Mutex<State<V>>,
+    ready: Condvar,
+    /// The tick of the `Cache::clock` when the value was last accessed. Updated under the read
+    /// lock of the shard.
+    last_access: AtomicU64,
+    /// Whether the value was accessed since the hand of the eviction last passed it.
+    referenced: AtomicBool,
+    /// The weight of the value, or 0 if there is none. See `CacheBuilder::weigher`. Updated under
+    /// the lock of `state`.
+    weight: AtomicUsize,
+    /// Whether the slot is in the map, and hence its weight counts in `Cache::weight`. Updated
+    /// under the write lock of the shard and the lock of `state`.
+    linked: AtomicBool,
+    /// Serializes the `Cache::compute`s of the key.
+    update: Mutex<()>,
+}
+
+impl<V> Slot<V> {
+    fn new(state: State<V>) -> Self {
+        Self {
+            state: Mutex::new(state),
+            ready: Condvar::new(),
+            last_access: AtomicU64::new(0),
+            referenced: AtomicBool::new(false),
+            weight: AtomicUsize::new(0),
+            linked: AtomicBool::new(false),
+            update: Mutex::new(()),
+        }
+    }
+
+    /// Whether the slot holds a value or an error that expired.
+    fn is_expired(&self, now: Instant) -> bool {
+        match self.state.try_lock().as_deref() {
+            Ok(
+                State::Ready {
+                    expiry: Some(expiry),
+                    ..
+                }
+                | State::Refreshing {
+                    expiry: Some(expiry),
+                    ..
+                },
+            ) => *expiry <= now,
+            Ok(State::Failed { until, .. }) => *until <= now,
+            _ => false,
+        }
+    }
+
+    /// Set the weight of the value, and adjust the `total` weight of the cache if the slot is in
+    /// it. Called under the lock of `state`.
+    fn set_weight(&self, weight: usize, total: &AtomicUsize) {
+        let old = self.weight.swap(weight, Ordering::Relaxed);
+        if self.linked.load(Ordering::Relaxed) {
+            let _ = total.fetch_add(weight, Ordering::Relaxed);
+            let _ = total.fetch_sub(old, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the slot holds a value or an error that can be evicted.
+    fn is_evictable(&self) -> bool {
+        matches!(
+            self.state.try_lock().as_deref(),
+            Ok(State::Ready { .. } | State::Refreshing { .. } | State::Failed { .. })
+        )
+    }
+}
+
+/// Part of the entries of a cache, with its own lock.
+#[derive(Debug)]
+struct Shard<K, V> {
+    map: HashMap<K, Arc<Slot<V>>>,
+    /// The entries of a bounded cache in the order the hand of the CLOCK eviction passes them,
+    /// starting at the hand. The entries removed otherwise are left behind, and skipped.
+    clock: VecDeque<(K, Weak<Slot<V>>)>,
+}
+
+impl<K, V> Shard<K, V> {
+    fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            clock: VecDeque::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Shard<K, V> {
+    /// Whether `slot` is still the entry of `key`.
+    fn is_linked(&self, key: &K, slot: &Weak<Slot<V>>) -> bool {
+        self.map
+            .get(key)
+            .is_some_and(|s| ptr::eq(Arc::as_ptr(s), slot.as_ptr()))
+    }
+
+    /// Insert the entry of `key`, behind the hand if the cache is `bounded`. Returns the entry it
+    /// replaces, if any.
+    fn insert(&mut self, key: K, slot: Arc<Slot<V>>, bounded: bool) -> Option<Arc<Slot<V>>> {
+        if bounded {
+            // Drop the entries left behind once they outnumber the others, so that the sweeps
+            // take amortized constant time.
+            if self.clock.len() > 2 * self.map.len() {
+                let clock = std::mem::take(&mut self.clock);
+                self.clock = clock
+                    .into_iter()
+                    .filter(|(k, s)| self.is_linked(k, s))
+                    .collect();
+            }
+            self.clock.push_back((key.clone(), Arc::downgrade(&slot)));
+        }
+        self.map.insert(key, slot)
+    }
+
+    /// Evict the first entry from the hand that is not referenced since the hand last passed it,
+    /// clearing the references on the way. The hand passes each entry at most once, and skips
+    /// `key`'s and the ones being computed. Returns the evicted entry, if any.
+    fn evict(&mut self, key: &K) -> Option<Arc<Slot<V>>> {
+        for _ in 0..self.clock.len() {
+            let (k, slot) = self.clock.pop_front().unwrap();
+            if !self.is_linked(&k, &slot) {
+                continue;
+            }
+            let evictable = k != *key
+                && self.map[&k].is_evictable()
+                && !self.map[&k].referenced.swap(false, Ordering::Relaxed);
+            if evictable {
+                return self.map.remove(&k);
+            }
+            self.clock.push_back((k, slot));
+        }
+        None
+    }
+}
 
 /// Cache that remembers the result for each key.
+///
+/// The entries are split into shards by the hash of their keys, so that the inserts of the keys in
+/// different shards don't contend for the same write lock.
 #[derive(Debug)]
 pub struct Cache<K, V> {
-    // todo! This is an example cache type. Build your own cache type that satisfies the
-    // specification for `get_or_insert_with`.
-    raw_map: RwLock<HashMap<K, V>>,
-    lock_map: RwLock<HashMap<K, AtomicBool>>,
+    shards: Box<[RwLock<Shard<K, V>>]>,
+    /// Chooses the shard of a key.
+    hasher: RandomState,
+    /// The number of values computed since the last sweep of a shard.
+    computed: AtomicUsize,
+    /// The number of entries, updated under the write locks of the shards.
+    len: AtomicUsize,
+    /// The maximum number of values, if bounded.
+    capacity: Option<usize>,
+    /// The maximum total weight of the values, if bounded.
+    max_weight: Option<usize>,
+    /// The total weight of the values. Shared with the jobs refreshing the values.
+    weight: Arc<AtomicUsize>,
+    weigher: Option<Weigher<K, V>>,
+    /// Ticks on every insert, to order the values by their last accesses for `save`. The accesses
+    /// only read it, so that the hits don't contend for it.
+    clock: AtomicU64,
+    /// The shard the next eviction starts from.
+    hand: AtomicUsize,
+    /// How long the errors of the fallible computations are cached, if at all.
+    negative_ttl: Option<Duration>,
+    refresh: Option<Refresh>,
+    /// Shared with the jobs refreshing the values.
+    counters: Arc<Counters>,
+}
+
+/// Statistics of a cache. See `Cache::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of accesses that returned a cached value or error, including the ones that
+    /// waited for another access to compute it.
+    pub hits: u64,
+    /// The number of accesses that computed the value.
+    pub misses: u64,
+    /// The number of computations that returned, including the failed ones and the refreshes.
+    pub loads: u64,
+    /// The total time of the `loads`.
+    pub total_load_time: Duration,
+    /// The number of values evicted to fit the capacity or swept after they expired.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// The average time of the `loads`.
+    pub fn average_load_time(&self) -> Duration {
+        if self.loads == 0 {
+            return Duration::ZERO;
+        }
+        self.total_load_time.div_f64(self.loads as f64)
+    }
+
+    /// The ratio of the `hits` to the accesses, or 0 if there is none.
+    pub fn hit_rate(&self) -> f64 {
+        let accesses = self.hits + self.misses;
+        if accesses == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / accesses as f64
+    }
+}
+
+/// Error of `Cache::get_or_try_insert_with_timeout` when another thread doesn't compute the value
+/// in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out waiting for the value")
+    }
+}
+
+/// Counters of `CacheStats`. Updated without locks, so that they don't slow down the hits.
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    loads: AtomicU64,
+    load_nanos: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Counters {
+    fn hit(&self) {
+        let _ = self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn load(&self, time: Duration) {
+        let _ = self.loads.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .load_nanos
+            .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn evict(&self, count: u64) {
+        let _ = self.evictions.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// A function that measures the weight of a value, e.g. its size in bytes.
+type WeigherFn<K, V> = dyn Fn(&K, &V) -> usize + Send + Sync;
+
+/// Debug-printable `WeigherFn`.
+struct Weigher<K, V>(Arc<WeigherFn<K, V>>);
+
+impl<K, V> Clone for Weigher<K, V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K, V> fmt::Debug for Weigher<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Weigher")
+    }
+}
+
+/// When the values become stale, and the pool that refreshes them.
+#[derive(Debug, Clone)]
+struct Refresh {
+    after: Duration,
+    pool: Arc<ThreadPool>,
+}
+
+/// Makes the value stale again if the job refreshing it panics, so that a later access retries.
+struct RefreshJob<V> {
+    slot: Arc<Slot<V>>,
+}
+
+impl<V> Drop for RefreshJob<V> {
+    fn drop(&mut self) {
+        let mut state = self.slot.state.lock().unwrap();
+        if let State::Refreshing { val, expiry } = &*state {
+            *state = State::Ready {
+                val: val.clone(),
+                expiry: *expiry,
+                refresh_at: Some(Instant::now()),
+            };
+        }
+    }
+}
+
+/// What `Cache::claim_expired` did to a slot.
+enum Claim {
+    /// The slot is set to `Computing`.
+    Claimed,
+    /// The slot holds an error that didn't expire.
+    Failed,
+    /// The slot holds a value that didn't expire, or is being computed.
+    Taken,
+}
+
+/// Abandons the slot if the computation of its value fails or panics.
+struct Computation<'a, K: Eq + Hash, V> {
+    cache: &'a Cache<K, V>,
+    key: &'a K,
+    slot: &'a Arc<Slot<V>>,
+}
+
+impl<K: Eq + Hash, V> Drop for Computation<'_, K, V> {
+    fn drop(&mut self) {
+        let mut shard = self.cache.shard(self.key).write().unwrap();
+        if shard
+            .map
+            .get(self.key)
+            .is_some_and(|slot| Arc::ptr_eq(slot, self.slot))
+        {
+            let _ = shard.map.remove(self.key);
+            self.cache.unlinked(self.slot);
+        }
+        drop(shard);
+        *self.slot.state.lock().unwrap() = State::Abandoned;
+        self.slot.ready.notify_all();
+    }
 }
 
 impl<K, V> Default for Cache<K, V> {
     fn default() -> Self {
+        CacheBuilder::new().build()
+    }
+}
+
+/// Builder of a `Cache` with custom configurations.
+#[derive(Debug, Clone)]
+pub struct CacheBuilder<K, V> {
+    shards: usize,
+    capacity: Option<usize>,
+    max_weight: Option<usize>,
+    weigher: Option<Weigher<K, V>>,
+    negative_ttl: Option<Duration>,
+    refresh: Option<Refresh>,
+}
+
+impl<K, V> Default for CacheBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> CacheBuilder<K, V> {
+    /// Create a new builder. By default, the cache has 16 shards, is unbounded, and doesn't cache
+    /// the errors.
+    pub fn new() -> Self {
         Self {
-            raw_map: RwLock::new(HashMap::new()),
-            lock_map: RwLock::new(HashMap::new()),
+            shards: DEFAULT_SHARDS,
+            capacity: None,
+            max_weight: None,
+            weigher: None,
+            negative_ttl: None,
+            refresh: None,
+        }
+    }
+
+    /// Set the number of shards. More shards allow more concurrent inserts. The eviction sweeps
+    /// the shards in turn, so with more shards it approximates the LRU order more coarsely.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    /// Bound the number of values. See `Cache::with_capacity`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Bound the total weight of the values. Inserting a value that makes the total weight exceed
+    /// `max_weight` evicts values not used recently until it doesn't, as `capacity` does.
+    /// The weights are measured by the `weigher`, or 1 for each value if there is none.
+    ///
+    /// NOTE: A value refreshed in the background may make the total weight exceed `max_weight`
+    /// until the next insert.
+    pub fn max_weight(mut self, max_weight: usize) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    /// Measure the weights of the values by `weigher`, e.g. by their sizes in bytes. See
+    /// `max_weight`.
+    pub fn weigher<W>(mut self, weigher: W) -> Self
+    where
+        W: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        self.weigher = Some(Weigher(Arc::new(weigher)));
+        self
+    }
+
+    /// Cache the errors of `Cache::try_get_or_insert_with` for `ttl`, so that the accesses to the
+    /// key in the meantime fail with the same error without computing the value again. By default,
+    /// the errors are not cached.
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// Make the values stale `after` they are computed. A stale value accessed by
+    /// `Cache::get_or_refresh_with` is returned as is, while a job in `pool` recomputes it.
+    pub fn refresh_after(mut self, after: Duration, pool: Arc<ThreadPool>) -> Self {
+        self.refresh = Some(Refresh { after, pool });
+        self
+    }
+
+    /// Create the cache.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of shards or the capacity is 0.
+    pub fn build(self) -> Cache<K, V> {
+        assert!(self.shards > 0);
+        assert!(self.capacity.is_none_or(|capacity| capacity > 0));
+        Cache {
+            shards: (0..self.shards)
+                .map(|_| RwLock::new(Shard::new()))
+                .collect(),
+            hasher: RandomState::new(),
+            computed: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            weight: Arc::default(),
+            weigher: self.weigher,
+            clock: AtomicU64::new(0),
+            hand: AtomicUsize::new(0),
+            negative_ttl: self.negative_ttl,
+            refresh: self.refresh,
+            counters: Arc::default(),
+        }
+    }
+}
+
+impl<K, V> Cache<K, V> {
+    /// Creates a cache that holds at most `capacity` values. Inserting a value into a full cache
+    /// evicts a value not used recently, chosen by the CLOCK approximation of LRU: the values
+    /// accessed since the last sweep are spared once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(capacity: usize) -> Self {
+        CacheBuilder::new().capacity(capacity).build()
+    }
+
+    /// Returns the number of entries in the cache, including the ones being computed and the
+    /// expired ones not swept yet.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns the statistics of the accesses so far.
+    pub fn stats(&self) -> CacheStats {
+        let counters = &self.counters;
+        CacheStats {
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            loads: counters.loads.load(Ordering::Relaxed),
+            total_load_time: Duration::from_nanos(counters.load_nanos.load(Ordering::Relaxed)),
+            evictions: counters.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the total weight of the values. See `CacheBuilder::weigher`.
+    pub fn weight(&self) -> usize {
+        self.weight.load(Ordering::Relaxed)
+    }
+
+    fn weigh(&self, key: &K, val: &V) -> usize {
+        self.weigher
+            .as_ref()
+            .map_or(1, |weigher| (weigher.0)(key, val))
+    }
+
+    /// Returns true if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all the entries, as `invalidate` does for each of them.
+    pub fn invalidate_all(&self) {
+        for shard in &self.shards {
+            let mut shard = shard.write().unwrap();
+            for (_, slot) in shard.map.drain() {
+                self.unlinked(&slot);
+            }
+            shard.clock.clear();
         }
     }
+
+    /// Whether the values are evicted, and hence the accesses to them are tracked.
+    fn is_bounded(&self) -> bool {
+        self.capacity.is_some() || self.max_weight.is_some()
+    }
+
+    /// Count an entry inserted into a shard, under its write lock.
+    fn linked(&self, slot: &Slot<V>) {
+        let _state = slot.state.lock().unwrap();
+        slot.linked.store(true, Ordering::Relaxed);
+        let _ = self.len.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .weight
+            .fetch_add(slot.weight.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Count an entry removed from a shard, under its write lock.
+    fn unlinked(&self, slot: &Slot<V>) {
+        let _state = slot.state.lock().unwrap();
+        slot.linked.store(false, Ordering::Relaxed);
+        let _ = self.len.fetch_sub(1, Ordering::Relaxed);
+        let _ = self
+            .weight
+            .fetch_sub(slot.weight.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+impl<K: Hash, V> Cache<K, V> {
+    fn shard(&self, key: &K) -> &RwLock<Shard<K, V>> {
+        &self.shards[self.hasher.hash_one(key) as usize % self.shards.len()]
+    }
 }
 
 impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
@@ -34,89 +597,740 @@ impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
     /// On the other hand, since `f` may consume a lot of resource (= money), it's undesirable to
     /// duplicate the work. That is, `f` should be run only once for each key. Specifically, even
     /// for concurrent invocations of `get_or_insert_with(key, f)`, `f` is called only once per key.
+    /// The concurrent invocations block until the value is computed. If `f` panics, one of them
+    /// computes the value instead.
     ///
     /// Hint: the [`Entry`] API may be useful in implementing this function.
     ///
     /// [`Entry`]: https://doc.rust-lang.org/stable/std/collections/hash_map/struct.HashMap.html#method.entry
     pub fn get_or_insert_with<F: FnOnce(K) -> V>(&self, key: K, f: F) -> V {
-        if self.lock_map.read().unwrap().get(&key).is_none() {
-            let mut m_wlock = self.lock_map.write().unwrap();
-            if m_wlock.get(&key).is_none() {
-                m_wlock.insert(key.clone(), AtomicBool::new(true));
-                drop(m_wlock);
+        V::clone(&self.get_or_insert(key, None, f))
+    }
+
+    /// Like `get_or_insert_with`, but the inserted value expires after `ttl`. An expired value is
+    /// recomputed by `f` on the next access, still only once for concurrent accesses.
+    ///
+    /// Expired values are swept from the cache every once in a while, or by `purge_expired`.
+    pub fn get_or_insert_with_ttl<F: FnOnce(K) -> V>(&self, key: K, ttl: Duration, f: F) -> V {
+        V::clone(&self.get_or_insert(key, Some(ttl), f))
+    }
+
+    /// Like `get_or_insert_with`, but `f` may fail. If it fails, the error is returned and the key
+    /// is left without a value, so that the next access computes it again. The concurrent
+    /// accesses waiting for the value retry with their own `f`.
+    ///
+    /// If the cache is built with a `negative_ttl`, the error is cached instead, and returned by
+    /// the accesses until it expires. Accesses with a different error type ignore the error.
+    pub fn try_get_or_insert_with<E, F>(&self, key: K, f: F) -> Result<V, E>
+    where
+        E: Clone + Send + Sync + 'static,
+        F: FnOnce(K) -> Result<V, E>,
+    {
+        self.get_or_try_insert(key, None, f)
+            .map(|val| V::clone(&val))
+    }
 
-                let val = f(key.clone());
-                self.raw_map
-                    .write()
-                    .unwrap()
-                    .insert(key.clone(), val.clone());
+    /// Like `try_get_or_insert_with`, but the inserted value expires after `ttl`, as in
+    /// `get_or_insert_with_ttl`.
+    pub fn try_get_or_insert_with_ttl<E, F>(&self, key: K, ttl: Duration, f: F) -> Result<V, E>
+    where
+        E: Clone + Send + Sync + 'static,
+        F: FnOnce(K) -> Result<V, E>,
+    {
+        self.get_or_try_insert(key, Some(ttl), f)
+            .map(|val| V::clone(&val))
+    }
 
-                #[allow(clippy::readonly_write_lock)]
-                let m_wlock = self.lock_map.write().unwrap();
-                m_wlock.get(&key).unwrap().store(false, Ordering::Release);
-                return val;
+    /// Retrieve the values of `keys`, computing the ones not in the cache by a single call of `f`,
+    /// e.g. a batched lookup of a database. `f` is given the missing keys, and must return their
+    /// values in the same order. The values being computed by the other accesses are waited for,
+    /// and the values are computed only once per key as in `get_or_insert_with`.
+    ///
+    /// If a value being computed by another access is abandoned, `f` is called again with its key
+    /// alone. The keys whose errors are cached (see `CacheBuilder::negative_ttl`) are given to
+    /// another call of `f`, whose values are returned without replacing the errors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns a wrong number of values.
+    pub fn get_or_insert_many<F: Fn(&[K]) -> Vec<V>>(&self, keys: &[K], f: F) -> Vec<V> {
+        // Claim the slots of the missing keys first, so that the duplicate keys wait for the values
+        // computed below.
+        let mut claimed = Vec::new();
+        let mut indices = Vec::new();
+        let mut failed = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let (slot, new) = self.slot(key);
+            if new {
+                claimed.push((key.clone(), slot));
+                indices.push(i);
+                continue;
+            }
+            match Self::claim_expired(&slot) {
+                Claim::Claimed => {
+                    claimed.push((key.clone(), slot));
+                    indices.push(i);
+                }
+                Claim::Failed => failed.push(i),
+                Claim::Taken => {}
             }
         }
 
+        let mut vals = vec![None; keys.len()];
+        if !claimed.is_empty() {
+            for (i, val) in indices.into_iter().zip(self.load_slots(&claimed, &f)) {
+                vals[i] = Some(val);
+            }
+        }
+        if !failed.is_empty() {
+            let failed_keys = failed.iter().map(|&i| keys[i].clone()).collect::<Vec<_>>();
+            let failed_vals = f(&failed_keys);
+            assert_eq!(
+                failed_vals.len(),
+                failed_keys.len(),
+                "the loader must return a value for each key"
+            );
+            for (i, val) in failed.into_iter().zip(failed_vals) {
+                vals[i] = Some(Arc::new(val));
+            }
+        }
+        keys.iter()
+            .zip(vals)
+            .map(|(key, val)| {
+                let val = val.unwrap_or_else(|| {
+                    self.get_or_insert(key.clone(), None, |key| {
+                        f(std::slice::from_ref(&key)).pop().unwrap()
+                    })
+                });
+                V::clone(&val)
+            })
+            .collect()
+    }
+
+    /// Like `get_or_insert_with`, but if another thread is computing the value, waits for it at
+    /// most for `timeout`. Returns `Err(Timeout)` without calling `f` if the value is not computed
+    /// in time, so that the caller may compute the value independently or give up. A computation by
+    /// `f` itself is not bounded by `timeout`.
+    pub fn get_or_try_insert_with_timeout<F: FnOnce(K) -> V>(
+        &self,
+        key: K,
+        timeout: Duration,
+        f: F,
+    ) -> Result<V, Timeout> {
+        let deadline = Instant::now() + timeout;
+        match self
+            .get_or_try_insert_until::<Infallible, _>(key, None, Some(deadline), |key| Ok(f(key)))?
+        {
+            Ok(val) => Ok(V::clone(&val)),
+        }
+    }
+
+    /// Like `get_or_insert_with`, but if the value is stale, returns it as is and recomputes it
+    /// with `f` in the background. See `CacheBuilder::refresh_after`. The value is refreshed by
+    /// one job at a time, and the accesses in the meantime return the stale value. If the job
+    /// panics, the value becomes stale again.
+    ///
+    /// NOTE: The other accesses, e.g. `get_or_insert_with`, return stale values without refreshing
+    /// them.
+    pub fn get_or_refresh_with<F>(&self, key: K, f: F) -> V
+    where
+        K: Send + 'static,
+        V: Send + Sync + 'static,
+        F: FnOnce(K) -> V + Send + 'static,
+    {
+        let Some(refresh) = &self.refresh else {
+            return self.get_or_insert_with(key, f);
+        };
+        let Some(slot) = self.shard(&key).read().unwrap().map.get(&key).cloned() else {
+            return self.get_or_insert_with(key, f);
+        };
+        let mut state = slot.state.lock().unwrap();
+        let now = Instant::now();
+        let State::Ready {
+            val,
+            expiry,
+            refresh_at: Some(refresh_at),
+        } = &*state
+        else {
+            drop(state);
+            return self.get_or_insert_with(key, f);
+        };
+        if *refresh_at > now || expiry.is_some_and(|expiry| expiry <= now) {
+            drop(state);
+            return self.get_or_insert_with(key, f);
+        }
+
+        let (val, expiry) = (val.clone(), *expiry);
+        *state = State::Refreshing {
+            val: val.clone(),
+            expiry,
+        };
+        drop(state);
+        self.tick(&slot);
+        self.counters.hit();
+
+        let job = RefreshJob { slot };
+        let after = refresh.after;
+        let counters = self.counters.clone();
+        let weigher = self.weigher.clone();
+        let total = self.weight.clone();
+        refresh.pool.execute(move || {
+            let start = Instant::now();
+            let new = Arc::new(f(key.clone()));
+            counters.load(start.elapsed());
+            let weight = weigher.map_or(1, |weigher| (weigher.0)(&key, &new));
+            let mut state = job.slot.state.lock().unwrap();
+            // Unless the value expired and is recomputed in the meantime.
+            if let State::Refreshing { expiry, .. } = &*state {
+                job.slot.set_weight(weight, &total);
+                *state = State::Ready {
+                    val: new,
+                    expiry: *expiry,
+                    refresh_at: Some(Instant::now() + after),
+                };
+            }
+        });
+        V::clone(&val)
+    }
+
+    /// Replace the value of `key` with a new one created by `f`, given the current value if there
+    /// is one. The new value never expires.
+    ///
+    /// The concurrent accesses to `key` return the current value until the new one is set, and the
+    /// new one after. The `compute`s of the same key are serialized, so that `f` is given the value
+    /// set by the previous one. If `f` panics, the current value is kept.
+    pub fn compute<F: FnOnce(K, Option<&V>) -> V>(&self, key: K, f: F) -> V {
         loop {
-            let m_lock = self.lock_map.read().unwrap();
-            if !m_lock.get(&key).unwrap().load(Ordering::Acquire) {
-                return self.raw_map.read().unwrap().get(&key).unwrap().clone();
-            }
-        }
-    }
-
-    /// Replace
-    pub fn replace_with<F: FnOnce(K) -> V>(&mut self, key: K, f: F) -> V {
-        let mut m_wlock = self.lock_map.write().unwrap();
-        if let Some(lock) = m_wlock.get(&key) {
-            lock.store(true, Ordering::Release);
-            drop(m_wlock);
-
-            let val = f(key.clone());
-            self.raw_map
-                .write()
-                .unwrap()
-                .insert(key.clone(), val.clone());
-
-            let mut m_wlock = self.lock_map.write().unwrap();
-            let lock = m_wlock.get(&key).unwrap();
-            lock.store(false, Ordering::Release);
-            drop(m_wlock);
-            val
-        } else {
-            let mut m_wlock = self.lock_map.write().unwrap();
-            if m_wlock.get(&key).is_none() {
-                m_wlock.insert(key.clone(), AtomicBool::new(true));
-                drop(m_wlock);
-
-                let val = f(key.clone());
-                self.raw_map
-                    .write()
-                    .unwrap()
-                    .insert(key.clone(), val.clone());
-
-                #[allow(clippy::readonly_write_lock)]
-                let m_wlock = self.lock_map.write().unwrap();
-                m_wlock.get(&key).unwrap().store(false, Ordering::Release);
-                return val;
-            }
-
-            let lock = m_wlock.get(&key).unwrap();
-            lock.store(true, Ordering::Release);
-            drop(m_wlock);
-
-            let val = f(key.clone());
-            self.raw_map
-                .write()
-                .unwrap()
-                .insert(key.clone(), val.clone());
-
-            let mut m_wlock = self.lock_map.write().unwrap();
-            let lock = m_wlock.get(&key).unwrap();
-            lock.store(false, Ordering::Release);
-            drop(m_wlock);
-            val
+            let (slot, new) = self.slot(&key);
+            if new {
+                let loaded =
+                    self.load_slot::<Infallible, _>(key, &slot, None, |key| Ok(f(key, None)));
+                match loaded {
+                    Ok(val) => return V::clone(&val),
+                }
+            }
+
+            let _update = slot.update.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut state = slot.state.lock().unwrap();
+            while let State::Computing = *state {
+                state = slot.ready.wait(state).unwrap();
+            }
+            if let State::Abandoned = *state {
+                continue;
+            }
+            let current = match Self::result::<Infallible>(&state) {
+                Some(Ok(val)) => Some(val),
+                _ => None,
+            };
+            drop(state);
+
+            let val = Arc::new(f(key.clone(), current.as_deref()));
+            let state = State::Ready {
+                val: val.clone(),
+                expiry: None,
+                refresh_at: self.refresh_at(),
+            };
+            self.complete(&key, &slot, state, self.weigh(&key, &val));
+            // Put the slot back if it was removed while `f` was running.
+            let mut shard = self.shard(&key).write().unwrap();
+            if !shard.map.get(&key).is_some_and(|s| Arc::ptr_eq(s, &slot)) {
+                if let Some(old) = shard.insert(key.clone(), slot.clone(), self.is_bounded()) {
+                    self.unlinked(&old);
+                }
+                self.linked(&slot);
+                drop(shard);
+                self.evict_if_full(&key);
+            }
+            return V::clone(&val);
+        }
+    }
+
+    /// Like `compute`, but `f` is not given the current value.
+    pub fn replace_with<F: FnOnce(K) -> V>(&self, key: K, f: F) -> V {
+        self.compute(key, |key, _| f(key))
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Cache<K, V> {
+    /// Like `get_or_insert_with`, but returns the value shared with the cache instead of a clone,
+    /// so that large values are not copied on every access.
+    pub fn get_or_insert_arc_with<F: FnOnce(K) -> V>(&self, key: K, f: F) -> Arc<V> {
+        self.get_or_insert(key, None, f)
+    }
+
+    /// Like `get_or_insert_with_ttl`, but returns the value shared with the cache.
+    pub fn get_or_insert_arc_with_ttl<F: FnOnce(K) -> V>(
+        &self,
+        key: K,
+        ttl: Duration,
+        f: F,
+    ) -> Arc<V> {
+        self.get_or_insert(key, Some(ttl), f)
+    }
+
+    /// The instant a value computed now becomes stale, if ever.
+    fn refresh_at(&self) -> Option<Instant> {
+        self.refresh
+            .as_ref()
+            .map(|refresh| Instant::now() + refresh.after)
+    }
+
+    /// Mark an access to the slot. Cheap enough for every hit: it only reads the clock, and
+    /// writes to the slot only if it is not marked yet.
+    fn tick(&self, slot: &Slot<V>) {
+        if self.is_bounded() {
+            if !slot.referenced.load(Ordering::Relaxed) {
+                slot.referenced.store(true, Ordering::Relaxed);
+            }
+            let tick = self.clock.load(Ordering::Relaxed);
+            if slot.last_access.load(Ordering::Relaxed) < tick {
+                slot.last_access.store(tick, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Mark a new slot as the most recently used one, but not as referenced, so that a value never
+    /// accessed again is evicted at the first sweep.
+    fn tick_new(&self, slot: &Slot<V>) {
+        if self.is_bounded() {
+            let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+            slot.last_access.store(tick, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the slot of `key`, inserting a new one being computed if there is none. Returns
+    /// true if the slot is new.
+    fn slot(&self, key: &K) -> (Arc<Slot<V>>, bool) {
+        let shard = self.shard(key);
+        if let Some(slot) = shard.read().unwrap().map.get(key) {
+            self.tick(slot);
+            return (slot.clone(), false);
+        }
+
+        let mut shard = shard.write().unwrap();
+        if let Some(slot) = shard.map.get(key) {
+            return (slot.clone(), false);
+        }
+        let slot = Arc::new(Slot::new(State::Computing));
+        self.tick_new(&slot);
+        let _ = shard.insert(key.clone(), slot.clone(), self.is_bounded());
+        self.linked(&slot);
+        drop(shard);
+        self.evict_if_full(key);
+        (slot, true)
+    }
+
+    /// Whether the cache holds more values than its capacity, or more weight than its maximum.
+    fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|capacity| self.len() > capacity)
+            || self
+                .max_weight
+                .is_some_and(|max_weight| self.weight() > max_weight)
+    }
+
+    /// Evict values other than `key`'s while the cache is full. The values being computed are not
+    /// evicted.
+    ///
+    /// The hand sweeps the shards in turn, each by its own CLOCK, and moves to the next shard after
+    /// each eviction, so that an eviction locks one shard at a time. A value accessed since the
+    /// last sweep of its shard is spared once, so the hand may pass every shard twice before it
+    /// evicts a value.
+    ///
+    /// NOTE: The concurrent inserts may evict more values than needed.
+    fn evict_if_full(&self, key: &K) {
+        let mut passed = 0;
+        while self.is_full() && passed < 2 * self.shards.len() {
+            let hand = self.hand.fetch_add(1, Ordering::Relaxed);
+            let mut shard = self.shards[hand % self.shards.len()].write().unwrap();
+            if let Some(slot) = shard.evict(key) {
+                self.unlinked(&slot);
+                self.counters.evict(1);
+                passed = 0;
+            } else {
+                passed += 1;
+            }
+        }
+    }
+
+    /// Compute the value of the slot that the current thread set to `Computing`, and wake up the
+    /// waiters.
+    fn load_slot<E, F>(
+        &self,
+        key: K,
+        slot: &Arc<Slot<V>>,
+        ttl: Option<Duration>,
+        f: F,
+    ) -> Result<Arc<V>, E>
+    where
+        E: Clone + Send + Sync + 'static,
+        F: FnOnce(K) -> Result<V, E>,
+    {
+        let computation = Computation {
+            cache: self,
+            key: &key,
+            slot,
+        };
+        let _ = self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+        let result = f(key.clone()).map(Arc::new);
+        self.counters.load(start.elapsed());
+        // Weighed while `computation` can still abandon the slot, in case the weigher panics.
+        let weight = match &result {
+            Ok(val) => self.weigh(&key, val),
+            Err(_) => 0,
+        };
+        let state = match &result {
+            Ok(val) => State::Ready {
+                val: val.clone(),
+                expiry: ttl.map(|ttl| Instant::now() + ttl),
+                refresh_at: self.refresh_at(),
+            },
+            Err(error) => match self.negative_ttl {
+                Some(negative_ttl) => State::Failed {
+                    error: Arc::new(error.clone()),
+                    until: Instant::now() + negative_ttl,
+                },
+                // Let the waiters retry.
+                None => {
+                    drop(computation);
+                    return result;
+                }
+            },
+        };
+        std::mem::forget(computation);
+        self.complete(&key, slot, state, weight);
+        result
+    }
+
+    /// Compute the values of the slots that the current thread set to `Computing` by a single call
+    /// of `f`, and wake up the waiters.
+    fn load_slots<F: Fn(&[K]) -> Vec<V>>(
+        &self,
+        claimed: &[(K, Arc<Slot<V>>)],
+        f: &F,
+    ) -> Vec<Arc<V>> {
+        let computations = claimed
+            .iter()
+            .map(|(key, slot)| Computation {
+                cache: self,
+                key,
+                slot,
+            })
+            .collect::<Vec<_>>();
+        let keys = claimed
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let _ = self
+            .counters
+            .misses
+            .fetch_add(keys.len() as u64, Ordering::Relaxed);
+        let start = Instant::now();
+        let vals = f(&keys);
+        self.counters.load(start.elapsed());
+        assert_eq!(
+            vals.len(),
+            keys.len(),
+            "the loader must return a value for each key"
+        );
+        let weights = claimed
+            .iter()
+            .zip(&vals)
+            .map(|((key, _), val)| self.weigh(key, val))
+            .collect::<Vec<_>>();
+        computations.into_iter().for_each(std::mem::forget);
+
+        let vals = vals.into_iter().map(Arc::new).collect::<Vec<_>>();
+        for (((key, slot), val), weight) in claimed.iter().zip(&vals).zip(weights) {
+            let state = State::Ready {
+                val: val.clone(),
+                expiry: None,
+                refresh_at: self.refresh_at(),
+            };
+            self.complete(key, slot, state, weight);
+        }
+        vals
+    }
+
+    /// Set the computed `state` of the slot and wake up the waiters.
+    fn complete(&self, key: &K, slot: &Slot<V>, state: State<V>, weight: usize) {
+        let mut guard = slot.state.lock().unwrap();
+        slot.set_weight(weight, &self.weight);
+        *guard = state;
+        drop(guard);
+        slot.ready.notify_all();
+
+        // The weight of the value is known only now.
+        if self.max_weight.is_some() {
+            self.evict_if_full(key);
+        }
+        // Only the shard of `key` is swept, so that the access doesn't stall on all the entries.
+        // The other shards are swept as the values of their own keys are computed.
+        if self.computed.fetch_add(1, Ordering::Relaxed) + 1 >= SWEEP_INTERVAL {
+            self.computed.store(0, Ordering::Relaxed);
+            let _ = self.purge_shard(self.shard(key), Instant::now());
+        }
+    }
+
+    /// Set the slot to `Computing` if its value or error expired.
+    fn claim_expired(slot: &Slot<V>) -> Claim {
+        let mut state = slot.state.lock().unwrap();
+        match &*state {
+            State::Computing | State::Abandoned => Claim::Taken,
+            // The error is valid whatever its type is, unlike in `result`.
+            State::Failed { until, .. } if *until > Instant::now() => Claim::Failed,
+            _ if Self::result::<Infallible>(&state).is_some() => Claim::Taken,
+            _ => {
+                *state = State::Computing;
+                Claim::Claimed
+            }
+        }
+    }
+
+    /// The result of a `Ready` or `Failed` state, unless it expired or the error is not an `E`.
+    fn result<E: Clone + 'static>(state: &State<V>) -> Option<Result<Arc<V>, E>> {
+        let now = Instant::now();
+        match state {
+            State::Ready { val, expiry, .. } | State::Refreshing { val, expiry }
+                if expiry.is_none_or(|expiry| expiry > now) =>
+            {
+                Some(Ok(val.clone()))
+            }
+            State::Failed { error, until } if *until > now => {
+                error.downcast_ref::<E>().cloned().map(Err)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_or_insert<F: FnOnce(K) -> V>(&self, key: K, ttl: Option<Duration>, f: F) -> Arc<V> {
+        match self.get_or_try_insert::<Infallible, _>(key, ttl, |key| Ok(f(key))) {
+            Ok(val) => val,
+        }
+    }
+
+    fn get_or_try_insert<E, F>(&self, key: K, ttl: Option<Duration>, f: F) -> Result<Arc<V>, E>
+    where
+        E: Clone + Send + Sync + 'static,
+        F: FnOnce(K) -> Result<V, E>,
+    {
+        match self.get_or_try_insert_until(key, ttl, None, f) {
+            Ok(result) => result,
+            Err(Timeout) => unreachable!(),
+        }
+    }
+
+    /// Waits for the value computed by another thread until `deadline`, if any.
+    fn get_or_try_insert_until<E, F>(
+        &self,
+        key: K,
+        ttl: Option<Duration>,
+        deadline: Option<Instant>,
+        f: F,
+    ) -> Result<Result<Arc<V>, E>, Timeout>
+    where
+        E: Clone + Send + Sync + 'static,
+        F: FnOnce(K) -> Result<V, E>,
+    {
+        loop {
+            let (slot, new) = self.slot(&key);
+            if new {
+                return Ok(self.load_slot(key, &slot, ttl, f));
+            }
+
+            let mut state = slot.state.lock().unwrap();
+            loop {
+                match &*state {
+                    State::Computing => {
+                        let Some(deadline) = deadline else {
+                            state = slot.ready.wait(state).unwrap();
+                            continue;
+                        };
+                        let timeout = deadline.saturating_duration_since(Instant::now());
+                        if timeout.is_zero() {
+                            return Err(Timeout);
+                        }
+                        state = slot.ready.wait_timeout(state, timeout).unwrap().0;
+                    }
+                    State::Abandoned => break,
+                    _ => {
+                        if let Some(result) = Self::result(&state) {
+                            self.counters.hit();
+                            return Ok(result);
+                        }
+                        // Refresh the expired value or error.
+                        *state = State::Computing;
+                        drop(state);
+                        return Ok(self.load_slot(key, &slot, ttl, f));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove the expired values. Returns the number of removed values.
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        self.shards
+            .iter()
+            .map(|shard| self.purge_shard(shard, now))
+            .sum()
+    }
+
+    /// Remove the values of `shard` that expired by `now`. Returns the number of removed values.
+    fn purge_shard(&self, shard: &RwLock<Shard<K, V>>, now: Instant) -> usize {
+        let mut purged = 0;
+        shard.write().unwrap().map.retain(|_, slot| {
+            let expired = slot.is_expired(now);
+            if expired {
+                self.unlinked(slot);
+                purged += 1;
+            }
+            !expired
+        });
+        self.counters.evict(purged as u64);
+        purged
+    }
+
+    /// Returns true if the cache holds a value of `key` that is not expired. A value being
+    /// computed doesn't count.
+    pub fn contains_key(&self, key: &K) -> bool {
+        let Some(slot) = self.shard(key).read().unwrap().map.get(key).cloned() else {
+            return false;
+        };
+        let state = slot.state.lock().unwrap();
+        matches!(Self::result::<Infallible>(&state), Some(Ok(_)))
+    }
+
+    /// Remove the entry of `key`. Returns true if there was one.
+    ///
+    /// If the value of `key` is being computed, the computation still completes and its result is
+    /// returned to the accesses already waiting for it, but it is not inserted into the cache. The
+    /// accesses after the invalidation compute the value again.
+    pub fn invalidate(&self, key: &K) -> bool {
+        let mut shard = self.shard(key).write().unwrap();
+        let Some(slot) = shard.map.remove(key) else {
+            return false;
+        };
+        self.unlinked(&slot);
+        true
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: Eq + Hash + Clone, V> Cache<K, V> {
+    /// Write the values that are not expired to a snapshot at `path`, so that a new cache can be
+    /// warmed up by `load`. The values are written with their expiry times in the wall-clock time,
+    /// from the least recently used one. The errors of the computations are not written.
+    ///
+    /// The snapshot is written to a temporary file first, so that a crash doesn't leave a partial
+    /// snapshot at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let now = Instant::now();
+        // `Instant`s are meaningless in another process, so the expiry times are converted.
+        let wall_now = SystemTime::now();
+        let mut entries = Vec::new();
+        for shard in &self.shards {
+            for (key, slot) in shard.read().unwrap().map.iter() {
+                let state = slot.state.lock().unwrap();
+                let (State::Ready { val, expiry, .. } | State::Refreshing { val, expiry }) =
+                    &*state
+                else {
+                    continue;
+                };
+                if expiry.is_some_and(|expiry| expiry <= now) {
+                    continue;
+                }
+                let expiry = expiry.map(|expiry| wall_now + (expiry - now));
+                let last_access = slot.last_access.load(Ordering::Relaxed);
+                entries.push((last_access, key.clone(), val.clone(), expiry));
+            }
+        }
+        entries.sort_unstable_by_key(|(last_access, ..)| *last_access);
+        let entries = entries
+            .iter()
+            .map(|(_, key, val, expiry)| (key, &**val, expiry))
+            .collect::<Vec<_>>();
+
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut writer = BufWriter::new(std::fs::File::create(&tmp)?);
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, &entries).map_err(io::Error::other)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// Insert the values of the snapshot at `path` written by `save`. Returns the number of
+    /// inserted values. The keys that already have entries are skipped.
+    ///
+    /// The snapshot may be written by a cache with a different configuration. The values are
+    /// inserted under the configuration of this cache: they are evicted if the cache is full, and
+    /// become stale after `CacheBuilder::refresh_after`. They expire at the same wall-clock time as
+    /// in the saved cache, so the values that expired since the snapshot was written are not
+    /// inserted.
+    pub fn load(&self, path: impl AsRef<Path>) -> io::Result<usize>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        let mut magic = [0; SNAPSHOT_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != *SNAPSHOT_MAGIC {
+            return Err(invalid("not a cache snapshot"));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != SNAPSHOT_VERSION {
+            return Err(invalid("unsupported snapshot version"));
+        }
+        // Deserializing from a slice checks the lengths in a corrupt snapshot against its size,
+        // rather than allocating them.
+        let mut bytes = Vec::new();
+        let _ = reader.read_to_end(&mut bytes)?;
+        let entries: Vec<(K, V, Option<SystemTime>)> = bincode::deserialize(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut inserted = 0;
+        for (key, val, expiry) in entries {
+            // The remaining time to live, or `None` if expired.
+            let ttl = match expiry {
+                None => None,
+                Some(expiry) => match expiry.duration_since(SystemTime::now()) {
+                    Ok(ttl) if !ttl.is_zero() => Some(ttl),
+                    _ => continue,
+                },
+            };
+            // Weighed before locking the shard, so that a panicking weigher does not poison it.
+            let weight = self.weigh(&key, &val);
+            let mut shard = self.shard(&key).write().unwrap();
+            if shard.map.contains_key(&key) {
+                continue;
+            }
+            let slot = Arc::new(Slot::new(State::Ready {
+                val: Arc::new(val),
+                expiry: ttl.map(|ttl| Instant::now() + ttl),
+                refresh_at: self.refresh_at(),
+            }));
+            slot.weight.store(weight, Ordering::Relaxed);
+            self.tick_new(&slot);
+            let _ = shard.insert(key.clone(), slot.clone(), self.is_bounded());
+            self.linked(&slot);
+            drop(shard);
+            self.evict_if_full(&key);
+            inserted += 1;
         }
+        Ok(inserted)
     }
 }
@@ -5,10 +5,14 @@ mod cache;
 mod handler;
 mod statistics;
 mod tcp;
-mod thread_pool;
 
-pub use cache::Cache;
+pub use cache::{Cache, CacheBuilder, CacheStats, Timeout};
 pub use handler::Handler;
 pub use statistics::{Report, Statistics};
 pub use tcp::CancellableTcpListener;
-pub use thread_pool::ThreadPool;
+
+pub use crate::pool::{
+    BatchHandle, CancellationToken, JobHandle, LatencyPercentiles, PanicInfo, PanicLog,
+    PanicPolicy, PoolStats, Priority, Scaling, ScheduleHandle, Scope, ThreadPool,
+    ThreadPoolBuilder,
+};
@@ -1,7 +1,7 @@
 //! TcpListener that can be cancelled.
 
 use std::io;
-use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Like `std::net::tcp::TcpListener`, but `cancel`lable.
@@ -34,6 +34,11 @@ impl CancellableTcpListener {
         })
     }
 
+    /// Wraps `TcpListener::local_addr`.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
     /// Signals the listener to stop accepting new connections.
     pub fn cancel(&self) -> io::Result<()> {
         // Set the flag first and make a bogus connection to itself to wake up the listener blocked
@@ -0,0 +1,142 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of rotated log files kept, named `<file>.1` (the newest) to `<file>.<n>`.
+const LOG_BACKUPS: usize = 3;
+
+/// Where the access log is written.
+#[derive(Debug, Clone)]
+pub enum LogTarget {
+    Stdout,
+    /// Appended to the file. When the file would grow larger than `max_size` bytes, it is rotated
+    /// to `<path>.1`.
+    File {
+        path: PathBuf,
+        max_size: u64,
+    },
+}
+
+/// An entry of the access log.
+#[derive(Debug)]
+pub struct Entry {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency: Duration,
+    /// Name of the thread that served the request.
+    pub worker: String,
+}
+
+/// Handle to the access log. Entries are sent to a dedicated logging thread, so logging never
+/// blocks on the writes. The thread exits once all the handles are dropped.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    sender: Sender<(SystemTime, Entry)>,
+}
+
+/// Writes the entries to the target, rotating the log file.
+struct Sink {
+    target: LogTarget,
+    writer: BufWriter<Box<dyn Write + Send>>,
+    /// The size of the log file.
+    size: u64,
+}
+
+fn open(path: &Path) -> io::Result<(Box<dyn Write + Send>, u64)> {
+    let file: File = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((Box::new(file), size))
+}
+
+fn backup(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    path.into()
+}
+
+impl Sink {
+    fn new(target: LogTarget) -> io::Result<Sink> {
+        let (writer, size) = match &target {
+            LogTarget::Stdout => (Box::new(io::stdout()) as Box<dyn Write + Send>, 0),
+            LogTarget::File { path, .. } => open(path)?,
+        };
+        Ok(Sink {
+            target,
+            writer: BufWriter::new(writer),
+            size,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if let LogTarget::File { path, max_size } = &self.target {
+            if self.size > 0 && self.size + line.len() as u64 > *max_size {
+                self.writer.flush()?;
+                for n in (1..LOG_BACKUPS).rev() {
+                    // The older backups may not exist yet.
+                    fs::rename(backup(path, n), backup(path, n + 1)).ok();
+                }
+                fs::rename(path, backup(path, 1))?;
+                let (writer, size) = open(path)?;
+                self.writer = BufWriter::new(writer);
+                self.size = size;
+            }
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Write the entries until all the senders are dropped.
+    fn run(mut self, receiver: Receiver<(SystemTime, Entry)>) {
+        while let Ok(mut next) = receiver.recv() {
+            // Write the queued entries at once and flush when the queue is empty.
+            loop {
+                let (time, entry) = next;
+                let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let line = format!(
+                    "time={}.{:03} method={} path={:?} status={} latency_ms={:.3} worker={:?}\n",
+                    time.as_secs(),
+                    time.subsec_millis(),
+                    entry.method,
+                    entry.path,
+                    entry.status,
+                    entry.latency.as_secs_f64() * 1000.0,
+                    entry.worker,
+                );
+                if let Err(e) = self.write(&line) {
+                    eprintln!("Failed to write the access log: {}", e);
+                }
+                match receiver.try_recv() {
+                    Ok(entry) => next = entry,
+                    Err(_) => break,
+                }
+            }
+            if let Err(e) = self.writer.flush() {
+                eprintln!("Failed to write the access log: {}", e);
+            }
+        }
+    }
+}
+
+impl AccessLog {
+    /// Start the logging thread writing to `target`. Join the returned handle after dropping all
+    /// the `AccessLog`s to wait for the entries to be written.
+    pub fn spawn(target: LogTarget) -> io::Result<(AccessLog, JoinHandle<()>)> {
+        let sink = Sink::new(target)?;
+        let (sender, receiver) = channel();
+        let thread = thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || sink.run(receiver))?;
+        Ok((AccessLog { sender }, thread))
+    }
+
+    /// Log `entry`. Never blocks.
+    pub fn log(&self, entry: Entry) {
+        // The logging thread exits only after all the senders are dropped.
+        self.sender.send((SystemTime::now(), entry)).ok();
+    }
+}
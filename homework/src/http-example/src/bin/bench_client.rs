@@ -0,0 +1,200 @@
+//! Load generator for the example server. Opens `-c` concurrent connections, sends `-n` GET
+//! requests on each of them, and reports the throughput and the latency percentiles.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::exit;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: bench_client [options]
+
+Options:
+    --addr <addr>  Address of the server [default: 127.0.0.1:8000]
+    --path <path>  Path to request [default: /echo/bench]
+    -c <n>         Number of concurrent connections [default: 8]
+    -n <n>         Number of requests per connection [default: 100]";
+
+struct Options {
+    addr: String,
+    path: String,
+    connections: usize,
+    requests: usize,
+}
+
+impl Options {
+    fn from_args() -> Result<Options, String> {
+        let mut options = Options {
+            addr: "127.0.0.1:8000".to_string(),
+            path: "/echo/bench".to_string(),
+            connections: 8,
+            requests: 100,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{}: missing value", arg))?;
+            let number = || match value.parse() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(format!("{}: not a positive number: {}", arg, value)),
+            };
+            match arg.as_str() {
+                "--addr" => options.addr = value,
+                "--path" => options.path = value,
+                "-c" => options.connections = number()?,
+                "-n" => options.requests = number()?,
+                _ => return Err(format!("Unknown option: {}\n\n{}", arg, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Read a response, skipping its body. Returns whether the server keeps the connection open.
+fn read_response(reader: &mut BufReader<TcpStream>) -> io::Result<bool> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if !line.starts_with("HTTP/1.1 ") {
+        return Err(invalid("invalid status line"));
+    }
+
+    let mut content_length = 0;
+    let mut chunked = false;
+    let mut keep_alive = true;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(invalid("invalid header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value
+                .parse()
+                .map_err(|_| invalid("invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("Connection") {
+            keep_alive = !value.eq_ignore_ascii_case("close");
+        }
+    }
+
+    if !chunked {
+        io::copy(&mut reader.take(content_length), &mut io::sink())?;
+        return Ok(keep_alive);
+    }
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size =
+            u64::from_str_radix(line.trim_end(), 16).map_err(|_| invalid("invalid chunk size"))?;
+        // The chunk and its CRLF, or the CRLF after the last chunk.
+        io::copy(&mut reader.take(size + 2), &mut io::sink())?;
+        if size == 0 {
+            return Ok(keep_alive);
+        }
+    }
+}
+
+/// Send `request` on the connection, connecting first if there is none, and read the response.
+fn send(
+    connection: &mut Option<BufReader<TcpStream>>,
+    addr: &str,
+    request: &str,
+) -> io::Result<bool> {
+    if connection.is_none() {
+        *connection = Some(BufReader::new(TcpStream::connect(addr)?));
+    }
+    let reader = connection.as_mut().unwrap();
+    reader.get_mut().write_all(request.as_bytes())?;
+    read_response(reader)
+}
+
+/// Send `requests` requests on a connection, reconnecting when the server closes it. Returns the
+/// latencies of the successful requests and the number of failed ones.
+fn run_connection(options: &Options) -> (Vec<Duration>, usize) {
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n",
+        options.path, options.addr
+    );
+    let mut latencies = Vec::with_capacity(options.requests);
+    let mut errors = 0;
+    let mut connection: Option<BufReader<TcpStream>> = None;
+    for _ in 0..options.requests {
+        let start = Instant::now();
+        match send(&mut connection, &options.addr, &request) {
+            Ok(keep_alive) => {
+                latencies.push(start.elapsed());
+                if !keep_alive {
+                    connection = None;
+                }
+            }
+            Err(_) => {
+                errors += 1;
+                connection = None;
+            }
+        }
+    }
+    (latencies, errors)
+}
+
+/// The `p`-th percentile of the sorted `latencies`.
+fn percentile(latencies: &[Duration], p: usize) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO;
+    }
+    latencies[(latencies.len() * p / 100).min(latencies.len() - 1)]
+}
+
+fn main() {
+    let options = Options::from_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+
+    let start = Instant::now();
+    let results = thread::scope(|s| {
+        let handles = (0..options.connections)
+            .map(|_| s.spawn(|| run_connection(&options)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    let elapsed = start.elapsed();
+
+    let errors = results.iter().map(|(_, errors)| errors).sum::<usize>();
+    let mut latencies = results
+        .into_iter()
+        .flat_map(|(latencies, _)| latencies)
+        .collect::<Vec<_>>();
+    latencies.sort_unstable();
+
+    println!(
+        "Requests:   {} ({} errors) in {:.2?}",
+        latencies.len() + errors,
+        errors,
+        elapsed
+    );
+    println!(
+        "Throughput: {:.1} requests/s",
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "Latency:    p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+        percentile(&latencies, 50),
+        percentile(&latencies, 90),
+        percentile(&latencies, 99),
+        latencies.last().copied().unwrap_or_default(),
+    );
+}
@@ -0,0 +1,185 @@
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cs431_homework::hello_server::CancellableTcpListener;
+
+use crate::access_log::LogTarget;
+use crate::tls;
+
+/// The default size at which the access log file is rotated.
+const ACCESS_LOG_MAX_SIZE: u64 = 10 << 20;
+
+const USAGE: &str = "\
+Usage: http-example [options]
+
+Options:
+    --addr <addr>                  Address to bind [env: HTTP_EXAMPLE_ADDR] [default: 127.0.0.1]
+    --port <port>                  Port to bind, 0 for any [env: HTTP_EXAMPLE_PORT] [default: 8000]
+    --threads <n>                  Number of worker threads [env: HTTP_EXAMPLE_THREADS] [default: 8]
+    --request-timeout <secs>       Time limit to read a request [env: HTTP_EXAMPLE_REQUEST_TIMEOUT] [default: 10]
+    --max-connections <n>          Maximum number of live connections [default: 64]
+    --root <dir>                   Serve the files under <dir> instead of echoing
+    --max-age <secs>               Cache the responses to GET requests for <secs>
+    --access-log <file>            Write the access log to <file> instead of stdout
+    --access-log-max-size <bytes>  Rotate the access log at <bytes> [default: 10 MiB]
+    --tls <cert.pem> <key.pem>     Serve over TLS
+    --inject-latency <ms>          Delay every response by <ms> milliseconds
+    -v                             Print more, e.g. the request headers [env: HTTP_EXAMPLE_VERBOSITY]
+    -q                             Print only the errors";
+
+/// The verbosity of the process. See `ServerConfig::verbosity`.
+static VERBOSITY: AtomicU8 = AtomicU8::new(1);
+
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+pub fn set_verbosity(verbosity: u8) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+/// Configuration of the server. `parse` reads it from the command line and the environment, and
+/// `default` is handy for servers on an ephemeral port (set `port` to 0).
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Address to bind.
+    pub addr: String,
+    /// Port to bind. If 0, the OS picks one.
+    pub port: u16,
+    /// Number of worker threads.
+    pub threads: usize,
+    /// How long a request may take to arrive after its first byte.
+    pub request_timeout: Duration,
+    /// 0 prints only the errors, 1 prints the progress, and 2 also prints the requests.
+    pub verbosity: u8,
+    /// Document root of the static files, canonicalized. If `None`, GET requests are echoed.
+    pub root: Option<PathBuf>,
+    /// How long the responses to GET requests are cached, in seconds. If `None`, they are not
+    /// cached.
+    pub max_age: Option<u64>,
+    /// The maximum number of live connections. More connections are rejected with 503.
+    pub max_connections: usize,
+    /// Where the access log is written.
+    pub access_log: LogTarget,
+    /// If set, the connections are served over TLS.
+    pub tls: Option<Arc<rustls::ServerConfig>>,
+    /// Added to the latency of every response, to simulate a slow server.
+    pub inject_latency: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: "127.0.0.1".to_string(),
+            port: 8000,
+            threads: 8,
+            request_timeout: Duration::from_secs(10),
+            verbosity: 1,
+            root: None,
+            max_age: None,
+            max_connections: 64,
+            access_log: LogTarget::Stdout,
+            tls: None,
+            inject_latency: Duration::ZERO,
+        }
+    }
+}
+
+/// Parse `value` of the option `name` as a positive number.
+fn positive<T: FromStr + Default + PartialOrd>(name: &str, value: &str) -> Result<T, String> {
+    match value.parse() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(format!("{}: not a positive number: {}", name, value)),
+    }
+}
+
+/// Parse `value` of the option `name`.
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("{}: {}: {}", name, e, value))
+}
+
+impl ServerConfig {
+    /// Read the configuration from the environment variables (looked up with `env`) and the
+    /// command line arguments `args`, without the program name. The arguments take precedence.
+    pub fn parse<I, E>(args: I, env: E) -> Result<ServerConfig, String>
+    where
+        I: IntoIterator<Item = String>,
+        E: Fn(&str) -> Option<String>,
+    {
+        let mut config = ServerConfig::default();
+        if let Some(addr) = env("HTTP_EXAMPLE_ADDR") {
+            config.addr = addr;
+        }
+        if let Some(port) = env("HTTP_EXAMPLE_PORT") {
+            config.port = parse("HTTP_EXAMPLE_PORT", &port)?;
+        }
+        if let Some(threads) = env("HTTP_EXAMPLE_THREADS") {
+            config.threads = positive("HTTP_EXAMPLE_THREADS", &threads)?;
+        }
+        if let Some(secs) = env("HTTP_EXAMPLE_REQUEST_TIMEOUT") {
+            config.request_timeout =
+                Duration::from_secs(positive("HTTP_EXAMPLE_REQUEST_TIMEOUT", &secs)?);
+        }
+        if let Some(verbosity) = env("HTTP_EXAMPLE_VERBOSITY") {
+            config.verbosity = parse("HTTP_EXAMPLE_VERBOSITY", &verbosity)?;
+        }
+
+        let mut access_log_path = None;
+        let mut access_log_max_size = ACCESS_LOG_MAX_SIZE;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{}: missing value", arg));
+            match arg.as_str() {
+                "--addr" => config.addr = value()?,
+                "--port" => config.port = parse(&arg, &value()?)?,
+                "--threads" => config.threads = positive(&arg, &value()?)?,
+                "--request-timeout" => {
+                    config.request_timeout = Duration::from_secs(positive(&arg, &value()?)?)
+                }
+                "--max-connections" => config.max_connections = positive(&arg, &value()?)?,
+                "--root" => {
+                    let dir = value()?;
+                    match PathBuf::from(&dir).canonicalize() {
+                        Ok(root) if root.is_dir() => config.root = Some(root),
+                        _ => return Err(format!("Not a directory: {}", dir)),
+                    }
+                }
+                "--max-age" => config.max_age = Some(positive(&arg, &value()?)?),
+                "--access-log" => access_log_path = Some(PathBuf::from(value()?)),
+                "--access-log-max-size" => access_log_max_size = positive(&arg, &value()?)?,
+                "--tls" => {
+                    let (cert, key) = (value()?, value()?);
+                    config.tls = Some(tls::server_config(Path::new(&cert), Path::new(&key))?);
+                }
+                "--inject-latency" => {
+                    config.inject_latency = Duration::from_millis(parse(&arg, &value()?)?)
+                }
+                "-v" => config.verbosity += 1,
+                "-q" => config.verbosity = 0,
+                _ => return Err(format!("Unknown option: {}\n\n{}", arg, USAGE)),
+            }
+        }
+        if let Some(path) = access_log_path {
+            config.access_log = LogTarget::File {
+                path,
+                max_size: access_log_max_size,
+            };
+        }
+        Ok(config)
+    }
+
+    /// Bind to the configured address and port.
+    pub fn bind(&self) -> io::Result<CancellableTcpListener> {
+        CancellableTcpListener::bind((self.addr.as_str(), self.port))
+    }
+}
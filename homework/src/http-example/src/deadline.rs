@@ -0,0 +1,80 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Connection to a client, plain or TLS.
+pub trait Stream: Read + Write {
+    /// The underlying socket.
+    fn socket(&self) -> &TcpStream;
+
+    /// Close the connection cleanly before it is dropped.
+    fn shutdown(&mut self) {}
+}
+
+impl Stream for TcpStream {
+    fn socket(&self) -> &TcpStream {
+        self
+    }
+}
+
+/// Wraps a `Stream`, failing a read with `TimedOut` if it waits longer than `timeout` or the
+/// deadline passes. Writes are passed through.
+#[derive(Debug)]
+pub struct DeadlineStream<S> {
+    stream: S,
+    timeout: Duration,
+    deadline: Option<Instant>,
+}
+
+impl<S: Stream> DeadlineStream<S> {
+    pub fn new(stream: S, timeout: Duration) -> DeadlineStream<S> {
+        DeadlineStream {
+            stream,
+            timeout,
+            deadline: None,
+        }
+    }
+
+    /// Set the timeout of each read.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set the deadline of all the reads until it is reset.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+}
+
+impl<S: Stream> Read for DeadlineStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut timeout = self.timeout;
+        if let Some(deadline) = self.deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            timeout = timeout.min(deadline - now);
+        }
+        self.stream.socket().set_read_timeout(Some(timeout))?;
+        self.stream.read(buf).map_err(|e| match e.kind() {
+            // Unix reports a timeout as `WouldBlock`.
+            io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut.into(),
+            _ => e,
+        })
+    }
+}
+
+impl<S: Stream> Write for DeadlineStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use crate::query::percent_decode;
+
+/// Content-Type of a file, inferred from its extension.
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "md" | "rs" => "text/plain; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Map the path of a request to a file under `root`, which must be canonical. A directory maps to
+/// its `index.html`. Returns `None` if there is no such file or the path escapes `root`.
+pub fn resolve(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(url_path)?;
+    let mut path = root.to_path_buf();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            _ if segment.contains(['\\', '\0']) => return None,
+            _ => path.push(segment),
+        }
+    }
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    // Symlinks may still point outside of `root`.
+    let path = path.canonicalize().ok()?;
+    if !path.starts_with(root) || !path.is_file() {
+        return None;
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn resolve_rejects_traversal() {
+        let dir = std::env::temp_dir().join(format!("http-example-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root/sub")).unwrap();
+        fs::write(dir.join("root/a.txt"), "a").unwrap();
+        fs::write(dir.join("root/sub/index.html"), "index").unwrap();
+        fs::write(dir.join("secret"), "secret").unwrap();
+        let root = dir.join("root").canonicalize().unwrap();
+
+        assert_eq!(resolve(&root, "/a.txt"), Some(root.join("a.txt")));
+        assert_eq!(
+            resolve(&root, "/./sub//"),
+            Some(root.join("sub/index.html"))
+        );
+        assert_eq!(resolve(&root, "/%61.txt"), Some(root.join("a.txt")));
+        assert_eq!(resolve(&root, "/missing"), None);
+        assert_eq!(resolve(&root, "/"), None);
+        assert_eq!(resolve(&root, "/../secret"), None);
+        assert_eq!(resolve(&root, "/sub/../../secret"), None);
+        assert_eq!(resolve(&root, "/%2e%2e/secret"), None);
+        assert_eq!(resolve(&root, "/..%2fsecret"), None);
+        assert_eq!(resolve(&root, "/..%5csecret"), None);
+        assert_eq!(resolve(&root, "/a.txt%00"), None);
+        assert_eq!(resolve(&root, "/%zz"), None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret"), root.join("link")).unwrap();
+            assert_eq!(resolve(&root, "/link"), None);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
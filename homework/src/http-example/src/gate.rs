@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Limits the number of live connections, like a semaphore that doesn't block.
+#[derive(Debug)]
+pub struct ConnectionGate {
+    live: AtomicUsize,
+    max: usize,
+}
+
+/// A live connection admitted by a `ConnectionGate`. Leaves the gate when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    gate: Arc<ConnectionGate>,
+}
+
+impl ConnectionGate {
+    pub fn new(max: usize) -> ConnectionGate {
+        ConnectionGate {
+            live: AtomicUsize::new(0),
+            max,
+        }
+    }
+
+    /// Admit a new connection, or return `None` if there are already `max` live connections.
+    pub fn try_enter(self: &Arc<Self>) -> Option<Permit> {
+        self.live
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |live| {
+                (live < self.max).then_some(live + 1)
+            })
+            .ok()?;
+        Some(Permit {
+            gate: Arc::clone(self),
+        })
+    }
+
+    /// The number of live connections.
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.gate.live.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -1,108 +1,630 @@
-pub mod threadpool;
+/// Print the message if the verbosity is at least `level`.
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        if crate::config::verbosity() >= $level {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod access_log;
+mod config;
+mod deadline;
+mod files;
+mod gate;
+mod metrics;
+mod multipart;
+mod query;
+mod request;
+mod response;
+mod router;
+mod tls;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
 
-use std::net::{TcpListener, TcpStream};
-use std::io::{Write, BufRead, BufReader};
-use std::time::Duration;
-use regex::Regex;
-use std::thread::sleep;
-use threadpool::ThreadPool;
+use access_log::{AccessLog, Entry};
+use config::ServerConfig;
+use cs431_homework::hello_server::Cache;
+use cs431_homework::pool::ThreadPool;
+use deadline::{DeadlineStream, Stream};
+use gate::ConnectionGate;
+use metrics::Metrics;
+use request::{Request, RequestError};
+use response::Response;
+use router::Router;
 
-fn invalid_request(stream: &mut TcpStream) {
-    println!("Bad request.");
-    stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\
-                    Content-Type: text/text\r\n\
-                    Content-Length: 71\r\n\r\n\
-                    Invalid request.").ok();
+/// How long an idle connection is kept open for the next request.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a read of a request may wait for the next bytes.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a write of a response may block.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The maximum number of requests served on a connection.
+const MAX_REQUESTS: usize = 100;
+/// The maximum latency that `/slow` simulates.
+const MAX_SLOW_LATENCY: Duration = Duration::from_secs(60);
+/// The maximum size of a request body.
+const MAX_BODY_SIZE: u64 = 1 << 20;
+/// The maximum number of cached responses.
+const CACHE_CAPACITY: usize = 1 << 10;
+
+/// State shared by the handlers.
+struct Server {
+    config: ServerConfig,
+    /// Responses keyed by the request path, with the instants they are rendered at. They expire
+    /// after `max_age`, and at most `CACHE_CAPACITY` of them are kept.
+    responses: Cache<String, (Response, Instant)>,
+    metrics: Arc<Metrics>,
+    shutting_down: Arc<AtomicBool>,
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let mut reader = BufReader::new(&stream);
+/// Respond with an error to a request that can't be read, and close the connection.
+fn invalid_request(stream: &mut impl Write, status: u16, message: &str) {
+    log!(1, "Bad request.");
+    Response::new(status)
+        .header("Connection", "close")
+        .text(message)
+        .write_to(stream)
+        .ok();
+}
 
-    // Parse header
-    let mut buf = String::new();
+/// Echo the path and the `content` query parameter of a GET request.
+fn echo_get(request: &Request) -> Response {
+    let content = request.query_param("content").unwrap_or("");
+    let retn_str = String::from("Halo! You are accessing ")
+        + request.path.as_str()
+        + "!\r\nYour content:\r\n"
+        + content;
+    log!(2, "Request parsed.\r\nReturning: {:?}", retn_str);
+    Response::ok().text(retn_str)
+}
 
-    if reader.read_line(&mut buf).is_err() {
-        invalid_request(&mut stream);
-        return;
+/// Echo the body of a POST request. The fields of a form are echoed decoded, and the files of a
+/// multipart form are echoed by their names and sizes.
+fn echo_body(request: &Request) -> Response {
+    let mut retn =
+        (String::from("Halo! You posted to ") + request.path.as_str() + "!\r\nYour content:\r\n")
+            .into_bytes();
+    if let Some(fields) = request.form() {
+        let mut fields = fields.into_iter().collect::<Vec<_>>();
+        fields.sort();
+        for (name, value) in fields {
+            retn.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+    } else if let Some(parts) = request.multipart() {
+        for part in parts {
+            match &part.filename {
+                Some(filename) => retn.extend_from_slice(
+                    format!(
+                        "{}: file {:?}, {} bytes\r\n",
+                        part.name,
+                        filename,
+                        part.data.len()
+                    )
+                    .as_bytes(),
+                ),
+                None => retn.extend_from_slice(
+                    format!("{}: {}\r\n", part.name, String::from_utf8_lossy(&part.data))
+                        .as_bytes(),
+                ),
+            }
+        }
+    } else {
+        retn.extend_from_slice(&request.body);
     }
+    log!(2, "Echoed {} bytes.", request.body.len());
+    Response::ok().body(retn)
+}
 
-    sleep(Duration::from_secs(1));
+/// Greet the `name` parameter.
+fn echo_name(request: &Request) -> Response {
+    Response::ok().text(format!(
+        "Halo, {}!",
+        request.param("name").unwrap_or_default()
+    ))
+}
 
-    println!("New connection! Starting to parse header.");
+/// Respond after the `ms` query parameter milliseconds, at most `MAX_SLOW_LATENCY`.
+fn slow(request: &Request) -> Response {
+    let Some(ms) = request.query_param("ms").and_then(|ms| ms.parse().ok()) else {
+        return Response::new(400).text("Usage: /slow?ms=<milliseconds>");
+    };
+    let latency = Duration::from_millis(ms).min(MAX_SLOW_LATENCY);
+    sleep(latency);
+    Response::ok().text(format!("Slept for {}ms.", latency.as_millis()))
+}
 
-    let mut path: String = String::new();
-    let mut content: String = String::new();
-        
-    if let Some(buf_s) = buf.strip_suffix("\r\n") {
-        buf = String::from(buf_s);
-    }
+/// Count from 1 to the `n` parameter, a line every 100ms, streaming the lines as they are counted.
+fn count(request: &Request) -> Response {
+    let Some(n) = request.param("n").and_then(|n| n.parse::<u32>().ok()) else {
+        return Response::new(400).text("Not a number.");
+    };
+    Response::ok().stream((1..=n).map(|i| {
+        sleep(Duration::from_millis(100));
+        format!("{}\r\n", i).into_bytes()
+    }))
+}
 
-    if let Some(req_capture) = Regex::new(r"GET (?<path>[A-z0-9_\/]+)(?:\?(?:content=(?<content>.*))|.*)? HTTP\/(?<version>\d\.\d)").unwrap()
-        .captures(buf.as_str())
-    {
-        req_capture["path"].clone_into(&mut path);
-        req_capture.name("content").map_or("", |m| m.as_str()).clone_into(&mut content);
-        println!("Path: {:?}", &path);
-        println!("Version: {:?}", &req_capture["version"]);
-        println!("Content: {:?}", &content)
-    }
-    else {
-        invalid_request(&mut stream);
-        return;
+/// Respond with the file under `root` that the `path` parameter maps to.
+fn serve_file(root: &Path, request: &Request) -> Response {
+    let Some(path) = files::resolve(root, request.param("path").unwrap_or_default()) else {
+        log!(1, "Not found: {}", request.path);
+        return Response::not_found();
+    };
+    log!(2, "Serving {:?}.", path);
+    Response::ok()
+        .content_type(files::content_type(&path))
+        .file(path)
+}
+
+/// Respond to a GET request with the cached response, rendering it with `render` if it is not
+/// cached or expired. Responses expire `max_age` seconds after they are rendered.
+///
+/// Only the successful responses to the requests without a query string are cached, so that a
+/// client can't fill the cache with the responses to made-up query strings or missing files.
+fn serve_cached(
+    server: &Server,
+    max_age: u64,
+    request: &Request,
+    render: impl FnOnce(&Request) -> Response,
+) -> Response {
+    if request.query.is_some() {
+        return render(request);
     }
-    
-    let mut failed = false;
-    println!("------- HEADER -------");
-    buf = String::new();
-    while reader.read_line(&mut buf).is_ok() {
-        if let Some(buf_s) = buf.strip_suffix("\r\n") {
-            buf = String::from(buf_s);
-        }
-        else {
-            invalid_request(&mut stream);
-            failed = true;
-            break;
+    let ttl = Duration::from_secs(max_age);
+    let rendered = server
+        .responses
+        .try_get_or_insert_with_ttl(request.path.clone(), ttl, |_| {
+            let response = render(request).buffered();
+            if response.status() == 200 {
+                Ok((response, Instant::now()))
+            } else {
+                Err(response)
+            }
+        });
+    match rendered {
+        Ok((response, rendered)) => {
+            let age = rendered.elapsed().as_secs();
+            response.header(
+                "Cache-Control",
+                format!("max-age={}", max_age.saturating_sub(age)),
+            )
         }
+        Err(response) => response,
+    }
+}
 
-        if buf.is_empty() {
-            break;
+/// Routes of the server.
+fn router(server: Arc<Server>) -> Router {
+    let mut router = Router::new();
+    let health = Arc::clone(&server);
+    router.get("/healthz", move |_| {
+        if health.shutting_down.load(Ordering::Acquire) {
+            return Response::new(503).text("Shutting down.");
         }
-        if let Some(header_capture) = Regex::new(r"([A-z\-]+):(.*)").unwrap()
-            .captures(buf.as_str())
-        {
-            println!("Name: {:?}, Content: {:?}", &header_capture[1], &header_capture[2].strip_prefix(" ").unwrap_or(&header_capture[2]));
+        Response::ok().text("OK")
+    });
+    let metrics = Arc::clone(&server.metrics);
+    router.get("/metrics", move |_| {
+        Response::ok()
+            .content_type("text/plain; version=0.0.4")
+            .text(metrics.render())
+    });
+    router.post("/*path", echo_body);
+    router.get("/echo/:name", echo_name);
+    router.get("/count/:n", count);
+    router.get("/slow", slow);
+    router.get("/*path", move |request| {
+        let render = |request: &Request| match &server.config.root {
+            Some(root) => serve_file(root, request),
+            None => echo_get(request),
+        };
+        match server.config.max_age {
+            Some(max_age) => serve_cached(&server, max_age, request, render),
+            None => render(request),
         }
+    });
+    router
+}
 
-        buf = String::new();
+/// Read the next request on the connection. Returns `None` if the connection is closed or idle for
+/// `IDLE_TIMEOUT` before the request starts. Once it starts, the whole request must arrive within
+/// `request_timeout`.
+fn read_request<S: Stream>(
+    reader: &mut BufReader<DeadlineStream<S>>,
+    request_timeout: Duration,
+) -> Result<Option<Request>, RequestError> {
+    reader.get_mut().set_timeout(IDLE_TIMEOUT);
+    reader.get_mut().set_deadline(None);
+    match reader.fill_buf() {
+        Ok([]) => return Ok(None),
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+        Err(e) => return Err(e.into()),
     }
-    println!("----------------------");
 
-    if failed {
+    reader.get_mut().set_timeout(READ_TIMEOUT);
+    reader
+        .get_mut()
+        .set_deadline(Some(Instant::now() + request_timeout));
+    let Some(mut request) = Request::read_head(reader)? else {
+        return Ok(None);
+    };
+    request.read_body(reader, MAX_BODY_SIZE)?;
+    Ok(Some(request))
+}
+
+/// State shared by the connections.
+struct Connections {
+    router: Router,
+    access_log: AccessLog,
+    shutting_down: Arc<AtomicBool>,
+    request_timeout: Duration,
+    inject_latency: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl Connections {
+    fn new(
+        config: ServerConfig,
+        access_log: AccessLog,
+        metrics: Arc<Metrics>,
+        shutting_down: Arc<AtomicBool>,
+    ) -> Connections {
+        Connections {
+            request_timeout: config.request_timeout,
+            inject_latency: config.inject_latency,
+            router: router(Arc::new(Server {
+                config,
+                responses: Cache::with_capacity(CACHE_CAPACITY),
+                metrics: Arc::clone(&metrics),
+                shutting_down: Arc::clone(&shutting_down),
+            })),
+            access_log,
+            shutting_down,
+            metrics,
+        }
+    }
+}
+
+/// Serve the requests on the connection until the client closes it, it is idle for
+/// `IDLE_TIMEOUT`, `MAX_REQUESTS` requests are served, or the server is shutting down.
+fn handle_connection<S: Stream>(connections: &Connections, stream: S) {
+    if let Err(e) = stream.socket().set_write_timeout(Some(WRITE_TIMEOUT)) {
+        log!(1, "Failed to set timeout: {:?}", e);
         return;
     }
+    let mut reader = BufReader::new(DeadlineStream::new(stream, IDLE_TIMEOUT));
+    serve_requests(connections, &mut reader);
+    reader.get_mut().get_mut().shutdown();
+}
 
-    // Receive content
-    let retn_str = String::from("Halo! You are accessing ") + path.as_str()
-                    + "!\r\nYour content:\r\n" + content.as_str();
+/// Serve the requests read by `reader`, writing the responses to its stream.
+fn serve_requests<S: Stream>(connections: &Connections, reader: &mut BufReader<DeadlineStream<S>>) {
+    for served in 1..=MAX_REQUESTS {
+        let mut request = match read_request(reader, connections.request_timeout) {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(RequestError::Io(e)) if e.kind() == io::ErrorKind::TimedOut => {
+                log!(1, "Request timed out.");
+                invalid_request(reader.get_mut(), 408, "Request timed out.");
+                return;
+            }
+            Err(RequestError::Io(e)) => {
+                log!(1, "Connection closed: {}", e);
+                return;
+            }
+            Err(e) => {
+                log!(1, "Failed to read request: {}", e);
+                let status = match e {
+                    RequestError::TooLarge => 413,
+                    RequestError::Unsupported(_) => 501,
+                    _ => 400,
+                };
+                invalid_request(reader.get_mut(), status, "Invalid request.");
+                return;
+            }
+        };
 
-    let retn_str = String::from("HTTP/1.1 200 OK\r\n\
-                    Server: Awsl\r\n\
-                    Cache-Control: no-store\r\n\
-                    Content-Type: text/text\r\n\
-                    Content-Length:") + retn_str.len().to_string().as_str() + "\r\n\r\n" + retn_str.as_str();
+        log!(1, "New request! {} {}", request.method, request.path);
+        log!(2, "Version: {:?}", &request.version);
+        log!(2, "------- HEADER -------");
+        for (name, value) in &request.headers {
+            log!(2, "Name: {:?}, Content: {:?}", name, value);
+        }
+        log!(2, "----------------------");
 
-    println!("Request parsed.\r\nReturning: {:?}", retn_str);
+        let keep_alive = request.keep_alive()
+            && served < MAX_REQUESTS
+            && !connections.shutting_down.load(Ordering::Acquire);
+        let keep_alive_value = format!(
+            "timeout={}, max={}",
+            IDLE_TIMEOUT.as_secs(),
+            MAX_REQUESTS - served
+        );
+        let start = Instant::now();
+        if !connections.inject_latency.is_zero() {
+            sleep(connections.inject_latency);
+        }
+        let response = connections.router.handle(&mut request);
+        let response = if keep_alive {
+            response
+                .header("Connection", "keep-alive")
+                .header("Keep-Alive", keep_alive_value)
+        } else {
+            response.header("Connection", "close")
+        };
 
-    stream.write_all(retn_str.as_bytes()).ok();
+        let result = response.write_to(reader.get_mut());
+        let latency = start.elapsed();
+        connections
+            .metrics
+            .record(&request.method.to_string(), response.status(), latency);
+        connections.access_log.log(Entry {
+            method: request.method.to_string(),
+            path: request.path,
+            status: response.status(),
+            latency,
+            worker: thread::current().name().unwrap_or_default().to_string(),
+        });
+        if let Err(e) = result {
+            log!(1, "Failed to respond: {:?}", e);
+            return;
+        }
+        if !keep_alive {
+            return;
+        }
+    }
 }
 
 fn main() {
-    let listener: TcpListener = TcpListener::bind("127.0.0.1:8000").expect("Cannot bind to address.");
-    let pool: ThreadPool = ThreadPool::new(8);
-    println!("Bind address: http://127.0.0.1:8000");
+    let config = ServerConfig::parse(std::env::args().skip(1), |name| std::env::var(name).ok())
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1);
+        });
+    config::set_verbosity(config.verbosity);
+    let listener = Arc::new(config.bind().expect("Cannot bind to address."));
+    let addr = listener
+        .local_addr()
+        .expect("Cannot get the bound address.");
+    let pool = Arc::new(
+        ThreadPool::builder()
+            .num_threads(config.threads)
+            .thread_name(|id| format!("worker-{}", id))
+            .build(),
+    );
+    let (access_log, logger) = AccessLog::spawn(config.access_log.clone()).unwrap_or_else(|e| {
+        eprintln!("Cannot open the access log: {}", e);
+        exit(1);
+    });
+    let shutting_down = Arc::new(AtomicBool::new(false));
+
+    // On the first Ctrl-C, stop accepting connections and finish the in-flight requests. On the
+    // second one, exit immediately.
+    let ctrlc_listener = Arc::downgrade(&listener);
+    let ctrlc_shutting_down = Arc::clone(&shutting_down);
+    ctrlc::set_handler(move || {
+        if ctrlc_shutting_down.swap(true, Ordering::AcqRel) {
+            exit(130);
+        }
+        println!("Shutting down. Press Ctrl-C again to exit immediately.");
+        if let Some(listener) = ctrlc_listener.upgrade() {
+            listener.cancel().ok();
+        }
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    let tls = config.tls.clone();
+    println!(
+        "Bind address: {}://{}",
+        if tls.is_some() { "https" } else { "http" },
+        addr
+    );
+    if let Some(root) = &config.root {
+        log!(1, "Serving files under {:?}", root);
+    }
+    let gate = Arc::new(ConnectionGate::new(config.max_connections));
+    let metrics = Arc::new(Metrics::new(Arc::clone(&gate), Arc::downgrade(&pool)));
+    let connections = Arc::new(Connections::new(
+        config,
+        access_log,
+        Arc::clone(&metrics),
+        shutting_down,
+    ));
 
     for stream in listener.incoming() {
-        let stream = stream.expect("Failed to listen on stream.");
-        pool.execute(move || handle_connection(stream));
+        let mut stream = stream.expect("Failed to listen on stream.");
+        // Reject the connection right away instead of queueing it behind the live ones.
+        let Some(permit) = gate.try_enter() else {
+            log!(1, "Too many connections ({}), rejecting.", gate.live());
+            metrics.reject();
+            // A TLS client can't read the response before the handshake, so just close it.
+            if tls.is_some() {
+                continue;
+            }
+            stream.set_write_timeout(Some(Duration::from_secs(1))).ok();
+            Response::new(503)
+                .header("Connection", "close")
+                .header("Retry-After", "1")
+                .text("Too many connections.")
+                .write_to(&mut stream)
+                .ok();
+            continue;
+        };
+        let connections = Arc::clone(&connections);
+        let tls = tls.clone();
+        pool.execute(move || {
+            match tls {
+                Some(tls) => match tls::accept(&tls, stream) {
+                    Ok(stream) => handle_connection(&connections, stream),
+                    Err(e) => log!(1, "Failed to start TLS: {}", e),
+                },
+                None => handle_connection(&connections, stream),
+            }
+            drop(permit);
+        });
+    }
+    // Close the listener. The Ctrl-C handler only holds a weak reference.
+    drop(listener);
+
+    // Idle keep-alive connections are closed after at most `IDLE_TIMEOUT`.
+    log!(1, "Waiting for the in-flight requests...");
+    pool.join();
+    // The logging thread exits after writing the entries of all the handles.
+    drop(connections);
+    logger.join().ok();
+    log!(1, "Bye.");
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Weak;
+
+    use super::*;
+    use crate::access_log::LogTarget;
+
+    /// Serve the connections of a server with `config` in the background, each on its own thread.
+    /// Returns the address of the server.
+    fn spawn_server(config: ServerConfig) -> SocketAddr {
+        let listener = config.bind().unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (access_log, _) = AccessLog::spawn(config.access_log.clone()).unwrap();
+        let gate = Arc::new(ConnectionGate::new(config.max_connections));
+        let metrics = Arc::new(Metrics::new(gate, Weak::new()));
+        let connections = Arc::new(Connections::new(
+            config,
+            access_log,
+            metrics,
+            Arc::default(),
+        ));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let connections = Arc::clone(&connections);
+                thread::spawn(move || handle_connection(&connections, stream.unwrap()));
+            }
+        });
+        addr
+    }
+
+    /// Read a response with a `Content-Length`. Returns its status, headers, and body.
+    fn read_response(reader: &mut impl BufRead) -> (u16, Vec<(String, String)>, Vec<u8>) {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let Some((name, value)) = line.trim_end().split_once(": ") else {
+                break;
+            };
+            headers.push((name.to_string(), value.to_string()));
+        }
+        let len = headers
+            .iter()
+            .find(|(name, _)| name == "Content-Length")
+            .unwrap()
+            .1
+            .parse()
+            .unwrap();
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+        (status, headers, body)
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn keep_alive_post_and_timeout() {
+        let log =
+            std::env::temp_dir().join(format!("http-example-access-{}.log", std::process::id()));
+        let addr = spawn_server(ServerConfig {
+            port: 0,
+            request_timeout: Duration::from_secs(1),
+            access_log: LogTarget::File {
+                path: log.clone(),
+                max_size: 1 << 20,
+            },
+            ..Default::default()
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        // The requests are served on the same connection.
+        stream
+            .write_all(b"GET /echo/kaist HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let (status, headers, body) = read_response(&mut reader);
+        assert_eq!(status, 200);
+        assert_eq!(header(&headers, "Connection"), Some("keep-alive"));
+        assert_eq!(body, b"Halo, kaist!");
+
+        let form = b"name=a+b&x=%21";
+        write!(stream, "POST /submit HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n", form.len()).unwrap();
+        stream.write_all(form).unwrap();
+        let (status, headers, body) = read_response(&mut reader);
+        assert_eq!(status, 200);
+        assert_eq!(header(&headers, "Connection"), Some("keep-alive"));
+        assert_eq!(
+            body,
+            b"Halo! You posted to /submit!\r\nYour content:\r\nname: a b\r\nx: !\r\n"
+        );
+
+        // A request that doesn't arrive within `request_timeout` is answered with 408, and the
+        // connection is closed.
+        let start = Instant::now();
+        stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        let (status, headers, _) = read_response(&mut reader);
+        assert_eq!(status, 408);
+        assert_eq!(header(&headers, "Connection"), Some("close"));
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(reader.read(&mut [0; 1]).unwrap(), 0);
+        std::fs::remove_file(log).ok();
+    }
+
+    #[test]
+    fn cache_successful_responses() {
+        let root = std::env::temp_dir().join(format!("http-example-root-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        let addr = spawn_server(ServerConfig {
+            port: 0,
+            root: Some(root.canonicalize().unwrap()),
+            max_age: Some(60),
+            ..Default::default()
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut get = |target: &str| {
+            write!(&stream, "GET {} HTTP/1.1\r\n\r\n", target).unwrap();
+            let (status, headers, _) = read_response(&mut reader);
+            (
+                status,
+                header(&headers, "Cache-Control").map(str::to_string),
+            )
+        };
+
+        assert_eq!(get("/a.txt"), (200, Some("max-age=60".to_string())));
+        // The responses to the requests with a query string are not cached.
+        assert_eq!(get("/a.txt?x=1"), (200, Some("no-store".to_string())));
+        // Neither are the errors, so a file created later is served.
+        assert_eq!(get("/b.txt"), (404, Some("no-store".to_string())));
+        std::fs::write(root.join("b.txt"), "b").unwrap();
+        assert_eq!(get("/b.txt").0, 200);
+        std::fs::remove_dir_all(root).ok();
     }
 }
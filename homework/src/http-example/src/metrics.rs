@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use cs431_homework::pool::ThreadPool;
+
+use crate::gate::ConnectionGate;
+
+/// Counters of the server, exposed in the Prometheus text format by `render`.
+#[derive(Debug)]
+pub struct Metrics {
+    /// The number of responses by the method and the status.
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    /// The total latency of the responses, in microseconds.
+    latency_micros: AtomicU64,
+    /// The number of connections rejected with 503.
+    rejected: AtomicU64,
+    gate: Arc<ConnectionGate>,
+    /// Weak, because the workers of the pool hold the metrics and must not drop the pool.
+    pool: Weak<ThreadPool>,
+}
+
+impl Metrics {
+    pub fn new(gate: Arc<ConnectionGate>, pool: Weak<ThreadPool>) -> Metrics {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            latency_micros: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            gate,
+            pool,
+        }
+    }
+
+    /// Count a response.
+    pub fn record(&self, method: &str, status: u16, latency: Duration) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), status))
+            .or_default() += 1;
+        self.latency_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Count a rejected connection.
+    pub fn reject(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let requests = self.requests.lock().unwrap().clone();
+
+        metric(
+            &mut out,
+            "http_requests_total",
+            "counter",
+            "Responses by method and status.",
+        );
+        for ((method, status), count) in &requests {
+            writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+                method, status, count
+            )
+            .unwrap();
+        }
+        metric(
+            &mut out,
+            "http_request_duration_seconds",
+            "summary",
+            "Time to handle and write the responses.",
+        );
+        let latency = self.latency_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        writeln!(out, "http_request_duration_seconds_sum {}", latency).unwrap();
+        writeln!(
+            out,
+            "http_request_duration_seconds_count {}",
+            requests.values().sum::<u64>()
+        )
+        .unwrap();
+        gauge(
+            &mut out,
+            "http_live_connections",
+            "Connections being served.",
+            self.gate.live(),
+        );
+        metric(
+            &mut out,
+            "http_rejected_connections_total",
+            "counter",
+            "Connections rejected with 503.",
+        );
+        writeln!(
+            out,
+            "http_rejected_connections_total {}",
+            self.rejected.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        if let Some(pool) = self.pool.upgrade() {
+            let stats = pool.stats();
+            gauge(
+                &mut out,
+                "pool_workers",
+                "Worker threads of the pool.",
+                pool.num_workers(),
+            );
+            gauge(
+                &mut out,
+                "pool_busy_workers",
+                "Workers executing a job.",
+                stats.running_jobs,
+            );
+            gauge(
+                &mut out,
+                "pool_queued_jobs",
+                "Jobs waiting in the queues.",
+                stats.queued_jobs,
+            );
+            metric(
+                &mut out,
+                "pool_completed_jobs_total",
+                "counter",
+                "Jobs that finished.",
+            );
+            writeln!(out, "pool_completed_jobs_total {}", stats.completed_jobs).unwrap();
+            metric(
+                &mut out,
+                "pool_panics_total",
+                "counter",
+                "Jobs that panicked.",
+            );
+            writeln!(out, "pool_panics_total {}", stats.panics).unwrap();
+            metric(
+                &mut out,
+                "pool_queue_latency_seconds",
+                "summary",
+                "Time the recent jobs waited in the queues.",
+            );
+            let latency = stats.queue_latency;
+            for (quantile, value) in [
+                ("0.5", latency.p50),
+                ("0.9", latency.p90),
+                ("0.99", latency.p99),
+                ("1", latency.max),
+            ] {
+                writeln!(
+                    out,
+                    "pool_queue_latency_seconds{{quantile=\"{}\"}} {}",
+                    quantile,
+                    value.as_secs_f64()
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Write the `HELP` and `TYPE` lines of a metric.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    metric(out, name, "gauge", help);
+    writeln!(out, "{} {}", name, value).unwrap();
+}
@@ -0,0 +1,143 @@
+/// Part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// Name of the form field.
+    pub name: String,
+    /// Name of the uploaded file, if the part is a file.
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Value of the parameter named `name` of a header value, e.g. `boundary` of
+/// `multipart/form-data; boundary=xyz`. The value may be quoted.
+fn header_param<'a>(value: &'a str, name: &str) -> Option<&'a str> {
+    value.split(';').skip(1).find_map(|param| {
+        let (n, v) = param.trim().split_once('=')?;
+        n.eq_ignore_ascii_case(name).then(|| {
+            v.strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(v)
+        })
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parse the head and the data of a part.
+fn parse_part(part: &[u8]) -> Option<Part> {
+    let head_len = find(part, b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&part[..head_len]).ok()?;
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+    for line in head.split("\r\n") {
+        let (header, value) = line.split_once(':')?;
+        let value = value.trim();
+        if header.eq_ignore_ascii_case("Content-Disposition") {
+            name = header_param(value, "name").map(str::to_string);
+            filename = header_param(value, "filename").map(str::to_string);
+        } else if header.eq_ignore_ascii_case("Content-Type") {
+            content_type = Some(value.to_string());
+        }
+    }
+    Some(Part {
+        name: name?,
+        filename,
+        content_type,
+        data: part[head_len + 4..].to_vec(),
+    })
+}
+
+/// Parse a `multipart/form-data` body, given the `Content-Type` of the request. Returns `None` if
+/// the content type is not `multipart/form-data` or the body is malformed.
+///
+/// NOTE: The whole body must be in memory, and nested multipart bodies are not parsed.
+pub fn parse(content_type: &str, body: &[u8]) -> Option<Vec<Part>> {
+    let mime = content_type.split(';').next()?.trim();
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    let boundary = header_param(content_type, "boundary")?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    // Each part follows a CRLF and the delimiter, except the first one that may lack the CRLF.
+    let next_delimiter = [b"\r\n".as_slice(), &delimiter].concat();
+
+    let mut rest = &body[find(body, &delimiter)? + delimiter.len()..];
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Some(parts);
+        }
+        rest = rest.strip_prefix(b"\r\n")?;
+        let len = find(rest, &next_delimiter)?;
+        parts.push(parse_part(&rest[..len])?);
+        rest = &rest[len + next_delimiter.len()..];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=\"xyz\"";
+
+    #[test]
+    fn parse_parts() {
+        let body = b"preamble\r\n--xyz\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            hello\r\n--xyz\r\n\
+            content-disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\r\nline 2\r\n--xyz--\r\n";
+        let parts = parse(CONTENT_TYPE, body).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            parts[0],
+            Part {
+                name: "title".to_string(),
+                filename: None,
+                content_type: None,
+                data: b"hello".to_vec()
+            }
+        );
+        assert_eq!(parts[1].name, "file");
+        assert_eq!(parts[1].filename.as_deref(), Some("a.txt"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("text/plain"));
+        assert_eq!(parts[1].data, b"line 1\r\nline 2");
+        assert_eq!(parse(CONTENT_TYPE, b"--xyz--"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn missing_boundary() {
+        let body = b"--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--xyz--";
+        assert_eq!(parse("multipart/form-data", body), None);
+        assert_eq!(parse("text/plain; boundary=xyz", body), None);
+        assert_eq!(parse(CONTENT_TYPE, b"no delimiter"), None);
+    }
+
+    #[test]
+    fn part_without_headers() {
+        assert_eq!(parse(CONTENT_TYPE, b"--xyz\r\n\r\nvalue\r\n--xyz--"), None);
+        // The name is required.
+        let body = b"--xyz\r\nContent-Type: text/plain\r\n\r\nvalue\r\n--xyz--";
+        assert_eq!(parse(CONTENT_TYPE, body), None);
+    }
+
+    #[test]
+    fn truncated_body() {
+        let body = b"--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--xyz--";
+        for len in 0..body.len() - 2 {
+            assert_eq!(
+                parse(CONTENT_TYPE, &body[..len]),
+                None,
+                "{:?}",
+                String::from_utf8_lossy(&body[..len])
+            );
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+/// Decode `%XX` escapes. Returns `None` if an escape is invalid or the result is not UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let hex = [iter.next()?, iter.next()?];
+        // `from_str_radix` would also accept a sign, e.g. `%+1`.
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Decode a name or a value of `application/x-www-form-urlencoded`, where `+` is a space. Returns
+/// it as is if it can't be decoded.
+fn decode_component(s: &str) -> String {
+    let s = s.replace('+', " ");
+    percent_decode(&s).unwrap_or(s)
+}
+
+/// Parse a query string or an `application/x-www-form-urlencoded` body, e.g. `a=1&b=x%20y`. A
+/// name without `=` has an empty value. If a name is repeated, the first value is kept.
+pub fn parse(query: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params
+            .entry(decode_component(name))
+            .or_insert_with(|| decode_component(value));
+    }
+    params
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percent_decode_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("%e2%9c%93").as_deref(), Some("\u{2713}"));
+        // `+` is a space only in forms.
+        assert_eq!(percent_decode("a+b").as_deref(), Some("a+b"));
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn parse_form() {
+        let params = parse("a=1&b=x+y%21&c&&a=2&d=");
+        assert_eq!(params.len(), 4);
+        assert_eq!(params["a"], "1");
+        assert_eq!(params["b"], "x y!");
+        assert_eq!(params["c"], "");
+        assert_eq!(params["d"], "");
+        assert_eq!(parse("na+me=%zz")["na me"], "%zz");
+    }
+}
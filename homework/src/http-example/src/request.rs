@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+use crate::multipart::{self, Part};
+use crate::query;
+
+/// The maximum length of the request line and of each header line, without the CRLF.
+const MAX_LINE_LENGTH: u64 = 8 << 10;
+/// The maximum number of headers of a request.
+const MAX_HEADERS: usize = 100;
+
+/// Method of an HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Other(String),
+}
+
+impl Method {
+    fn parse(method: &str) -> Method {
+        match method {
+            "GET" => Method::Get,
+            "POST" => Method::Post,
+            _ => Method::Other(method.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Get => write!(f, "GET"),
+            Method::Post => write!(f, "POST"),
+            Method::Other(method) => write!(f, "{}", method),
+        }
+    }
+}
+
+/// Error while reading a request.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The connection failed.
+    Io(io::Error),
+    /// The request is not a valid HTTP request.
+    Malformed(&'static str),
+    /// The body is longer than the limit, or a line or the number of headers of the head is.
+    TooLarge,
+    /// The request uses a feature that the server doesn't support.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Io(e) => write!(f, "{}", e),
+            RequestError::Malformed(reason) => write!(f, "malformed request: {}", reason),
+            RequestError::TooLarge => write!(f, "request is too large"),
+            RequestError::Unsupported(feature) => write!(f, "unsupported {}", feature),
+        }
+    }
+}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> Self {
+        RequestError::Io(e)
+    }
+}
+
+/// HTTP request. The head is read by `read_head`, and then the body by `read_body`.
+#[derive(Debug)]
+pub struct Request {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    /// Decoded parameters of the query string.
+    pub query_params: HashMap<String, String>,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Parameters of the path, set by the `Router`.
+    pub params: HashMap<String, String>,
+}
+
+/// Read a line without the trailing CRLF. Fails if the line is not terminated, or without reading
+/// the rest of it if it is longer than `MAX_LINE_LENGTH`.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, RequestError> {
+    let mut buf = String::new();
+    let limit = MAX_LINE_LENGTH + 2;
+    reader.by_ref().take(limit).read_line(&mut buf)?;
+    match buf.strip_suffix("\r\n") {
+        Some(line) => Ok(line.to_string()),
+        None if buf.len() as u64 == limit => Err(RequestError::TooLarge),
+        None => Err(RequestError::Malformed("line is not terminated by CRLF")),
+    }
+}
+
+impl Request {
+    /// Read the request line and the headers from `reader`, leaving the body unread. Returns
+    /// `None` if the connection is closed before the request starts.
+    pub fn read_head<R: BufRead>(reader: &mut R) -> Result<Option<Request>, RequestError> {
+        if reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let line = read_line(reader)?;
+        let mut parts = line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(RequestError::Malformed("invalid request line"));
+        };
+        let Some(version) = version.strip_prefix("HTTP/") else {
+            return Err(RequestError::Malformed("invalid HTTP version"));
+        };
+        if method.is_empty()
+            || !method.bytes().all(|b| b.is_ascii_uppercase())
+            || !target.starts_with('/')
+        {
+            return Err(RequestError::Malformed("invalid request line"));
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (target, None),
+        };
+        let mut request = Request {
+            method: Method::parse(method),
+            path: path.to_string(),
+            query_params: query.as_deref().map(query::parse).unwrap_or_default(),
+            query,
+            version: version.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            params: HashMap::new(),
+        };
+
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(RequestError::Malformed("invalid header"));
+            };
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                return Err(RequestError::Malformed("invalid header name"));
+            }
+            if request.headers.len() == MAX_HEADERS {
+                return Err(RequestError::TooLarge);
+            }
+            request.headers.push((
+                name.to_string(),
+                value.trim_matches([' ', '\t']).to_string(),
+            ));
+        }
+        Ok(Some(request))
+    }
+
+    /// Whether the client wants to keep the connection open after this request. Defaults to true
+    /// for HTTP/1.1 and false for HTTP/1.0.
+    pub fn keep_alive(&self) -> bool {
+        match self.header("Connection") {
+            Some(c) if c.eq_ignore_ascii_case("close") => false,
+            Some(c) if c.eq_ignore_ascii_case("keep-alive") => true,
+            _ => self.version == "1.1",
+        }
+    }
+
+    /// Value of the header named `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Decoded value of the query parameter named `name`.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query_params.get(name).map(String::as_str)
+    }
+
+    /// Decoded fields of an `application/x-www-form-urlencoded` body, or `None` if the body is not
+    /// a form or not UTF-8.
+    pub fn form(&self) -> Option<HashMap<String, String>> {
+        let mime = self.header("Content-Type")?.split(';').next()?.trim();
+        if !mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return None;
+        }
+        Some(query::parse(std::str::from_utf8(&self.body).ok()?))
+    }
+
+    /// Parts of a `multipart/form-data` body, or `None` if the body is not multipart or is
+    /// malformed.
+    pub fn multipart(&self) -> Option<Vec<Part>> {
+        multipart::parse(self.header("Content-Type")?, &self.body)
+    }
+
+    /// Length of the body given by `Content-Length`, or 0 if there is none.
+    pub fn content_length(&self) -> Result<u64, RequestError> {
+        match self.header("Content-Length") {
+            None => Ok(0),
+            Some(len) => len
+                .parse()
+                .map_err(|_| RequestError::Malformed("invalid Content-Length")),
+        }
+    }
+
+    /// Read the body, which follows the head in `reader`, framed by `Content-Length` or the
+    /// chunked transfer encoding. Fails without reading the rest of it once it is longer than
+    /// `limit` bytes.
+    ///
+    /// A request framed ambiguously, e.g. with both `Content-Length` and `Transfer-Encoding`, is
+    /// rejected: a proxy in front of the server may frame it differently, and smuggle a request
+    /// in its body.
+    pub fn read_body<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        limit: u64,
+    ) -> Result<(), RequestError> {
+        self.body.clear();
+        let count = |name: &str| {
+            self.headers
+                .iter()
+                .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+                .count()
+        };
+        match (count("Content-Length"), count("Transfer-Encoding")) {
+            (0 | 1, 0) => {}
+            (0, 1) => {
+                let coding = self.header("Transfer-Encoding").unwrap_or_default();
+                if !coding.eq_ignore_ascii_case("chunked") {
+                    return Err(RequestError::Unsupported("transfer coding"));
+                }
+                return self.read_chunked_body(reader, limit);
+            }
+            _ => return Err(RequestError::Malformed("ambiguous body length")),
+        }
+
+        let len = self.content_length()?;
+        if len > limit {
+            return Err(RequestError::TooLarge);
+        }
+        reader.take(len).read_to_end(&mut self.body)?;
+        if (self.body.len() as u64) < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+
+    /// Read a body in the chunked transfer encoding. The chunk extensions and the trailer are
+    /// ignored.
+    fn read_chunked_body<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        limit: u64,
+    ) -> Result<(), RequestError> {
+        loop {
+            let line = read_line(reader)?;
+            let size = line
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches([' ', '\t']);
+            if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(RequestError::Malformed("invalid chunk size"));
+            }
+            let size = u64::from_str_radix(size, 16).map_err(|_| RequestError::TooLarge)?;
+            if size == 0 {
+                break;
+            }
+            if size > limit - self.body.len() as u64 {
+                return Err(RequestError::TooLarge);
+            }
+            let start = self.body.len();
+            reader.take(size).read_to_end(&mut self.body)?;
+            if ((self.body.len() - start) as u64) < size {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if !read_line(reader)?.is_empty() {
+                return Err(RequestError::Malformed("chunk is not terminated by CRLF"));
+            }
+        }
+        for _ in 0..=MAX_HEADERS {
+            if read_line(reader)?.is_empty() {
+                return Ok(());
+            }
+        }
+        Err(RequestError::TooLarge)
+    }
+
+    /// Value of the path parameter named `name`. See `Router`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read(bytes: &[u8]) -> Result<Option<Request>, RequestError> {
+        Request::read_head(&mut &*bytes)
+    }
+
+    fn malformed(bytes: &[u8]) -> bool {
+        matches!(read(bytes), Err(RequestError::Malformed(_)))
+    }
+
+    #[test]
+    fn read_head() {
+        let mut reader: &[u8] =
+            b"GET /a/b?x=1%202&y HTTP/1.1\r\nHost: localhost\r\nX-Test:  v 1 \t\r\n\r\nbody";
+        let request = Request::read_head(&mut reader).unwrap().unwrap();
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/a/b");
+        assert_eq!(request.query.as_deref(), Some("x=1%202&y"));
+        assert_eq!(request.query_param("x"), Some("1 2"));
+        assert_eq!(request.query_param("y"), Some(""));
+        assert_eq!(request.version, "1.1");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("X-TEST"), Some("v 1"));
+        // The body is left unread.
+        assert_eq!(reader, b"body");
+        assert!(read(b"").unwrap().is_none());
+        assert_eq!(
+            read(b"PATCH / HTTP/1.0\r\n\r\n").unwrap().unwrap().method,
+            Method::Other("PATCH".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_request_line() {
+        assert!(malformed(b"GET /\r\n\r\n"));
+        assert!(malformed(b"GET / HTTP/1.1 extra\r\n\r\n"));
+        assert!(malformed(b"GET  / HTTP/1.1\r\n\r\n"));
+        assert!(malformed(b"get / HTTP/1.1\r\n\r\n"));
+        assert!(malformed(b" / HTTP/1.1\r\n\r\n"));
+        assert!(malformed(b"GET a HTTP/1.1\r\n\r\n"));
+        assert!(malformed(b"GET / HTTX/1.1\r\n\r\n"));
+        assert!(malformed(b"GET / HTTP/1.1\n\n"));
+        assert!(malformed(b"GET / HTTP/1.1"));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert!(malformed(b"GET / HTTP/1.1\r\nHost localhost\r\n\r\n"));
+        assert!(malformed(b"GET / HTTP/1.1\r\n: value\r\n\r\n"));
+        assert!(malformed(b"GET / HTTP/1.1\r\nBad Name: value\r\n\r\n"));
+        assert!(malformed(b"GET / HTTP/1.1\r\nHost: localhost\r\n"));
+    }
+
+    #[test]
+    fn too_large_head() {
+        let too_large = |bytes: &[u8]| matches!(read(bytes), Err(RequestError::TooLarge));
+        let long = "a".repeat(MAX_LINE_LENGTH as usize);
+        assert!(read(format!("GET /{} HTTP/1.1\r\n\r\n", &long[14..]).as_bytes()).is_ok());
+        assert!(too_large(
+            format!("GET /{} HTTP/1.1\r\n\r\n", &long[13..]).as_bytes()
+        ));
+        assert!(too_large(
+            format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", long).as_bytes()
+        ));
+
+        let headers = "X: y\r\n".repeat(MAX_HEADERS);
+        assert!(read(format!("GET / HTTP/1.1\r\n{}\r\n", headers).as_bytes()).is_ok());
+        assert!(too_large(
+            format!("GET / HTTP/1.1\r\n{}X: y\r\n\r\n", headers).as_bytes()
+        ));
+    }
+
+    #[test]
+    fn read_body() {
+        let mut reader: &[u8] = b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcdef";
+        let mut request = Request::read_head(&mut reader).unwrap().unwrap();
+        request.read_body(&mut reader, 3).unwrap();
+        assert_eq!(request.body, b"abc");
+        assert_eq!(reader, b"def");
+        assert!(matches!(
+            request.read_body(&mut &b"ab"[..], 3),
+            Err(RequestError::Io(_))
+        ));
+        assert!(matches!(
+            request.read_body(&mut &b"abc"[..], 2),
+            Err(RequestError::TooLarge)
+        ));
+        request.headers[0].1 = "three".to_string();
+        assert!(matches!(
+            request.read_body(&mut &b"abc"[..], 3),
+            Err(RequestError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn read_chunked_body() {
+        let mut reader: &[u8] = b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n3\r\nabc\r\nA;x=y\r\n0123456789\r\n0\r\nX: y\r\n\r\nnext";
+        let mut request = Request::read_head(&mut reader).unwrap().unwrap();
+        request.read_body(&mut reader, 13).unwrap();
+        assert_eq!(request.body, b"abc0123456789");
+        // The chunks are not parsed as the next request.
+        assert_eq!(reader, b"next");
+
+        let body = |request: &mut Request, mut bytes: &[u8]| request.read_body(&mut bytes, 3);
+        assert!(matches!(
+            body(&mut request, b"4\r\nabcd\r\n0\r\n\r\n"),
+            Err(RequestError::TooLarge)
+        ));
+        assert!(matches!(
+            body(&mut request, b"2\r\nab\r\n2\r\ncd\r\n0\r\n\r\n"),
+            Err(RequestError::TooLarge)
+        ));
+        assert!(matches!(
+            body(&mut request, b"+3\r\nabc\r\n0\r\n\r\n"),
+            Err(RequestError::Malformed(_))
+        ));
+        assert!(matches!(
+            body(&mut request, b"3\r\nabcd\r\n0\r\n\r\n"),
+            Err(RequestError::Malformed(_))
+        ));
+        assert!(matches!(
+            body(&mut request, b"3\r\nab"),
+            Err(RequestError::Io(_))
+        ));
+        request.headers[0].1 = "gzip".to_string();
+        assert!(matches!(
+            body(&mut request, b""),
+            Err(RequestError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn ambiguous_body_length() {
+        let ambiguous = |bytes: &[u8]| {
+            let mut reader = bytes;
+            let mut request = Request::read_head(&mut reader).unwrap().unwrap();
+            matches!(
+                request.read_body(&mut reader, 1 << 10),
+                Err(RequestError::Malformed(_))
+            )
+        };
+        assert!(ambiguous(
+            b"POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"
+        ));
+        assert!(ambiguous(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"));
+        assert!(ambiguous(
+            b"POST / HTTP/1.1\r\nContent-Length: 0\r\nContent-Length: 5\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn keep_alive() {
+        let keep_alive = |bytes: &[u8]| read(bytes).unwrap().unwrap().keep_alive();
+        assert!(keep_alive(b"GET / HTTP/1.1\r\n\r\n"));
+        assert!(!keep_alive(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n"));
+        assert!(!keep_alive(b"GET / HTTP/1.0\r\n\r\n"));
+        assert!(keep_alive(
+            b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n"
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+type ChunkIter = Box<dyn Iterator<Item = Vec<u8>> + Send>;
+
+/// Body of a response.
+#[derive(Clone)]
+enum Body {
+    Bytes(Arc<[u8]>),
+    /// Streamed from the file when the response is written.
+    File(PathBuf),
+    /// Written with `Transfer-Encoding: chunked` as the chunks are produced. Taken by the first
+    /// write of the response.
+    Stream(Arc<Mutex<Option<ChunkIter>>>),
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Body::File(path) => write!(f, "File({:?})", path),
+            Body::Stream(_) => write!(f, "Stream"),
+        }
+    }
+}
+
+/// HTTP response, built by the handlers and written by the connection.
+#[derive(Debug, Clone)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// Writes each `write` as a chunk of the chunked transfer encoding. `finish` writes the last
+/// chunk.
+struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty chunk would end the body.
+        if !buf.is_empty() {
+            write!(self.inner, "{:x}\r\n", buf.len())?;
+            self.inner.write_all(buf)?;
+            self.inner.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Body of a response being written.
+enum Payload<'a> {
+    Bytes(&'a [u8]),
+    File(File, u64),
+    Chunks(ChunkIter),
+}
+
+impl Response {
+    /// Empty `text/plain` response with the given status code.
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+            body: Body::Bytes(Arc::new([])),
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn ok() -> Response {
+        Response::new(200)
+    }
+
+    pub fn not_found() -> Response {
+        Response::new(404).text("Not found.")
+    }
+
+    /// Set the header named `name`, replacing the existing one.
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Response {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn content_type(self, content_type: &str) -> Response {
+        self.header("Content-Type", content_type)
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = Body::Bytes(body.into().into());
+        self
+    }
+
+    pub fn text(self, text: impl Into<String>) -> Response {
+        self.body(text.into())
+    }
+
+    /// Respond with the content of the file at `path`, without buffering it.
+    pub fn file(mut self, path: PathBuf) -> Response {
+        self.body = Body::File(path);
+        self
+    }
+
+    /// Respond with the chunks produced by `chunks`, sending each chunk as soon as it is produced
+    /// without buffering the whole body. The chunks are produced only once and shared by the
+    /// clones of the response, so use `buffered` to write the response more than once.
+    pub fn stream<I>(mut self, chunks: I) -> Response
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        self.body = Body::Stream(Arc::new(Mutex::new(Some(Box::new(chunks.into_iter())))));
+        self
+    }
+
+    /// Collect the chunks of a streamed response, so that it can be written more than once.
+    pub fn buffered(mut self) -> Response {
+        if let Body::Stream(chunks) = &self.body {
+            let chunks = chunks.lock().unwrap().take();
+            self.body = Body::Bytes(
+                chunks
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .into(),
+            );
+        }
+        self
+    }
+
+    /// Write the response to `stream`. `Cache-Control` defaults to `no-store`.
+    pub fn write_to(&self, stream: &mut impl Write) -> io::Result<()> {
+        let payload = match &self.body {
+            Body::Bytes(bytes) => Payload::Bytes(bytes),
+            Body::File(path) => {
+                let file = File::open(path)?;
+                let len = file.metadata()?.len();
+                Payload::File(file, len)
+            }
+            Body::Stream(chunks) => {
+                let chunks = chunks.lock().unwrap().take();
+                Payload::Chunks(chunks.unwrap_or_else(|| Box::new(std::iter::empty())))
+            }
+        };
+
+        let mut out = BufWriter::new(stream);
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nServer: Awsl\r\n",
+            self.status,
+            reason(self.status)
+        )?;
+        match &payload {
+            Payload::Bytes(bytes) => write!(out, "Content-Length: {}\r\n", bytes.len())?,
+            Payload::File(_, len) => write!(out, "Content-Length: {}\r\n", len)?,
+            Payload::Chunks(_) => write!(out, "Transfer-Encoding: chunked\r\n")?,
+        }
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("Cache-Control"))
+        {
+            write!(out, "Cache-Control: no-store\r\n")?;
+        }
+        for (name, value) in &self.headers {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        write!(out, "\r\n")?;
+
+        match payload {
+            Payload::Bytes(bytes) => out.write_all(bytes)?,
+            // The file may grow while being written.
+            Payload::File(file, len) => {
+                io::copy(&mut file.take(len), &mut out)?;
+            }
+            Payload::Chunks(chunks) => {
+                let mut chunked = ChunkedWriter { inner: &mut out };
+                for chunk in chunks {
+                    chunked.write_all(&chunk)?;
+                    // Send the chunk right away.
+                    chunked.flush()?;
+                }
+                chunked.finish()?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn written(response: &Response) -> String {
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn content_length() {
+        let response = Response::ok()
+            .text("hello")
+            .header("cache-control", "max-age=1");
+        let written = written(&response);
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(written.contains("\r\nContent-Length: 5\r\n"));
+        assert!(written.contains("\r\ncache-control: max-age=1\r\n"));
+        assert!(!written.contains("no-store"));
+        assert!(written.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn chunked_framing() {
+        let chunks = vec![b"hello".to_vec(), Vec::new(), b", chunked world!".to_vec()];
+        let response = Response::ok().stream(chunks.clone());
+        let written_once = written(&response);
+        assert!(written_once.contains("\r\nTransfer-Encoding: chunked\r\n"));
+        assert!(!written_once.contains("Content-Length"));
+        // The empty chunk is skipped, as it would end the body.
+        assert!(written_once.ends_with("\r\n\r\n5\r\nhello\r\n10\r\n, chunked world!\r\n0\r\n\r\n"));
+        // The chunks are produced only once.
+        assert!(written(&response).ends_with("\r\n\r\n0\r\n\r\n"));
+
+        let buffered = Response::ok().stream(chunks).buffered();
+        assert!(written(&buffered).contains("\r\nContent-Length: 21\r\n"));
+        assert_eq!(written(&buffered), written(&buffered));
+    }
+}
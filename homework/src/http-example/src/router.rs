@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::request::{Method, Request};
+use crate::response::Response;
+
+/// Handler of the requests matching a route.
+pub type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// Segment of a route pattern.
+enum Segment {
+    /// Matches the segment itself.
+    Literal(String),
+    /// `:name` matches any segment, given to the handler as the parameter `name`.
+    Param(String),
+    /// `*name` matches the rest of the path, given to the handler as the parameter `name`. Must be
+    /// the last segment.
+    Rest(String),
+}
+
+struct Route {
+    method: Method,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+impl Route {
+    /// Parameters of the route if it matches `path`.
+    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+        let mut path_segments = segments(path);
+        for segment in &self.pattern {
+            match segment {
+                Segment::Literal(literal) => {
+                    if path_segments.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), path_segments.next()?.to_string());
+                }
+                Segment::Rest(name) => {
+                    params.insert(name.clone(), path_segments.collect::<Vec<_>>().join("/"));
+                    return Some(params);
+                }
+            }
+        }
+        if path_segments.next().is_some() {
+            return None;
+        }
+        Some(params)
+    }
+}
+
+/// Dispatches the requests to the handlers of the first matching routes, in the order of
+/// registration. Responds with 405 if only the method doesn't match, and with 404 if nothing
+/// matches.
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router { routes: Vec::new() }
+    }
+
+    /// Register a route for `method` and the path `pattern`, e.g. `/echo/:name` or `/files/*path`.
+    pub fn route<F>(&mut self, method: Method, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let pattern = segments(pattern)
+            .map(|s| {
+                if let Some(name) = s.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = s.strip_prefix('*') {
+                    Segment::Rest(name.to_string())
+                } else {
+                    Segment::Literal(s.to_string())
+                }
+            })
+            .collect();
+        self.routes.push(Route {
+            method,
+            pattern,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// Respond to `request` with the matching handler. Sets the parameters of `request`.
+    pub fn handle(&self, request: &mut Request) -> Response {
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            let Some(params) = route.matches(&request.path) else {
+                continue;
+            };
+            if route.method != request.method {
+                let method = route.method.to_string();
+                if !allowed.contains(&method) {
+                    allowed.push(method);
+                }
+                continue;
+            }
+            request.params = params;
+            return (route.handler)(request);
+        }
+        if allowed.is_empty() {
+            return Response::not_found();
+        }
+        Response::new(405)
+            .header("Allow", allowed.join(", "))
+            .text("Method not allowed.")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> Request {
+        let head = format!("{} {} HTTP/1.1\r\n\r\n", method, path);
+        Request::read_head(&mut head.as_bytes()).unwrap().unwrap()
+    }
+
+    /// The response as written to the connection.
+    fn written(response: Response) -> String {
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn params() {
+        let mut router = Router::new();
+        router.get("/users/:id/posts/:post", |_| Response::new(201));
+        router.get("/users/:id", |_| Response::ok());
+        let mut req = request("GET", "/users/42/posts/7");
+        assert_eq!(router.handle(&mut req).status(), 201);
+        assert_eq!(req.param("id"), Some("42"));
+        assert_eq!(req.param("post"), Some("7"));
+        let mut req = request("GET", "/users/42/");
+        assert_eq!(router.handle(&mut req).status(), 200);
+        assert_eq!(req.param("id"), Some("42"));
+        assert_eq!(req.param("post"), None);
+        assert_eq!(router.handle(&mut request("GET", "/users")).status(), 404);
+        assert_eq!(
+            router
+                .handle(&mut request("GET", "/users/42/posts"))
+                .status(),
+            404
+        );
+    }
+
+    #[test]
+    fn wildcard() {
+        let mut router = Router::new();
+        router.get("/static/index", |_| Response::new(201));
+        router.get("/static/*path", |_| Response::ok());
+        let mut req = request("GET", "/static/a/b/c.txt");
+        assert_eq!(router.handle(&mut req).status(), 200);
+        assert_eq!(req.param("path"), Some("a/b/c.txt"));
+        let mut req = request("GET", "/static");
+        assert_eq!(router.handle(&mut req).status(), 200);
+        assert_eq!(req.param("path"), Some(""));
+        // The first matching route wins.
+        assert_eq!(
+            router.handle(&mut request("GET", "/static/index")).status(),
+            201
+        );
+        assert_eq!(router.handle(&mut request("GET", "/other")).status(), 404);
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let mut router = Router::new();
+        router.post("/submit", |_| Response::ok());
+        router.route(Method::Other("PUT".to_string()), "/submit", |_| {
+            Response::ok()
+        });
+        router.post("/*path", |_| Response::ok());
+        let response = router.handle(&mut request("GET", "/submit"));
+        assert_eq!(response.status(), 405);
+        assert!(written(response).contains("\r\nAllow: POST, PUT\r\n"));
+        assert_eq!(router.handle(&mut request("PUT", "/submit")).status(), 200);
+        assert_eq!(router.handle(&mut request("POST", "/submit")).status(), 200);
+    }
+}
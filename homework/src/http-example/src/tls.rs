@@ -0,0 +1,47 @@
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use crate::deadline::Stream;
+
+/// TLS connection to a client. The handshake is done by the first read or write.
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+impl Stream for TlsStream {
+    fn socket(&self) -> &TcpStream {
+        &self.sock
+    }
+
+    fn shutdown(&mut self) {
+        self.conn.send_close_notify();
+        self.flush().ok();
+    }
+}
+
+/// Load the certificate chain in `cert` and the private key in `key`, in PEM.
+pub fn server_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Cannot read the certificates in {:?}: {}", cert, e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("Cannot read the private key in {:?}: {}", key, e))?;
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+    Ok(Arc::new(config))
+}
+
+/// Start a TLS session on an accepted connection.
+pub fn accept(config: &Arc<ServerConfig>, stream: TcpStream) -> Result<TlsStream, rustls::Error> {
+    Ok(StreamOwned::new(
+        ServerConnection::new(Arc::clone(config))?,
+        stream,
+    ))
+}
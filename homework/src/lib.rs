@@ -15,6 +15,8 @@ pub mod hazard_pointer;
 pub mod hello_server;
 mod linked_list;
 mod list_set;
+pub mod pool;
+pub mod reclaim;
 
 pub mod test;
 
@@ -22,6 +24,12 @@ pub use adt::{ConcurrentMap, ConcurrentSet};
 pub use arc::Arc;
 pub use boc::CownPtr;
 pub use elim_stack::ElimStack;
-pub use hash_table::{GrowableArray, SplitOrderedList};
+pub use hash_table::{
+    GrowableArray, GrowableArrayIter, SplitOrderedHashMap, SplitOrderedList, SplitOrderedListIter,
+    StripedHashMap,
+};
 pub use linked_list::LinkedList;
-pub use list_set::{FineGrainedListSet, OptimisticFineGrainedListSet};
+pub use list_set::{
+    ConcurrentSortedMap, FineGrainedListSet, FineGrainedListSetCursor,
+    OptimisticFineGrainedListSet, PoisonPolicy, SnapshotError,
+};
@@ -1,6 +1,8 @@
 use std::cmp::Ordering::*;
-use std::ops::{Deref, DerefMut};
-use std::sync::{Mutex, MutexGuard};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use std::{mem, ptr};
 
 use crate::ConcurrentSet;
@@ -15,6 +17,23 @@ struct Node<T> {
 #[derive(Debug)]
 pub struct FineGrainedListSet<T> {
     head: Mutex<*mut Node<T>>,
+    /// Number of nodes, updated after each successful insert and remove.
+    len: AtomicUsize,
+    policy: PoisonPolicy,
+}
+
+/// What the operations of `FineGrainedListSet` do with a lock poisoned by a panic, e.g. of a
+/// comparison of `T` while the lock is held.
+///
+/// Each update of the list is a single store made after the comparisons, so a panic does not
+/// leave the list inconsistent, and it is safe to recover.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PoisonPolicy {
+    /// Panics, as with `Mutex::lock().unwrap()`.
+    #[default]
+    Panic,
+    /// Ignores the poison and acquires the lock.
+    Recover,
 }
 
 unsafe impl<T: Send> Send for FineGrainedListSet<T> {}
@@ -30,8 +49,19 @@ unsafe impl<T: Send> Sync for FineGrainedListSet<T> {}
 ///
 /// If `cursor` is currently at node 2, then `cursor.0` should be the `MutexGuard` obtained from the
 /// `next` of node 1. In particular, `cursor.0.as_ref().unwrap()` creates a shared reference to node
-/// 2.
-struct Cursor<'l, T>(MutexGuard<'l, *mut Node<T>>);
+/// 2. `cursor.1` is the data of node 1, or `None` if the cursor is at the head. Node 1 is not
+/// removed while `cursor.0` is held, as removing it locks the `next` of its previous node and then
+/// its own `next`. `cursor.2` is the list, for its `len` and `PoisonPolicy`.
+///
+/// The cursor only moves forward, holding the lock at its position. Hence the keys sought in
+/// ascending order on one cursor are found in a single traversal, e.g. when inserting a sorted
+/// batch of keys.
+#[derive(Debug)]
+pub struct Cursor<'l, T>(
+    MutexGuard<'l, *mut Node<T>>,
+    Option<&'l T>,
+    &'l FineGrainedListSet<T>,
+);
 
 impl<T> Node<T> {
     fn new(data: T, next: *mut Self) -> *mut Self {
@@ -42,10 +72,43 @@ impl<T> Node<T> {
     }
 }
 
-impl<T: Ord> Cursor<'_, T> {
-    /// Moves the cursor to the position of key in the sorted list.
-    /// Returns whether the value was found.
-    fn find(&mut self, key: &T) -> bool {
+impl<'l, T> Cursor<'l, T> {
+    /// Returns the data of the node at the cursor, or `None` if the cursor is at the end.
+    pub fn current(&self) -> Option<&T> {
+        unsafe { self.0.as_ref() }.map(|node| &node.data)
+    }
+
+    /// Removes the node at the cursor, and leaves the cursor at the next node. Returns the removed
+    /// value, or `None` if the cursor is at the end.
+    pub fn take_current(&mut self) -> Option<T> {
+        if self.0.is_null() {
+            return None;
+        }
+        let node = *self.0;
+        // Lock `next` through a shared reference: another cursor may still hold its guard and the
+        // data of the node, until it moves on.
+        let next = *self.2.lock(unsafe { &(*node).next });
+        *self.0 = next;
+        // SAFETY: the node is unlinked while we hold the lock of the previous `next`, after its own
+        // `next` is released by the other cursors, so no other thread can reach it afterwards.
+        let node = unsafe { Box::from_raw(node) };
+        let _ = self.2.len.fetch_sub(1, Relaxed);
+        Some(node.data)
+    }
+}
+
+impl<'l, T: Ord> Cursor<'l, T> {
+    /// Moves the cursor forward to the position of key in the sorted list, without going back to
+    /// the head. Returns whether the value was found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is not greater than the data of the node before the cursor, as the cursor
+    /// has passed the position of `key`.
+    pub fn seek_from_current(&mut self, key: &T) -> bool {
+        if let Some(prev) = self.1 {
+            assert!(prev < key, "keys should be sought in ascending order");
+        }
         while !self.0.is_null() {
             unsafe {
                 let node = self.0.as_ref().unwrap();
@@ -56,77 +119,234 @@ impl<T: Ord> Cursor<'_, T> {
                     return false;
                 }
 
-                self.0 = node.next.lock().unwrap();
+                self.0 = self.2.lock(&node.next);
+                self.1 = Some(&node.data);
             }
         }
         false
     }
+
+    /// Inserts `key` at its position at or after the cursor, and leaves the cursor at the new
+    /// node. Returns whether the value was newly inserted. See `seek_from_current`.
+    pub fn insert(&mut self, key: T) -> bool {
+        if self.seek_from_current(&key) {
+            return false;
+        }
+        *self.0 = Node::new(key, *self.0);
+        let _ = self.2.len.fetch_add(1, Relaxed);
+        true
+    }
+
+    /// Links the sorted values of `run` at the cursor, all of which should be between the data of
+    /// the previous node and that of the current node, with a single store. The cursor stays at
+    /// the current node.
+    fn splice(&mut self, run: Vec<T>) {
+        let len = run.len();
+        let mut chain = *self.0;
+        let mut last: *mut Node<T> = ptr::null_mut();
+        for value in run.into_iter().rev() {
+            chain = Node::new(value, chain);
+            if last.is_null() {
+                last = chain;
+            }
+        }
+        if last.is_null() {
+            return;
+        }
+
+        // SAFETY: the new nodes are not published yet, so no other thread can remove them, and
+        // locking `next` of the last one is uncontended.
+        let last = unsafe { &*last };
+        let next = self.2.lock(&last.next);
+        *self.0 = chain;
+        self.0 = next;
+        self.1 = Some(&last.data);
+        let _ = self.2.len.fetch_add(len, Relaxed);
+    }
+
+    /// Removes `key` at its position at or after the cursor, and leaves the cursor at the next
+    /// node. Returns whether the value was present. See `seek_from_current`.
+    pub fn remove(&mut self, key: &T) -> bool {
+        self.take(key).is_some()
+    }
+
+    /// Same as `remove`, but returns the removed value.
+    pub fn take(&mut self, key: &T) -> Option<T> {
+        if !self.seek_from_current(key) {
+            return None;
+        }
+        self.take_current()
+    }
+}
+
+impl PoisonPolicy {
+    fn lock<U>(self, mutex: &Mutex<U>) -> MutexGuard<'_, U> {
+        match self {
+            Self::Panic => mutex.lock().unwrap(),
+            Self::Recover => mutex.lock().unwrap_or_else(PoisonError::into_inner),
+        }
+    }
 }
 
 impl<T> FineGrainedListSet<T> {
-    /// Creates a new list.
+    /// Creates a new list, which panics on poisoned locks.
     pub fn new() -> Self {
+        Self::with_poison_policy(PoisonPolicy::Panic)
+    }
+
+    /// Creates a new list with the given `PoisonPolicy`.
+    pub fn with_poison_policy(policy: PoisonPolicy) -> Self {
         Self {
             head: Mutex::new(ptr::null_mut()),
+            len: AtomicUsize::new(0),
+            policy,
         }
     }
+
+    fn lock<'l, U>(&self, mutex: &'l Mutex<U>) -> MutexGuard<'l, U> {
+        self.policy.lock(mutex)
+    }
+
+    /// Number of elements, without traversing the list. It may be off while elements are
+    /// inserted or removed concurrently.
+    pub fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    /// Returns `true` if there are no elements. See `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
-impl<T: Ord> FineGrainedListSet<T> {
-    fn find(&self, key: &T) -> (bool, Cursor<'_, T>) {
-        let mut cursor = Cursor(self.head.lock().unwrap());
-        if cursor.find(key) {
-            (true, cursor)
-        } else {
-            (false, cursor)
+impl<T> FineGrainedListSet<T> {
+    /// Inserts the values, which need not be sorted, in a single traversal. Returns the number of
+    /// values newly inserted.
+    ///
+    /// The values are sorted first. Then each run of values between two adjacent nodes, or after
+    /// the last node, is linked as a chain at once, instead of locking each new node as `insert`
+    /// does.
+    pub fn append_sorted(&self, mut values: Vec<T>) -> usize
+    where
+        T: Ord,
+    {
+        values.sort_unstable();
+        values.dedup();
+        let mut inserted = 0;
+        let mut cursor = self.cursor();
+        let mut values = values.into_iter().peekable();
+        while let Some(value) = values.next() {
+            if cursor.seek_from_current(&value) {
+                continue;
+            }
+            let mut run = vec![value];
+            while let Some(value) =
+                values.next_if(|value| cursor.current().is_none_or(|curr| value < curr))
+            {
+                run.push(value);
+            }
+            inserted += run.len();
+            cursor.splice(run);
         }
+        inserted
+    }
+
+    /// Removes `key` and returns it. The value is moved out of the list, as no other thread can
+    /// access a removed node.
+    pub fn take(&self, key: &T) -> Option<T>
+    where
+        T: Ord,
+    {
+        self.cursor().take(key)
+    }
+
+    /// Removes the smallest value and returns it. It only locks the head and the first node.
+    pub fn pop_min(&self) -> Option<T> {
+        self.cursor().take_current()
+    }
+
+    /// Returns a cursor at the head of the list. See `Cursor`.
+    ///
+    /// NOTE: The cursor holds a lock, so other threads cannot pass it until it is dropped.
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor(self.lock(&self.head), None, self)
     }
 }
 
 impl<T: Ord> ConcurrentSet<T> for FineGrainedListSet<T> {
     fn contains(&self, key: &T) -> bool {
-        self.find(key).0
+        self.cursor().seek_from_current(key)
     }
 
     fn insert(&self, key: T) -> bool {
-        let cursor = self.find(&key);
-        if cursor.0 {
-            return false;
-        }
-
-        let mut prev = cursor.1.0;
-        *prev = Node::new(key, *prev);
-        true
+        self.cursor().insert(key)
     }
 
     fn remove(&self, key: &T) -> bool {
-        let cursor = self.find(key);
-        if !cursor.0 {
-            return false;
-        }
+        self.cursor().remove(key)
+    }
 
-        let mut prev = cursor.1.0;
-        let mut node = unsafe { Box::from_raw(*prev) };
-        *prev = *node.next.lock().unwrap();
-        drop(node);
-        true
+    fn pop_min(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        FineGrainedListSet::pop_min(self)
     }
 }
 
 #[derive(Debug)]
 pub struct Iter<'l, T> {
     cursor: MutexGuard<'l, *mut Node<T>>,
+    policy: PoisonPolicy,
 }
 
 impl<T> FineGrainedListSet<T> {
     /// An iterator visiting all elements.
     pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            cursor: self.head.lock().unwrap(),
+            cursor: self.lock(&self.head),
+            policy: self.policy,
         }
     }
 }
 
+impl<T: Ord> FineGrainedListSet<T> {
+    /// An iterator visiting the elements greater than or equal to `key`. The elements before `key`
+    /// are passed with lock coupling, but not visited.
+    pub fn iter_from(&self, key: &T) -> Iter<'_, T> {
+        let mut cursor = self.cursor();
+        let _ = cursor.seek_from_current(key);
+        Iter {
+            cursor: cursor.0,
+            policy: self.policy,
+        }
+    }
+
+    /// An iterator visiting the elements in `range`. See `iter_from`.
+    ///
+    /// NOTE: As with `iter`, the lock at the position of the iterator is held until it is dropped,
+    /// even after the end of `range`.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> impl Iterator<Item = &T> {
+        let iter = match range.start_bound() {
+            Bound::Included(start) => self.iter_from(start),
+            Bound::Excluded(start) => {
+                let mut iter = self.iter_from(start);
+                // `iter_from` stops at `start` if it is present.
+                if unsafe { iter.cursor.as_ref() }.is_some_and(|node| node.data == *start) {
+                    let _ = iter.next();
+                }
+                iter
+            }
+            Bound::Unbounded => self.iter(),
+        };
+        iter.take_while(move |data| match range.end_bound() {
+            Bound::Included(end) => *data <= end,
+            Bound::Excluded(end) => *data < end,
+            Bound::Unbounded => true,
+        })
+    }
+}
+
 impl<'l, T> Iterator for Iter<'l, T> {
     type Item = &'l T;
 
@@ -138,7 +358,7 @@ impl<'l, T> Iterator for Iter<'l, T> {
         } else {
             unsafe {
                 let node = guard.as_ref().unwrap();
-                let next = node.next.lock().unwrap();
+                let next = self.policy.lock(&node.next);
 
                 *guard = next;
 
@@ -150,16 +370,39 @@ impl<'l, T> Iterator for Iter<'l, T> {
 
 impl<T> Drop for FineGrainedListSet<T> {
     fn drop(&mut self) {
-        let mut this = self.head.lock().unwrap();
-        let mut next: MutexGuard<'_, *mut Node<T>>;
-
-        while !this.is_null() {
-            unsafe {
-                let node = this.as_ref().unwrap();
-                *this.deref_mut() = *node.next.lock().unwrap();
-                drop(Box::from_raw(node as *const _ as *mut Node<T>));
-            }
+        // Since we have `&mut self`, no other thread can hold a lock in the list. Hence we take the
+        // pointers out with `get_mut` instead of locking, so that no `MutexGuard` borrows a node
+        // while the node is being freed. A lock poisoned by a panic, e.g. of a misused `Cursor`,
+        // does not leave the list inconsistent, as each update is a single store.
+        let mut curr = *self.head.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let mut len = 0;
+        while !curr.is_null() {
+            // SAFETY: every node is created by `Node::new` and is reachable only from the list.
+            let mut node = unsafe { Box::from_raw(curr) };
+            curr = *node.next.get_mut().unwrap_or_else(PoisonError::into_inner);
+            len += 1;
         }
+        // Verify `len` while walking the whole list anyway.
+        debug_assert_eq!(
+            len,
+            *self.len.get_mut(),
+            "`len` is out of sync with the list"
+        );
+    }
+}
+
+impl<T: Ord> FromIterator<T> for FineGrainedListSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let set = Self::new();
+        let _ = set.append_sorted(iter.into_iter().collect());
+        set
+    }
+}
+
+/// See `append_sorted`.
+impl<T: Ord> Extend<T> for FineGrainedListSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let _ = self.append_sorted(iter.into_iter().collect());
     }
 }
 
@@ -1,5 +1,7 @@
 mod fine_grained;
 mod optimistic_fine_grained;
+mod sorted_map;
 
-pub use fine_grained::FineGrainedListSet;
-pub use optimistic_fine_grained::OptimisticFineGrainedListSet;
+pub use fine_grained::{Cursor as FineGrainedListSetCursor, FineGrainedListSet, PoisonPolicy};
+pub use optimistic_fine_grained::{OptimisticFineGrainedListSet, SnapshotError};
+pub use sorted_map::ConcurrentSortedMap;
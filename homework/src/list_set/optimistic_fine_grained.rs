@@ -1,4 +1,6 @@
+use std::borrow::Borrow;
 use std::cmp::Ordering::*;
+use std::fmt;
 use std::mem::{self, ManuallyDrop};
 use std::ops::Deref;
 use std::sync::atomic::Ordering::*;
@@ -16,13 +18,19 @@ struct Node<T> {
 }
 
 /// Concurrent sorted singly linked list using fine-grained optimistic locking.
+///
+/// The readers do not lock the nodes, so a removed node may still be read by the threads that
+/// reached it before it was unlinked. Hence `remove` and `take_cloned` hand the unlinked node to
+/// the `crossbeam_epoch` collector, and it is dropped once every thread pinned at that time is
+/// unpinned. The nodes still in the list are dropped with the list.
 #[derive(Debug)]
 pub struct OptimisticFineGrainedListSet<T> {
     head: SeqLock<Atomic<Node<T>>>,
 }
 
 unsafe impl<T: Send> Send for OptimisticFineGrainedListSet<T> {}
-unsafe impl<T: Send> Sync for OptimisticFineGrainedListSet<T> {}
+// The readers share `&T` across threads, e.g. by `get` and `iter`.
+unsafe impl<T: Send + Sync> Sync for OptimisticFineGrainedListSet<T> {}
 
 #[derive(Debug)]
 struct Cursor<'g, T> {
@@ -45,11 +53,14 @@ impl<'g, T: Ord> Cursor<'g, T> {
     /// Returns whether the value was found.
     ///
     /// Return `Err(())` if the cursor cannot move.
-    fn find(&mut self, key: &T, guard: &'g Guard) -> Result<bool, ()> {
+    fn find<Q: Ord + ?Sized>(&mut self, key: &Q, guard: &'g Guard) -> Result<bool, ()>
+    where
+        T: Borrow<Q>,
+    {
         while !self.curr.is_null() {
             unsafe {
                 let node = self.curr.as_ref().unwrap();
-                if &node.data == key {
+                if node.data.borrow() == key {
                     if !self.prev.validate() {
                         self.prev.restart();
                         self.curr = self.prev.load(Acquire, guard);
@@ -60,7 +71,7 @@ impl<'g, T: Ord> Cursor<'g, T> {
                     }
                     return Ok(true);
                 }
-                if &node.data > key {
+                if node.data.borrow() > key {
                     if !self.prev.validate() {
                         self.prev.restart();
                         self.curr = self.prev.load(Acquire, guard);
@@ -84,7 +95,11 @@ impl<'g, T: Ord> Cursor<'g, T> {
                 mem::swap(&mut prev, &mut self.prev);
                 fence(Release);
                 self.curr = self.prev.load(Acquire, guard);
-                prev.finish();
+                // The node may have been removed after the validation above, and before we began
+                // reading its `next`, which is then null.
+                if !prev.finish() {
+                    return Err(());
+                }
             }
         }
         if self.prev.validate() {
@@ -94,6 +109,36 @@ impl<'g, T: Ord> Cursor<'g, T> {
     }
 }
 
+impl<'g, T> Cursor<'g, T> {
+    /// Unlinks the node at the cursor, which should not be null, and returns it.
+    ///
+    /// Return `Err(())` if the cursor is invalidated.
+    fn unlink(self, guard: &'g Guard) -> Result<Shared<'g, Node<T>>, ()> {
+        let handle = self.prev.upgrade()?;
+        let curr_handle = unsafe { self.curr.deref().next.write_lock() };
+        let next = curr_handle.swap(Shared::null(), Relaxed, guard);
+        handle.store(next, Release);
+        Ok(self.curr)
+    }
+}
+
+impl<T: Clone> Node<T> {
+    /// Returns a clone of the data of the node that is unlinked by us, and destroys the node with
+    /// `guard`. The optimistic readers that have reached the node may still read its data, so it
+    /// is cloned rather than moved out.
+    ///
+    /// # Safety
+    ///
+    /// The node should be unlinked by us, and every reader should be pinned.
+    unsafe fn reclaim(node: Shared<'_, Self>, guard: &Guard) -> T {
+        unsafe {
+            let value = node.deref().data.clone();
+            guard.defer_destroy(node);
+            value
+        }
+    }
+}
+
 impl<T> OptimisticFineGrainedListSet<T> {
     /// Creates a new list.
     pub fn new() -> Self {
@@ -110,7 +155,43 @@ impl<T> OptimisticFineGrainedListSet<T> {
 }
 
 impl<T: Ord> OptimisticFineGrainedListSet<T> {
-    fn find<'g>(&'g self, key: &T, guard: &'g Guard) -> Result<(bool, Cursor<'g, T>), ()> {
+    /// Unlinks the node of `key`, and returns it if it was present. The caller should destroy the
+    /// node with `guard`, as other threads may still read it.
+    fn unlink<'g, Q: Ord + ?Sized>(
+        &'g self,
+        key: &Q,
+        guard: &'g Guard,
+    ) -> Option<Shared<'g, Node<T>>>
+    where
+        T: Borrow<Q>,
+    {
+        loop {
+            let mut cursor = self.find(key, guard);
+
+            if cursor.is_err() {
+                continue;
+            }
+
+            let cursor = cursor.unwrap();
+            if !cursor.0 {
+                cursor.1.prev.finish();
+                return None;
+            }
+
+            if let Ok(node) = cursor.1.unlink(guard) {
+                return Some(node);
+            }
+        }
+    }
+
+    fn find<'g, Q: Ord + ?Sized>(
+        &'g self,
+        key: &Q,
+        guard: &'g Guard,
+    ) -> Result<(bool, Cursor<'g, T>), ()>
+    where
+        T: Borrow<Q>,
+    {
         let mut cursor = self.head(guard);
         if let Ok(res) = cursor.find(key, guard) {
             if cursor.prev.validate() {
@@ -124,23 +205,43 @@ impl<T: Ord> OptimisticFineGrainedListSet<T> {
             Err(())
         }
     }
-}
 
-impl<T: Ord> ConcurrentSet<T> for OptimisticFineGrainedListSet<T> {
-    fn contains(&self, key: &T) -> bool {
-        loop {
-            let guard = pin();
-            if let Ok(res) = self.find(key, &guard) {
-                if res.1.prev.validate() {
-                    res.1.prev.finish();
-                    return res.0;
+    /// Same as `contains`, but with the given guard, so that a reader making many lookups pins
+    /// the epoch once.
+    pub fn contains_with(&self, key: &T, guard: &Guard) -> bool {
+        self.get(key, guard).is_some()
+    }
+
+    /// Returns `true` iff the set contains all of `keys`, which are looked up in a single
+    /// traversal. The traversal restarts from the head only when the cursor is invalidated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` is not sorted.
+    pub fn contains_all(&self, keys: &[T], guard: &Guard) -> bool {
+        assert!(keys.is_sorted(), "keys should be sorted");
+        let mut cursor = self.head(guard);
+        for key in keys {
+            loop {
+                match cursor.find(key, guard) {
+                    Ok(true) => break,
+                    Ok(false) => {
+                        cursor.prev.finish();
+                        return false;
+                    }
+                    Err(()) => {
+                        cursor.prev.finish();
+                        cursor = self.head(guard);
+                    }
                 }
-                res.1.prev.finish();
             }
         }
+        cursor.prev.finish();
+        true
     }
 
-    fn insert(&self, key: T) -> bool {
+    /// Inserts `key`, or gives it back if it is already present.
+    pub(super) fn try_insert(&self, key: T) -> Result<(), T> {
         let guard = pin();
         loop {
             let mut cursor = self.find(&key, &guard);
@@ -152,7 +253,7 @@ impl<T: Ord> ConcurrentSet<T> for OptimisticFineGrainedListSet<T> {
             let cursor = cursor.unwrap();
             if cursor.0 {
                 cursor.1.prev.finish();
-                return false;
+                return Err(key);
             }
 
             let handle = cursor.1.prev.upgrade();
@@ -163,41 +264,109 @@ impl<T: Ord> ConcurrentSet<T> for OptimisticFineGrainedListSet<T> {
             let handle = handle.unwrap();
             handle.store(Node::new(key, cursor.1.curr), Release);
 
-            return true;
+            return Ok(());
         }
     }
 
-    fn remove(&self, key: &T) -> bool {
-        let guard = pin();
+    /// Returns the element equal to `key`. The reference is valid while `guard` is pinned, even
+    /// if the element is removed.
+    pub(super) fn get<'g, Q: Ord + ?Sized>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g T>
+    where
+        T: Borrow<Q>,
+    {
         loop {
-            let mut cursor = self.find(key, &guard);
-
-            if cursor.is_err() {
+            let Ok((found, cursor)) = self.find(key, guard) else {
                 continue;
+            };
+            let curr = cursor.curr;
+            if cursor.prev.finish() {
+                // SAFETY: the node was in the list while `guard` is pinned, so it is destroyed only
+                // after `guard` is unpinned.
+                return found.then(|| unsafe { &curr.deref().data });
             }
+        }
+    }
 
-            let cursor = cursor.unwrap();
-            if !cursor.0 {
-                cursor.1.prev.finish();
-                return false;
-            }
+    /// Removes the element equal to `key`, and returns it. The element is dropped after `guard`
+    /// is unpinned. See `get`.
+    pub(super) fn delete<'g, Q: Ord + ?Sized>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g T>
+    where
+        T: Borrow<Q>,
+    {
+        let node = self.unlink(key, guard)?;
+        // SAFETY: the node is unlinked by us, and every reader is pinned.
+        unsafe {
+            guard.defer_destroy(node);
+            Some(&node.deref().data)
+        }
+    }
+}
 
-            let handle = cursor.1.prev.upgrade();
-            if handle.is_err() {
-                continue;
-            }
+impl<T: Ord + Clone> OptimisticFineGrainedListSet<T> {
+    /// Removes `key` and returns a clone of it. The removed element itself cannot be moved out, as
+    /// the readers that have reached its node may still read it.
+    pub fn take_cloned(&self, key: &T) -> Option<T> {
+        let guard = pin();
+        let node = self.unlink(key, &guard)?;
+        // SAFETY: the node is unlinked by us, and every reader is pinned.
+        Some(unsafe { Node::reclaim(node, &guard) })
+    }
+}
 
-            let curr_handle = unsafe { cursor.1.curr.deref().next.write_lock() };
+impl<T: Ord> ConcurrentSet<T> for OptimisticFineGrainedListSet<T> {
+    fn contains(&self, key: &T) -> bool {
+        self.contains_with(key, &pin())
+    }
 
-            let handle = handle.unwrap();
-            let next = curr_handle.swap(Shared::null(), Relaxed, &guard);
-            handle.store(next, Release);
+    fn insert(&self, key: T) -> bool {
+        self.try_insert(key).is_ok()
+    }
+
+    fn remove(&self, key: &T) -> bool {
+        self.delete(key, &pin()).is_some()
+    }
 
-            return true;
+    fn pop_min(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let guard = pin();
+        loop {
+            let cursor = self.head(&guard);
+            if cursor.curr.is_null() {
+                if cursor.prev.finish() {
+                    return None;
+                }
+                continue;
+            }
+            if let Ok(node) = cursor.unlink(&guard) {
+                // SAFETY: the node is unlinked by us, and every reader is pinned.
+                return Some(unsafe { Node::reclaim(node, &guard) });
+            }
         }
     }
 }
 
+/// Error of [`OptimisticFineGrainedListSet::snapshot`] when every pass over the list was
+/// invalidated by concurrent writers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotError {
+    /// Number of passes that were made.
+    pub attempts: usize,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "list was modified during each of {} snapshot attempts",
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
 #[derive(Debug)]
 pub struct Iter<'g, T> {
     // Can be dropped without validation, because the only way to use cursor.curr is next().
@@ -214,6 +383,24 @@ impl<T> OptimisticFineGrainedListSet<T> {
             guard,
         }
     }
+
+    /// Maximum number of passes made by `snapshot`.
+    const SNAPSHOT_ATTEMPTS: usize = 64;
+
+    /// Returns all elements in ascending order. The iteration is restarted from the head whenever
+    /// validation fails, until a pass completes without interference.
+    ///
+    /// Returns `Err` if the writers keep invalidating the passes.
+    pub fn snapshot<'g>(&'g self, guard: &'g Guard) -> Result<Vec<&'g T>, SnapshotError> {
+        for _ in 0..Self::SNAPSHOT_ATTEMPTS {
+            if let Ok(values) = self.iter(guard).collect() {
+                return Ok(values);
+            }
+        }
+        Err(SnapshotError {
+            attempts: Self::SNAPSHOT_ATTEMPTS,
+        })
+    }
 }
 
 impl<'g, T> Iterator for Iter<'g, T> {
@@ -252,14 +439,11 @@ impl<'g, T> Iterator for Iter<'g, T> {
 
 impl<T> Drop for OptimisticFineGrainedListSet<T> {
     fn drop(&mut self) {
-        let guard = pin();
-        let mut this: WriteGuard<'_, Atomic<Node<T>>> = self.head.write_lock();
-
-        let mut ptr = this.deref().load(Acquire, &guard);
-        while !ptr.is_null() {
-            drop(this);
-            this = unsafe { ptr.deref().next.write_lock() };
-            ptr = this.deref().load(Acquire, &guard);
+        let mut o_curr = mem::take(self.head.get_mut());
+        // SAFETY: since we have `&mut self`, no other thread can access the nodes. Hence we have
+        // sole ownership of them.
+        while let Some(curr) = unsafe { o_curr.try_into_owned() }.map(Owned::into_box) {
+            o_curr = curr.next.into_inner();
         }
     }
 }
@@ -0,0 +1,129 @@
+//! Sorted map on the optimistic list set.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+use crossbeam_epoch::Guard;
+
+use super::OptimisticFineGrainedListSet;
+use crate::ConcurrentMap;
+
+/// Key-value pair of `ConcurrentSortedMap`, ordered by the key only.
+#[derive(Debug)]
+struct Entry<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K, V> Borrow<K> for Entry<K, V> {
+    fn borrow(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K: PartialEq, V> PartialEq for Entry<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Eq, V> Eq for Entry<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Entry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Entry<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Concurrent sorted map, which is an `OptimisticFineGrainedListSet` of the key-value pairs
+/// ordered by the key.
+///
+/// The values are read without locking, and a removed value is dropped once the guards pinned at
+/// that time are unpinned. Hence the references returned by `get` and `remove` are valid while
+/// the guard is pinned.
+#[derive(Debug)]
+pub struct ConcurrentSortedMap<K, V> {
+    entries: OptimisticFineGrainedListSet<Entry<K, V>>,
+    /// Number of entries. Incremented before each insert and decremented back if it fails, and
+    /// decremented after each successful remove, so that it never drops below 0.
+    len: AtomicUsize,
+}
+
+impl<K, V> ConcurrentSortedMap<K, V> {
+    /// Creates a new map.
+    pub fn new() -> Self {
+        Self {
+            entries: OptimisticFineGrainedListSet::new(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Iterates over the keys and values in ascending order of the keys. See
+    /// `OptimisticFineGrainedListSet::iter` for the validation.
+    pub fn iter<'g>(
+        &'g self,
+        guard: &'g Guard,
+    ) -> impl Iterator<Item = Result<(&'g K, &'g V), ()>> {
+        self.entries
+            .iter(guard)
+            .map(|entry| entry.map(|entry| (&entry.key, &entry.value)))
+    }
+}
+
+impl<K: Ord, V> ConcurrentSortedMap<K, V> {
+    /// Returns the value of `key`.
+    pub fn get<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
+        self.entries.get(key, guard).map(|entry| &entry.value)
+    }
+
+    /// Inserts `value` for `key`, or gives it back if `key` is already present.
+    pub fn insert(&self, key: K, value: V) -> Result<(), V> {
+        // Count the entry before it is published, as a concurrent `remove` may uncount it at once.
+        let _ = self.len.fetch_add(1, Relaxed);
+        self.entries
+            .try_insert(Entry { key, value })
+            .map_err(|entry| {
+                let _ = self.len.fetch_sub(1, Relaxed);
+                entry.value
+            })
+    }
+
+    /// Removes `key`, and returns its value.
+    pub fn remove<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
+        let entry = self.entries.delete(key, guard)?;
+        let _ = self.len.fetch_sub(1, Relaxed);
+        Some(&entry.value)
+    }
+}
+
+impl<K: Ord, V> ConcurrentMap<K, V> for ConcurrentSortedMap<K, V> {
+    fn lookup<'a>(&'a self, key: &K, guard: &'a Guard) -> Option<&'a V> {
+        self.get(key, guard)
+    }
+
+    fn insert(&self, key: K, value: V, guard: &Guard) -> Result<(), V> {
+        ConcurrentSortedMap::insert(self, key, value)
+    }
+
+    fn delete<'a>(&'a self, key: &K, guard: &'a Guard) -> Result<&'a V, ()> {
+        self.remove(key, guard).ok_or(())
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+}
+
+impl<K, V> Default for ConcurrentSortedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
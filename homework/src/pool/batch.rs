@@ -0,0 +1,132 @@
+//! Batches of jobs of a `ThreadPool`.
+
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Weak};
+
+use crossbeam_channel::Sender;
+
+use super::queue::ThreadPoolInner;
+use super::scope::{ScopeState, ScopedJob};
+use super::{Job, Priority, ThreadPool, WorkerStats};
+
+/// Handle to wait for a batch of jobs. See `ThreadPool::execute_batch`.
+#[derive(Debug)]
+pub struct BatchHandle {
+    state: Arc<ScopeState>,
+}
+
+impl BatchHandle {
+    /// Returns true if all the jobs of the batch finished or were dropped without being executed.
+    pub fn is_finished(&self) -> bool {
+        *self.state.job_count.lock().unwrap() == 0
+    }
+}
+
+/// The jobs of a batch that are not pushed to the local queue of a worker yet. If dropped without
+/// being pushed, their job count is finished.
+struct PendingBatch {
+    jobs: Vec<Job>,
+    inner: Weak<ThreadPoolInner>,
+    /// Weak like that of `Task`, so that a pending batch doesn't keep the workers alive.
+    job_sender: Weak<Sender<Job>>,
+}
+
+impl PendingBatch {
+    /// Push the jobs to the local queue of the current worker. If the batch is not run from the
+    /// local queue, e.g. by a worker exiting after a panic, push them to the job queue instead even
+    /// if it is full, or run them here if the pool is being dropped.
+    fn push(mut self) {
+        let Some(inner) = self.inner.upgrade() else {
+            return;
+        };
+        // Take the jobs one by one, so that the rest are finished by `drop` on a panic.
+        self.jobs.reverse();
+        while let Some(job) = self.jobs.pop() {
+            let Err(job) = inner.push_local(job) else {
+                continue;
+            };
+            if let Some(job_sender) = self.job_sender.upgrade() {
+                // Never wait for room, as in `Task::wake`: the worker pushing the batch may be the
+                // one that would drain the full queue. The overflow is bounded by the batch.
+                inner.enqueue_overflow(Priority::Normal as usize);
+                job_sender.send(job).unwrap();
+            } else {
+                let _ = inner.run_job(job, &WorkerStats::default());
+            }
+        }
+    }
+}
+
+impl Drop for PendingBatch {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.upgrade() {
+            let n = self.jobs.len();
+            self.jobs.clear();
+            inner.finish_jobs(n);
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Execute a batch of new jobs in the thread pool, like `execute` for each job, but with a
+    /// single send to the job queue and a single update of the job count. The whole batch is taken
+    /// by a worker that pushes the jobs to its local queue, so that the other workers steal them.
+    /// The returned handle can be passed to `join_batch` to wait only for the jobs of the batch.
+    pub fn execute_batch<I, F>(&self, jobs: I) -> BatchHandle
+    where
+        I: IntoIterator<Item = F>,
+        F: FnOnce() + Send + 'static,
+    {
+        let state = Arc::new(ScopeState::default());
+        if self.inner.closed.load(Ordering::Acquire) {
+            return BatchHandle { state };
+        }
+        let mut jobs = jobs
+            .into_iter()
+            .map(|f| {
+                let mut job = ScopedJob {
+                    f: Some(f),
+                    state: Arc::clone(&state),
+                };
+                Job::new(move || (job.f.take().unwrap())())
+            })
+            .collect::<Vec<_>>();
+        if jobs.is_empty() {
+            return BatchHandle { state };
+        }
+        *state.job_count.lock().unwrap() = jobs.len();
+        self.inner.start_jobs(jobs.len());
+
+        // The batch runs its first job itself, so it is counted as that job.
+        let rest = PendingBatch {
+            jobs: jobs.split_off(1),
+            inner: Arc::downgrade(&self.inner),
+            job_sender: Arc::downgrade(self.task_sender()),
+        };
+        let first = jobs.pop().unwrap();
+        let batch = Job::new(move || {
+            rest.push();
+            first.f.call();
+        });
+        if let Err(batch) = self.inner.push_local(batch) {
+            self.inner.enqueue_from_caller(Priority::Normal as usize);
+            self.job_sender(Priority::Normal).send(batch).unwrap();
+            self.scale_up();
+        }
+        BatchHandle { state }
+    }
+
+    /// Block the current thread until all the jobs of the batch finish or are dropped without
+    /// being executed. Unlike `join`, this doesn't wait for the other jobs.
+    ///
+    /// NOTE: If this is called from a job of the pool, the current worker is blocked while waiting
+    /// for the jobs. This may deadlock if all the workers are blocked like that.
+    pub fn join_batch(&self, batch: &BatchHandle) {
+        let job_count = batch.state.job_count.lock().unwrap();
+        let _unused = batch
+            .state
+            .job_count_zero
+            .wait_while(job_count, |job_count| *job_count != 0)
+            .unwrap();
+    }
+}
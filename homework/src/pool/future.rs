@@ -0,0 +1,112 @@
+//! Futures run on a `ThreadPool`.
+
+use core::future::Future;
+use core::pin::Pin;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crossbeam_channel::{Sender, bounded};
+
+use super::queue::ThreadPoolInner;
+use super::{Job, JobHandle, Priority, ThreadPool};
+
+/// A future spawned by `ThreadPool::spawn_future`. Each poll runs as a job of the pool, and waking
+/// the task queues another such job.
+struct Task {
+    /// `None` if the future is finished or panicked.
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    /// Whether a job polling this task is queued and not started yet, so that multiple wakes
+    /// between two polls queue only one job.
+    scheduled: AtomicBool,
+    inner: Weak<ThreadPoolInner>,
+    /// Weak so that pending tasks don't keep the workers alive after the pool is dropped.
+    job_sender: Weak<Sender<Job>>,
+}
+
+impl Task {
+    /// Queue a job polling this task, unless one is already queued or the pool is gone.
+    fn schedule(self: Arc<Self>) {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let (Some(inner), Some(job_sender)) = (self.inner.upgrade(), self.job_sender.upgrade())
+        else {
+            return;
+        };
+        if inner.closed.load(Ordering::Acquire) {
+            return;
+        }
+        inner.start_job();
+        // Waking must never block: a worker blocked on the full queue may be the one that would
+        // drain it. So push the job to the local queue on a worker, and otherwise take room in the
+        // queue even if it is full. The overflow is bounded, as each task queues at most one job.
+        let job = match inner.push_local(Job::new(move || self.poll())) {
+            Ok(()) => return,
+            Err(job) => job,
+        };
+        if !inner.enqueue(Priority::Normal as usize, false) {
+            inner.enqueue_overflow(Priority::Normal as usize);
+        }
+        job_sender.send(job).unwrap();
+    }
+
+    fn poll(self: Arc<Self>) {
+        // Clear the flag before polling, so that a wake during the poll queues another poll.
+        self.scheduled.store(false, Ordering::Release);
+        // Hold the lock while polling, so that such a poll waits for this one.
+        let mut slot = self.future.lock().unwrap();
+        let Some(future) = slot.as_mut() else {
+            return;
+        };
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(&mut cx))) {
+            Ok(Poll::Pending) => {}
+            Ok(Poll::Ready(())) => *slot = None,
+            Err(payload) => {
+                // Never poll a panicked future again.
+                *slot = None;
+                drop(slot);
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+}
+
+impl ThreadPool {
+    /// Run a future to completion on the thread pool. Each poll of the future runs as a job, and
+    /// the future is polled again (as a new job) whenever its waker is woken. The returned handle
+    /// gives the future's output, or `None` if the future panicked.
+    ///
+    /// NOTE: `join` only waits for the polls that are queued or running, not for the futures that
+    /// are waiting to be woken. The futures that are not finished are dropped when the pool is
+    /// dropped or `shutdown`.
+    pub fn spawn_future<F>(&self, future: F) -> JobHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (sender, receiver) = bounded(1);
+        let future = async move {
+            // The handle may have been dropped already.
+            let _ = sender.send(future.await);
+        };
+        let job_sender = self.task_sender();
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            scheduled: AtomicBool::new(false),
+            inner: Arc::downgrade(&self.inner),
+            job_sender: Arc::downgrade(job_sender),
+        });
+        task.schedule();
+        JobHandle { result: receiver }
+    }
+}
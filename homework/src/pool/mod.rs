@@ -0,0 +1,865 @@
+//! Thread pool that joins all thread when dropped.
+//!
+//! This is the pool shared by the hello server (`crate::hello_server`), the BoC runtime
+//! (`crate::boc`), and the HTTP example (`src/http-example`).
+#![deny(unsafe_code)]
+
+mod batch;
+mod future;
+mod queue;
+mod scope;
+mod timer;
+
+use core::fmt;
+// NOTE: Crossbeam channels are MPMC, which means that you don't need to wrap the receiver in
+// Arc<Mutex<..>>. Just clone the receiver and give it to each worker thread.
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock, Weak};
+use std::time::{Duration, Instant};
+use std::{mem, thread};
+
+use chrono::prelude::{DateTime, Local};
+use crossbeam_channel::{Receiver, Sender, TryRecvError, bounded, unbounded};
+use lazy_static::lazy_static;
+
+pub use self::batch::BatchHandle;
+use self::queue::ThreadPoolInner;
+pub use self::scope::Scope;
+pub use self::timer::ScheduleHandle;
+use self::timer::Timer;
+
+/// A closure that can be run from a `Box`.
+trait FnBox: Send {
+    fn call(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send + 'static> FnBox for F {
+    fn call(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+struct Job {
+    f: Box<dyn FnBox>,
+    /// When the job was queued.
+    queued_at: Instant,
+    /// The token of a cancellable job, which races with the workers to take the job from the job
+    /// queue. See `ThreadPool::execute_cancellable`.
+    token: Option<CancellationToken>,
+}
+
+impl Job {
+    fn new<F: FnOnce() + Send + 'static>(f: F) -> Self {
+        Self {
+            f: Box::new(f),
+            queued_at: Instant::now(),
+            token: None,
+        }
+    }
+
+    /// Take the job received from a job queue. Returns false if the job is already taken by
+    /// `CancellationToken::cancel`, which released its room and job count.
+    fn take(&self) -> bool {
+        self.token.as_ref().is_none_or(CancellationToken::take)
+    }
+}
+
+thread_local! {
+    /// The local job queue of the current thread if it is a worker, with the pool it belongs to.
+    static LOCAL_QUEUE: RefCell<Option<(*const ThreadPoolInner, crossbeam_deque::Worker<Job>)>> =
+        const { RefCell::new(None) };
+}
+
+/// What a worker does after a job panicked. In any case, the panic is recorded in the pool (see
+/// `ThreadPool::take_panics`), and the other jobs are not affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// The worker keeps running the next jobs.
+    Ignore,
+    /// The worker thread exits and a new worker thread takes its place.
+    #[default]
+    RestartWorker,
+    /// The whole process is aborted.
+    Abort,
+}
+
+/// Priority of a job. Workers always take the queued job with the highest priority first. See
+/// `ThreadPool::execute_with_priority`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// For latency-sensitive jobs.
+    High,
+    /// The priority of the jobs given to `ThreadPool::execute`.
+    #[default]
+    Normal,
+    /// For bulk jobs.
+    Low,
+}
+
+impl Priority {
+    /// The number of priorities, i.e. the number of job queues of a pool.
+    const COUNT: usize = 3;
+}
+
+/// Configuration of a `ThreadPool` whose number of workers changes with the load. See
+/// `ThreadPool::with_scaling`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scaling {
+    /// The number of workers that are always kept.
+    pub min_workers: usize,
+    /// The maximum number of workers.
+    pub max_workers: usize,
+    /// A new worker is spawned when a job is executed while more than this many jobs are queued.
+    pub queue_threshold: usize,
+    /// A worker exits after being idle for this long, unless there are only `min_workers` workers.
+    pub idle_timeout: Duration,
+}
+
+/// Statistics of a pool. See `ThreadPool::stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The number of jobs waiting in the queues.
+    pub queued_jobs: usize,
+    /// The number of jobs being executed.
+    pub running_jobs: usize,
+    /// The number of jobs that finished, including the ones that panicked.
+    pub completed_jobs: usize,
+    /// The number of jobs that panicked.
+    pub panics: usize,
+    /// The total time that each worker spent executing jobs, with the workers' ids.
+    pub worker_busy_time: Vec<(usize, Duration)>,
+    /// How long the recent jobs waited in the queues before being executed.
+    pub queue_latency: LatencyPercentiles,
+}
+
+/// Percentiles of latencies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    /// The median.
+    pub p50: Duration,
+    /// The 90th percentile.
+    pub p90: Duration,
+    /// The 99th percentile.
+    pub p99: Duration,
+    /// The maximum.
+    pub max: Duration,
+}
+
+impl LatencyPercentiles {
+    /// Computes the percentiles of the latencies, which must be sorted.
+    fn from_sorted(latencies: &[Duration]) -> Self {
+        let Some(&max) = latencies.last() else {
+            return Self::default();
+        };
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        Self {
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max,
+        }
+    }
+}
+
+/// Statistics of a worker, updated by the worker itself.
+#[derive(Debug, Default)]
+struct WorkerStats {
+    /// The total time spent executing jobs.
+    busy_time: Mutex<Duration>,
+    /// How long the recent jobs waited in the queues, at most `WorkerStats::LATENCY_SAMPLES` of
+    /// them.
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl WorkerStats {
+    const LATENCY_SAMPLES: usize = 1024;
+
+    fn record_latency(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == Self::LATENCY_SAMPLES {
+            let _ = latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+}
+
+#[derive(Debug)]
+struct Worker {
+    _id: usize,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Worker {
+    /// When dropped, the thread's `JoinHandle` must be `join`ed.  If the worker panics, then this
+    /// function should panic too.
+    ///
+    /// NOTE: The thread is detached if not `join`ed explicitly.
+    fn drop(&mut self) {
+        self.thread.take().unwrap().join().unwrap();
+    }
+}
+
+/// A function that names the worker threads, given the workers' ids.
+type ThreadNameFn = Arc<dyn Fn(usize) -> String + Send + Sync>;
+
+/// How the worker threads are spawned. See `ThreadPoolBuilder`.
+#[derive(Clone, Default)]
+struct WorkerConfig {
+    thread_name: Option<ThreadNameFn>,
+    stack_size: Option<usize>,
+    /// The worker with id `i` is pinned to the core `core_ids[i % core_ids.len()]`.
+    core_ids: Vec<usize>,
+}
+
+impl fmt::Debug for WorkerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkerConfig")
+            .field("thread_name", &self.thread_name.as_ref().map(|_| ".."))
+            .field("stack_size", &self.stack_size)
+            .field("core_ids", &self.core_ids)
+            .finish()
+    }
+}
+
+/// Information about a panicked job.
+#[derive(Debug)]
+pub struct PanicInfo {
+    time: DateTime<Local>,
+    info: String,
+}
+
+impl PanicInfo {
+    /// The time that the job panicked.
+    pub fn time(&self) -> DateTime<Local> {
+        self.time
+    }
+
+    /// The panic message.
+    pub fn message(&self) -> &str {
+        &self.info
+    }
+}
+
+/// The panics of the jobs of a pool, in the order they are recorded. See `ThreadPool::panic_log`.
+#[derive(Debug, Default)]
+pub struct PanicLog {
+    entries: Mutex<Vec<PanicInfo>>,
+}
+
+impl PanicLog {
+    fn push(&self, info: PanicInfo) {
+        self.entries.lock().unwrap().push(info);
+    }
+
+    /// The number of panics recorded and not drained yet.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns true if there is no panic recorded and not drained yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Take all the panics recorded so far, so that they are not reported again (in particular,
+    /// not when the pool is dropped).
+    pub fn drain(&self) -> Vec<PanicInfo> {
+        mem::take(&mut *self.entries.lock().unwrap())
+    }
+}
+
+lazy_static! {
+    /// The pool returned by `ThreadPool::global`.
+    static ref THREADPOOL: ThreadPool = ThreadPool::new(8);
+}
+
+impl fmt::Display for PanicInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[Panic:] [{}] {}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.info
+        )
+    }
+}
+
+/// Handle to the return value of a job. See `ThreadPool::execute_with_result`.
+#[derive(Debug)]
+pub struct JobHandle<R> {
+    result: Receiver<R>,
+}
+
+impl<R> JobHandle<R> {
+    /// Block the current thread until the job finishes and return its return value. Returns `None`
+    /// if the job panicked or was dropped without being executed (see `ThreadPool::shutdown`).
+    pub fn join(self) -> Option<R> {
+        self.result.recv().ok()
+    }
+
+    /// Return the job's return value if the job has already finished, like `join`. Gives the
+    /// handle back if the job is not finished yet.
+    pub fn try_join(self) -> Result<Option<R>, Self> {
+        match self.result.try_recv() {
+            Ok(r) => Ok(Some(r)),
+            Err(TryRecvError::Disconnected) => Ok(None),
+            Err(TryRecvError::Empty) => Err(self),
+        }
+    }
+}
+
+/// Token to cancel a job. See `ThreadPool::execute_cancellable`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    /// Whether the job is taken from the job queue, by a worker or by `cancel`.
+    taken: AtomicBool,
+    /// The pool whose job queue holds the job. Not set for a token without a job.
+    pool: OnceLock<Weak<ThreadPoolInner>>,
+}
+
+impl CancellationToken {
+    /// Cancel the job. If the job is still queued, it won't be executed, and it releases its room
+    /// in the queue and its job count at once. If it is running, it may stop early by checking
+    /// `is_cancelled`.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+        let Some(inner) = self.state.pool.get().and_then(Weak::upgrade) else {
+            return;
+        };
+        if self.take() {
+            inner.dequeue(Priority::Normal as usize);
+            inner.finish_job();
+        }
+    }
+
+    /// Returns whether the job is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Take the job from the job queue. Returns whether it was not taken yet.
+    fn take(&self) -> bool {
+        !self.state.taken.swap(true, Ordering::AcqRel)
+    }
+}
+
+/// Builder of a `ThreadPool` with custom configurations.
+#[derive(Debug, Clone)]
+pub struct ThreadPoolBuilder {
+    num_threads: usize,
+    queue_cap: Option<usize>,
+    panic_policy: PanicPolicy,
+    scaling: Option<Scaling>,
+    worker_config: WorkerConfig,
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// Create a new builder. By default, the pool has as many threads as the available
+    /// parallelism, unbounded job queues, and `PanicPolicy::default()`.
+    pub fn new() -> Self {
+        Self {
+            num_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_cap: None,
+            panic_policy: PanicPolicy::default(),
+            scaling: None,
+            worker_config: WorkerConfig::default(),
+        }
+    }
+
+    /// Set the number of threads. Ignored if `scaling` is set.
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Bound the job queue for each priority to hold at most `queue_cap` jobs. See
+    /// `ThreadPool::with_capacity`.
+    pub fn queue_capacity(mut self, queue_cap: usize) -> Self {
+        self.queue_cap = Some(queue_cap);
+        self
+    }
+
+    /// Set how the workers handle the panics of the jobs.
+    pub fn panic_policy(mut self, panic_policy: PanicPolicy) -> Self {
+        self.panic_policy = panic_policy;
+        self
+    }
+
+    /// Spawn and retire the threads with the load. See `ThreadPool::with_scaling`.
+    pub fn scaling(mut self, scaling: Scaling) -> Self {
+        self.scaling = Some(scaling);
+        self
+    }
+
+    /// Name the worker threads with the given function of the workers' ids. The ids are unique in
+    /// the pool, and a worker that replaces a retired or panicked one gets a new id.
+    pub fn thread_name<F>(mut self, thread_name: F) -> Self
+    where
+        F: Fn(usize) -> String + Send + Sync + 'static,
+    {
+        self.worker_config.thread_name = Some(Arc::new(thread_name));
+        self
+    }
+
+    /// Set the stack size of the worker threads in bytes.
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.worker_config.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Pin the worker threads to the given CPU cores, in a round-robin manner by the workers'
+    /// ids. Pinning is best effort: it is silently skipped if the OS refuses it.
+    pub fn core_ids(mut self, core_ids: Vec<usize>) -> Self {
+        self.worker_config.core_ids = core_ids;
+        self
+    }
+
+    /// Create the pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of threads (or `min_workers` of `scaling`) is 0, or `min_workers` of
+    /// `scaling` is larger than `max_workers`.
+    pub fn build(self) -> ThreadPool {
+        let size = match self.scaling {
+            Some(scaling) => {
+                assert!(scaling.min_workers <= scaling.max_workers);
+                scaling.min_workers
+            }
+            None => self.num_threads,
+        };
+        assert!(size > 0);
+
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            (0..Priority::COUNT).map(|_| unbounded()).unzip();
+        let (wake_send, wake_recv) = bounded(1);
+        let inner = Arc::new(ThreadPoolInner {
+            _workers: Mutex::new(Vec::with_capacity(size)),
+            job_recvs: receivers.try_into().unwrap(),
+            queued: Mutex::new([0; Priority::COUNT]),
+            queue_not_full: Condvar::new(),
+            queue_cap: self.queue_cap,
+            stealers: RwLock::new(Vec::with_capacity(size)),
+            worker_stats: Mutex::new(Vec::with_capacity(size)),
+            completed_jobs: AtomicUsize::new(0),
+            panic_count: AtomicUsize::new(0),
+            next_worker_id: AtomicUsize::new(0),
+            idle_workers: AtomicUsize::new(0),
+            wake_send,
+            wake_recv,
+            job_count: Mutex::new(0),
+            job_count_zero: Condvar::new(),
+            closed: AtomicBool::new(false),
+            panic_policy: self.panic_policy,
+            worker_config: self.worker_config,
+            scaling: self.scaling,
+            num_workers: AtomicUsize::new(size),
+            panics: PanicLog::default(),
+        });
+
+        for _id in 0..size {
+            ThreadPool::_push_worker(Arc::clone(&inner));
+        }
+
+        ThreadPool {
+            inner,
+            job_senders: Some(senders.try_into().unwrap()),
+            timer: OnceLock::new(),
+            task_sender: OnceLock::new(),
+        }
+    }
+}
+
+/// Thread pool.
+#[derive(Debug)]
+pub struct ThreadPool {
+    inner: Arc<ThreadPoolInner>,
+    /// Senders of the job queues, indexed by `Priority`.
+    job_senders: Option<[Sender<Job>; Priority::COUNT]>,
+    /// Created when a delayed or periodic job is executed for the first time.
+    timer: OnceLock<Timer>,
+    /// The sender shared (weakly) by the tasks of `spawn_future` and the batches of
+    /// `execute_batch`.
+    task_sender: OnceLock<Arc<Sender<Job>>>,
+}
+
+impl ThreadPool {
+    /// Create a new ThreadPool with `size` threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn new(size: usize) -> Self {
+        ThreadPoolBuilder::new().num_threads(size).build()
+    }
+
+    /// Create a builder of a ThreadPool with custom configurations.
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    /// Returns the pool shared by the whole process. It has 8 threads and is created when this
+    /// function is first called.
+    ///
+    /// NOTE: The shared pool is never dropped, so its panics are only reported via `take_panics`.
+    pub fn global() -> &'static Self {
+        &THREADPOOL
+    }
+
+    /// Create a new ThreadPool with `size` threads, whose job queue for each priority holds at most
+    /// `queue_cap` jobs. If the queue is full, `execute` blocks until a worker takes a job from the
+    /// queue, or a queued job is cancelled.
+    ///
+    /// NOTE: The jobs executed from a worker of the pool never block, as the worker may be the one
+    /// that would take a job from the queue. They are queued even if the queue is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn with_capacity(size: usize, queue_cap: usize) -> Self {
+        ThreadPoolBuilder::new()
+            .num_threads(size)
+            .queue_capacity(queue_cap)
+            .build()
+    }
+
+    /// Create a new ThreadPool that starts with `scaling.min_workers` threads and spawns or retires
+    /// threads according to `scaling`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scaling.min_workers` is 0 or larger than `scaling.max_workers`.
+    pub fn with_scaling(scaling: Scaling) -> Self {
+        ThreadPoolBuilder::new().scaling(scaling).build()
+    }
+
+    /// Create a new ThreadPool with `size` threads, whose workers handle the panics of the jobs
+    /// according to `panic_policy`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn with_panic_policy(size: usize, panic_policy: PanicPolicy) -> Self {
+        ThreadPoolBuilder::new()
+            .num_threads(size)
+            .panic_policy(panic_policy)
+            .build()
+    }
+
+    fn _push_worker(inner: Arc<ThreadPoolInner>) {
+        let worker_inner = Arc::clone(&inner);
+        let mut workers = inner._workers.lock().unwrap();
+        // Join the retired workers.
+        workers.retain(|worker| !worker.thread.as_ref().unwrap().is_finished());
+        let _id: usize = inner.next_worker_id.fetch_add(1, Ordering::Relaxed);
+
+        let config = &inner.worker_config;
+        let mut builder = thread::Builder::new();
+        if let Some(thread_name) = &config.thread_name {
+            builder = builder.name(thread_name(_id));
+        }
+        if let Some(stack_size) = config.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let core_id =
+            (!config.core_ids.is_empty()).then(|| config.core_ids[_id % config.core_ids.len()]);
+
+        workers.push(Worker {
+            _id,
+            thread: Some(
+                builder
+                    .spawn(move || {
+                        if let Some(id) = core_id {
+                            let _ = core_affinity::set_for_current(core_affinity::CoreId { id });
+                        }
+                        let local = crossbeam_deque::Worker::new_fifo();
+                        worker_inner
+                            .stealers
+                            .write()
+                            .unwrap()
+                            .push((_id, local.stealer()));
+                        LOCAL_QUEUE.set(Some((Arc::as_ptr(&worker_inner), local)));
+                        let stats = Arc::new(WorkerStats::default());
+                        worker_inner
+                            .worker_stats
+                            .lock()
+                            .unwrap()
+                            .push((_id, Arc::clone(&stats)));
+
+                        while let Some(job) = worker_inner.recv_job() {
+                            if worker_inner.run_job(job, &stats)
+                                && worker_inner.panic_policy == PanicPolicy::RestartWorker
+                            {
+                                ThreadPool::_push_worker(Arc::clone(&worker_inner));
+                                break;
+                            }
+                        }
+
+                        // The jobs executed by a panicked job may be left in the local queue.
+                        let (_, local) = LOCAL_QUEUE.take().unwrap();
+                        while let Some(job) = local.pop() {
+                            let _ = worker_inner.run_job(job, &stats);
+                        }
+                        worker_inner
+                            .worker_stats
+                            .lock()
+                            .unwrap()
+                            .retain(|(id, _)| *id != _id);
+                        worker_inner
+                            .stealers
+                            .write()
+                            .unwrap()
+                            .retain(|(id, _)| *id != _id);
+                    })
+                    .expect("failed to spawn a worker thread"),
+            ),
+        })
+    }
+
+    /// Execute a new job in the thread pool. If the job queue is full, block until there is room
+    /// for the job.
+    ///
+    /// The job is silently dropped if the pool is already `shutdown`.
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.execute_with_priority(f, Priority::Normal);
+    }
+
+    /// Execute a new job with the given priority in the thread pool, like `execute`. The job runs
+    /// after the queued jobs of higher priorities, but before those of lower priorities.
+    ///
+    /// NOTE: If this is called from a worker of the pool with `Priority::Normal`, the job is pushed
+    /// to the worker's local queue, which is not bounded. Idle workers steal jobs from the other
+    /// workers' local queues. With the other priorities, the job is queued even if the queue is
+    /// full. See `with_capacity`.
+    pub fn execute_with_priority<F>(&self, f: F, priority: Priority)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.inner.closed.load(Ordering::Acquire) {
+            return;
+        }
+        self.inner.start_job();
+        let mut job = Job::new(f);
+        if priority == Priority::Normal {
+            match self.inner.push_local(job) {
+                Ok(()) => return,
+                Err(j) => job = j,
+            }
+        }
+        self.inner.enqueue_from_caller(priority as usize);
+        self.job_sender(priority).send(job).unwrap();
+        self.scale_up();
+    }
+
+    fn job_sender(&self, priority: Priority) -> &Sender<Job> {
+        &self.job_senders.as_ref().unwrap()[priority as usize]
+    }
+
+    fn task_sender(&self) -> &Arc<Sender<Job>> {
+        self.task_sender
+            .get_or_init(|| Arc::new(self.job_sender(Priority::Normal).clone()))
+    }
+
+    /// Spawn a new worker if there are too many queued jobs.
+    fn scale_up(&self) {
+        let Some(scaling) = self.inner.scaling else {
+            return;
+        };
+        if self.inner.queue_len() > scaling.queue_threshold
+            && self
+                .inner
+                .num_workers
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    (n < scaling.max_workers).then_some(n + 1)
+                })
+                .is_ok()
+        {
+            Self::_push_worker(Arc::clone(&self.inner));
+        }
+    }
+
+    /// Execute a new job in the thread pool, like `execute`. The returned handle can be used to get
+    /// the job's return value.
+    pub fn execute_with_result<F, R>(&self, f: F) -> JobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = bounded(1);
+        self.execute(move || {
+            // The handle may have been dropped already.
+            let _ = sender.send(f());
+        });
+        JobHandle { result: receiver }
+    }
+
+    /// Execute a new job in the thread pool, like `execute`, that can be cancelled via the returned
+    /// token. The job is given the token so that it can check whether it is cancelled while
+    /// running.
+    ///
+    /// A cancelled job releases its room in the queue and its job count at once, so that `join`
+    /// doesn't wait for it. Unlike `execute`, the job is always pushed to the job queue, even from
+    /// a worker, so that it can be taken from there.
+    ///
+    /// NOTE: The closure of a cancelled job is dropped only when a worker reaches it in the queue.
+    pub fn execute_cancellable<F>(&self, f: F) -> CancellationToken
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let token = CancellationToken::default();
+        if self.inner.closed.load(Ordering::Acquire) {
+            return token;
+        }
+        let _ = token.state.pool.set(Arc::downgrade(&self.inner));
+        let job_token = token.clone();
+        let mut job = Job::new(move || {
+            if !job_token.is_cancelled() {
+                f(&job_token);
+            }
+        });
+        job.token = Some(token.clone());
+        self.inner.start_job();
+        self.inner.enqueue_from_caller(Priority::Normal as usize);
+        self.job_sender(Priority::Normal).send(job).unwrap();
+        self.scale_up();
+        token
+    }
+
+    /// Try to execute a new job in the thread pool without blocking. Gives the job back if the job
+    /// queue is full or the pool is already `shutdown`.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.inner.closed.load(Ordering::Acquire) {
+            return Err(f);
+        }
+        if !self.inner.enqueue(Priority::Normal as usize, false) {
+            return Err(f);
+        }
+        self.inner.start_job();
+        self.job_sender(Priority::Normal).send(Job::new(f)).unwrap();
+        self.scale_up();
+        Ok(())
+    }
+
+    /// Returns the current statistics of the pool.
+    pub fn stats(&self) -> PoolStats {
+        let job_count = *self.inner.job_count.lock().unwrap();
+        let queued_jobs = self.inner.queue_len();
+        let worker_stats = self.inner.worker_stats.lock().unwrap();
+        let mut latencies = worker_stats
+            .iter()
+            .flat_map(|(_, stats)| stats.latencies.lock().unwrap().clone())
+            .collect::<Vec<_>>();
+        latencies.sort_unstable();
+        PoolStats {
+            queued_jobs,
+            // Both are read without synchronization, so the difference may be off.
+            running_jobs: job_count.saturating_sub(queued_jobs),
+            completed_jobs: self.inner.completed_jobs.load(Ordering::Relaxed),
+            panics: self.inner.panic_count.load(Ordering::Relaxed),
+            worker_busy_time: worker_stats
+                .iter()
+                .map(|(id, stats)| (*id, *stats.busy_time.lock().unwrap()))
+                .collect(),
+            queue_latency: LatencyPercentiles::from_sorted(&latencies),
+        }
+    }
+
+    /// Returns the current number of worker threads.
+    pub fn num_workers(&self) -> usize {
+        self.inner.num_workers.load(Ordering::Acquire)
+    }
+
+    /// Block the current thread until all jobs in the pool have been executed.
+    ///
+    /// NOTE: This method has nothing to do with `JoinHandle::join`.
+    pub fn join(&self) {
+        self.inner.wait_empty();
+    }
+
+    /// Block the current thread until all jobs in the pool have been executed, like `join`, or
+    /// until `timeout` elapses. Returns whether all jobs have been executed.
+    pub fn join_timeout(&self, timeout: Duration) -> bool {
+        self.inner.wait_empty_timeout(timeout)
+    }
+
+    /// Stop accepting new jobs and wait for the jobs in the pool to finish, at most for `timeout`.
+    /// Then the jobs that are still in the queue are dropped without being executed. Returns
+    /// whether all the jobs finished in time.
+    ///
+    /// NOTE: Jobs that are already running can't be interrupted. After a timeout, they keep
+    /// running until they finish on their own.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        self.inner.closed.store(true, Ordering::Release);
+        if self.inner.wait_empty_timeout(timeout) {
+            return true;
+        }
+        self.inner.drop_queued_jobs();
+        false
+    }
+
+    /// Returns true if a job panicked and the panic is not drained from the `panic_log` yet.
+    pub fn panic(&self) -> bool {
+        !self.inner.panics.is_empty()
+    }
+
+    /// The panics of the jobs of this pool.
+    pub fn panic_log(&self) -> &PanicLog {
+        &self.inner.panics
+    }
+
+    /// Take the panics of the jobs recorded so far. Same as `self.panic_log().drain()`.
+    pub fn take_panics(&self) -> Vec<PanicInfo> {
+        self.inner.panics.drain()
+    }
+}
+
+impl Drop for ThreadPool {
+    /// When dropped, all worker threads' `JoinHandle` must be `join`ed. If a job panicked and the
+    /// panic is not taken by `take_panics`, then this function should panic too.
+    fn drop(&mut self) {
+        // The timer holds a sender, so stop it first.
+        drop(self.timer.take());
+        drop(self.task_sender.take());
+        drop(self.job_senders.take().unwrap());
+        self.join();
+        // A worker may push a new worker while exiting, so repeat until there is none.
+        loop {
+            let workers = mem::take(&mut *self.inner._workers.lock().unwrap());
+            if workers.is_empty() {
+                break;
+            }
+            drop(workers);
+        }
+        let panic_info = self.take_panics();
+        if !panic_info.is_empty() && !thread::panicking() {
+            panic!(
+                "{}",
+                panic_info
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            );
+        }
+    }
+}
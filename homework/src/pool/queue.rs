@@ -0,0 +1,340 @@
+//! Job queues of a `ThreadPool` and the scheduling of their jobs on the workers.
+
+use core::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use std::{process, ptr};
+
+use chrono::prelude::Local;
+use crossbeam_channel::{Receiver, Select, Sender};
+use crossbeam_deque::{Steal, Stealer};
+
+use super::{
+    Job, LOCAL_QUEUE, PanicInfo, PanicLog, PanicPolicy, Priority, Scaling, Worker, WorkerConfig,
+    WorkerStats,
+};
+
+/// Internal data structure for tracking the current job status. This is shared by worker closures
+/// via `Arc` so that the workers can report to the pool that it started/finished a job.
+#[derive(Debug)]
+pub(super) struct ThreadPoolInner {
+    /// The number of jobs that are queued or running.
+    pub(super) job_count: Mutex<usize>,
+    /// Notified when `job_count` becomes 0.
+    pub(super) job_count_zero: Condvar,
+    pub(super) _workers: Mutex<Vec<Worker>>,
+    /// Job queues, indexed by `Priority`. These are the injectors: the jobs executed from a worker
+    /// with `Priority::Normal` are pushed to its local queue instead.
+    pub(super) job_recvs: [Receiver<Job>; Priority::COUNT],
+    /// The number of jobs in each job queue that are not taken yet. The channels are unbounded,
+    /// and the jobs are bounded by this instead, so that a cancelled job releases its room at
+    /// once.
+    pub(super) queued: Mutex<[usize; Priority::COUNT]>,
+    /// Notified when a job is taken from a job queue.
+    pub(super) queue_not_full: Condvar,
+    /// The bound of each job queue, if any. See `ThreadPool::with_capacity`.
+    pub(super) queue_cap: Option<usize>,
+    /// Stealers of the workers' local queues, with the workers' ids.
+    pub(super) stealers: RwLock<Vec<(usize, Stealer<Job>)>>,
+    /// Statistics of the workers, with the workers' ids.
+    pub(super) worker_stats: Mutex<Vec<(usize, Arc<WorkerStats>)>>,
+    /// The number of jobs that finished.
+    pub(super) completed_jobs: AtomicUsize,
+    /// The number of jobs that panicked, including the ones taken by `ThreadPool::take_panics`.
+    pub(super) panic_count: AtomicUsize,
+    /// Source of the workers' ids.
+    pub(super) next_worker_id: AtomicUsize,
+    /// The number of workers that are about to block or blocked on the job queues.
+    pub(super) idle_workers: AtomicUsize,
+    /// Channel to wake up an idle worker when a job is pushed to a local queue. It holds at most
+    /// one pending wake-up, so that idle workers are not woken up spuriously over and over.
+    pub(super) wake_send: Sender<()>,
+    pub(super) wake_recv: Receiver<()>,
+    /// Whether the pool stopped accepting new jobs. See `ThreadPool::shutdown`.
+    pub(super) closed: AtomicBool,
+    pub(super) panic_policy: PanicPolicy,
+    pub(super) worker_config: WorkerConfig,
+    /// `None` if the number of workers is fixed.
+    pub(super) scaling: Option<Scaling>,
+    /// The number of workers that are not exiting.
+    pub(super) num_workers: AtomicUsize,
+    /// Panics of the jobs that are not drained yet.
+    pub(super) panics: PanicLog,
+}
+
+impl ThreadPoolInner {
+    /// Increment the job count.
+    pub(super) fn start_job(&self) {
+        self.start_jobs(1);
+    }
+
+    /// Increment the job count by `n`.
+    pub(super) fn start_jobs(&self, n: usize) {
+        *self.job_count.lock().unwrap() += n;
+    }
+
+    /// Decrement the job count, and wake up the waiters if it becomes 0.
+    pub(super) fn finish_job(&self) {
+        self.finish_jobs(1);
+    }
+
+    /// Decrement the job count by `n`, and wake up the waiters if it becomes 0.
+    pub(super) fn finish_jobs(&self, n: usize) {
+        let mut job_count = self.job_count.lock().unwrap();
+        *job_count -= n;
+        if *job_count == 0 {
+            self.job_count_zero.notify_all();
+        }
+    }
+
+    /// Wait until the job count becomes 0.
+    pub(super) fn wait_empty(&self) {
+        let job_count = self.job_count.lock().unwrap();
+        let _unused = self
+            .job_count_zero
+            .wait_while(job_count, |job_count| *job_count != 0)
+            .unwrap();
+    }
+
+    /// Wait until the job count becomes 0 or `timeout` elapses. Returns whether the job count
+    /// became 0.
+    pub(super) fn wait_empty_timeout(&self, timeout: Duration) -> bool {
+        let job_count = self.job_count.lock().unwrap();
+        let (_unused, result) = self
+            .job_count_zero
+            .wait_timeout_while(job_count, timeout, |job_count| *job_count != 0)
+            .unwrap();
+        !result.timed_out()
+    }
+
+    /// Take room for a job in the job queue `queue`, waiting for it if `wait`. Returns whether it
+    /// took room, i.e. false if the queue is full and not `wait`.
+    pub(super) fn enqueue(&self, queue: usize, wait: bool) -> bool {
+        let mut queued = self.queued.lock().unwrap();
+        if let Some(cap) = self.queue_cap {
+            if !wait && queued[queue] >= cap {
+                return false;
+            }
+            queued = self
+                .queue_not_full
+                .wait_while(queued, |queued| queued[queue] >= cap)
+                .unwrap();
+        }
+        queued[queue] += 1;
+        true
+    }
+
+    /// Take room for a job in the job queue `queue` without waiting, even if it is full.
+    pub(super) fn enqueue_overflow(&self, queue: usize) {
+        self.queued.lock().unwrap()[queue] += 1;
+    }
+
+    /// Take room for a job executed via `ThreadPool`, waiting for it unless the current thread is a
+    /// worker of this pool. A worker takes the room even if the queue is full, as in
+    /// `Task::schedule`: it may be the one that would drain the queue, and would deadlock waiting.
+    pub(super) fn enqueue_from_caller(&self, queue: usize) {
+        if !self.enqueue(queue, !self.is_worker()) {
+            self.enqueue_overflow(queue);
+        }
+    }
+
+    /// Release the room of a job taken from the job queue `queue`.
+    pub(super) fn dequeue(&self, queue: usize) {
+        self.queued.lock().unwrap()[queue] -= 1;
+        self.queue_not_full.notify_all();
+    }
+
+    /// Drop all the jobs that are still in the queues.
+    pub(super) fn drop_queued_jobs(&self) {
+        for (queue, job_recv) in self.job_recvs.iter().enumerate() {
+            while let Ok(job) = job_recv.try_recv() {
+                if job.take() {
+                    self.dequeue(queue);
+                    drop(job);
+                    self.finish_job();
+                }
+            }
+        }
+        for (_, stealer) in self.stealers.read().unwrap().iter() {
+            loop {
+                match stealer.steal() {
+                    Steal::Success(job) => {
+                        drop(job);
+                        self.finish_job();
+                    }
+                    Steal::Empty => break,
+                    Steal::Retry => {}
+                }
+            }
+        }
+    }
+
+    /// The number of queued jobs.
+    pub(super) fn queue_len(&self) -> usize {
+        self.queued.lock().unwrap().iter().sum::<usize>()
+            + self
+                .stealers
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(_, stealer)| stealer.len())
+                .sum::<usize>()
+    }
+
+    /// Whether the current thread is a worker of this pool.
+    pub(super) fn is_worker(&self) -> bool {
+        LOCAL_QUEUE.with_borrow(|local| matches!(local, Some((pool, _)) if ptr::eq(*pool, self)))
+    }
+
+    /// Push a job to the local queue of the current thread if it is a worker of this pool. Gives
+    /// the job back otherwise.
+    pub(super) fn push_local(&self, job: Job) -> Result<(), Job> {
+        LOCAL_QUEUE.with_borrow(|local| match local {
+            Some((pool, local)) if ptr::eq(*pool, self) => {
+                local.push(job);
+                Ok(())
+            }
+            _ => Err(job),
+        })?;
+        // Wake up an idle worker so that it steals the job. See `recv_job`.
+        fence(Ordering::SeqCst);
+        if self.idle_workers.load(Ordering::SeqCst) > 0 {
+            let _ = self.wake_send.try_send(());
+        }
+        Ok(())
+    }
+
+    /// Try to take a job without blocking from, in this order: the high priority queue, the local
+    /// queue of the current worker, the other priority queues, and the local queues of the other
+    /// workers. Returns `Err(())` if the job queues are disconnected, i.e. the pool is dropped.
+    pub(super) fn try_find_job(&self) -> Result<Option<Job>, ()> {
+        let mut disconnected = true;
+        let mut try_recv = |priority: Priority| loop {
+            match self.job_recvs[priority as usize].try_recv() {
+                Ok(job) => {
+                    if job.take() {
+                        self.dequeue(priority as usize);
+                        break Some(job);
+                    }
+                }
+                Err(e) => {
+                    disconnected &= e.is_disconnected();
+                    break None;
+                }
+            }
+        };
+        if let Some(job) = try_recv(Priority::High)
+            .or_else(|| {
+                LOCAL_QUEUE.with_borrow(|local| local.as_ref().and_then(|(_, local)| local.pop()))
+            })
+            .or_else(|| try_recv(Priority::Normal))
+            .or_else(|| try_recv(Priority::Low))
+        {
+            return Ok(Some(job));
+        }
+        loop {
+            let stealers = self.stealers.read().unwrap();
+            match stealers
+                .iter()
+                .map(|(_, stealer)| stealer.steal())
+                .collect()
+            {
+                Steal::Success(job) => return Ok(Some(job)),
+                Steal::Empty => break,
+                Steal::Retry => {}
+            }
+        }
+        if disconnected { Err(()) } else { Ok(None) }
+    }
+
+    /// Take a job from the queues, blocking if there is none. Returns `None` if the worker should
+    /// exit, i.e. the pool is dropped or the worker is retired after being idle for too long.
+    pub(super) fn recv_job(&self) -> Option<Job> {
+        loop {
+            if let Some(job) = self.try_find_job().ok()? {
+                return Some(job);
+            }
+
+            // Announce that this worker is idle before checking the queues again, so that a job
+            // pushed to a local queue in the meantime is either found here or wakes this worker up.
+            let _ = self.idle_workers.fetch_add(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            let job = self.try_find_job();
+            if !matches!(job, Ok(None)) {
+                let _ = self.idle_workers.fetch_sub(1, Ordering::SeqCst);
+                return job.ok().flatten();
+            }
+
+            // Wait until any queue becomes ready or a job is pushed to a local queue, and then try
+            // again from the highest priority.
+            let mut select = Select::new();
+            for job_recv in &self.job_recvs {
+                let _ = select.recv(job_recv);
+            }
+            let wake = select.recv(&self.wake_recv);
+            let ready = match self.scaling {
+                None => Ok(select.ready()),
+                Some(scaling) => select.ready_timeout(scaling.idle_timeout),
+            };
+            let _ = self.idle_workers.fetch_sub(1, Ordering::SeqCst);
+            match ready {
+                Ok(index) if index == wake => {
+                    let _ = self.wake_recv.try_recv();
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    let scaling = self.scaling.unwrap();
+                    if self
+                        .num_workers
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                            (n > scaling.min_workers).then(|| n - 1)
+                        })
+                        .is_ok()
+                    {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run a job on a worker with the given statistics, and record its panic, if any. Returns
+    /// whether the job panicked.
+    pub(super) fn run_job(&self, job: Job, stats: &WorkerStats) -> bool {
+        let start = Instant::now();
+        stats.record_latency(start.saturating_duration_since(job.queued_at));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| job.f.call()));
+        *stats.busy_time.lock().unwrap() += start.elapsed();
+        let _ = self.completed_jobs.fetch_add(1, Ordering::Relaxed);
+        let Err(payload) = result else {
+            self.finish_job();
+            return false;
+        };
+        // Record the panic before finishing the job, so that it is visible to the threads `join`ing
+        // the pool.
+        self.record_panic(payload);
+        self.finish_job();
+        if self.panic_policy == PanicPolicy::Abort {
+            process::abort();
+        }
+        true
+    }
+
+    /// Record the panic of a job with the given panic payload.
+    pub(super) fn record_panic(&self, payload: Box<dyn Any + Send>) {
+        let info = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            String::from("Explicit Panic.")
+        };
+        self.panics.push(PanicInfo {
+            time: Local::now(),
+            info,
+        });
+        let _ = self.panic_count.fetch_add(1, Ordering::Relaxed);
+    }
+}
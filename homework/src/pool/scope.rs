@@ -0,0 +1,112 @@
+//! Scoped jobs of a `ThreadPool`, which may borrow the data outliving the scope.
+
+use core::any::Any;
+use core::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::ThreadPool;
+
+/// State of a `Scope` or a `BatchHandle` shared with its jobs.
+#[derive(Debug, Default)]
+pub(super) struct ScopeState {
+    /// The number of the jobs that are not finished yet.
+    pub(super) job_count: Mutex<usize>,
+    /// Notified when `job_count` becomes 0.
+    pub(super) job_count_zero: Condvar,
+    /// The panic payload of the first job that panicked.
+    pub(super) panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+/// A job spawned in a `Scope` or a batch. Its state is notified when it is dropped, i.e. when it
+/// finished or it is dropped without being executed.
+pub(super) struct ScopedJob<F> {
+    pub(super) f: Option<F>,
+    pub(super) state: Arc<ScopeState>,
+}
+
+impl<F> Drop for ScopedJob<F> {
+    fn drop(&mut self) {
+        // Drop the closure first, as it may borrow the data that outlives the scope.
+        drop(self.f.take());
+        let mut job_count = self.state.job_count.lock().unwrap();
+        *job_count -= 1;
+        if *job_count == 0 {
+            self.state.job_count_zero.notify_all();
+        }
+    }
+}
+
+/// A scope to execute jobs that may borrow the data outliving the scope. See `ThreadPool::scope`.
+#[derive(Debug)]
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    _scope: PhantomData<&'scope mut &'scope ()>,
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Execute a new job in the scope's thread pool, like `ThreadPool::execute`. Unlike
+    /// `ThreadPool::execute`, the job may borrow the data that outlives the scope.
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        *self.state.job_count.lock().unwrap() += 1;
+        let mut job = ScopedJob {
+            f: Some(f),
+            state: Arc::clone(&self.state),
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            let f = job.f.take().unwrap();
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+                let _ = job.state.panic.lock().unwrap().get_or_insert(payload);
+            }
+        });
+        // SAFETY: `ThreadPool::scope` doesn't return until all the jobs spawned in the scope are
+        // dropped, so the job doesn't outlive `'scope`.
+        #[allow(unsafe_code)]
+        let job = unsafe {
+            mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Box<dyn FnOnce() + Send + 'static>>(
+                job,
+            )
+        };
+        self.pool.execute(job);
+    }
+}
+
+impl ThreadPool {
+    /// Create a scope to execute jobs that may borrow the data outliving the scope, like
+    /// `std::thread::scope`. Blocks the current thread until all the jobs spawned in the scope
+    /// finish. If `f` or any of the jobs panicked, then this function panics too.
+    ///
+    /// NOTE: If this is called from a job of the pool, the current worker is blocked while waiting
+    /// for the jobs. This may deadlock if all the workers are blocked like that.
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState::default()),
+            _scope: PhantomData,
+            _env: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        let job_count = scope.state.job_count.lock().unwrap();
+        let _unused = scope
+            .state
+            .job_count_zero
+            .wait_while(job_count, |job_count| *job_count != 0)
+            .unwrap();
+
+        let result = result.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        if let Some(payload) = scope.state.panic.lock().unwrap().take() {
+            panic::resume_unwind(payload);
+        }
+        result
+    }
+}
@@ -0,0 +1,227 @@
+//! Delayed and periodic jobs of a `ThreadPool`.
+
+use core::cmp::{Ordering as CmpOrdering, Reverse};
+use core::fmt;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+
+use super::queue::ThreadPoolInner;
+use super::{Job, Priority, ThreadPool};
+
+/// Handle to a periodic job. See `ThreadPool::execute_every`.
+#[derive(Debug, Clone)]
+pub struct ScheduleHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduleHandle {
+    /// Stop executing the job. The executions that are already in the job queue are not affected.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Returns whether the job is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+enum TimerTask {
+    Once(Box<dyn FnOnce() + Send>),
+    Every {
+        interval: Duration,
+        f: Arc<dyn Fn() + Send + Sync>,
+        cancelled: Arc<AtomicBool>,
+    },
+}
+
+/// A job waiting in a `Timer`.
+struct TimerEntry {
+    /// When the job should be queued.
+    at: Instant,
+    /// Breaks the ties of `at`, so that the jobs with the same `at` are queued in order.
+    seq: u64,
+    task: TimerTask,
+}
+
+impl fmt::Debug for TimerEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerEntry")
+            .field("at", &self.at)
+            .field("seq", &self.seq)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for TimerEntry {}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    /// The waiting jobs, the earliest first.
+    entries: BinaryHeap<Reverse<TimerEntry>>,
+    next_seq: u64,
+    stopped: bool,
+}
+
+impl TimerState {
+    fn push(&mut self, at: Instant, task: TimerTask) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.entries.push(Reverse(TimerEntry { at, seq, task }));
+    }
+}
+
+/// A thread that queues the delayed and periodic jobs of a pool when they are due. See
+/// `ThreadPool::execute_after` and `ThreadPool::execute_every`.
+#[derive(Debug)]
+pub(super) struct Timer {
+    state: Arc<(Mutex<TimerState>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Timer {
+    fn new(inner: Arc<ThreadPoolInner>, job_sender: Sender<Job>) -> Self {
+        let state = Arc::new((Mutex::new(TimerState::default()), Condvar::new()));
+        let timer_state = Arc::clone(&state);
+        let thread = thread::spawn(move || {
+            let (lock, cvar) = &*timer_state;
+            let mut state = lock.lock().unwrap();
+            while !state.stopped {
+                let now = Instant::now();
+                let Some(Reverse(entry)) = state.entries.peek() else {
+                    state = cvar.wait(state).unwrap();
+                    continue;
+                };
+                if entry.at > now {
+                    let timeout = entry.at - now;
+                    state = cvar.wait_timeout(state, timeout).unwrap().0;
+                    continue;
+                }
+
+                let Reverse(entry) = state.entries.pop().unwrap();
+                let f: Box<dyn FnOnce() + Send> = match entry.task {
+                    TimerTask::Once(f) => f,
+                    TimerTask::Every {
+                        interval,
+                        f,
+                        cancelled,
+                    } => {
+                        if cancelled.load(Ordering::Acquire) {
+                            continue;
+                        }
+                        let f_clone = Arc::clone(&f);
+                        state.push(
+                            entry.at + interval,
+                            TimerTask::Every {
+                                interval,
+                                f,
+                                cancelled,
+                            },
+                        );
+                        Box::new(move || f_clone())
+                    }
+                };
+                if inner.closed.load(Ordering::Acquire) {
+                    continue;
+                }
+                // Queue the job without holding the lock: `enqueue` blocks while the bounded job
+                // queue is full, and `execute_after`, `execute_every`, and `drop` need the lock.
+                // Unlike a worker, this thread may wait for room, as the workers drain the queue
+                // without it.
+                drop(state);
+                inner.start_job();
+                let _ = inner.enqueue(Priority::Normal as usize, true);
+                job_sender.send(Job::new(f)).unwrap();
+                state = lock.lock().unwrap();
+            }
+        });
+        Self {
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    fn push(&self, at: Instant, task: TimerTask) {
+        let (state, cvar) = &*self.state;
+        state.lock().unwrap().push(at, task);
+        cvar.notify_one();
+    }
+}
+
+impl Drop for Timer {
+    /// Stops the timer thread. The jobs that are not due yet are dropped.
+    fn drop(&mut self) {
+        let (state, cvar) = &*self.state;
+        state.lock().unwrap().stopped = true;
+        cvar.notify_one();
+        self.thread.take().unwrap().join().unwrap();
+    }
+}
+
+impl ThreadPool {
+    fn timer(&self) -> &Timer {
+        self.timer.get_or_init(|| {
+            Timer::new(
+                Arc::clone(&self.inner),
+                self.job_sender(Priority::Normal).clone(),
+            )
+        })
+    }
+
+    /// Execute a new job in the thread pool after `delay`.
+    ///
+    /// NOTE: `join` doesn't wait for the job before it is due. The job is dropped if the pool is
+    /// dropped before the job is due. If the job queue is full when the job is due, the job and the
+    /// jobs due after it wait for room.
+    pub fn execute_after<F>(&self, delay: Duration, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.timer()
+            .push(Instant::now() + delay, TimerTask::Once(Box::new(f)));
+    }
+
+    /// Execute a new job in the thread pool every `interval`, starting after `interval`, until it
+    /// is cancelled via the returned handle.
+    ///
+    /// NOTE: An execution of the job may overlap with the previous one if the job takes longer
+    /// than `interval`.
+    pub fn execute_every<F>(&self, interval: Duration, f: F) -> ScheduleHandle
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.timer().push(
+            Instant::now() + interval,
+            TimerTask::Every {
+                interval,
+                f: Arc::new(f),
+                cancelled: Arc::clone(&cancelled),
+            },
+        );
+        ScheduleHandle { cancelled }
+    }
+}
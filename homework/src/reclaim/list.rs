@@ -0,0 +1,466 @@
+//! Lock-free sorted list, generic over the reclamation scheme.
+
+use core::marker::PhantomData;
+#[cfg(not(feature = "check-loom"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::{fmt, mem, ptr};
+
+use crossbeam_epoch::Guard;
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use super::{Epoch, Reclaim};
+
+/// Node of `List`.
+#[derive(Debug)]
+pub struct Node<K, V> {
+    /// The next node. Its LSB is 1 iff this node is deleted.
+    next: AtomicPtr<Node<K, V>>,
+    key: K,
+    value: V,
+}
+
+/// Whether the LSB of `pointer` is set.
+fn is_marked<T>(pointer: *mut T) -> bool {
+    pointer.addr() & 1 == 1
+}
+
+/// `pointer` with its LSB set.
+fn marked<T>(pointer: *mut T) -> *mut T {
+    pointer.map_addr(|addr| addr | 1)
+}
+
+/// `pointer` with its LSB cleared.
+fn unmarked<T>(pointer: *mut T) -> *mut T {
+    pointer.map_addr(|addr| addr & !1)
+}
+
+impl<K, V> Node<K, V> {
+    /// Creates a new node.
+    pub fn new(key: K, value: V) -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            key,
+            value,
+        }
+    }
+
+    /// Returns the key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the value.
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// Takes the value out of the node.
+    pub fn into_value(self) -> V {
+        self.value
+    }
+}
+
+/// Lock-free sorted list of Harris, whose deleted nodes are reclaimed with `R`.
+///
+/// Searches unlink the deleted nodes they pass by, as in the variant of Michael, instead of
+/// skipping them. Hence a search protects only the node it is at and the previous one, as hazard
+/// pointers need.
+pub struct List<K, V, R: Reclaim = Epoch> {
+    head: AtomicPtr<Node<K, V>>,
+    _nodes: PhantomData<Box<Node<K, V>>>,
+    _reclaim: PhantomData<fn() -> R>,
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, R: Reclaim> fmt::Debug for List<K, V, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("List").field("head", &self.head).finish()
+    }
+}
+
+/// Position in a `List`, at the node `curr` that the pointer `prev` points to. `prev` is the head
+/// or the `next` of a node.
+///
+/// `prev_guard` protects the node of `prev`, and `curr_guard` protects `curr`. The nodes that the
+/// cursor unlinks are retired with `guard`.
+pub struct Cursor<'l, K, V, R: Reclaim> {
+    prev: &'l AtomicPtr<Node<K, V>>,
+    curr: *mut Node<K, V>,
+    prev_guard: R::Guard,
+    curr_guard: R::Guard,
+    guard: &'l R::Guard,
+}
+
+impl<K, V, R: Reclaim> fmt::Debug for Cursor<'_, K, V, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cursor")
+            .field("prev", &self.prev)
+            .field("curr", &self.curr)
+            .finish()
+    }
+}
+
+impl<'l, K, V, R: Reclaim> Cursor<'l, K, V, R> {
+    /// Creates a cursor at the node that `prev` points to.
+    ///
+    /// # Safety
+    ///
+    /// `prev` must be the head of a list, or the `next` of a node that is never deleted while the
+    /// cursor is alive.
+    unsafe fn new(prev: &'l AtomicPtr<Node<K, V>>, guard: &'l R::Guard) -> Self {
+        let mut curr_guard = R::guard();
+        let curr = R::protect(&mut curr_guard, prev);
+        Self {
+            prev,
+            curr,
+            prev_guard: R::guard(),
+            curr_guard,
+            guard,
+        }
+    }
+
+    /// Creates a cursor at the node after `node`. Deleted nodes are retired with `guard`.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be in a list, and must never be deleted while the cursor is alive.
+    pub unsafe fn after(node: &'l Node<K, V>, guard: &'l R::Guard) -> Self {
+        unsafe { Self::new(&node.next, guard) }
+    }
+
+    /// Returns the current node, or `None` if the cursor is at the end of the list.
+    pub fn curr(&self) -> Option<&Node<K, V>> {
+        unsafe { self.curr.as_ref() }
+    }
+
+    /// Returns the guard that protects the current node.
+    pub fn into_guard(self) -> R::Guard {
+        self.curr_guard
+    }
+
+    /// Moves the cursor to the first node whose key is not less than `key`, unlinking the deleted
+    /// nodes on the way. Returns whether the key of the node is `key`.
+    ///
+    /// Returns `Err(())` if the node of `prev` is deleted, or `prev` is changed otherwise, so that
+    /// the cursor cannot move on. It has to be recreated then.
+    pub fn find(&mut self, key: &K) -> Result<bool, ()>
+    where
+        K: Ord,
+    {
+        loop {
+            let Some(curr) = (unsafe { self.curr.as_ref() }) else {
+                return Ok(false);
+            };
+            let next = curr.next.load(Ordering::Acquire);
+
+            if is_marked(next) {
+                let next = unmarked(next);
+                if self
+                    .prev
+                    .compare_exchange(self.curr, next, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    return Err(());
+                }
+                // SAFETY: `curr` is unlinked by us.
+                unsafe { R::retire(self.guard, self.curr) };
+                // `next` is valid as long as `prev` still points to it.
+                R::try_protect(&mut self.curr_guard, next, self.prev).map_err(|_| ())?;
+                self.curr = next;
+                continue;
+            }
+
+            if curr.key >= *key {
+                return Ok(curr.key == *key);
+            }
+
+            mem::swap(&mut self.prev_guard, &mut self.curr_guard);
+            self.prev = unsafe { &(*self.curr).next };
+            // `next` is valid as long as `curr` still points to it and is not deleted.
+            R::try_protect(&mut self.curr_guard, next, self.prev).map_err(|_| ())?;
+            self.curr = next;
+        }
+    }
+
+    /// Inserts `node` between `prev` and `curr`, and returns it. Gives it back if `prev` no
+    /// longer points to `curr`.
+    ///
+    /// The cursor does not protect the inserted node, and has to be recreated to go on.
+    pub fn insert(&self, node: Box<Node<K, V>>) -> Result<*mut Node<K, V>, Box<Node<K, V>>> {
+        node.next.store(self.curr, Ordering::Relaxed);
+        let node = Box::into_raw(node);
+        match self
+            .prev
+            .compare_exchange(self.curr, node, Ordering::Release, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(node),
+            Err(_) => Err(unsafe { Box::from_raw(node) }),
+        }
+    }
+
+    /// Deletes the current node by marking it, and unlinks it if `prev` still points to it.
+    /// Returns `Err(())` if it is already deleted.
+    ///
+    /// The cursor still protects the node afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cursor is at the end of the list.
+    pub fn delete(&self) -> Result<(), ()> {
+        let curr = self.curr().expect("the cursor should be at a node");
+        let mut next = curr.next.load(Ordering::Acquire);
+        loop {
+            if is_marked(next) {
+                return Err(());
+            }
+            match curr.next.compare_exchange(
+                next,
+                marked(next),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(current) => next = current,
+            }
+        }
+
+        if self
+            .prev
+            .compare_exchange(self.curr, next, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            // SAFETY: `curr` is unlinked by us.
+            unsafe { R::retire(self.guard, self.curr) };
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> List<K, V> {
+    /// Creates a new list.
+    pub fn new() -> Self {
+        Self::new_in(Epoch)
+    }
+}
+
+impl<K, V, R: Reclaim> List<K, V, R> {
+    /// Creates a new list with the reclamation scheme `R`.
+    pub fn new_in(_reclaim: R) -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _nodes: PhantomData,
+            _reclaim: PhantomData,
+        }
+    }
+
+    /// Creates a cursor at the first node. Deleted nodes are retired with `guard`.
+    pub fn head<'l>(&'l self, guard: &'l R::Guard) -> Cursor<'l, K, V, R> {
+        unsafe { Cursor::new(&self.head, guard) }
+    }
+}
+
+impl<K: Ord, V, R: Reclaim> List<K, V, R> {
+    /// Creates a cursor at the node of `key` or where it would be inserted, and returns whether it
+    /// is found.
+    fn find<'l>(&'l self, key: &K, guard: &'l R::Guard) -> (bool, Cursor<'l, K, V, R>) {
+        loop {
+            let mut cursor = self.head(guard);
+            if let Ok(found) = cursor.find(key) {
+                return (found, cursor);
+            }
+        }
+    }
+
+    /// Returns the value of `key`, which `guard` protects from then on.
+    pub fn lookup<'g>(&'g self, key: &K, guard: &'g mut R::Guard) -> Option<&'g V> {
+        let (found, cursor) = self.find(key, guard);
+        if !found {
+            return None;
+        }
+        let curr = cursor.curr;
+        R::hand_over(cursor.into_guard(), guard);
+        Some(unsafe { &(*curr).value })
+    }
+
+    /// Inserts `value` for `key`, or gives it back if `key` is already present.
+    pub fn insert(&self, key: K, value: V, guard: &R::Guard) -> Result<(), V> {
+        let mut node = Box::new(Node::new(key, value));
+        loop {
+            let (found, cursor) = self.find(&node.key, guard);
+            if found {
+                return Err(node.into_value());
+            }
+            match cursor.insert(node) {
+                Ok(_) => return Ok(()),
+                Err(n) => node = n,
+            }
+        }
+    }
+
+    /// Deletes `key`, and returns its value, which `guard` protects from then on.
+    pub fn delete<'g>(&'g self, key: &K, guard: &'g mut R::Guard) -> Option<&'g V> {
+        loop {
+            let (found, cursor) = self.find(key, guard);
+            if !found {
+                return None;
+            }
+            if cursor.delete().is_err() {
+                continue;
+            }
+            let curr = cursor.curr;
+            R::hand_over(cursor.into_guard(), guard);
+            return Some(unsafe { &(*curr).value });
+        }
+    }
+}
+
+impl<K, V> List<K, V> {
+    /// Returns an iterator over the nodes that are not deleted, in order.
+    ///
+    /// The nodes inserted or deleted concurrently may or may not be visited. The deleted nodes are
+    /// passed through rather than unlinked, as `guard` protects them.
+    pub fn iter<'g>(&'g self, guard: &'g Guard) -> Iter<'g, K, V> {
+        let _ = guard;
+        Iter {
+            curr: self.head.load(Ordering::Acquire),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the nodes after `node`, which is in the list, as `iter`.
+    pub fn iter_after<'g>(&'g self, node: &'g Node<K, V>, guard: &'g Guard) -> Iter<'g, K, V> {
+        let _ = guard;
+        Iter {
+            curr: unmarked(node.next.load(Ordering::Acquire)),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the nodes of a `List` with `Epoch`. See `List::iter`.
+#[derive(Debug)]
+pub struct Iter<'g, K, V> {
+    curr: *mut Node<K, V>,
+    _marker: PhantomData<(&'g Node<K, V>, &'g Guard)>,
+}
+
+impl<'g, K, V> Iterator for Iter<'g, K, V> {
+    type Item = &'g Node<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = unsafe { self.curr.as_ref() }?;
+            let next = node.next.load(Ordering::Acquire);
+            self.curr = unmarked(next);
+            if !is_marked(next) {
+                return Some(node);
+            }
+        }
+    }
+}
+
+impl<K, V> Default for List<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, R: Reclaim> Drop for List<K, V, R> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "check-loom"))]
+        let mut curr = *self.head.get_mut();
+        #[cfg(feature = "check-loom")]
+        let mut curr = self.head.load(Ordering::Relaxed);
+
+        // The deleted nodes that are still linked are freed as well, as they are not retired yet.
+        while !curr.is_null() {
+            let node = unsafe { Box::from_raw(unmarked(curr)) };
+            curr = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod tests {
+    use std::thread::scope;
+
+    use super::List;
+    use crate::hazard_pointer::collect;
+    use crate::reclaim::{Epoch, HazardPointers, Reclaim};
+    use crate::test::scale;
+
+    fn smoke<R: Reclaim>(reclaim: R) {
+        let list = List::new_in(reclaim);
+        let mut guard = R::guard();
+        for i in (0..64).rev() {
+            assert_eq!(list.insert(i, i.to_string(), &guard), Ok(()));
+        }
+        assert_eq!(
+            list.insert(7, "x".to_string(), &guard),
+            Err("x".to_string())
+        );
+        for i in 0..64 {
+            assert_eq!(list.lookup(&i, &mut guard), Some(&i.to_string()));
+        }
+        for i in (0..64).step_by(2) {
+            assert_eq!(list.delete(&i, &mut guard), Some(&i.to_string()));
+            assert_eq!(list.delete(&i, &mut guard), None);
+        }
+        for i in 0..64 {
+            assert_eq!(list.lookup(&i, &mut guard).is_some(), i % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn smoke_epoch() {
+        smoke(Epoch);
+    }
+
+    #[test]
+    fn smoke_hazard_pointers() {
+        smoke(HazardPointers);
+        collect();
+    }
+
+    // Each thread inserts and deletes its own keys, interleaved with those of the others. A
+    // deleted value is read after the other threads may have unlinked its node.
+    fn concurrent<R: Reclaim>(reclaim: R) {
+        const THREADS: usize = 4;
+        const KEYS: usize = scale(256, 16);
+        const ROUNDS: usize = scale(32, 2);
+
+        let list = List::new_in(reclaim);
+        scope(|s| {
+            for t in 0..THREADS {
+                let list = &list;
+                let _ = s.spawn(move || {
+                    let mut guard = R::guard();
+                    for _ in 0..ROUNDS {
+                        for key in (t..KEYS).step_by(THREADS) {
+                            assert_eq!(list.insert(key, Box::new(key), &guard), Ok(()));
+                        }
+                        for key in (t..KEYS).step_by(THREADS) {
+                            assert_eq!(list.lookup(&key, &mut guard).map(|v| **v), Some(key));
+                            assert_eq!(list.delete(&key, &mut guard).map(|v| **v), Some(key));
+                        }
+                        collect();
+                    }
+                });
+            }
+        });
+        let mut guard = R::guard();
+        for key in 0..KEYS {
+            assert!(list.lookup(&key, &mut guard).is_none());
+        }
+    }
+
+    #[test]
+    fn concurrent_epoch() {
+        concurrent(Epoch);
+    }
+
+    #[test]
+    fn concurrent_hazard_pointers() {
+        concurrent(HazardPointers);
+    }
+}
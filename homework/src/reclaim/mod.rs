@@ -0,0 +1,180 @@
+//! Reclamation schemes for lock-free data structures.
+//!
+//! A data structure generic over `Reclaim` can be instantiated with either crossbeam-epoch
+//! (`Epoch`) or the hazard pointers of this crate (`HazardPointers`), e.g. `list::List` and
+//! `SplitOrderedList`.
+
+pub mod list;
+
+#[cfg(not(feature = "check-loom"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crossbeam_epoch::Shared;
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::hazard_pointer::{self, Shield};
+
+/// Memory reclamation scheme.
+///
+/// A guard protects the pointers it loads with `protect` from being freed while it is alive.
+/// Depending on the scheme, it protects all of them (`Epoch`) or only the last one
+/// (`HazardPointers`), so a data structure needing several pointers at once uses several guards.
+pub trait Reclaim {
+    /// Protection of the loaded pointers.
+    type Guard;
+
+    /// Creates a new guard.
+    fn guard() -> Self::Guard;
+
+    /// Loads a pointer from `src` and protects it with `guard`.
+    fn protect<T>(guard: &mut Self::Guard, src: &AtomicPtr<T>) -> *mut T;
+
+    /// Protects `pointer` with `guard` if `src` still holds it, comparing the tags too. Otherwise,
+    /// returns the value of `src`.
+    fn try_protect<T>(
+        guard: &mut Self::Guard,
+        pointer: *mut T,
+        src: &AtomicPtr<T>,
+    ) -> Result<(), *mut T>;
+
+    /// Moves the protection of `from` to `to`, dropping the protection of `to`.
+    fn hand_over(from: Self::Guard, to: &mut Self::Guard);
+
+    /// Retires a pointer, which is freed once it is no longer protected.
+    ///
+    /// # Safety
+    ///
+    /// * `pointer` must be removed from shared memory before calling this function, and must be
+    ///   valid.
+    /// * The same `pointer` should only be retired once.
+    /// * `pointer` may be freed by another thread.
+    unsafe fn retire<T>(guard: &Self::Guard, pointer: *mut T);
+}
+
+/// Epoch-based reclamation with crossbeam-epoch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Epoch;
+
+impl Reclaim for Epoch {
+    type Guard = crossbeam_epoch::Guard;
+
+    fn guard() -> Self::Guard {
+        crossbeam_epoch::pin()
+    }
+
+    fn protect<T>(_guard: &mut Self::Guard, src: &AtomicPtr<T>) -> *mut T {
+        // The pinned guard protects everything that is reachable now.
+        src.load(Ordering::Acquire)
+    }
+
+    fn try_protect<T>(
+        _guard: &mut Self::Guard,
+        pointer: *mut T,
+        src: &AtomicPtr<T>,
+    ) -> Result<(), *mut T> {
+        let current = src.load(Ordering::Acquire);
+        if current == pointer {
+            Ok(())
+        } else {
+            Err(current)
+        }
+    }
+
+    fn hand_over(from: Self::Guard, _to: &mut Self::Guard) {
+        // `to` is pinned as well, and hence protects what `from` does. It is kept rather than
+        // replaced, as it may be pinned to another collector, which the pointers are retired to.
+        drop(from);
+    }
+
+    unsafe fn retire<T>(guard: &Self::Guard, pointer: *mut T) {
+        unsafe { guard.defer_destroy(Shared::from(pointer as *const T)) };
+    }
+}
+
+/// Reclamation with the hazard pointers of `hazard_pointer`, using the global `HAZARDS`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HazardPointers;
+
+impl Reclaim for HazardPointers {
+    type Guard = Shield<'static>;
+
+    fn guard() -> Self::Guard {
+        Shield::default()
+    }
+
+    fn protect<T>(guard: &mut Self::Guard, src: &AtomicPtr<T>) -> *mut T {
+        guard.protect(src)
+    }
+
+    fn try_protect<T>(
+        guard: &mut Self::Guard,
+        pointer: *mut T,
+        src: &AtomicPtr<T>,
+    ) -> Result<(), *mut T> {
+        guard.try_protect(pointer, src)
+    }
+
+    fn hand_over(from: Self::Guard, to: &mut Self::Guard) {
+        *to = from;
+    }
+
+    unsafe fn retire<T>(_guard: &Self::Guard, pointer: *mut T) {
+        unsafe { hazard_pointer::retire(pointer) };
+    }
+}
+
+#[cfg(all(test, not(feature = "check-loom")))]
+mod tests {
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+    use std::thread::scope;
+
+    use super::{Epoch, HazardPointers, Reclaim};
+    use crate::test::scale;
+
+    /// Increments the counter in `count` by replacing it, and retires the old one.
+    fn increment<R: Reclaim>(count: &AtomicPtr<usize>) {
+        let mut guard = R::guard();
+        loop {
+            let cur = R::protect(&mut guard, count);
+            let new = Box::into_raw(Box::new(unsafe { *cur } + 1));
+            if count
+                .compare_exchange(cur, new, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                unsafe { R::retire(&guard, cur) };
+                return;
+            }
+            drop(unsafe { Box::from_raw(new) });
+        }
+    }
+
+    fn counter<R: Reclaim>() {
+        const THREADS: usize = scale(4, 2);
+        const ITER: usize = scale(1024 * 4, 64);
+
+        let count = AtomicPtr::new(Box::into_raw(Box::new(0usize)));
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        increment::<R>(&count);
+                    }
+                });
+            }
+        });
+        let cur = count.swap(ptr::null_mut(), Ordering::Acquire);
+        assert_eq!(*unsafe { Box::from_raw(cur) }, THREADS * ITER);
+    }
+
+    #[test]
+    fn counter_epoch() {
+        counter::<Epoch>();
+    }
+
+    #[test]
+    fn counter_hazard_pointers() {
+        counter::<HazardPointers>();
+    }
+}
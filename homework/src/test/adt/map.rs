@@ -63,6 +63,7 @@ pub fn stress_sequential<
                 println!("iteration {i}: lookup({key:?}) ({non}existing)");
 
                 assert_eq!(map.lookup(&key, &pin()), hmap_res);
+                assert_eq!(map.contains_key(&key, &pin()), hmap_res.is_some());
             }
             Ops::Insert => {
                 let key = K::rand_gen(&mut rng);
@@ -102,6 +103,7 @@ pub fn stress_sequential<
                 assert_eq!(map.delete(&key, &pin()).cloned(), hmap_res);
             }
         }
+        assert_eq!(map.len(), hashmap.len());
     }
 }
 
@@ -348,3 +350,44 @@ pub fn log_concurrent<
 
     assert_logs_consistent(&logs);
 }
+
+/// Runs the same random operations on the map `M` and on the reference map `R`, e.g.
+/// `StripedHashMap`, in a single thread, and checks that they give the same results.
+pub fn cross_check<
+    K: Clone + Debug + Eq + RandGen,
+    V: Clone + Debug + Eq + RandGen,
+    M: Default + ConcurrentMap<K, V>,
+    R: Default + ConcurrentMap<K, V>,
+>(
+    steps: usize,
+) {
+    let mut rng = thread_rng();
+    let map = M::default();
+    let reference = R::default();
+
+    for i in 0..steps {
+        let op = OPS.choose(&mut rng).unwrap();
+        let key = K::rand_gen(&mut rng);
+        let guard = pin();
+
+        match op {
+            Ops::Lookup => {
+                println!("iteration {i}: lookup({key:?})");
+                assert_eq!(map.lookup(&key, &guard), reference.lookup(&key, &guard));
+            }
+            Ops::Insert => {
+                let value = V::rand_gen(&mut rng);
+                println!("iteration {i}: insert({key:?}, {value:?})");
+                assert_eq!(
+                    map.insert(key.clone(), value.clone(), &guard),
+                    reference.insert(key, value, &guard)
+                );
+            }
+            Ops::Delete => {
+                println!("iteration {i}: delete({key:?})");
+                assert_eq!(map.delete(&key, &guard), reference.delete(&key, &guard));
+            }
+        }
+        assert_eq!(map.len(), reference.len());
+    }
+}
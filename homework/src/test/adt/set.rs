@@ -2,25 +2,53 @@
 
 use core::fmt::Debug;
 use core::hash::Hash;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering::*;
+use std::collections::BTreeSet;
+use std::thread::scope;
 
 use crossbeam_epoch::Guard;
+use rand::prelude::*;
 
 use super::map;
 use crate::test::RandGen;
 use crate::{ConcurrentMap, ConcurrentSet};
 
-// A set can be seen as a map with value `()`. Thus, we can reuse the tests for maps.
-impl<T, S: ConcurrentSet<T>> ConcurrentMap<T, ()> for S {
+/// A set seen as a map with value `()`, so that we can reuse the tests for maps.
+///
+/// NOTE: This is a wrapper rather than a blanket impl for all sets, which would conflict with the
+/// impls of `ConcurrentMap` for maps generic over the key, e.g. `SplitOrderedHashMap`. It counts
+/// the values for `len`, which `ConcurrentSet` does not provide.
+#[derive(Debug, Default)]
+struct SetMap<S>(S, AtomicUsize);
+
+impl<T, S: ConcurrentSet<T>> ConcurrentMap<T, ()> for SetMap<S> {
     fn lookup<'a>(&'a self, key: &T, _guard: &'a Guard) -> Option<&'a ()> {
-        if self.contains(key) { Some(&()) } else { None }
+        if self.0.contains(key) {
+            Some(&())
+        } else {
+            None
+        }
     }
 
     fn insert(&self, key: T, _value: (), _guard: &Guard) -> Result<(), ()> {
-        if self.insert(key) { Ok(()) } else { Err(()) }
+        if !self.0.insert(key) {
+            return Err(());
+        }
+        let _ = self.1.fetch_add(1, Relaxed);
+        Ok(())
     }
 
     fn delete<'a>(&'a self, key: &T, _guard: &'a Guard) -> Result<&'a (), ()> {
-        if self.remove(key) { Ok(&()) } else { Err(()) }
+        if !self.0.remove(key) {
+            return Err(());
+        }
+        let _ = self.1.fetch_sub(1, Relaxed);
+        Ok(&())
+    }
+
+    fn len(&self) -> usize {
+        self.1.load(Relaxed)
     }
 }
 
@@ -28,7 +56,7 @@ impl<T, S: ConcurrentSet<T>> ConcurrentMap<T, ()> for S {
 pub fn stress_sequential<T: Debug + Clone + Eq + Hash + RandGen, S: Default + ConcurrentSet<T>>(
     steps: usize,
 ) {
-    map::stress_sequential::<T, (), S>(steps);
+    map::stress_sequential::<T, (), SetMap<S>>(steps);
 }
 
 /// See `map::stress_concurrent`.
@@ -36,7 +64,7 @@ pub fn stress_concurrent<T: Debug + Eq + RandGen, S: Default + Sync + Concurrent
     threads: usize,
     steps: usize,
 ) {
-    map::stress_concurrent::<T, (), S>(threads, steps);
+    map::stress_concurrent::<T, (), SetMap<S>>(threads, steps);
 }
 
 /// See `map::log_concurrent`.
@@ -47,5 +75,95 @@ pub fn log_concurrent<
     threads: usize,
     steps: usize,
 ) {
-    map::log_concurrent::<T, (), S>(threads, steps);
+    map::log_concurrent::<T, (), SetMap<S>>(threads, steps);
+}
+
+/// Randomly runs `contains`, `insert` and `remove` concurrently on a small range of keys, and
+/// checks the results against a model.
+///
+/// The keys are interleaved so that every `threads + 1`-th key is owned by a thread, and the
+/// others are shared. Each thread checks the results on its own keys against its own `BTreeSet`,
+/// while the other threads update the neighboring shared keys. For the shared keys, the successful
+/// inserts and removes are counted per key. They should alternate, so that there is at most one
+/// more insert than removes, which tells whether the key is in the set at the end.
+pub fn model_concurrent<S: Default + Sync + ConcurrentSet<usize>>(threads: usize, steps: usize) {
+    #[derive(Debug)]
+    enum Ops {
+        Contains,
+        Insert,
+        Remove,
+    }
+    const OPS: [Ops; 3] = [Ops::Contains, Ops::Insert, Ops::Remove];
+    const KEYS_PER_OWNER: usize = 16;
+
+    let owners = threads + 1;
+    let shared = threads;
+    let keys = owners * KEYS_PER_OWNER;
+    let set = S::default();
+    // The numbers of successful inserts and removes of each key.
+    let counts = (0..keys)
+        .map(|_| (AtomicUsize::new(0), AtomicUsize::new(0)))
+        .collect::<Vec<_>>();
+
+    let models = scope(|s| {
+        let handles = (0..threads)
+            .map(|t| {
+                let (set, counts) = (&set, &counts);
+                s.spawn(move || {
+                    let mut rng = thread_rng();
+                    let mut model = BTreeSet::new();
+                    for _ in 0..steps {
+                        let owner = if rng.r#gen() { t } else { shared };
+                        let key = rng.gen_range(0..KEYS_PER_OWNER) * owners + owner;
+                        let op = OPS.choose(&mut rng).unwrap();
+
+                        if owner == t {
+                            let (result, expected) = match op {
+                                Ops::Contains => (set.contains(&key), model.contains(&key)),
+                                Ops::Insert => (set.insert(key), model.insert(key)),
+                                Ops::Remove => (set.remove(&key), model.remove(&key)),
+                            };
+                            assert_eq!(result, expected, "thread {t}: {op:?}({key})");
+                            continue;
+                        }
+                        match op {
+                            Ops::Contains => {
+                                let _ = set.contains(&key);
+                            }
+                            Ops::Insert => {
+                                if set.insert(key) {
+                                    let _ = counts[key].0.fetch_add(1, Relaxed);
+                                }
+                            }
+                            Ops::Remove => {
+                                if set.remove(&key) {
+                                    let _ = counts[key].1.fetch_add(1, Relaxed);
+                                }
+                            }
+                        }
+                    }
+                    model
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut model = models.into_iter().flatten().collect::<BTreeSet<_>>();
+    for (key, (inserted, removed)) in counts.into_iter().enumerate() {
+        let (inserted, removed) = (inserted.into_inner(), removed.into_inner());
+        assert!(
+            inserted == removed || inserted == removed + 1,
+            "key {key}: inserted {inserted} times but removed {removed} times"
+        );
+        if inserted > removed {
+            let _ = model.insert(key);
+        }
+    }
+    for key in 0..keys {
+        assert_eq!(set.contains(&key), model.contains(&key), "key {key}");
+    }
 }
@@ -6,3 +6,17 @@ pub mod loom;
 pub mod rand;
 
 pub use rand::RandGen;
+
+/// Size of a test: `native` when the tests run natively, and `miri` under Miri, which is orders of
+/// magnitude slower.
+///
+/// Run the tests under Miri with e.g.
+///
+/// ```text
+/// MIRIFLAGS="-Zmiri-tree-borrows -Zmiri-ignore-leaks" cargo +nightly miri test --test elim_stack
+/// ```
+///
+/// crossbeam-epoch is not clean under stacked borrows, and leaks the garbage left at exit.
+pub const fn scale<T: Copy>(native: T, miri: T) -> T {
+    if cfg!(miri) { miri } else { native }
+}
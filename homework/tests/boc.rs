@@ -7,6 +7,7 @@ mod boc_fibonacci {
     use cs431_homework::boc::run_when;
     use cs431_homework::{CownPtr, tuple_list, when};
 
+    #[allow(clippy::needless_return)]
     fn fibonacci_inner(n: usize, sender: Option<Sender<usize>>) -> CownPtr<usize> {
         if n == 0 {
             CownPtr::new(0)
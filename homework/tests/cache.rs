@@ -1,10 +1,11 @@
-use std::sync::Barrier;
+use std::panic::{AssertUnwindSafe, catch_unwind};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::thread::scope;
+use std::sync::{Arc, Barrier};
+use std::thread::{scope, sleep};
 use std::time::Duration;
 
 use crossbeam_channel::bounded;
-use cs431_homework::hello_server::Cache;
+use cs431_homework::hello_server::{Cache, CacheBuilder, CacheStats, ThreadPool, Timeout};
 
 const NUM_THREADS: usize = 8;
 const NUM_KEYS: usize = 128;
@@ -111,3 +112,600 @@ fn cache_no_reader_block() {
         t1_quit_sender.send(()).unwrap();
     });
 }
+
+#[test]
+fn cache_ttl_expires() {
+    let cache = Cache::default();
+    let ttl = Duration::from_millis(100);
+    assert_eq!(cache.get_or_insert_with_ttl(1, ttl, |_| 1), 1);
+    assert_eq!(cache.get_or_insert_with_ttl(1, ttl, |_| panic!()), 1);
+    assert_eq!(cache.get_or_insert_with(2, |_| 2), 2);
+
+    sleep(ttl * 2);
+    assert_eq!(cache.get_or_insert_with_ttl(1, ttl, |_| 10), 10);
+    assert_eq!(cache.get_or_insert_with_ttl(1, ttl, |_| panic!()), 10);
+    assert_eq!(cache.get_or_insert_with(2, |_| panic!()), 2);
+
+    sleep(ttl * 2);
+    assert_eq!(cache.purge_expired(), 1);
+    assert_eq!(cache.get_or_insert_with(2, |_| panic!()), 2);
+}
+
+// The computations sweep the expired values of their shards every once in a while.
+#[test]
+fn cache_ttl_swept() {
+    let cache = CacheBuilder::new().shards(1).build();
+    let ttl = Duration::from_millis(100);
+    assert_eq!(cache.get_or_insert_with_ttl(0, ttl, |_| 0), 0);
+
+    sleep(ttl * 2);
+    for key in 1..=64 {
+        assert_eq!(cache.get_or_insert_with(key, |key| key), key);
+    }
+    assert_eq!(cache.len(), 64);
+    assert_eq!(cache.purge_expired(), 0);
+}
+
+#[test]
+fn cache_ttl_no_duplicate_concurrent() {
+    let cache = Cache::default();
+    let barrier = Barrier::new(NUM_THREADS);
+    let num_compute = AtomicUsize::new(0);
+    scope(|s| {
+        for _ in 0..NUM_THREADS {
+            let _ = s.spawn(|| {
+                let _ = barrier.wait();
+                for key in 0..NUM_KEYS {
+                    let _ = cache.get_or_insert_with_ttl(key, Duration::from_secs(60), |k| {
+                        let _ = num_compute.fetch_add(1, Ordering::Relaxed);
+                        k
+                    });
+                }
+            });
+        }
+    });
+    assert_eq!(num_compute.load(Ordering::Relaxed), NUM_KEYS);
+}
+
+#[test]
+fn cache_capacity_evicts_lru() {
+    let cache = Cache::with_capacity(2);
+    assert_eq!(cache.get_or_insert_with(1, |_| 1), 1);
+    assert_eq!(cache.get_or_insert_with(2, |_| 2), 2);
+    // 1 is used more recently than 2.
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+    assert_eq!(cache.get_or_insert_with(3, |_| 3), 3);
+    assert_eq!(cache.len(), 2);
+
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+    assert_eq!(cache.get_or_insert_with(3, |_| panic!()), 3);
+    assert_eq!(cache.get_or_insert_with(2, |_| 20), 20);
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn cache_capacity_concurrent() {
+    let cache = Cache::with_capacity(NUM_KEYS / 4);
+    let barrier = Barrier::new(NUM_THREADS);
+    scope(|s| {
+        for _ in 0..NUM_THREADS {
+            let _ = s.spawn(|| {
+                let _ = barrier.wait();
+                for key in 0..NUM_KEYS {
+                    assert_eq!(cache.get_or_insert_with(key, |k| k), key);
+                }
+            });
+        }
+    });
+    assert!(cache.len() <= NUM_KEYS / 4);
+}
+
+#[test]
+fn cache_panic_recompute() {
+    let cache = &Cache::default();
+
+    scope(|s| {
+        let (t1_quit_sender, t1_quit_receiver) = bounded::<()>(0);
+        // T1 panics while inserting 1.
+        let t1 = s.spawn(move || {
+            cache.get_or_insert_with(1, |_| {
+                let _ = t1_quit_receiver.recv();
+                panic!("T1 failed")
+            })
+        });
+        // T2 waits for T1, and then inserts 1 itself.
+        let t2 = s.spawn(move || {
+            sleep(Duration::from_millis(100));
+            cache.get_or_insert_with(1, |k| k)
+        });
+
+        sleep(Duration::from_millis(200));
+        drop(t1_quit_sender);
+        assert!(t1.join().is_err());
+        assert_eq!(t2.join().unwrap(), 1);
+    });
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+}
+
+#[test]
+fn cache_error_retry() {
+    let cache = Cache::default();
+    assert_eq!(
+        cache.try_get_or_insert_with(1, |_| Err("failed")),
+        Err("failed")
+    );
+    assert_eq!(cache.len(), 0);
+    assert_eq!(cache.try_get_or_insert_with::<&str, _>(1, Ok), Ok(1));
+    assert_eq!(
+        cache.try_get_or_insert_with::<&str, _>(1, |_| panic!()),
+        Ok(1)
+    );
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+}
+
+#[test]
+fn cache_negative_ttl() {
+    let ttl = Duration::from_millis(100);
+    let cache = CacheBuilder::new().negative_ttl(ttl).build();
+    assert_eq!(
+        cache.try_get_or_insert_with(1, |_| Err("failed")),
+        Err("failed")
+    );
+    assert_eq!(
+        cache.try_get_or_insert_with::<&str, _>(1, |_| panic!()),
+        Err("failed")
+    );
+
+    sleep(ttl * 2);
+    assert_eq!(cache.try_get_or_insert_with::<&str, _>(1, Ok), Ok(1));
+    assert_eq!(
+        cache.try_get_or_insert_with::<&str, _>(1, |_| panic!()),
+        Ok(1)
+    );
+}
+
+#[test]
+fn cache_invalidate() {
+    let cache = Cache::default();
+    assert!(!cache.contains_key(&1));
+    assert_eq!(cache.get_or_insert_with(1, |_| 1), 1);
+    assert_eq!(cache.get_or_insert_with(2, |_| 2), 2);
+    assert!(cache.contains_key(&1));
+
+    assert!(cache.invalidate(&1));
+    assert!(!cache.invalidate(&1));
+    assert!(!cache.contains_key(&1));
+    assert_eq!(cache.get_or_insert_with(1, |_| 10), 10);
+    assert_eq!(cache.get_or_insert_with(2, |_| panic!()), 2);
+
+    cache.invalidate_all();
+    assert!(cache.is_empty());
+    assert_eq!(cache.get_or_insert_with(2, |_| 20), 20);
+}
+
+#[test]
+fn cache_invalidate_in_flight() {
+    let cache = &Cache::default();
+
+    scope(|s| {
+        let (t1_quit_sender, t1_quit_receiver) = bounded::<()>(0);
+        // T1 computes 1 slowly.
+        let t1 = s.spawn(move || {
+            cache.get_or_insert_with(1, |_| {
+                let _ = t1_quit_receiver.recv();
+                1
+            })
+        });
+        // T2 waits for T1.
+        let t2 = s.spawn(move || {
+            sleep(Duration::from_millis(100));
+            cache.get_or_insert_with(1, |_| panic!())
+        });
+
+        sleep(Duration::from_millis(200));
+        assert!(!cache.contains_key(&1));
+        assert!(cache.invalidate(&1));
+        drop(t1_quit_sender);
+        assert_eq!(t1.join().unwrap(), 1);
+        assert_eq!(t2.join().unwrap(), 1);
+    });
+    // The value computed before the invalidation is discarded.
+    assert!(!cache.contains_key(&1));
+    assert_eq!(cache.get_or_insert_with(1, |_| 10), 10);
+}
+
+#[test]
+fn cache_shards() {
+    for shards in [1, 4, 64] {
+        let cache = CacheBuilder::new()
+            .shards(shards)
+            .capacity(NUM_KEYS)
+            .build();
+        let barrier = Barrier::new(NUM_THREADS);
+        let num_compute = AtomicUsize::new(0);
+        scope(|s| {
+            for _ in 0..NUM_THREADS {
+                let _ = s.spawn(|| {
+                    let _ = barrier.wait();
+                    for key in 0..NUM_KEYS {
+                        let _ = cache.get_or_insert_with(key, |k| {
+                            let _ = num_compute.fetch_add(1, Ordering::Relaxed);
+                            k
+                        });
+                    }
+                });
+            }
+        });
+        assert_eq!(num_compute.load(Ordering::Relaxed), NUM_KEYS);
+        assert_eq!(cache.len(), NUM_KEYS);
+    }
+}
+
+#[test]
+fn cache_arc_shared() {
+    // Not `Clone`.
+    struct Page(Vec<u8>);
+
+    let cache = Cache::default();
+    let page = cache.get_or_insert_arc_with(1, |_| Page(vec![0; 1024]));
+    let hit = cache.get_or_insert_arc_with(1, |_| panic!());
+    assert!(Arc::ptr_eq(&page, &hit));
+    assert_eq!(hit.0.len(), 1024);
+}
+
+#[test]
+fn cache_refresh_ahead() {
+    let pool = Arc::new(ThreadPool::new(1));
+    let after = Duration::from_millis(100);
+    let cache = CacheBuilder::new()
+        .refresh_after(after, pool.clone())
+        .build();
+    assert_eq!(cache.get_or_refresh_with(1, |_| 1), 1);
+    assert_eq!(cache.get_or_refresh_with(1, |_| panic!()), 1);
+
+    // The stale value is returned while it is refreshed only once.
+    sleep(after * 2);
+    let (refresh_sender, refresh_receiver) = bounded::<()>(0);
+    assert_eq!(
+        cache.get_or_refresh_with(1, move |_| {
+            let _ = refresh_receiver.recv();
+            10
+        }),
+        1
+    );
+    assert_eq!(cache.get_or_refresh_with(1, |_| panic!()), 1);
+    drop(refresh_sender);
+    pool.join();
+    assert_eq!(cache.get_or_refresh_with(1, |_| panic!()), 10);
+
+    // A panicking refresh leaves the stale value to be refreshed again.
+    sleep(after * 2);
+    assert_eq!(cache.get_or_refresh_with(1, |_| panic!()), 10);
+    pool.join();
+    assert_eq!(pool.take_panics().len(), 1);
+    assert_eq!(cache.get_or_refresh_with(1, |_| 20), 10);
+    pool.join();
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 20);
+}
+
+#[test]
+fn cache_stats() {
+    let cache = Cache::with_capacity(2);
+    assert_eq!(cache.stats(), CacheStats::default());
+    assert_eq!(
+        cache.get_or_insert_with(1, |_| {
+            sleep(Duration::from_millis(10));
+            1
+        }),
+        1
+    );
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+    assert_eq!(cache.get_or_insert_with(2, |_| 2), 2);
+    assert_eq!(cache.get_or_insert_with(3, |_| 3), 3);
+
+    let stats = cache.stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 3);
+    assert_eq!(stats.loads, 3);
+    assert_eq!(stats.evictions, 1);
+    assert!(stats.total_load_time >= Duration::from_millis(10));
+    assert!(stats.average_load_time() >= Duration::from_millis(10) / 3);
+    assert_eq!(stats.hit_rate(), 0.25);
+}
+
+#[test]
+fn cache_wait_timeout() {
+    let cache = &Cache::default();
+
+    scope(|s| {
+        let (t1_quit_sender, t1_quit_receiver) = bounded::<()>(0);
+        // T1 computes 1 slowly.
+        let t1 = s.spawn(move || {
+            cache.get_or_insert_with(1, |_| {
+                let _ = t1_quit_receiver.recv();
+                1
+            })
+        });
+
+        sleep(Duration::from_millis(100));
+        assert_eq!(
+            cache.get_or_try_insert_with_timeout(1, Duration::from_millis(100), |_| panic!()),
+            Err(Timeout)
+        );
+        drop(t1_quit_sender);
+        assert_eq!(t1.join().unwrap(), 1);
+    });
+    assert_eq!(
+        cache.get_or_try_insert_with_timeout(1, Duration::ZERO, |_| panic!()),
+        Ok(1)
+    );
+    assert_eq!(
+        cache.get_or_try_insert_with_timeout(2, Duration::ZERO, |k| k),
+        Ok(2)
+    );
+}
+
+#[test]
+fn cache_weigher_evicts_lru() {
+    let cache = CacheBuilder::new()
+        .max_weight(10)
+        .weigher(|_, v: &String| v.len())
+        .build();
+    assert_eq!(cache.get_or_insert_with(1, |_| "aaaa".to_string()), "aaaa");
+    assert_eq!(cache.get_or_insert_with(2, |_| "bbbb".to_string()), "bbbb");
+    assert_eq!(cache.weight(), 8);
+    // 1 is used more recently than 2.
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), "aaaa");
+
+    assert_eq!(cache.get_or_insert_with(3, |_| "cccc".to_string()), "cccc");
+    assert_eq!(cache.weight(), 8);
+    assert!(!cache.contains_key(&2));
+
+    // A heavy value evicts all the others.
+    assert_eq!(
+        cache.get_or_insert_with(4, |_| "d".repeat(10)),
+        "d".repeat(10)
+    );
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.weight(), 10);
+}
+
+#[test]
+fn cache_weight_tracks_removals() {
+    let cache = CacheBuilder::new().weigher(|_, v: &usize| *v).build();
+    assert_eq!(cache.get_or_insert_with(1, |_| 4), 4);
+    assert_eq!(cache.get_or_insert_with(2, |_| 6), 6);
+    assert_eq!(cache.weight(), 10);
+    assert_eq!(cache.compute(1, |_, _| 7), 7);
+    assert_eq!(cache.weight(), 13);
+    assert!(cache.invalidate(&2));
+    assert_eq!(cache.weight(), 7);
+    // The weight of a value invalidated while it is computed doesn't count.
+    let val = cache.compute(1, |_, _| {
+        assert!(cache.invalidate(&1));
+        3
+    });
+    assert_eq!(val, 3);
+    assert_eq!(cache.weight(), 3);
+    cache.invalidate_all();
+    assert_eq!(cache.weight(), 0);
+}
+
+#[test]
+fn cache_weigher_panic() {
+    let cache = CacheBuilder::new()
+        .weigher(|_, v: &usize| {
+            assert_ne!(*v, 0, "zero weight");
+            *v
+        })
+        .build();
+    let panicked = catch_unwind(AssertUnwindSafe(|| cache.get_or_insert_with(1, |_| 0)));
+    assert!(panicked.is_err());
+    // The slot is abandoned, so the key is computed again instead of waiting forever.
+    assert_eq!(cache.get_or_insert_with(1, |_| 1), 1);
+
+    let panicked = catch_unwind(AssertUnwindSafe(|| {
+        cache.get_or_insert_many(&[2, 3], |keys| keys.iter().map(|_| 0).collect())
+    }));
+    assert!(panicked.is_err());
+    assert_eq!(
+        cache.get_or_insert_many(&[2, 3], |keys| keys.to_vec()),
+        [2, 3]
+    );
+    assert_eq!(cache.weight(), 6);
+}
+
+#[test]
+fn cache_many() {
+    let cache = Cache::default();
+    assert_eq!(cache.get_or_insert_with(2, |k| k * 10), 20);
+    let vals = cache.get_or_insert_many(&[1, 2, 3, 1], |keys| {
+        assert_eq!(keys, [1, 3]);
+        keys.iter().map(|k| k * 10).collect()
+    });
+    assert_eq!(vals, [10, 20, 30, 10]);
+    assert_eq!(cache.get_or_insert_with(3, |_| panic!()), 30);
+}
+
+#[test]
+fn cache_many_negative_ttl() {
+    let cache = CacheBuilder::new()
+        .negative_ttl(Duration::from_secs(60))
+        .build();
+    assert_eq!(
+        cache.try_get_or_insert_with(1, |_| Err("failed")),
+        Err("failed")
+    );
+    let vals = cache.get_or_insert_many(&[1, 2], |keys| keys.iter().map(|k| k * 10).collect());
+    assert_eq!(vals, [10, 20]);
+    // The cached error is not replaced.
+    assert_eq!(
+        cache.try_get_or_insert_with::<&str, _>(1, |_| panic!()),
+        Err("failed")
+    );
+    assert_eq!(cache.get_or_insert_with(2, |_| panic!()), 20);
+}
+
+#[test]
+fn cache_many_no_duplicate_concurrent() {
+    let cache = Cache::default();
+    let barrier = Barrier::new(NUM_THREADS);
+    let num_compute = AtomicUsize::new(0);
+    scope(|s| {
+        for t in 0..NUM_THREADS {
+            let (cache, barrier, num_compute) = (&cache, &barrier, &num_compute);
+            let _ = s.spawn(move || {
+                let _ = barrier.wait();
+                // Overlapping batches in different orders.
+                for batch in (0..NUM_KEYS).collect::<Vec<_>>().chunks(8 + t) {
+                    let mut batch = batch.to_vec();
+                    if t % 2 == 1 {
+                        batch.reverse();
+                    }
+                    let vals = cache.get_or_insert_many(&batch, |keys| {
+                        let _ = num_compute.fetch_add(keys.len(), Ordering::Relaxed);
+                        keys.to_vec()
+                    });
+                    assert_eq!(vals, batch);
+                }
+            });
+        }
+    });
+    assert_eq!(num_compute.load(Ordering::Relaxed), NUM_KEYS);
+}
+
+#[test]
+fn cache_compute() {
+    let cache = Cache::default();
+    assert_eq!(cache.compute(1, |_, old| old.map_or(1, |v| v + 1)), 1);
+    assert_eq!(cache.compute(1, |_, old| old.map_or(1, |v| v + 1)), 2);
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 2);
+    assert_eq!(cache.replace_with(1, |_| 10), 10);
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 10);
+}
+
+#[test]
+fn cache_compute_evicts() {
+    let cache = Cache::with_capacity(2);
+    assert_eq!(cache.get_or_insert_with(1, |_| 1), 1);
+    assert_eq!(cache.get_or_insert_with(2, |_| 2), 2);
+    // 1 is removed and 3 takes its place while 1 is computed, so putting 1 back evicts a value.
+    let val = cache.compute(1, |_, _| {
+        assert!(cache.invalidate(&1));
+        assert_eq!(cache.get_or_insert_with(3, |_| 3), 3);
+        10
+    });
+    assert_eq!(val, 10);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.stats().evictions, 1);
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 10);
+}
+
+#[test]
+fn cache_compute_concurrent() {
+    const NUM_COMPUTES: usize = 100;
+    let cache = Cache::default();
+    let barrier = Barrier::new(NUM_THREADS + 1);
+    scope(|s| {
+        for _ in 0..NUM_THREADS {
+            let _ = s.spawn(|| {
+                let _ = barrier.wait();
+                for _ in 0..NUM_COMPUTES {
+                    let _ = cache.compute(1, |_, old| old.map_or(1, |v| v + 1));
+                }
+            });
+        }
+        // The reader sees the values in order.
+        let _ = s.spawn(|| {
+            let _ = barrier.wait();
+            let mut last = 0;
+            while last < NUM_THREADS * NUM_COMPUTES {
+                let val = cache.get_or_insert_with(1, |_| 0);
+                assert!(val >= last);
+                last = val;
+            }
+        });
+    });
+    assert_eq!(
+        cache.get_or_insert_with(1, |_| panic!()),
+        NUM_THREADS * NUM_COMPUTES
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn cache_snapshot() {
+    let path = std::env::temp_dir().join(format!("cs431-cache-{}.snapshot", std::process::id()));
+    let cache = Cache::<usize, String>::with_capacity(8);
+    assert_eq!(cache.get_or_insert_with(1, |k| k.to_string()), "1");
+    assert_eq!(cache.get_or_insert_with(2, |k| k.to_string()), "2");
+    assert_eq!(
+        cache.get_or_insert_with_ttl(3, Duration::from_millis(1), |k| k.to_string()),
+        "3"
+    );
+    // 1 is used more recently than 2.
+    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), "1");
+    sleep(Duration::from_millis(10));
+    cache.save(&path).unwrap();
+
+    let warm = Cache::<usize, String>::default();
+    assert_eq!(warm.load(&path).unwrap(), 2);
+    assert_eq!(warm.get_or_insert_with(1, |_| panic!()), "1");
+    assert_eq!(warm.get_or_insert_with(2, |_| panic!()), "2");
+    assert!(!warm.contains_key(&3));
+
+    // A smaller cache keeps the most recently used value.
+    let small = Cache::<usize, String>::with_capacity(1);
+    assert_eq!(small.load(&path).unwrap(), 2);
+    assert_eq!(small.len(), 1);
+    assert!(small.contains_key(&1));
+
+    std::fs::write(&path, b"garbage").unwrap();
+    assert!(warm.load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// The values keep expiring while the snapshot is not loaded.
+#[cfg(feature = "serde")]
+#[test]
+fn cache_snapshot_weigher_panic() {
+    let path = std::env::temp_dir().join(format!(
+        "cs431-cache-weigher-{}.snapshot",
+        std::process::id()
+    ));
+    let cache = Cache::<usize, usize>::default();
+    assert_eq!(cache.get_or_insert_with(0, |k| k), 0);
+    cache.save(&path).unwrap();
+
+    let warm = CacheBuilder::new()
+        .weigher(|_, v: &usize| {
+            assert_ne!(*v, 0, "zero weight");
+            *v
+        })
+        .build();
+    assert!(catch_unwind(AssertUnwindSafe(|| warm.load(&path))).is_err());
+    // The shard of the key is not poisoned.
+    assert_eq!(warm.get_or_insert_with(0, |_| 1), 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn cache_snapshot_expiry() {
+    let path = std::env::temp_dir().join(format!(
+        "cs431-cache-expiry-{}.snapshot",
+        std::process::id()
+    ));
+    let cache = Cache::<usize, String>::default();
+    assert_eq!(
+        cache.get_or_insert_with_ttl(1, Duration::from_millis(100), |k| k.to_string()),
+        "1"
+    );
+    cache.save(&path).unwrap();
+    sleep(Duration::from_millis(200));
+
+    let warm = Cache::<usize, String>::default();
+    assert_eq!(warm.load(&path).unwrap(), 0);
+    assert!(!warm.contains_key(&1));
+    std::fs::remove_file(&path).unwrap();
+}
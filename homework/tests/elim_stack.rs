@@ -1,17 +1,23 @@
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::iter;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
 use std::thread::scope;
+use std::time::Duration;
 
 use cs431_homework::elim_stack::{ElimStack, Stack};
+use cs431_homework::test::scale;
 
 #[test]
 fn push_example_simple() {
+    const THREADS: usize = scale(10, 2);
+    const ITER: usize = scale(10_000, 64);
+
     let stack = ElimStack::default();
 
     scope(|scope| {
         let mut handles = Vec::new();
-        for _ in 0..10 {
+        for _ in 0..THREADS {
             let handle = scope.spawn(|| {
-                for i in 0..10_000 {
+                for i in 0..ITER {
                     stack.push(i);
                     assert!(stack.pop().is_some());
                 }
@@ -39,13 +45,16 @@ fn push_pop_single_thread() {
 
 #[test]
 fn push_pop_multi_thread() {
+    const THREADS: usize = scale(4, 2);
+    const ITER: usize = scale(5_000, 64);
+
     let stack = ElimStack::default();
 
     scope(|scope| {
         let mut handles = Vec::new();
-        for _ in 0..4 {
+        for _ in 0..THREADS {
             let handle = scope.spawn(|| {
-                for i in 0..5_000 {
+                for i in 0..ITER {
                     stack.push(i);
                     assert!(stack.pop().is_some());
                 }
@@ -57,6 +66,33 @@ fn push_pop_multi_thread() {
     assert!(stack.pop().is_none());
 }
 
+#[test]
+fn with_config() {
+    const THREADS: usize = scale(8, 2);
+    const ITER: usize = scale(5_000, 64);
+
+    for (slots, max_wait) in [(1, Duration::ZERO), (4, Duration::from_micros(100))] {
+        let stack = ElimStack::with_config(slots, max_wait);
+        scope(|scope| {
+            for _ in 0..THREADS {
+                let _ = scope.spawn(|| {
+                    for i in 0..ITER {
+                        stack.push(i);
+                        assert!(stack.pop().is_some());
+                    }
+                });
+            }
+        });
+        assert!(stack.pop().is_none());
+    }
+}
+
+#[test]
+#[should_panic(expected = "at least one slot")]
+fn with_config_no_slots() {
+    let _ = ElimStack::<i32>::with_config(0, Duration::ZERO);
+}
+
 #[test]
 fn pop_empty_stack() {
     let stack: ElimStack<i32> = ElimStack::default();
@@ -65,13 +101,16 @@ fn pop_empty_stack() {
 
 #[test]
 fn stress_test() {
+    const THREADS: usize = scale(10, 2);
+    const ITER: usize = scale(10_000, 64);
+
     let stack = ElimStack::default();
 
     scope(|scope| {
         let mut handles = Vec::new();
-        for _ in 0..10 {
+        for _ in 0..THREADS {
             let handle = scope.spawn(|| {
-                for i in 0..10_000 {
+                for i in 0..ITER {
                     stack.push(i);
                 }
             });
@@ -83,29 +122,33 @@ fn stress_test() {
     while stack.pop().is_some() {
         count += 1;
     }
-    assert_eq!(count, 100_000);
+    assert_eq!(count, THREADS * ITER);
 }
 
 #[test]
 fn concurrent_push_pop() {
+    const PUSHERS: usize = scale(1_000, 4);
+    const POPPERS: usize = scale(5, 2);
+    const ITER: i32 = scale(1_000, 32);
+
     let stack: ElimStack<i32> = ElimStack::default();
     let count = AtomicI32::new(0);
 
     scope(|scope| {
         let mut handles = Vec::new();
-        for _ in 0..1_000 {
+        for _ in 0..PUSHERS {
             let handle = scope.spawn(|| {
-                for i in 0..1_000 {
+                for i in 0..ITER {
                     stack.push(i);
                 }
             });
             handles.push(handle);
         }
 
-        for _ in 0..5 {
+        for _ in 0..POPPERS {
             let handle = scope.spawn(|| {
-                while count.load(Ordering::Acquire) < 1_000_000 {
-                    if let Some(_) = stack.pop() {
+                while count.load(Ordering::Acquire) < PUSHERS as i32 * ITER {
+                    if stack.pop().is_some() {
                         count.fetch_add(1, Ordering::Release);
                     }
                 }
@@ -116,3 +159,46 @@ fn concurrent_push_pop() {
 
     assert!(stack.pop().is_none());
 }
+
+/// Pushes distinct values while popping concurrently, with few slots so that pushes and pops meet
+/// in them often, and checks that each value is popped exactly once.
+#[test]
+fn no_loss_or_duplication() {
+    const PUSHERS: usize = scale(4, 2);
+    const POPPERS: usize = scale(4, 2);
+    const ITER: usize = scale(10_000, 64);
+
+    let stack = ElimStack::with_config(2, Duration::from_micros(100));
+    let pushed = AtomicUsize::new(0);
+
+    let mut popped = scope(|scope| {
+        for t in 0..PUSHERS {
+            let (stack, pushed) = (&stack, &pushed);
+            let _ = scope.spawn(move || {
+                for i in 0..ITER {
+                    stack.push(t * ITER + i);
+                }
+                let _ = pushed.fetch_add(1, Ordering::Release);
+            });
+        }
+        let handles = (0..POPPERS)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut popped = Vec::new();
+                    while pushed.load(Ordering::Acquire) < PUSHERS {
+                        popped.extend(stack.pop());
+                    }
+                    popped
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    popped.extend(iter::from_fn(|| stack.pop()));
+
+    popped.sort_unstable();
+    assert!(popped.into_iter().eq(0..PUSHERS * ITER));
+}
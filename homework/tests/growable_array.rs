@@ -2,10 +2,14 @@
 
 use core::ops::Deref;
 use core::sync::atomic::Ordering::*;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+use std::thread::scope;
 
 use crossbeam_epoch::{Guard, Owned, Shared, pin};
 use cs431_homework::test::adt::map;
+use cs431_homework::test::scale;
 use cs431_homework::{ConcurrentMap, GrowableArray};
+use rand::Rng;
 use stack::{Node, Stack};
 
 #[derive(Debug)]
@@ -13,6 +17,7 @@ struct ArrayMap<V> {
     array: GrowableArray<Node<V>>,
     /// dump everything into a stack and drop them later
     storage: Stack<V>,
+    len: AtomicUsize,
 }
 
 impl<V> Default for ArrayMap<V> {
@@ -26,6 +31,7 @@ impl<V> ArrayMap<V> {
         Self {
             array: GrowableArray::new(),
             storage: Stack::new(),
+            len: AtomicUsize::new(0),
         }
     }
 }
@@ -49,6 +55,7 @@ impl<V> ConcurrentMap<u32, V> for ArrayMap<V> {
                 // SAFETY: `n` is created in this function, hence this is the unique push of `n`.
                 // Also, `n` is not used again.
                 unsafe { self.storage.push_node(n, guard) };
+                let _ = self.len.fetch_add(1, Relaxed);
                 Ok(())
             }
             Err(e) => Err(e.new.into_box().into_inner()),
@@ -63,10 +70,17 @@ impl<V> ConcurrentMap<u32, V> for ArrayMap<V> {
             return Err(());
         }
         match slot.compare_exchange(curr, Shared::null(), AcqRel, Acquire, guard) {
-            Ok(_) => Ok(unsafe { curr.deref() }),
+            Ok(_) => {
+                let _ = self.len.fetch_sub(1, Relaxed);
+                Ok(unsafe { curr.deref() })
+            }
             Err(_) => Err(()), // already removed
         }
     }
+
+    fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
 }
 
 mod stack {
@@ -179,36 +193,327 @@ fn smoke() {
     assert_eq!(list.lookup(&37, &guard), None);
 }
 
+#[test]
+fn get_if_exists() {
+    let array = GrowableArray::<usize>::new();
+    let guard = pin();
+
+    let value = Box::into_raw(Box::new(37));
+    array
+        .get(37, &guard)
+        .store(Shared::from(value as *const _), Relaxed);
+    assert_eq!(
+        array
+            .get_if_exists(37, &guard)
+            .unwrap()
+            .load(Relaxed, &guard),
+        Shared::from(value as *const _)
+    );
+    assert!(
+        array
+            .get_if_exists(42, &guard)
+            .unwrap()
+            .load(Relaxed, &guard)
+            .is_null()
+    );
+    // the segments for larger indices are not allocated by `get_if_exists`.
+    assert!(array.get_if_exists(1 << 20, &guard).is_none());
+    assert!(array.get_if_exists(1 << 20, &guard).is_none());
+    let _ = array.get(1 << 20, &guard);
+    assert!(array.get_if_exists(1 << 20, &guard).is_some());
+    assert!(array.get_if_exists(2 << 20, &guard).is_none());
+
+    drop(unsafe { Box::from_raw(value) });
+}
+
+#[test]
+fn small_segments() {
+    let array = GrowableArray::<usize, 2>::new();
+    let guard = pin();
+
+    let indices = [0, 1, 5, 37, 1 << 20, 1 << 40, usize::MAX >> 1];
+    let values = indices.map(|i| Box::into_raw(Box::new(i)));
+    for (&i, &value) in indices.iter().zip(&values) {
+        array
+            .get(i, &guard)
+            .store(Shared::from(value as *const _), Relaxed);
+    }
+    for (&i, &value) in indices.iter().zip(&values) {
+        let ptr = array
+            .get_if_exists(i, &guard)
+            .unwrap()
+            .load(Relaxed, &guard);
+        assert_eq!(ptr, Shared::from(value as *const _));
+        assert_eq!(unsafe { *ptr.deref() }, i);
+    }
+    assert!(
+        array
+            .get_if_exists(38, &guard)
+            .unwrap()
+            .load(Relaxed, &guard)
+            .is_null()
+    );
+
+    drop(array);
+    for value in values {
+        drop(unsafe { Box::from_raw(value) });
+    }
+}
+
+fn iter_occupied<const LOGSIZE: usize>() {
+    let array = GrowableArray::<usize, LOGSIZE>::new();
+    let guard = pin();
+    assert_eq!(array.iter(&guard).count(), 0);
+
+    let indices = [0, 3, 5, 37, 1024, 1 << 20, 1 << 40, usize::MAX];
+    let values = indices.map(|i| Box::into_raw(Box::new(i)));
+    // store out of order, and allocate a few segments without storing anything.
+    for (&i, &value) in indices.iter().zip(&values).rev() {
+        array
+            .get(i, &guard)
+            .store(Shared::from(value as *const _), Relaxed);
+    }
+    let _ = array.get(42, &guard);
+    let _ = array.get(1 << 30, &guard);
+
+    let occupied = array
+        .iter(&guard)
+        .map(|(i, ptr)| (i, unsafe { *ptr.deref() }))
+        .collect::<Vec<_>>();
+    assert_eq!(occupied, indices.map(|i| (i, i)));
+
+    drop(array);
+    for value in values {
+        drop(unsafe { Box::from_raw(value) });
+    }
+}
+
+#[test]
+fn iter() {
+    iter_occupied::<10>();
+    iter_occupied::<2>();
+    iter_occupied::<3>();
+}
+
+#[test]
+fn set_compare_exchange() {
+    let array = GrowableArray::<usize>::new();
+    let guard = pin();
+
+    let first = Shared::from(Box::into_raw(Box::new(1)) as *const _);
+    let second = Shared::from(Box::into_raw(Box::new(2)) as *const _);
+    array.set(1 << 20, first, &guard);
+    assert_eq!(array.get(1 << 20, &guard).load(Relaxed, &guard), first);
+
+    assert_eq!(
+        array.compare_exchange_at(1 << 20, Shared::null(), second, &guard),
+        Err(first)
+    );
+    assert_eq!(
+        array.compare_exchange_at(1 << 20, first, second, &guard),
+        Ok(second)
+    );
+    assert_eq!(
+        array.compare_exchange_at(37, Shared::null(), first, &guard),
+        Ok(first)
+    );
+    assert_eq!(array.get(1 << 20, &guard).load(Relaxed, &guard), second);
+    assert_eq!(array.get(37, &guard).load(Relaxed, &guard), first);
+
+    drop(array);
+    unsafe {
+        drop(first.into_owned());
+        drop(second.into_owned());
+    }
+}
+
+#[test]
+fn allocated_bytes_height() {
+    let array = GrowableArray::<usize, 4>::new();
+    let guard = pin();
+    assert_eq!(array.allocated_bytes(), 0);
+    assert_eq!(array.height(&guard), 0);
+
+    // lookups do not allocate.
+    assert!(array.get_if_exists(1 << 12, &guard).is_none());
+    assert!(array.get_if_exists(0, &guard).is_none());
+    assert_eq!(array.iter(&guard).count(), 0);
+    assert_eq!(array.allocated_bytes(), 0);
+
+    let _ = array.get(3, &guard);
+    let segment = array.allocated_bytes();
+    assert_ne!(segment, 0);
+    assert_eq!(array.height(&guard), 0);
+
+    // the root grows twice, and a segment is allocated at each level below the root.
+    let _ = array.get(1 << 12, &guard);
+    assert_eq!(array.height(&guard), 3);
+    assert_eq!(array.allocated_bytes(), 7 * segment);
+    let _ = array.get(1 << 12, &guard);
+    let _ = array.get(2, &guard);
+    assert_eq!(array.allocated_bytes(), 7 * segment);
+
+    array.truncate(15, &guard);
+    assert_eq!(array.height(&guard), 0);
+    assert_eq!(array.allocated_bytes(), segment);
+}
+
+fn get_many_same_as_get<const LOGSIZE: usize>() {
+    let array = GrowableArray::<usize, LOGSIZE>::new();
+    let other = GrowableArray::<usize, LOGSIZE>::new();
+    let guard = pin();
+    assert!(array.get_many(&[], &guard).is_empty());
+
+    let indices = [37, 0, 1 << 20, 38, 37, 5, 1 << 20 | 1, 1 << 40, 1024];
+    let slots = array.get_many(&indices, &guard);
+    assert_eq!(slots.len(), indices.len());
+    for (&i, &slot) in indices.iter().zip(&slots) {
+        assert!(core::ptr::eq(slot, array.get(i, &guard)));
+        let _ = other.get(i, &guard);
+    }
+    // the same segments are allocated as with `get`.
+    assert_eq!(array.allocated_bytes(), other.allocated_bytes());
+}
+
+#[test]
+fn get_many() {
+    get_many_same_as_get::<10>();
+    get_many_same_as_get::<2>();
+    get_many_same_as_get::<3>();
+}
+
+fn truncate_above<const LOGSIZE: usize>() {
+    let array = GrowableArray::<usize, LOGSIZE>::new();
+    let guard = pin();
+
+    let indices = [0, 3, 37, 1024, 5000, 1 << 20, 1 << 40];
+    let values = indices.map(|i| Box::into_raw(Box::new(i)));
+    for (&i, &value) in indices.iter().zip(&values) {
+        array
+            .get(i, &guard)
+            .store(Shared::from(value as *const _), Relaxed);
+    }
+
+    array.truncate(5000, &guard);
+    let occupied = array.iter(&guard).map(|(i, _)| i).collect::<Vec<_>>();
+    assert_eq!(occupied, [0, 3, 37, 1024, 5000]);
+    assert!(array.get_if_exists(1 << 20, &guard).is_none());
+    assert!(array.get_if_exists(1 << 40, &guard).is_none());
+
+    array.truncate(40, &guard);
+    for (&i, &value) in indices.iter().zip(&values).take(3) {
+        let ptr = array
+            .get_if_exists(i, &guard)
+            .unwrap()
+            .load(Relaxed, &guard);
+        assert_eq!(ptr, Shared::from(value as *const _));
+    }
+    assert!(array.get_if_exists(5000, &guard).is_none());
+
+    // the array grows again after truncated.
+    array
+        .get(1 << 40, &guard)
+        .store(Shared::from(values[6] as *const _), Relaxed);
+    let occupied = array.iter(&guard).map(|(i, _)| i).collect::<Vec<_>>();
+    assert_eq!(occupied, [0, 3, 37, 1 << 40]);
+
+    drop(array);
+    for value in values {
+        drop(unsafe { Box::from_raw(value) });
+    }
+}
+
+#[test]
+fn truncate() {
+    truncate_above::<10>();
+    truncate_above::<2>();
+    truncate_above::<3>();
+}
+
+// `get` and `get_many` return the slots of their indices while `truncate` lowers the root.
+#[test]
+fn truncate_concurrent_get() {
+    const THREADS: usize = scale(4, 2);
+    const STEPS: usize = scale(4096 * 16, 64);
+    const INDICES: usize = 4096;
+
+    let array = GrowableArray::<usize, 2>::new();
+    // Slot `i` only ever holds a pointer to `values[i]`.
+    let values = (0..INDICES).collect::<Vec<_>>();
+    let done = AtomicBool::new(false);
+    scope(|s| {
+        let _ = s.spawn(|| {
+            let mut rng = rand::thread_rng();
+            while !done.load(Relaxed) {
+                array.truncate(rng.gen_range(0..INDICES >> 6), &pin());
+            }
+        });
+        let workers = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut rng = rand::thread_rng();
+                    for _ in 0..STEPS {
+                        let guard = pin();
+                        let index = rng.gen_range(0..INDICES);
+                        let value = Shared::from(&values[index] as *const _);
+                        let current =
+                            match array.compare_exchange_at(index, Shared::null(), value, &guard) {
+                                Ok(new) => new,
+                                Err(current) => current,
+                            };
+                        assert_eq!(unsafe { *current.deref() }, index);
+
+                        let indices = [index, rng.gen_range(0..INDICES)];
+                        for (&index, slot) in indices.iter().zip(array.get_many(&indices, &guard)) {
+                            if let Some(&value) = unsafe { slot.load(Acquire, &guard).as_ref() } {
+                                assert_eq!(value, index);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let results = workers.into_iter().map(|w| w.join()).collect::<Vec<_>>();
+        done.store(true, Relaxed);
+        for result in results {
+            result.unwrap();
+        }
+    });
+}
+
 #[test]
 fn stress_sequential() {
-    const STEPS: usize = 4096;
+    const STEPS: usize = scale(4096, 128);
     map::stress_sequential::<_, _, ArrayMap<usize>>(STEPS);
 }
 
 #[test]
 fn lookup_concurrent() {
-    const THREADS: usize = 4;
-    const STEPS: usize = 4096;
+    const THREADS: usize = scale(4, 2);
+    const STEPS: usize = scale(4096, 64);
     map::lookup_concurrent::<_, _, ArrayMap<usize>>(THREADS, STEPS);
 }
 
 #[test]
 fn insert_concurrent() {
-    const THREADS: usize = 8;
-    const STEPS: usize = 4096 * 4;
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(4096 * 4, 64);
     map::insert_concurrent::<_, _, ArrayMap<usize>>(THREADS, STEPS);
 }
 
 #[test]
 fn stress_concurrent() {
-    const THREADS: usize = if cfg!(sanitize = "thread") { 4 } else { 16 };
-    const STEPS: usize = 4096 * if cfg!(sanitize = "thread") { 128 } else { 512 };
+    const THREADS: usize = scale(if cfg!(sanitize = "thread") { 4 } else { 16 }, 2);
+    const STEPS: usize = scale(
+        4096 * if cfg!(sanitize = "thread") { 128 } else { 512 },
+        128,
+    );
     map::stress_concurrent::<_, _, ArrayMap<usize>>(THREADS, STEPS);
 }
 
 #[test]
 fn log_concurrent() {
-    const THREADS: usize = if cfg!(sanitize = "thread") { 4 } else { 16 };
-    const STEPS: usize = 4096 * if cfg!(sanitize = "thread") { 16 } else { 64 };
+    const THREADS: usize = scale(if cfg!(sanitize = "thread") { 4 } else { 16 }, 2);
+    const STEPS: usize = scale(4096 * if cfg!(sanitize = "thread") { 16 } else { 64 }, 128);
     map::log_concurrent::<_, _, ArrayMap<usize>>(THREADS, STEPS);
 }
@@ -0,0 +1,97 @@
+//! Checks that `GrowableArray` frees all of its segments. It is a separate test binary, as the
+//! counting allocator is global.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::Ordering::*;
+use core::sync::atomic::{AtomicUsize, fence};
+use std::alloc::System;
+use std::sync::{Barrier, Mutex};
+use std::thread::scope;
+
+use crossbeam_epoch::pin;
+use cs431_homework::GrowableArray;
+use cs431_homework::test::scale;
+
+const LOGSIZE: usize = 5;
+
+/// Allocator that counts the live pointer arrays of the segments of `GrowableArray<_, LOGSIZE>`.
+struct Counting;
+
+static SEGMENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The tests share `SEGMENTS`, so they should not run concurrently.
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn is_segment(layout: Layout) -> bool {
+    layout == Layout::array::<usize>(1 << LOGSIZE).unwrap()
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if is_segment(layout) {
+            let _ = SEGMENTS.fetch_add(1, Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if is_segment(layout) {
+            let _ = SEGMENTS.fetch_sub(1, Relaxed);
+        }
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Threads racing to grow the root and to install the same segments should free the segments of
+/// the losing CASes.
+#[test]
+fn racing_get() {
+    const THREADS: usize = scale(8, 2);
+    const ROUNDS: usize = scale(256, 4);
+
+    let _lock = LOCK.lock().unwrap();
+    for _ in 0..ROUNDS {
+        let array = GrowableArray::<usize, LOGSIZE>::new();
+        let barrier = Barrier::new(THREADS);
+        scope(|s| {
+            for t in 0..THREADS {
+                let (array, barrier) = (&array, &barrier);
+                let _ = s.spawn(move || {
+                    let guard = pin();
+                    let _ = barrier.wait();
+                    let _ = array.get(usize::MAX >> 1, &guard);
+                    let _ = array.get(t << (LOGSIZE * 3), &guard);
+                });
+            }
+        });
+        drop(array);
+        fence(SeqCst);
+        assert_eq!(SEGMENTS.load(Relaxed), 0);
+    }
+}
+
+/// The segments detached by `truncate` should be freed once the epoch advances.
+#[test]
+fn truncate() {
+    let _lock = LOCK.lock().unwrap();
+    let array = GrowableArray::<usize, LOGSIZE>::new();
+    {
+        let guard = pin();
+        for i in 0..64 {
+            let _ = array.get(i << (LOGSIZE * 2), &guard);
+        }
+        array.truncate(0, &guard);
+    }
+    drop(array);
+
+    for _ in 0..1024 {
+        if SEGMENTS.load(Relaxed) == 0 {
+            break;
+        }
+        pin().flush();
+    }
+    assert_eq!(SEGMENTS.load(Relaxed), 0);
+}
@@ -3,7 +3,8 @@ use core::sync::atomic::{AtomicPtr, Ordering::*};
 use std::thread::{scope, sleep};
 use std::time::Duration;
 
-use cs431_homework::hazard_pointer::{Shield, collect, retire};
+use cs431_homework::hazard_pointer::{HazardPointer, Shield, collect, retire};
+use cs431_homework::test::scale;
 #[cfg(feature = "check-loom")]
 use loom::sync::atomic::{AtomicPtr, Ordering::*};
 use queue::Queue;
@@ -11,8 +12,8 @@ use stack::Stack;
 
 #[test]
 fn counter() {
-    const THREADS: usize = 4;
-    const ITER: usize = 1024 * 16;
+    const THREADS: usize = scale(4, 2);
+    const ITER: usize = scale(1024 * 16, 128);
 
     let count = AtomicPtr::new(Box::leak(Box::new(0usize)));
     scope(|s| {
@@ -49,8 +50,8 @@ fn counter() {
 // like `counter`, but trigger interesting interleaving using `sleep` and always call `collect`.
 #[test]
 fn counter_sleep() {
-    const THREADS: usize = 4;
-    const ITER: usize = 1024 * 16;
+    const THREADS: usize = scale(4, 2);
+    const ITER: usize = scale(1024 * 16, 64);
 
     let count = AtomicPtr::new(Box::leak(Box::new(0usize)));
     scope(|s| {
@@ -93,10 +94,46 @@ fn counter_sleep() {
     unsafe { retire(cur) };
 }
 
+// like `counter`, but with the typed `HazardPointer`.
+#[test]
+fn counter_typed() {
+    const THREADS: usize = scale(4, 2);
+    const ITER: usize = scale(1024 * 16, 128);
+
+    let count = AtomicPtr::new(Box::leak(Box::new(0usize)));
+    scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                let mut hazard = HazardPointer::default();
+                for _ in 0..ITER {
+                    loop {
+                        let cur = unsafe { hazard.protect(&count) };
+                        let new_ptr = Box::into_raw(Box::new(*cur + 1));
+                        if count
+                            .compare_exchange(cur.as_ptr(), new_ptr, AcqRel, Acquire)
+                            .is_ok()
+                        {
+                            let cur_ptr = cur.as_ptr();
+                            drop(cur);
+                            unsafe { retire(cur_ptr) };
+                            break;
+                        }
+                        drop(unsafe { Box::from_raw(new_ptr) });
+                    }
+                }
+            });
+        }
+    });
+    let cur = count.load(Acquire);
+    // exclusive access
+    assert_eq!(unsafe { *cur }, THREADS * ITER);
+    unsafe { retire(cur) };
+}
+
 #[test]
 fn stack() {
-    const THREADS: usize = 8;
-    const ITER: usize = 1024 * 16;
+    const THREADS: usize = scale(8, 2);
+    const ITER: usize = scale(1024 * 16, 128);
 
     let stack = Stack::default();
     scope(|s| {
@@ -115,8 +152,8 @@ fn stack() {
 
 #[test]
 fn queue() {
-    const THREADS: usize = 8;
-    const ITER: usize = 1024 * 32;
+    const THREADS: usize = scale(8, 2);
+    const ITER: usize = scale(1024 * 32, 128);
 
     let queue = Queue::default();
     scope(|s| {
@@ -134,8 +171,8 @@ fn queue() {
 
 #[test]
 fn stack_queue() {
-    const THREADS: usize = 8;
-    const ITER: usize = 1024 * 16;
+    const THREADS: usize = scale(8, 2);
+    const ITER: usize = scale(1024 * 16, 128);
 
     let stack = Stack::default();
     let queue = Queue::default();
@@ -253,6 +290,95 @@ mod sync {
     }
 }
 
+/// Models of a reader protecting a pointer racing with a writer unlinking and retiring it. The
+/// pointee is a loom `UnsafeCell`, so loom reports a use-after-free as a causality violation
+/// between the read of the reader and the write of the destructor.
+#[cfg(feature = "check-loom")]
+mod model {
+    use core::ptr;
+
+    use cs431_homework::hazard_pointer::*;
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::sync::atomic::AtomicPtr;
+    use loom::sync::atomic::Ordering::*;
+    use loom::{model, thread};
+
+    struct Node {
+        value: UnsafeCell<usize>,
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.value.with_mut(|v| unsafe { *v = 0 });
+        }
+    }
+
+    fn new_node() -> *mut Node {
+        Box::into_raw(Box::new(Node {
+            value: UnsafeCell::new(123),
+        }))
+    }
+
+    /// Unlink the node in `atomic`, retire it, and collect.
+    fn unlink_retire_collect(atomic: &AtomicPtr<Node>) {
+        let node = atomic.swap(ptr::null_mut(), AcqRel);
+        unsafe { retire(node) };
+        collect();
+    }
+
+    fn read(node: *mut Node) {
+        unsafe { &*node }
+            .value
+            .with(|v| assert_eq!(unsafe { *v }, 123));
+    }
+
+    #[test]
+    fn protect_retire() {
+        model(|| {
+            let atomic = Arc::new(AtomicPtr::new(new_node()));
+
+            let th = {
+                let atomic = atomic.clone();
+                thread::spawn(move || {
+                    let shield = Shield::default();
+                    let node = shield.protect(&atomic);
+                    if !node.is_null() {
+                        read(node);
+                    }
+                })
+            };
+
+            unlink_retire_collect(&atomic);
+            th.join().unwrap();
+        })
+    }
+
+    #[test]
+    fn load_try_protect_retire() {
+        model(|| {
+            let atomic = Arc::new(AtomicPtr::new(new_node()));
+
+            let th = {
+                let atomic = atomic.clone();
+                thread::spawn(move || {
+                    let node = atomic.load(Relaxed);
+                    if node.is_null() {
+                        return;
+                    }
+                    let shield = Shield::default();
+                    if shield.try_protect(node, &atomic).is_ok() {
+                        read(node);
+                    }
+                })
+            };
+
+            unlink_retire_collect(&atomic);
+            th.join().unwrap();
+        })
+    }
+}
+
 mod stack {
     use core::mem::MaybeUninit;
     use core::ptr;
@@ -1,11 +1,15 @@
+use std::cmp::Ordering;
 use std::collections::HashSet;
-use std::iter::zip;
+use std::iter::{from_fn, zip};
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::{Acquire, Release};
 use std::thread;
 
 use cs431_homework::test::adt::set;
-use cs431_homework::{ConcurrentSet, FineGrainedListSet};
+use cs431_homework::test::scale;
+use cs431_homework::{ConcurrentSet, FineGrainedListSet, PoisonPolicy};
 use rand::prelude::*;
 
 #[test]
@@ -21,31 +25,297 @@ fn smoke() {
     assert!(set.remove(&3));
 }
 
+// A sorted batch is inserted and removed in a single traversal each.
+#[test]
+fn cursor() {
+    let set = FineGrainedListSet::new();
+    assert!(set.insert(4));
+    {
+        let mut cursor = set.cursor();
+        for i in 1..8 {
+            assert_eq!(cursor.insert(i), i != 4);
+            assert_eq!(cursor.current(), Some(&i));
+        }
+    }
+    assert!(set.iter().copied().eq(1..8));
+    {
+        let mut cursor = set.cursor();
+        assert!(cursor.seek_from_current(&2));
+        assert!(cursor.remove(&3));
+        assert_eq!(cursor.current(), Some(&4));
+        assert!(!cursor.remove(&10));
+        assert_eq!(cursor.current(), None);
+    }
+    assert!(set.iter().copied().eq([1, 2, 4, 5, 6, 7]));
+}
+
+#[test]
+#[should_panic(expected = "ascending order")]
+fn cursor_descending() {
+    let set = FineGrainedListSet::new();
+    let mut cursor = set.cursor();
+    assert!(cursor.insert(2));
+    let _ = cursor.seek_from_current(&3);
+    let _ = cursor.seek_from_current(&1);
+}
+
+#[test]
+fn len() {
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(1024, 64);
+
+    let set = FineGrainedListSet::new();
+    assert!(set.is_empty());
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let set = &set;
+            let _ = s.spawn(move || {
+                for i in 0..STEPS {
+                    assert!(set.insert(t * STEPS + i));
+                }
+                for i in (0..STEPS).step_by(2) {
+                    assert!(set.remove(&(t * STEPS + i)));
+                }
+            });
+        }
+    });
+    assert_eq!(set.len(), THREADS * STEPS / 2);
+    assert_eq!(set.len(), set.iter().count());
+}
+
+#[test]
+fn iter_from_range() {
+    let set = FineGrainedListSet::new();
+    for i in (0..20).step_by(2) {
+        assert!(set.insert(i));
+    }
+    assert!(set.iter_from(&7).copied().eq((8..20).step_by(2)));
+    assert!(set.iter_from(&8).copied().eq((8..20).step_by(2)));
+    assert_eq!(set.iter_from(&20).next(), None);
+
+    let range = |r: (Bound<i32>, Bound<i32>)| set.range(r).copied().collect::<Vec<_>>();
+    assert_eq!(range((Included(4), Excluded(10))), [4, 6, 8]);
+    assert_eq!(range((Excluded(4), Included(10))), [6, 8, 10]);
+    assert_eq!(range((Excluded(5), Unbounded)), [6, 8, 10, 12, 14, 16, 18]);
+    assert_eq!(range((Unbounded, Excluded(3))), [0, 2]);
+    assert!(set.range(7..8).next().is_none());
+}
+
+#[test]
+fn take() {
+    const THREADS: usize = scale(8, 2);
+    const KEYS: usize = scale(512, 32);
+
+    let set = FineGrainedListSet::new();
+    assert!(set.insert("cat".to_string()));
+    assert_eq!(set.take(&"cat".to_string()), Some("cat".to_string()));
+    assert_eq!(set.take(&"cat".to_string()), None);
+
+    // each key is taken by exactly one thread, while the others read it.
+    for i in 0..KEYS {
+        assert!(set.insert(i.to_string()));
+    }
+    let taken = thread::scope(|s| {
+        let handles = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    (0..KEYS)
+                        .filter_map(|i| {
+                            let _ = set.contains(&i.to_string());
+                            set.take(&i.to_string())
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(taken.len(), KEYS);
+    assert_eq!(
+        taken.into_iter().collect::<HashSet<_>>(),
+        (0..KEYS).map(|i| i.to_string()).collect()
+    );
+}
+
+#[test]
+fn pop_min() {
+    const THREADS: usize = scale(8, 2);
+    const KEYS: usize = scale(4096, 64);
+
+    let set = FineGrainedListSet::new();
+    assert_eq!(set.pop_min(), None);
+    for i in [3, 1, 2] {
+        assert!(set.insert(i));
+    }
+    assert_eq!(set.pop_min(), Some(1));
+    assert_eq!(set.pop_min(), Some(2));
+    assert_eq!(set.pop_min(), Some(3));
+    assert_eq!(set.pop_min(), None);
+
+    // Each thread sees its values in order.
+    for i in 0..KEYS {
+        assert!(set.insert(i));
+    }
+    let popped = thread::scope(|s| {
+        let handles = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    let popped = from_fn(|| set.pop_min()).collect::<Vec<_>>();
+                    assert!(popped.windows(2).all(|k| k[0] < k[1]));
+                    popped
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(popped.len(), KEYS);
+    assert_eq!(
+        popped.into_iter().collect::<HashSet<_>>(),
+        (0..KEYS).collect()
+    );
+}
+
+/// Key whose comparisons panic if either side is `Touchy(0)`.
+#[derive(Debug)]
+struct Touchy(usize);
+
+impl PartialEq for Touchy {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Touchy {}
+
+impl PartialOrd for Touchy {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Touchy {
+    fn cmp(&self, other: &Self) -> Ordering {
+        assert!(self.0 != 0 && other.0 != 0, "compared Touchy(0)");
+        self.0.cmp(&other.0)
+    }
+}
+
+/// Panics in a comparison while the locks of the head and of the first node are held.
+fn poison(set: &FineGrainedListSet<Touchy>) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| set.insert(Touchy(0))));
+    assert!(result.is_err());
+}
+
+#[test]
+fn poison_recover() {
+    let set = FineGrainedListSet::with_poison_policy(PoisonPolicy::Recover);
+    for i in [1, 2, 3] {
+        assert!(set.insert(Touchy(i)));
+    }
+    poison(&set);
+
+    // The list is intact, and the poisoned locks are acquired again.
+    assert!(set.iter().map(|t| t.0).eq([1, 2, 3]));
+    assert!(set.insert(Touchy(4)));
+    assert!(set.remove(&Touchy(1)));
+    assert!(set.contains(&Touchy(2)));
+    assert_eq!(set.take(&Touchy(4)).map(|t| t.0), Some(4));
+    assert!(set.iter().map(|t| t.0).eq([2, 3]));
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn poison_panic() {
+    let set = FineGrainedListSet::new();
+    for i in [1, 2, 3] {
+        assert!(set.insert(Touchy(i)));
+    }
+    poison(&set);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| set.contains(&Touchy(2))));
+    assert!(result.is_err());
+    // Dropping the list does not panic.
+}
+
+#[test]
+fn bulk() {
+    let mut set = [5, 1, 9, 3, 1, 7]
+        .into_iter()
+        .collect::<FineGrainedListSet<_>>();
+    assert!(set.iter().copied().eq([1, 3, 5, 7, 9]));
+    assert_eq!(set.len(), 5);
+
+    // runs between the existing nodes and after the last one.
+    set.extend([4, 12, 0, 2, 11, 10, 5, 6]);
+    assert!(set.iter().copied().eq((0..8).chain(9..13)));
+    assert_eq!(set.len(), 12);
+    assert_eq!(set.append_sorted(vec![8, 3, 13, 8]), 2);
+    assert!(set.iter().copied().eq(0..14));
+    assert_eq!(set.len(), 14);
+}
+
+#[test]
+fn append_sorted_concurrent() {
+    const THREADS: usize = scale(8, 2);
+    const KEYS: usize = scale(4096, 64);
+
+    let set = FineGrainedListSet::new();
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let set = &set;
+            let _ = s.spawn(move || {
+                let mut keys = (0..KEYS).collect::<Vec<_>>();
+                keys.shuffle(&mut thread_rng());
+                let inserted = set.append_sorted(keys);
+                assert!(inserted <= KEYS);
+                assert!(set.insert(KEYS + t));
+            });
+        }
+    });
+    assert!(set.iter().copied().eq(0..KEYS + THREADS));
+    assert_eq!(set.len(), KEYS + THREADS);
+}
+
 #[test]
 fn stress_sequential() {
-    const STEPS: usize = 4096;
+    const STEPS: usize = scale(4096, 128);
     set::stress_sequential::<_, FineGrainedListSet<u8>>(STEPS);
 }
 
 #[test]
 fn stress_concurrent() {
-    const THREADS: usize = 16;
-    const STEPS: usize = 4096 * 16;
+    const THREADS: usize = scale(16, 2);
+    const STEPS: usize = scale(4096 * 16, 128);
     set::stress_concurrent::<_, FineGrainedListSet<u8>>(THREADS, STEPS);
 }
 
 #[test]
 fn log_concurrent() {
-    const THREADS: usize = 16;
-    const STEPS: usize = 4096 * 16;
+    const THREADS: usize = scale(16, 2);
+    const STEPS: usize = scale(4096 * 16, 128);
     set::log_concurrent::<_, FineGrainedListSet<u8>>(THREADS, STEPS);
 }
 
+/// Checks the results against a model while the neighboring keys are updated concurrently, so
+/// that the races on validation and retries are exercised.
+#[test]
+fn model_concurrent() {
+    const THREADS: usize = scale(if cfg!(sanitize = "thread") { 4 } else { 16 }, 2);
+    const STEPS: usize = scale(4096 * 4, 128);
+    set::model_concurrent::<FineGrainedListSet<usize>>(THREADS, STEPS);
+}
+
 /// Check the consistency of iterator while other operations are running concurrently.
 #[test]
 fn iter_consistent() {
-    const THREADS: usize = 15;
-    const STEPS: usize = 4096 * 16;
+    const THREADS: usize = scale(15, 2);
+    const STEPS: usize = scale(4096 * 16, 128);
 
     let set = FineGrainedListSet::new();
 
@@ -3,3 +3,4 @@
 
 mod fine_grained;
 mod optimistic_fine_grained;
+mod sorted_map;
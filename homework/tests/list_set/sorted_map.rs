@@ -0,0 +1,100 @@
+use std::thread;
+
+use crossbeam_epoch::pin;
+use cs431_homework::test::adt::map;
+use cs431_homework::test::scale;
+use cs431_homework::{ConcurrentMap, ConcurrentSortedMap, StripedHashMap};
+
+#[test]
+fn smoke() {
+    let map = ConcurrentSortedMap::new();
+    let guard = pin();
+
+    assert_eq!(map.insert(37, "a"), Ok(()));
+    assert_eq!(map.insert(42, "b"), Ok(()));
+    assert_eq!(map.insert(1, "c"), Ok(()));
+    assert_eq!(map.insert(37, "d"), Err("d"));
+    assert_eq!(map.get(&37, &guard), Some(&"a"));
+    assert_eq!(map.get(&2, &guard), None);
+    assert_eq!(
+        map.iter(&guard).collect::<Result<Vec<_>, _>>(),
+        Ok(vec![(&1, &"c"), (&37, &"a"), (&42, &"b")])
+    );
+
+    // The removed value is valid while the guard is pinned.
+    let removed = map.remove(&37, &guard);
+    assert_eq!(removed, Some(&"a"));
+    assert_eq!(map.remove(&37, &guard), None);
+    assert_eq!(map.get(&37, &guard), None);
+    assert_eq!(removed, Some(&"a"));
+}
+
+#[test]
+fn insert_concurrent_distinct_values() {
+    const THREADS: usize = scale(8, 2);
+    const KEYS: usize = scale(512, 32);
+
+    // Each key keeps the value of the thread that inserted it first.
+    let map = ConcurrentSortedMap::new();
+    let winners = thread::scope(|s| {
+        let handles = (0..THREADS)
+            .map(|t| {
+                let map = &map;
+                s.spawn(move || (0..KEYS).filter(|&k| map.insert(k, t).is_ok()).count())
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .sum::<usize>()
+    });
+    assert_eq!(winners, KEYS);
+
+    let guard = pin();
+    let entries = map.iter(&guard).collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(entries.iter().map(|&(&k, _)| k).eq(0..KEYS));
+    for (k, &t) in entries {
+        assert_eq!(map.get(k, &guard), Some(&t));
+    }
+}
+
+#[test]
+fn len_concurrent() {
+    const THREADS: usize = scale(4, 2);
+    const STEPS: usize = scale(4096, 64);
+
+    // The threads race to insert and remove the same key, while the length is checked.
+    let map = ConcurrentSortedMap::new();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                let guard = pin();
+                for i in 0..STEPS {
+                    let _ = map.insert(0, i);
+                    let _ = map.remove(&0, &guard);
+                    assert!(ConcurrentMap::len(&map) <= THREADS);
+                }
+            });
+        }
+    });
+    assert_eq!(ConcurrentMap::len(&map), 0);
+}
+
+#[test]
+fn stress_sequential() {
+    const STEPS: usize = scale(4096, 128);
+    map::stress_sequential::<String, usize, ConcurrentSortedMap<_, _>>(STEPS);
+}
+
+#[test]
+fn log_concurrent() {
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(4096 * 4, 128);
+    map::log_concurrent::<u8, usize, ConcurrentSortedMap<_, _>>(THREADS, STEPS);
+}
+
+#[test]
+fn cross_check_striped() {
+    const STEPS: usize = scale(4096, 128);
+    map::cross_check::<u8, usize, ConcurrentSortedMap<_, _>, StripedHashMap<_, _>>(STEPS);
+}
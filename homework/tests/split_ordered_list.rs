@@ -1,8 +1,16 @@
 #![feature(cfg_sanitize)]
 
+use core::hash::{BuildHasherDefault, Hasher};
+use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+use std::thread::scope;
+
 use crossbeam_epoch as epoch;
+use cs431_homework::hazard_pointer::collect;
+use cs431_homework::reclaim::{Epoch, HazardPointers, Reclaim};
 use cs431_homework::test::adt::map;
-use cs431_homework::{ConcurrentMap, SplitOrderedList};
+use cs431_homework::test::scale;
+use cs431_homework::{ConcurrentMap, SplitOrderedHashMap, SplitOrderedList, StripedHashMap};
 
 #[test]
 pub fn smoke() {
@@ -27,36 +35,605 @@ pub fn smoke() {
     assert_eq!(list.lookup(&37, &guard), None);
 }
 
+#[test]
+pub fn get_or_insert_with_upsert() {
+    let list = SplitOrderedList::new();
+
+    let mut guard = epoch::pin();
+
+    assert_eq!(list.get_or_insert_with(37, || 37, &mut guard), &37);
+    assert_eq!(
+        list.get_or_insert_with(37, || panic!("called"), &mut guard),
+        &37
+    );
+    assert_eq!(list.lookup(&37, &guard), Some(&37));
+
+    assert_eq!(list.upsert(37, 38, &mut guard), Some(&37));
+    assert_eq!(list.lookup(&37, &guard), Some(&38));
+    assert_eq!(list.upsert(42, 42, &mut guard), None);
+    assert_eq!(list.lookup(&42, &guard), Some(&42));
+    assert_eq!(list.delete(&42, &guard), Ok(&42));
+    assert_eq!(list.lookup(&42, &guard), None);
+}
+
+// All the threads get the same value, and each calls the closure at most once.
+#[test]
+fn get_or_insert_with_concurrent() {
+    const THREADS: usize = scale(8, 2);
+    const KEYS: usize = scale(1024, 16);
+
+    let list = SplitOrderedList::new();
+    let calls = AtomicUsize::new(0);
+    let values = scope(|s| {
+        let handles = (0..THREADS)
+            .map(|t| {
+                let (list, calls) = (&list, &calls);
+                s.spawn(move || {
+                    let mut guard = epoch::pin();
+                    (0..KEYS)
+                        .map(|key| {
+                            *list.get_or_insert_with(
+                                key,
+                                || {
+                                    let _ = calls.fetch_add(1, Relaxed);
+                                    t
+                                },
+                                &mut guard,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let guard = epoch::pin();
+    for key in 0..KEYS {
+        let value = *list.lookup(&key, &guard).unwrap();
+        assert!(values.iter().all(|v| v[key] == value));
+    }
+    assert!(calls.load(Relaxed) >= KEYS);
+    assert!(calls.load(Relaxed) <= KEYS * THREADS);
+}
+
+// The keys are always found while they are upserted concurrently, and each value is either
+// replaced by exactly one upsert or left in the list.
+#[test]
+fn upsert_concurrent() {
+    const THREADS: usize = scale(4, 2);
+    const STEPS: usize = scale(4096, 64);
+    const KEYS: usize = 16;
+
+    let list = SplitOrderedList::new();
+    let guard = epoch::pin();
+    // The initial values are `0..KEYS`, and the thread `t` upserts the values from `(t + 1) *
+    // STEPS`.
+    for key in 0..KEYS {
+        assert_eq!(list.insert(key, key, &guard), Ok(()));
+    }
+    let done = AtomicBool::new(false);
+    let mut replaced = scope(|s| {
+        let upserters = (0..THREADS)
+            .map(|t| {
+                let list = &list;
+                s.spawn(move || {
+                    let mut guard = epoch::pin();
+                    (0..STEPS)
+                        .map(|i| {
+                            *list
+                                .upsert(i % KEYS, (t + 1) * STEPS + i, &mut guard)
+                                .unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let reader = s.spawn(|| {
+            while !done.load(Relaxed) {
+                let guard = epoch::pin();
+                for key in 0..KEYS {
+                    assert!(list.lookup(&key, &guard).is_some());
+                }
+            }
+        });
+        let results = upserters.into_iter().map(|u| u.join()).collect::<Vec<_>>();
+        done.store(true, Relaxed);
+        reader.join().unwrap();
+        results
+            .into_iter()
+            .flat_map(Result::unwrap)
+            .collect::<Vec<_>>()
+    });
+
+    replaced.extend((0..KEYS).map(|key| *list.lookup(&key, &guard).unwrap()));
+    replaced.sort_unstable();
+    let values = (0..KEYS)
+        .chain((1..=THREADS).flat_map(|t| t * STEPS..(t + 1) * STEPS))
+        .collect::<Vec<_>>();
+    assert_eq!(replaced, values);
+    assert_eq!(list.len(), KEYS);
+}
+
+#[test]
+pub fn range() {
+    let list = SplitOrderedList::new();
+
+    let guard = epoch::pin();
+    for key in (0..256).step_by(3) {
+        assert_eq!(list.insert(key, key, &guard), Ok(()));
+    }
+    assert_eq!(list.insert(usize::MAX >> 1, 0, &guard), Ok(()));
+
+    let keys = |items: Vec<(usize, &usize)>| items.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+    // narrow ranges are collected from the buckets of their keys.
+    assert_eq!(keys(list.range(10..20, &guard)), [12, 15, 18]);
+    assert_eq!(keys(list.range(9..=18, &guard)), [9, 12, 15, 18]);
+    assert_eq!(keys(list.range(60..70, &guard)), [60, 63, 66, 69]);
+    assert!(list.range(10..10, &guard).is_empty());
+    // wide ranges are collected from the whole list.
+    assert_eq!(
+        keys(list.range(..200, &guard)),
+        (0..200).step_by(3).collect::<Vec<_>>()
+    );
+    assert_eq!(keys(list.range(250.., &guard)), [252, 255, usize::MAX >> 1]);
+    assert_eq!(list.range(.., &guard).len(), list.len());
+}
+
+#[test]
+pub fn iter_len() {
+    let list = SplitOrderedList::new();
+
+    let guard = epoch::pin();
+    assert_eq!(list.iter(&guard).count(), 0);
+    assert!(list.is_empty());
+
+    let keys = [0, 1, 37, 42, 1024, 1 << 40, usize::MAX >> 1];
+    for key in keys {
+        assert_eq!(list.insert(key, key + 1, &guard), Ok(()));
+    }
+    assert_eq!(list.delete(&42, &guard), Ok(&43));
+    assert_eq!(list.len(), keys.len() - 1);
+
+    let items = list.iter(&guard).collect::<Vec<_>>();
+    let mut expected = keys
+        .into_iter()
+        .filter(|&key| key != 42)
+        .map(|key| (key, key + 1))
+        .collect::<Vec<_>>();
+    // in split order.
+    expected.sort_by_key(|(key, _)| key.reverse_bits());
+    assert_eq!(
+        items,
+        expected.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+pub fn snapshot_from_iter() {
+    let list = SplitOrderedList::new();
+
+    let guard = epoch::pin();
+    for key in 0..100 {
+        assert_eq!(list.insert(key, key.to_string(), &guard), Ok(()));
+    }
+    let snapshot = list.snapshot(&guard);
+    assert_eq!(snapshot.len(), 100);
+    assert!(snapshot.is_sorted_by_key(|(key, _)| key.reverse_bits()));
+
+    // the snapshot is not affected by later changes.
+    assert_eq!(list.delete(&37, &guard), Ok(&"37".to_string()));
+    assert!(snapshot.contains(&(37, "37".to_string())));
+
+    let rebuilt = snapshot.into_iter().collect::<SplitOrderedList<_>>();
+    assert_eq!(rebuilt.len(), 100);
+    for key in 0..100 {
+        assert_eq!(rebuilt.lookup(&key, &guard), Some(&key.to_string()));
+    }
+
+    // later values replace earlier ones.
+    let list = [(1, 1), (2, 2), (1, 3)]
+        .into_iter()
+        .collect::<SplitOrderedList<_>>();
+    assert_eq!(list.len(), 2);
+    assert_eq!(list.lookup(&1, &guard), Some(&3));
+}
+
+// The keys with the MSB set are told apart from the ones without it.
+#[test]
+pub fn full_key_range() {
+    let list = SplitOrderedList::new();
+
+    let guard = epoch::pin();
+    let keys = [0, 1, usize::MAX >> 1, 1 << 63, (1 << 63) | 1, usize::MAX];
+    for key in keys {
+        assert_eq!(list.insert(key, key, &guard), Ok(()));
+    }
+    for key in keys {
+        assert_eq!(list.lookup(&key, &guard), Some(&key));
+    }
+    assert_eq!(list.iter(&guard).count(), keys.len());
+    assert_eq!(
+        list.range((1 << 63).., &guard),
+        [
+            (1 << 63, &(1 << 63)),
+            ((1 << 63) | 1, &((1 << 63) | 1)),
+            (usize::MAX, &usize::MAX)
+        ]
+    );
+    assert_eq!(list.range(..=usize::MAX, &guard).len(), keys.len());
+    assert_eq!(
+        list.range(usize::MAX - 2.., &guard),
+        [(usize::MAX, &usize::MAX)]
+    );
+
+    assert_eq!(list.delete(&(1 << 63), &guard), Ok(&(1 << 63)));
+    assert_eq!(list.lookup(&(1 << 63), &guard), None);
+    assert_eq!(list.lookup(&0, &guard), Some(&0));
+}
+
+// Iterating while other threads delete visits each remaining item once, in split order.
+#[test]
+fn iter_concurrent_delete() {
+    const KEYS: usize = scale(4096, 64);
+
+    let list = SplitOrderedList::new();
+    let guard = epoch::pin();
+    for key in 0..KEYS {
+        assert_eq!(list.insert(key, key, &guard), Ok(()));
+    }
+    scope(|s| {
+        let _ = s.spawn(|| {
+            let guard = epoch::pin();
+            for key in (0..KEYS).step_by(2) {
+                assert_eq!(list.delete(&key, &guard), Ok(&key));
+            }
+        });
+        let keys = list.iter(&guard).map(|(k, _)| k).collect::<Vec<_>>();
+        assert!(keys.is_sorted_by_key(|key| key.reverse_bits()));
+        assert!(keys.windows(2).all(|w| w[0] != w[1]));
+        assert!((1..KEYS).step_by(2).all(|key| keys.contains(&key)));
+    });
+    assert_eq!(list.iter(&guard).count(), KEYS / 2);
+}
+
+// The keys that stay in the list are always found while it grows and shrinks.
+#[test]
+fn shrink_concurrent_lookup() {
+    const THREADS: usize = scale(4, 2);
+    const ROUNDS: usize = scale(64, 2);
+    const KEYS: usize = scale(4096, 64);
+    // Few enough to shrink the list below a segment of `buckets`, so that its root is lowered.
+    const STAYING: usize = 128;
+
+    let list = SplitOrderedList::with_config(2, 1);
+    let guard = epoch::pin();
+    // The odd keys below `STAYING` stay, and the even keys come and go.
+    for key in (1..STAYING).step_by(2) {
+        assert_eq!(list.insert(key, key, &guard), Ok(()));
+    }
+    let done = AtomicBool::new(false);
+    scope(|s| {
+        let churners = (0..THREADS)
+            .map(|t| {
+                let list = &list;
+                s.spawn(move || {
+                    let guard = epoch::pin();
+                    for _ in 0..ROUNDS {
+                        for key in (t * 2..KEYS).step_by(THREADS * 2) {
+                            assert_eq!(list.insert(key, key, &guard), Ok(()));
+                        }
+                        for key in (t * 2..KEYS).step_by(THREADS * 2) {
+                            assert_eq!(list.delete(&key, &guard), Ok(&key));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let reader = s.spawn(|| {
+            while !done.load(Relaxed) {
+                let guard = epoch::pin();
+                for key in (1..STAYING).step_by(2) {
+                    assert_eq!(list.lookup(&key, &guard), Some(&key));
+                }
+            }
+        });
+        let results = churners.into_iter().map(|c| c.join()).collect::<Vec<_>>();
+        done.store(true, Relaxed);
+        for result in results {
+            result.unwrap();
+        }
+        reader.join().unwrap();
+    });
+    for key in 0..KEYS {
+        assert_eq!(
+            list.lookup(&key, &guard),
+            (key < STAYING && key % 2 == 1).then_some(&key)
+        );
+    }
+}
+
+fn reclaim_smoke<R: Reclaim>(reclaim: R) {
+    let list = SplitOrderedList::with_config_in(2, 1, reclaim);
+    let mut guard = R::guard();
+    for key in 0..256 {
+        assert_eq!(list.insert(key, key.to_string(), &guard), Ok(()));
+    }
+    assert_eq!(
+        list.insert(7, "x".to_string(), &guard),
+        Err("x".to_string())
+    );
+    assert_eq!(
+        list.get_or_insert_with(7, || panic!("called"), &mut guard),
+        "7"
+    );
+    assert_eq!(
+        list.get_or_insert_with(256, || "256".to_string(), &mut guard),
+        "256"
+    );
+    assert_eq!(
+        list.upsert(7, "y".to_string(), &mut guard),
+        Some(&"7".to_string())
+    );
+    assert_eq!(list.get(&7, &mut guard), Some(&"y".to_string()));
+    for key in (0..256).step_by(2) {
+        assert_eq!(
+            list.unlink(&key, &mut guard).cloned(),
+            (key != 7).then(|| key.to_string())
+        );
+        assert_eq!(list.unlink(&key, &mut guard), None);
+    }
+    assert_eq!(list.len(), 129);
+    for key in 0..256 {
+        assert_eq!(list.get(&key, &mut guard).is_some(), key % 2 == 1);
+    }
+    let mut expected = (1..256usize)
+        .step_by(2)
+        .chain([256])
+        .map(|key| (key, key.to_string()))
+        .collect::<Vec<_>>();
+    expected[3].1 = "y".to_string();
+    expected.sort_by_key(|(key, _)| key.reverse_bits());
+    assert_eq!(list.snapshot(&guard), expected);
+}
+
+#[test]
+fn reclaim_smoke_epoch() {
+    reclaim_smoke(Epoch);
+}
+
+#[test]
+fn reclaim_smoke_hazard_pointers() {
+    reclaim_smoke(HazardPointers);
+    collect();
+}
+
+// As `shrink_concurrent_lookup`, but the values are also upserted and read while they are
+// replaced, so that the values and nodes are reclaimed with `R` during the lookups.
+fn reclaim_concurrent<R: Reclaim>(reclaim: R) {
+    const THREADS: usize = scale(4, 2);
+    const ROUNDS: usize = scale(32, 2);
+    const KEYS: usize = scale(1024, 64);
+    const STAYING: usize = 64;
+
+    let list = SplitOrderedList::with_config_in(2, 1, reclaim);
+    let guard = R::guard();
+    for key in (1..STAYING).step_by(2) {
+        assert_eq!(list.insert(key, Box::new(key), &guard), Ok(()));
+    }
+    let done = AtomicBool::new(false);
+    scope(|s| {
+        let churners = (0..THREADS)
+            .map(|t| {
+                let list = &list;
+                s.spawn(move || {
+                    let mut guard = R::guard();
+                    for _ in 0..ROUNDS {
+                        for key in (t * 2..KEYS).step_by(THREADS * 2) {
+                            assert_eq!(list.insert(key, Box::new(key), &guard), Ok(()));
+                        }
+                        for key in (t * 2..KEYS).step_by(THREADS * 2) {
+                            assert_eq!(list.unlink(&key, &mut guard).map(|v| **v), Some(key));
+                        }
+                        for key in (1..STAYING).step_by(2) {
+                            let old = list.upsert(key, Box::new(key), &mut guard);
+                            assert_eq!(old.map(|v| **v), Some(key));
+                        }
+                        collect();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let reader = s.spawn(|| {
+            let mut guard = R::guard();
+            while !done.load(Relaxed) {
+                for key in (1..STAYING).step_by(2) {
+                    assert_eq!(list.get(&key, &mut guard).map(|v| **v), Some(key));
+                }
+                let snapshot = list.snapshot(&guard);
+                for key in (1..STAYING).step_by(2) {
+                    assert!(snapshot.contains(&(key, Box::new(key))));
+                }
+            }
+            collect();
+        });
+        let results = churners.into_iter().map(|c| c.join()).collect::<Vec<_>>();
+        done.store(true, Relaxed);
+        for result in results {
+            result.unwrap();
+        }
+        reader.join().unwrap();
+    });
+    assert_eq!(list.len(), STAYING / 2);
+}
+
+#[test]
+fn reclaim_concurrent_epoch() {
+    reclaim_concurrent(Epoch);
+}
+
+#[test]
+fn reclaim_concurrent_hazard_pointers() {
+    reclaim_concurrent(HazardPointers);
+}
+
 #[test]
 fn stress_sequential() {
-    const STEPS: usize = 4096;
+    const STEPS: usize = scale(4096, 128);
     map::stress_sequential::<_, _, SplitOrderedList<usize>>(STEPS);
 }
 
 #[test]
 fn lookup_concurrent() {
-    const THREADS: usize = 4;
-    const STEPS: usize = 4096;
+    const THREADS: usize = scale(4, 2);
+    const STEPS: usize = scale(4096, 64);
     map::lookup_concurrent::<_, _, SplitOrderedList<usize>>(THREADS, STEPS);
 }
 
 #[test]
 fn insert_concurrent() {
-    const THREADS: usize = 8;
-    const STEPS: usize = 4096 * 4;
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(4096 * 4, 64);
     map::insert_concurrent::<_, _, SplitOrderedList<usize>>(THREADS, STEPS);
 }
 
 #[test]
 fn stress_concurrent() {
-    const THREADS: usize = if cfg!(sanitize = "thread") { 4 } else { 16 };
-    const STEPS: usize = 4096 * if cfg!(sanitize = "thread") { 128 } else { 512 };
+    const THREADS: usize = scale(if cfg!(sanitize = "thread") { 4 } else { 16 }, 2);
+    const STEPS: usize = scale(
+        4096 * if cfg!(sanitize = "thread") { 128 } else { 512 },
+        128,
+    );
     map::stress_concurrent::<_, _, SplitOrderedList<usize>>(THREADS, STEPS);
 }
 
 #[test]
 fn log_concurrent() {
-    const THREADS: usize = if cfg!(sanitize = "thread") { 4 } else { 16 };
-    const STEPS: usize = 4096 * if cfg!(sanitize = "thread") { 16 } else { 64 };
+    const THREADS: usize = scale(if cfg!(sanitize = "thread") { 4 } else { 16 }, 2);
+    const STEPS: usize = scale(4096 * if cfg!(sanitize = "thread") { 16 } else { 64 }, 128);
     map::log_concurrent::<_, _, SplitOrderedList<usize>>(THREADS, STEPS);
 }
+
+/// Hasher that maps every key to one of 4 hashes, so that most keys collide.
+#[derive(Debug, Default)]
+struct Colliding(u64);
+
+impl Hasher for Colliding {
+    fn finish(&self) -> u64 {
+        self.0 % 4
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.wrapping_mul(31).wrapping_add(byte.into());
+        }
+    }
+}
+
+type CollidingHashMap<V> = SplitOrderedHashMap<String, V, BuildHasherDefault<Colliding>>;
+
+#[test]
+pub fn hash_map_smoke() {
+    let map = SplitOrderedHashMap::new();
+
+    let guard = epoch::pin();
+
+    assert_eq!(map.insert("cat".to_string(), 37, &guard), Ok(()));
+    assert_eq!(map.lookup(&"fox".to_string(), &guard), None);
+    assert_eq!(map.lookup(&"cat".to_string(), &guard), Some(&37));
+    assert_eq!(map.insert("cat".to_string(), 42, &guard), Err(42));
+
+    assert_eq!(map.insert("fox".to_string(), 42, &guard), Ok(()));
+    assert_eq!(map.delete(&"cat".to_string(), &guard), Ok(&37));
+    assert_eq!(map.lookup(&"cat".to_string(), &guard), None);
+    assert_eq!(map.lookup(&"fox".to_string(), &guard), Some(&42));
+    assert_eq!(map.delete(&"cat".to_string(), &guard), Err(()));
+}
+
+// The keys of the same hash are told apart, and a chain emptied by deletes can be reused.
+#[test]
+pub fn hash_map_collisions() {
+    let map = CollidingHashMap::default();
+
+    let guard = epoch::pin();
+
+    for i in 0..64 {
+        assert_eq!(map.insert(i.to_string(), i, &guard), Ok(()));
+    }
+    for i in 0..64 {
+        assert_eq!(map.lookup(&i.to_string(), &guard), Some(&i));
+    }
+    for i in 0..64 {
+        assert_eq!(map.delete(&i.to_string(), &guard), Ok(&i));
+        assert_eq!(map.lookup(&i.to_string(), &guard), None);
+    }
+    for i in 0..64 {
+        assert_eq!(map.insert(i.to_string(), i + 1, &guard), Ok(()));
+        assert_eq!(map.lookup(&i.to_string(), &guard), Some(&(i + 1)));
+    }
+}
+
+// Chains are sealed and removed while other threads insert into them. Each thread owns its keys,
+// so its entries must never be lost with a chain removed by another thread.
+#[test]
+fn hash_map_seal_concurrent() {
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(4096 * 4, 64);
+    let map = CollidingHashMap::default();
+
+    scope(|s| {
+        for t in 0..THREADS {
+            let map = &map;
+            let _ = s.spawn(move || {
+                let key = t.to_string();
+                for i in 0..STEPS {
+                    let guard = epoch::pin();
+                    assert_eq!(map.insert(key.clone(), i, &guard), Ok(()));
+                    assert_eq!(map.lookup(&key, &guard), Some(&i));
+                    assert_eq!(map.delete(&key, &guard), Ok(&i));
+                }
+            });
+        }
+    });
+    assert_eq!(map.len(), 0);
+}
+
+#[test]
+fn hash_map_stress_sequential() {
+    const STEPS: usize = scale(4096, 128);
+    map::stress_sequential::<String, usize, SplitOrderedHashMap<_, _>>(STEPS);
+    map::stress_sequential::<String, usize, CollidingHashMap<_>>(STEPS);
+}
+
+#[test]
+fn hash_map_log_concurrent() {
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(4096 * 16, 128);
+    map::log_concurrent::<String, usize, SplitOrderedHashMap<_, _>>(THREADS, STEPS);
+    // fewer steps, as the long chains of colliding keys are scanned linearly.
+    map::log_concurrent::<String, usize, CollidingHashMap<_>>(THREADS, STEPS / 64);
+}
+
+#[test]
+fn striped_stress_sequential() {
+    const STEPS: usize = scale(4096, 128);
+    map::stress_sequential::<String, usize, StripedHashMap<_, _>>(STEPS);
+}
+
+#[test]
+fn striped_log_concurrent() {
+    const THREADS: usize = scale(8, 2);
+    const STEPS: usize = scale(4096 * 16, 128);
+    map::log_concurrent::<String, usize, StripedHashMap<_, _>>(THREADS, STEPS);
+}
+
+// The lock-free maps give the same results as the lock-striped one.
+#[test]
+fn cross_check_striped() {
+    const STEPS: usize = scale(4096 * 4, 128);
+    map::cross_check::<usize, usize, SplitOrderedList<_>, StripedHashMap<_, _>>(STEPS);
+    map::cross_check::<String, usize, SplitOrderedHashMap<_, _>, StripedHashMap<_, _>>(STEPS);
+}
@@ -0,0 +1,86 @@
+//! Checks that `SplitOrderedList` frees the sentinel nodes that lose the race to initialize a
+//! bucket. It is a separate test binary, as the counting allocator is global.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering::*;
+use std::alloc::System;
+use std::sync::{Arc, Barrier};
+use std::thread::spawn;
+
+use crossbeam_epoch::pin;
+use cs431_homework::test::scale;
+use cs431_homework::{ConcurrentMap, SplitOrderedList};
+
+/// Value of the list. It is large so that the values are told apart from the other allocations.
+type Payload = [u8; 4000];
+
+/// Allocator that counts the live nodes of `SplitOrderedList<Payload>`, sentinel or not, and the
+/// live values.
+struct Counting;
+
+static NODES: AtomicUsize = AtomicUsize::new(0);
+
+/// A node is the next pointer of the list, the split-order key, which takes two words, and the
+/// pointer to the value. A value is allocated separately.
+fn is_node(layout: Layout) -> bool {
+    layout.size() == 4 * mem::size_of::<usize>() || layout.size() == mem::size_of::<Payload>()
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if is_node(layout) {
+            let _ = NODES.fetch_add(1, Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if is_node(layout) {
+            let _ = NODES.fetch_sub(1, Relaxed);
+        }
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Threads racing to initialize the same bucket and its parents should free the sentinel nodes
+/// they did not insert.
+#[test]
+fn racing_bucket_init() {
+    const THREADS: usize = scale(16, 2);
+    const ROUNDS: usize = scale(1024, 4);
+    const BUCKETS: usize = 1 << 10;
+
+    // The allocations of the same size as a node that are not of the list, e.g. of the test
+    // harness.
+    let others = NODES.load(Relaxed);
+    for _ in 0..ROUNDS {
+        // Only the bucket 0 is initialized, so the first access to the last bucket initializes
+        // all of its ancestors.
+        let list = Arc::new(SplitOrderedList::<Payload>::with_config(BUCKETS, 2));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        // Unlike scoped threads, joining them waits until they exit and free their own allocations,
+        // which may have the same size as a node.
+        let handles = (0..THREADS)
+            .map(|t| {
+                let (list, barrier) = (list.clone(), barrier.clone());
+                spawn(move || {
+                    let guard = pin();
+                    let _ = barrier.wait();
+                    let key = BUCKETS - 1 + t * BUCKETS;
+                    assert_eq!(list.lookup(&key, &guard), None);
+                    assert_eq!(list.insert(key, [t as u8; 4000], &guard), Ok(()));
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(list);
+        assert_eq!(NODES.load(Relaxed), others);
+    }
+}
@@ -1,10 +1,13 @@
+use core::future::Future;
+use core::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Barrier};
-use std::thread::sleep;
-use std::time::Duration;
+use std::sync::{Arc, Barrier, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::bounded;
-use cs431_homework::hello_server::ThreadPool;
+use cs431_homework::hello_server::{PanicPolicy, Priority, Scaling, ThreadPool};
 
 const NUM_THREADS: usize = 4;
 const NUM_JOBS: usize = 1024;
@@ -59,10 +62,20 @@ fn thread_pool_drop_block() {
     assert_eq!(counter.load(Ordering::Relaxed), NUM_JOBS);
 }
 
+/// Like `thread_pool_drop_block`, but dropping the pool instead of joining it.
+#[test]
+fn thread_pool_drop_block_dropped() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let counter = Arc::new(AtomicUsize::new(0));
+    run_jobs(&pool, &counter);
+    drop(pool);
+    assert_eq!(counter.load(Ordering::Relaxed), NUM_JOBS);
+}
+
 /// This indirectly tests if the worker threads' `JoinHandle`s are joined when the pool is
 /// dropped.
 #[test]
-// #[should_panic]
+#[should_panic]
 fn thread_pool_drop_propagate_panic() {
     let pool = ThreadPool::new(NUM_THREADS);
     pool.execute(move || {
@@ -70,3 +83,762 @@ fn thread_pool_drop_propagate_panic() {
     });
     assert!(pool.panic());
 }
+
+/// The panic of a job is reported by `panic` once the job is joined.
+#[test]
+fn thread_pool_join_panic() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    pool.execute(move || {
+        panic!();
+    });
+    pool.join();
+    assert!(pool.panic());
+    // Otherwise, `drop` panics.
+    assert_eq!(pool.take_panics().len(), 1);
+}
+
+/// The shared pool is the same for every caller.
+#[test]
+fn thread_pool_global() {
+    assert!(core::ptr::eq(ThreadPool::global(), ThreadPool::global()));
+    let counter = Arc::new(AtomicUsize::new(0));
+    run_jobs(ThreadPool::global(), &counter);
+    ThreadPool::global().join();
+    assert_eq!(counter.load(Ordering::Relaxed), NUM_JOBS);
+}
+
+#[test]
+fn thread_pool_scope() {
+    let pool = ThreadPool::new(4);
+    let mut numbers = (0..64).collect::<Vec<usize>>();
+    let sum = AtomicUsize::new(0);
+    pool.scope(|s| {
+        for chunk in numbers.chunks_mut(8) {
+            let sum = &sum;
+            s.spawn(move || {
+                for n in chunk {
+                    *n *= 2;
+                    let _ = sum.fetch_add(*n, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    assert_eq!(sum.load(Ordering::Relaxed), 64 * 63);
+    assert_eq!(numbers, (0..64).map(|n| n * 2).collect::<Vec<_>>());
+}
+
+#[test]
+fn thread_pool_scope_nested_spawn() {
+    let pool = ThreadPool::new(2);
+    let counter = AtomicUsize::new(0);
+    let result = pool.scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..8 {
+                    s.spawn(|| {
+                        let _ = counter.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+            });
+        }
+        42
+    });
+    assert_eq!(result, 42);
+    assert_eq!(counter.load(Ordering::Relaxed), 64);
+}
+
+#[test]
+#[should_panic(expected = "scoped job")]
+fn thread_pool_scope_propagates_panic() {
+    let pool = ThreadPool::new(2);
+    let finished = AtomicUsize::new(0);
+    pool.scope(|s| {
+        s.spawn(|| panic!("scoped job"));
+        s.spawn(|| {
+            sleep(Duration::from_millis(50));
+            let _ = finished.fetch_add(1, Ordering::Relaxed);
+        });
+    });
+}
+
+#[test]
+fn thread_pool_shutdown_waits_for_jobs() {
+    let pool = ThreadPool::new(4);
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..16 {
+        let counter = counter.clone();
+        pool.execute(move || {
+            sleep(Duration::from_millis(10));
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    assert!(pool.shutdown(Duration::from_secs(10)));
+    assert_eq!(counter.load(Ordering::Relaxed), 16);
+}
+
+#[test]
+fn thread_pool_shutdown_drops_queued_jobs() {
+    let pool = ThreadPool::new(2);
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..64 {
+        let counter = counter.clone();
+        pool.execute(move || {
+            sleep(Duration::from_millis(50));
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    assert!(!pool.shutdown(Duration::from_millis(100)));
+    // Only the jobs that were running at the timeout may still finish.
+    sleep(Duration::from_millis(200));
+    assert!(counter.load(Ordering::Relaxed) < 64);
+    pool.join();
+}
+
+#[test]
+fn thread_pool_try_execute_full_queue() {
+    let pool = ThreadPool::with_capacity(1, 1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    let (start_send, start_recv) = crossbeam_channel::bounded(0);
+    pool.execute(move || {
+        start_send.send(()).unwrap();
+        block_recv.recv().unwrap();
+    });
+    // Wait until the worker takes the first job, so that the queue is empty.
+    start_recv.recv().unwrap();
+    assert!(pool.try_execute(|| {}).is_ok());
+    let counter = Arc::new(AtomicUsize::new(0));
+    let counter_clone = counter.clone();
+    let job = pool
+        .try_execute(move || {
+            let _ = counter_clone.fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap_err();
+    block_send.send(()).unwrap();
+    pool.join();
+    job();
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn thread_pool_execute_backpressure() {
+    let pool = ThreadPool::with_capacity(2, 1);
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..16 {
+        let counter = counter.clone();
+        pool.execute(move || {
+            sleep(Duration::from_millis(5));
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 16);
+}
+
+/// A job executing jobs on the full queue doesn't wait for room that only its worker would make.
+#[test]
+fn thread_pool_execute_from_worker_full_queue() {
+    let pool = Arc::new(ThreadPool::with_capacity(1, 1));
+    let counter = Arc::new(AtomicUsize::new(0));
+    let pool_clone = pool.clone();
+    let counter_clone = counter.clone();
+    pool.execute(move || {
+        for _ in 0..4 {
+            let counter = counter_clone.clone();
+            let _ = pool_clone.execute_cancellable(move |_| {
+                let _ = counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+    });
+    assert!(pool.join_timeout(Duration::from_secs(10)));
+    assert_eq!(counter.load(Ordering::Relaxed), 4);
+}
+
+#[test]
+fn thread_pool_execute_batch() {
+    let pool = ThreadPool::new(2);
+    let (unblock_send, unblock_recv) = crossbeam_channel::bounded::<()>(0);
+    pool.execute(move || unblock_recv.recv().unwrap());
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let batch = pool.execute_batch((0..1000).map(|_| {
+        let counter = counter.clone();
+        move || {
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+    // Doesn't wait for the blocked job.
+    pool.join_batch(&batch);
+    assert!(batch.is_finished());
+    assert_eq!(counter.load(Ordering::Relaxed), 1000);
+
+    unblock_send.send(()).unwrap();
+    pool.join();
+    assert_eq!(pool.stats().completed_jobs, 1001);
+
+    let empty = pool.execute_batch(Vec::<fn()>::new());
+    pool.join_batch(&empty);
+}
+
+#[test]
+fn thread_pool_execute_batch_from_panicked_job() {
+    let pool = Arc::new(ThreadPool::new(1));
+    let counter = Arc::new(AtomicUsize::new(0));
+    let (batch_send, batch_recv) = crossbeam_channel::bounded(1);
+    let pool_clone = pool.clone();
+    let counter_clone = counter.clone();
+    pool.execute(move || {
+        // The batch is left in the local queue, and run by the worker on its way out.
+        let batch = pool_clone.execute_batch((0..16).map(|_| {
+            let counter = counter_clone.clone();
+            move || {
+                let _ = counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+        batch_send.send(batch).unwrap();
+        panic!("batch");
+    });
+    let batch = batch_recv.recv().unwrap();
+    pool.join_batch(&batch);
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 16);
+    assert_eq!(pool.take_panics().len(), 1);
+}
+
+#[test]
+fn thread_pool_execute_batch_from_panicked_job_full_queue() {
+    let pool = Arc::new(ThreadPool::with_capacity(1, 1));
+    let counter = Arc::new(AtomicUsize::new(0));
+    let (batch_send, batch_recv) = crossbeam_channel::bounded(1);
+    let (release_send, release_recv) = crossbeam_channel::bounded::<()>(0);
+    let pool_clone = pool.clone();
+    let counter_clone = counter.clone();
+    pool.execute(move || {
+        let batch = pool_clone.execute_batch((0..16).map(|_| {
+            let counter = counter_clone.clone();
+            move || {
+                let _ = counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+        // Keeps the replacement worker busy, so that no worker drains the job queue while the
+        // batch is queued on the way out.
+        pool_clone.execute_with_priority(move || release_recv.recv().unwrap(), Priority::High);
+        batch_send.send(batch).unwrap();
+        panic!("batch");
+    });
+    let batch = batch_recv.recv().unwrap();
+    // The first job of the batch runs once the rest are queued, even if the queue is full.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while counter.load(Ordering::Relaxed) == 0 && Instant::now() < deadline {
+        sleep(Duration::from_millis(1));
+    }
+    let queued = counter.load(Ordering::Relaxed) > 0;
+    release_send.send(()).unwrap();
+    pool.join_batch(&batch);
+    pool.join();
+    assert!(queued);
+    assert_eq!(counter.load(Ordering::Relaxed), 16);
+    assert_eq!(pool.take_panics().len(), 1);
+}
+
+#[test]
+fn thread_pool_execute_with_result() {
+    let pool = ThreadPool::new(4);
+    let handles = (0..16)
+        .map(|i| pool.execute_with_result(move || i * i))
+        .collect::<Vec<_>>();
+    let sum = handles
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .sum::<usize>();
+    assert_eq!(sum, (0..16).map(|i| i * i).sum());
+}
+
+#[test]
+fn thread_pool_job_handle_try_join() {
+    let pool = ThreadPool::new(1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    let handle = pool.execute_with_result(move || {
+        block_recv.recv().unwrap();
+        42
+    });
+    let handle = handle.try_join().unwrap_err();
+    block_send.send(()).unwrap();
+    pool.join();
+    assert_eq!(handle.try_join().unwrap(), Some(42));
+}
+
+#[test]
+fn thread_pool_take_panics() {
+    for policy in [PanicPolicy::Ignore, PanicPolicy::RestartWorker] {
+        let pool = ThreadPool::with_panic_policy(2, policy);
+        let counter = Arc::new(AtomicUsize::new(0));
+        for i in 0..16 {
+            let counter = counter.clone();
+            pool.execute(move || {
+                assert!(i % 4 != 0, "job {i}");
+                let _ = counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        pool.join();
+        assert_eq!(counter.load(Ordering::Relaxed), 12);
+        let mut panics = pool.take_panics();
+        panics.sort_by(|a, b| a.message().cmp(b.message()));
+        let messages = panics.iter().map(|p| p.message()).collect::<Vec<_>>();
+        assert_eq!(messages, ["job 0", "job 12", "job 4", "job 8"]);
+        assert!(!pool.panic());
+    }
+}
+
+#[test]
+fn thread_pool_panic_log() {
+    let pool = ThreadPool::new(1);
+    let log = pool.panic_log();
+    assert!(log.is_empty());
+    for i in 0..3 {
+        pool.execute(move || panic!("job {i}"));
+        pool.join();
+        assert_eq!(log.len(), i + 1);
+    }
+    let messages = log
+        .drain()
+        .iter()
+        .map(|p| p.message().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["job 0", "job 1", "job 2"]);
+    assert_eq!(log.len(), 0);
+    assert!(!pool.panic());
+
+    // Panics are not shared between pools.
+    let other = ThreadPool::new(1);
+    other.execute(|| panic!("other"));
+    other.join();
+    assert_eq!(other.panic_log().len(), 1);
+    assert!(log.is_empty());
+    let _ = other.take_panics();
+}
+
+#[test]
+#[should_panic(expected = "job")]
+fn thread_pool_drop_propagates_panics() {
+    let pool = ThreadPool::with_panic_policy(2, PanicPolicy::Ignore);
+    pool.execute(|| panic!("job"));
+}
+
+#[test]
+fn thread_pool_scaling() {
+    let pool = ThreadPool::with_scaling(Scaling {
+        min_workers: 1,
+        max_workers: 4,
+        queue_threshold: 2,
+        idle_timeout: Duration::from_millis(100),
+    });
+    assert_eq!(pool.num_workers(), 1);
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..32 {
+        let counter = counter.clone();
+        pool.execute(move || {
+            sleep(Duration::from_millis(10));
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    assert_eq!(pool.num_workers(), 4);
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 32);
+    // The idle workers retire after the idle timeout, but when depends on the scheduler.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.num_workers() > 1 && Instant::now() < deadline {
+        sleep(Duration::from_millis(10));
+    }
+    assert_eq!(pool.num_workers(), 1);
+}
+
+#[test]
+fn thread_pool_priority() {
+    let pool = ThreadPool::new(1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    pool.execute(move || block_recv.recv().unwrap());
+    let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+    for (i, priority) in [Priority::Low, Priority::Normal, Priority::High]
+        .into_iter()
+        .cycle()
+        .take(9)
+        .enumerate()
+    {
+        let order = order.clone();
+        pool.execute_with_priority(move || order.lock().unwrap().push((priority, i)), priority);
+    }
+    block_send.send(()).unwrap();
+    pool.join();
+    let order = order.lock().unwrap();
+    let mut sorted = order.clone();
+    sorted.sort();
+    assert_eq!(*order, sorted);
+}
+
+/// Jobs executing jobs of the other priorities on the full queues don't wait for room.
+#[test]
+fn thread_pool_priority_from_worker_full_queue() {
+    let pool = Arc::new(ThreadPool::with_capacity(1, 1));
+    let counter = Arc::new(AtomicUsize::new(0));
+    for priority in [Priority::High, Priority::Low] {
+        let pool_clone = pool.clone();
+        let counter = counter.clone();
+        pool.execute(move || {
+            for _ in 0..4 {
+                let counter = counter.clone();
+                pool_clone.execute_with_priority(
+                    move || {
+                        let _ = counter.fetch_add(1, Ordering::Relaxed);
+                    },
+                    priority,
+                );
+            }
+        });
+    }
+    assert!(pool.join_timeout(Duration::from_secs(10)));
+    assert_eq!(counter.load(Ordering::Relaxed), 8);
+}
+
+#[test]
+fn thread_pool_join_timeout() {
+    let pool = ThreadPool::new(1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    pool.execute(move || block_recv.recv().unwrap());
+    assert!(!pool.join_timeout(Duration::from_millis(50)));
+    block_send.send(()).unwrap();
+    assert!(pool.join_timeout(Duration::from_secs(10)));
+}
+
+#[test]
+fn thread_pool_execute_from_worker() {
+    let pool = Arc::new(ThreadPool::new(4));
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..16 {
+        let pool_clone = pool.clone();
+        let counter = counter.clone();
+        pool.execute(move || {
+            for _ in 0..16 {
+                let counter = counter.clone();
+                pool_clone.execute(move || {
+                    let _ = counter.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        });
+    }
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 256);
+}
+
+/// A job in a local queue is stolen by an idle worker, while the job that executed it waits.
+#[test]
+fn thread_pool_steal() {
+    let pool = Arc::new(ThreadPool::new(2));
+    let pool_clone = pool.clone();
+    let handle = pool.execute_with_result(move || {
+        let (done_send, done_recv) = crossbeam_channel::bounded(1);
+        pool_clone.execute(move || done_send.send(()).unwrap());
+        done_recv.recv_timeout(Duration::from_secs(10)).is_ok()
+    });
+    assert_eq!(handle.join(), Some(true));
+}
+
+#[test]
+fn thread_pool_stats() {
+    let pool = ThreadPool::with_panic_policy(2, PanicPolicy::Ignore);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    let (start_send, start_recv) = crossbeam_channel::bounded(0);
+    for _ in 0..2 {
+        let block_recv = block_recv.clone();
+        let start_send = start_send.clone();
+        pool.execute(move || {
+            start_send.send(()).unwrap();
+            block_recv.recv().unwrap();
+        });
+    }
+    start_recv.recv().unwrap();
+    start_recv.recv().unwrap();
+    for i in 0..8 {
+        pool.execute(move || assert!(i % 4 != 0));
+    }
+    let stats = pool.stats();
+    assert_eq!(stats.queued_jobs, 8);
+    assert_eq!(stats.running_jobs, 2);
+    assert_eq!(stats.completed_jobs, 0);
+
+    sleep(Duration::from_millis(10));
+    block_send.send(()).unwrap();
+    block_send.send(()).unwrap();
+    pool.join();
+    let _ = pool.take_panics();
+    let stats = pool.stats();
+    assert_eq!(stats.queued_jobs, 0);
+    assert_eq!(stats.running_jobs, 0);
+    assert_eq!(stats.completed_jobs, 10);
+    assert_eq!(stats.panics, 2);
+    assert_eq!(stats.worker_busy_time.len(), 2);
+    let busy_time = stats
+        .worker_busy_time
+        .iter()
+        .map(|(_, t)| *t)
+        .sum::<Duration>();
+    assert!(busy_time >= Duration::from_millis(20));
+    assert!(stats.queue_latency.max >= Duration::from_millis(10));
+    assert!(stats.queue_latency.p50 <= stats.queue_latency.max);
+}
+
+#[test]
+fn thread_pool_execute_after() {
+    let pool = ThreadPool::new(2);
+    let (send, recv) = crossbeam_channel::unbounded();
+    let start = Instant::now();
+    for i in [3, 1, 2] {
+        let send = send.clone();
+        pool.execute_after(Duration::from_millis(i * 50), move || send.send(i).unwrap());
+    }
+    let order = (0..3).map(|_| recv.recv().unwrap()).collect::<Vec<_>>();
+    assert_eq!(order, [1, 2, 3]);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+}
+
+#[test]
+fn thread_pool_execute_after_full_queue() {
+    let pool = ThreadPool::with_capacity(1, 1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    let (start_send, start_recv) = crossbeam_channel::bounded(0);
+    pool.execute(move || {
+        start_send.send(()).unwrap();
+        block_recv.recv().unwrap();
+    });
+    // Wait until the worker takes the first job, and then fill the queue.
+    start_recv.recv().unwrap();
+    pool.execute(|| {});
+
+    // The timer thread blocks on the full queue, but doesn't keep others from scheduling.
+    let counter = Arc::new(AtomicUsize::new(0));
+    for _ in 0..2 {
+        let counter = counter.clone();
+        pool.execute_after(Duration::ZERO, move || {
+            let _ = counter.fetch_add(1, Ordering::Relaxed);
+        });
+        sleep(Duration::from_millis(50));
+    }
+    block_send.send(()).unwrap();
+    while counter.load(Ordering::Relaxed) < 2 {
+        sleep(Duration::from_millis(5));
+    }
+    pool.join();
+}
+
+#[test]
+fn thread_pool_execute_every() {
+    let pool = ThreadPool::new(2);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let counter_clone = counter.clone();
+    let handle = pool.execute_every(Duration::from_millis(20), move || {
+        let _ = counter_clone.fetch_add(1, Ordering::Relaxed);
+    });
+    while counter.load(Ordering::Relaxed) < 3 {
+        sleep(Duration::from_millis(5));
+    }
+    handle.cancel();
+    assert!(handle.is_cancelled());
+    sleep(Duration::from_millis(30));
+    pool.join();
+    let count = counter.load(Ordering::Relaxed);
+    sleep(Duration::from_millis(100));
+    assert_eq!(counter.load(Ordering::Relaxed), count);
+}
+
+#[test]
+fn thread_pool_execute_cancellable() {
+    let pool = ThreadPool::new(1);
+    let (start_send, start_recv) = crossbeam_channel::bounded(0);
+    let running = pool.execute_cancellable(move |token| {
+        start_send.send(()).unwrap();
+        while !token.is_cancelled() {
+            sleep(Duration::from_millis(1));
+        }
+    });
+    let counter = Arc::new(AtomicUsize::new(0));
+    let counter_clone = counter.clone();
+    let queued = pool.execute_cancellable(move |_| {
+        let _ = counter_clone.fetch_add(1, Ordering::Relaxed);
+    });
+    start_recv.recv().unwrap();
+    queued.cancel();
+    running.cancel();
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn thread_pool_execute_cancellable_releases_capacity() {
+    let pool = ThreadPool::with_capacity(1, 1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    let (start_send, start_recv) = crossbeam_channel::bounded(0);
+    pool.execute(move || {
+        start_send.send(()).unwrap();
+        block_recv.recv().unwrap();
+    });
+    // Wait until the worker takes the first job, so that the queue is empty.
+    start_recv.recv().unwrap();
+    let counter = Arc::new(AtomicUsize::new(0));
+    let counter_clone = counter.clone();
+    let token = pool.execute_cancellable(move |_| {
+        let _ = counter_clone.fetch_add(1, Ordering::Relaxed);
+    });
+    assert!(pool.try_execute(|| {}).is_err());
+    assert_eq!(pool.stats().queued_jobs, 1);
+
+    // The cancelled job leaves the queue while the worker is still blocked.
+    token.cancel();
+    assert_eq!(pool.stats().queued_jobs, 0);
+    let counter_clone = counter.clone();
+    assert!(
+        pool.try_execute(move || {
+            let _ = counter_clone.fetch_add(10, Ordering::Relaxed);
+        })
+        .is_ok()
+    );
+    block_send.send(()).unwrap();
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 10);
+}
+
+#[test]
+fn thread_pool_execute_cancellable_join() {
+    let pool = ThreadPool::new(1);
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    pool.execute(move || block_recv.recv().unwrap());
+    let token = pool.execute_cancellable(|_| {});
+    token.cancel();
+    // Only the blocked job is left.
+    assert_eq!(pool.stats().running_jobs + pool.stats().queued_jobs, 1);
+    block_send.send(()).unwrap();
+    pool.join();
+}
+
+#[test]
+fn thread_pool_execute_after_shutdown() {
+    let pool = ThreadPool::new(2);
+    let counter = Arc::new(AtomicUsize::new(0));
+    assert!(pool.shutdown(Duration::from_secs(1)));
+    let counter_clone = counter.clone();
+    pool.execute(move || {
+        let _ = counter_clone.fetch_add(1, Ordering::Relaxed);
+    });
+    pool.join();
+    assert_eq!(counter.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn thread_pool_spawn_future() {
+    /// Wakes itself and returns `Pending` for the given number of times.
+    struct YieldNow(usize);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 == 0 {
+                return Poll::Ready(());
+            }
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    /// Ready when the flag is set by another thread.
+    struct Flag(Arc<Mutex<(bool, Option<Waker>)>>);
+
+    impl Future for Flag {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = self.0.lock().unwrap();
+            if state.0 {
+                return Poll::Ready(());
+            }
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    let pool = ThreadPool::new(2);
+    let handles = (0..8)
+        .map(|i| {
+            pool.spawn_future(async move {
+                YieldNow(i).await;
+                i * 2
+            })
+        })
+        .collect::<Vec<_>>();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join(), Some(i * 2));
+    }
+
+    let state = Arc::new(Mutex::new((false, None)));
+    let handle = pool.spawn_future(Flag(state.clone()));
+    sleep(Duration::from_millis(100));
+    let handle = handle.try_join().unwrap_err();
+    let waker = {
+        let mut state = state.lock().unwrap();
+        state.0 = true;
+        state.1.take().unwrap()
+    };
+    let _unused = thread::spawn(move || waker.wake()).join();
+    assert_eq!(handle.join(), Some(()));
+
+    // A panicked future gives `None` and is recorded as a panic of the pool.
+    let handle = pool.spawn_future(async {
+        YieldNow(1).await;
+        panic!("future");
+    });
+    assert_eq!(handle.join(), None);
+    pool.join();
+    assert_eq!(pool.take_panics().len(), 1);
+
+    // Waking from the only worker of a pool whose queue is full doesn't block the worker.
+    let pool = ThreadPool::with_capacity(1, 1);
+    let state = Arc::new(Mutex::new((false, None)));
+    let handle = pool.spawn_future(Flag(state.clone()));
+    while state.lock().unwrap().1.is_none() {
+        sleep(Duration::from_millis(1));
+    }
+    let (block_send, block_recv) = crossbeam_channel::bounded::<()>(0);
+    let (start_send, start_recv) = crossbeam_channel::bounded(0);
+    pool.execute(move || {
+        start_send.send(()).unwrap();
+        block_recv.recv().unwrap();
+        let waker = {
+            let mut state = state.lock().unwrap();
+            state.0 = true;
+            state.1.take().unwrap()
+        };
+        waker.wake();
+    });
+    // Wait until the worker takes the job, and then fill the queue.
+    start_recv.recv().unwrap();
+    pool.execute(|| {});
+    block_send.send(()).unwrap();
+    assert_eq!(handle.join(), Some(()));
+    pool.join();
+}
+
+#[test]
+fn thread_pool_builder() {
+    let pool = ThreadPool::builder()
+        .num_threads(2)
+        .thread_name(|id| format!("worker-{id}"))
+        .stack_size(1 << 20)
+        .core_ids(vec![0])
+        .build();
+    let handles = (0..4)
+        .map(|_| pool.execute_with_result(|| thread::current().name().map(str::to_string)))
+        .collect::<Vec<_>>();
+    for handle in handles {
+        let name = handle.join().unwrap().unwrap();
+        assert!(name == "worker-0" || name == "worker-1", "{name}");
+    }
+}